@@ -1,4 +1,5 @@
 pub mod cpal;
+pub mod null;
 
 #[derive(Debug,Copy,Clone)]
 pub struct AudioSample {
@@ -0,0 +1,17 @@
+use crate::audio::{AudioDevice, AudioSample};
+
+/*
+Audio device that discards all the samples: used in headless mode or when no sound card is available
+ */
+#[derive(Default)]
+pub struct NullAudioDevice {}
+
+impl NullAudioDevice {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl AudioDevice for NullAudioDevice {
+    fn play_sample(&mut self, _sample: AudioSample) {}
+}
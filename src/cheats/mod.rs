@@ -1,10 +1,12 @@
-use tracing::error;
+use std::path::Path;
+use tracing::{error, info, warn};
 use crate::core::memory::{Memory, ReadMemoryAccess};
 
 /*
 
   30aaaaaa 00dd   ;-8bit Write  [aaaaaa]=dd
   80aaaaaa dddd   ;-16bit Write [aaaaaa]=dddd
+  90aaaaaa dddddddd ;-32bit Write [aaaaaa]=dddddddd
 
   D0aaaaaa dddd   ;-16bit/Equal     If dddd=[aaaaaa] then (exec next code)
   D1aaaaaa dddd   ;-16bit/NotEqual  If dddd<>[aaaaaa] then (exec next code)
@@ -22,6 +24,20 @@ use crate::core::memory::{Memory, ReadMemoryAccess};
   5000nnbb dddd   ;\Slide Code aka Patch Code aka Serial Repeater
   aaaaaaaa ??ee   ;/for i=0 to nn-1, [aaaaaaaa+(i*bb)]=dddd+(i*??ee), next i
   00000000 0000   ;-Dummy (do nothing?) needed between slides (CD version only)
+
+  C0aaaaaa dddd   ;-If dddd=[aaaaaa] then (exec all following codes)
+  C1000000 nnnn   ;-Delay nnnn frames before activating next codes
+  00aaaaaa nnnn   ;-Delay, as C1
+  C2ssssss nnnn   ;\Copy nnnn bytes from [ssssss] to [dddddd]
+  80dddddd 0000   ;/
+  D4000000 dddd   ;-If dddd=JoypadButtons then (exec next code)
+  D5000000 dddd   ;-If dddd=JoypadButtons then turn on all following codes
+  D6000000 dddd   ;-If dddd=JoypadButtons then turn off all following codes
+
+Joypad buttons (controller #1, pressed=1, combined with OR):
+  0100 Select 0200 L3 0400 R3 0800 Start 1000 Up 2000 Right 4000 Down 8000 Left
+  0001 L2 0002 R2 0004 L1 0008 R1 0010 Triangle 0020 Circle 0040 Cross 0080 Square
+The delay counts the frames since the cheat has been switched on.
  */
 
 #[derive(Debug)]
@@ -32,11 +48,11 @@ pub enum CheatIfOp {
     LessThan,
 }
 #[derive(Debug)]
-pub enum Cheat {
+pub enum CheatCode {
     WriteMemory {
         address: u32,
         value: u32,
-        is_byte: bool,
+        size: u8,
     },
     If {
         address: u32,
@@ -54,199 +70,437 @@ pub enum Cheat {
         nn: u8,
         bb: u8,
         dddd: u32,
-        op: Option<Box<Cheat>>,
+        op: Option<Box<CheatCode>>,
+    },
+    ActivateIf {
+        address: u32,
+        value: u32,
+    },
+    Delay {
+        frames: u32,
+    },
+    Copy {
+        source: u32,
+        destination: u32,
+        len: u32,
+    },
+    JoypadIf {
+        buttons: u16,
+    },
+    JoypadOn {
+        buttons: u16,
+    },
+    JoypadOff {
+        buttons: u16,
+    },
+}
+
+/*
+A named list of codes, switched on and off as a whole.
+Cheats with the same group are switched on and off together.
+ */
+#[derive(Debug)]
+pub struct Cheat {
+    pub name: String,
+    pub group: Option<String>,
+    pub enabled: bool,
+    pub codes: Vec<CheatCode>,
+    // frames since the cheat has been switched on
+    frames: u32,
+    // D5/D6 state
+    joypad_on: bool,
+}
+
+impl Cheat {
+    pub fn new(name:String,group:Option<String>,enabled:bool,codes:Vec<CheatCode>) -> Self {
+        let mut cheat = Self { name, group, enabled, codes, frames: 0, joypad_on: true };
+        cheat.reset();
+        cheat
+    }
+
+    /*
+    Name used to switch the cheat on and off: the group if any
+     */
+    pub fn label(&self) -> &str {
+        self.group.as_deref().unwrap_or(&self.name)
+    }
+
+    fn reset(&mut self) {
+        self.frames = 0;
+        // with a D5 code the following codes are off until the buttons are pressed
+        self.joypad_on = !self.codes.iter().any(|c| matches!(c,CheatCode::JoypadOn { .. }));
     }
 }
+
 #[derive(Debug)]
 pub struct Cheats {
     pub cheats: Vec<Cheat>,
+    // label selected with the hotkeys
+    selected: Option<usize>,
 }
 
 impl Cheats {
     pub fn new() -> Self {
-        Self { cheats: vec![] }
+        Self { cheats: vec![], selected: None }
     }
 
+    /*
+    Codes of the configuration file: a single cheat, always on
+     */
     pub fn parse(codes:&Vec<String>) -> Self {
         let mut cheats = Cheats::new();
-        let mut patch : Option<Cheat> = None;
+        let codes = Self::parse_codes(codes);
+        if !codes.is_empty() {
+            cheats.add(Cheat::new(String::from("Configuration codes"),None,true,codes));
+        }
+        cheats
+    }
+
+    pub fn parse_codes(codes:&Vec<String>) -> Vec<CheatCode> {
+        let mut cheat_codes = Vec::new();
+        let mut patch : Option<CheatCode> = None;
+        let mut copy : Option<CheatCode> = None;
 
         for code in codes {
             let upper_code = code.to_uppercase();
             let code_value = upper_code.split_whitespace().collect::<Vec<&str>>();
-            if code_value.len() != 2 || code_value[0].len() < 2 {
+            if code_value.len() != 2 || code_value[0].len() != 8 {
                 error!("Invalid cheat code: {}", code);
                 continue;
             }
 
             let cmd = &code_value[0][0..2];
+            let address = u32::from_str_radix(&code_value[0][2..], 16).ok();
+            let value = u32::from_str_radix(code_value[1], 16).ok();
+            let (Some(address),Some(value)) = (address,value) else {
+                error!("Syntax error on cheat code: {}", code);
+                continue;
+            };
             match cmd {
-                "30"|"80" => {
-                    let address = u32::from_str_radix(&code_value[0][2..], 16).ok();
-                    let value = u32::from_str_radix(&code_value[1], 16).ok();
-                    match (address, value) {
-                        (Some(address), Some(value)) => {
-                            let cheat = Cheat::WriteMemory { address, value, is_byte: cmd == "30" };
-
-                            if let Some(Cheat::Patch { nn,bb, dddd, .. }) = patch.take() {
-                                cheats.add(Cheat::Patch { nn, bb, dddd, op: Some(Box::new(cheat)) })
-                            }
-                            else {
-                                cheats.add(cheat);
-                            }
-                        },
-                        _ => {
-                            error!("Syntax error on cheat code: {}", code);
-                        }
+                "30"|"80"|"90" => {
+                    let size = match cmd { "30" => 8, "80" => 16, _ => 32 };
+                    if let Some(CheatCode::Copy { source, len, .. }) = copy.take() {
+                        cheat_codes.push(CheatCode::Copy { source, destination: address, len });
+                        continue;
+                    }
+                    let cheat = CheatCode::WriteMemory { address, value, size };
+
+                    if let Some(CheatCode::Patch { nn,bb, dddd, .. }) = patch.take() {
+                        cheat_codes.push(CheatCode::Patch { nn, bb, dddd, op: Some(Box::new(cheat)) })
+                    }
+                    else {
+                        cheat_codes.push(cheat);
                     }
                 }
                 "D0"|"D1"|"D2"|"D3"|"E0"|"E1"|"E2"|"E3" => {
-                    let address = u32::from_str_radix(&code_value[0][2..], 16).ok();
-                    let value = u32::from_str_radix(&code_value[1], 16).ok();
-                    match (address, value) {
-                        (Some(address), Some(value)) => {
-                            let if_op = match &cmd[1..2] {
-                                "0" => CheatIfOp::Equal,
-                                "1" => CheatIfOp::NotEqual,
-                                "2" => CheatIfOp::LessThan,
-                                "3" => CheatIfOp::GreaterThan,
-                                _ => unreachable!(),
-                            };
-                            cheats.add(Cheat::If { address, value, op: if_op, is_byte: &cmd[0..1] == "E" });
-                        },
-                        _ => {
-                            error!("Syntax error on cheat code: {}", code);
-                        }
-                    }
+                    let if_op = match &cmd[1..2] {
+                        "0" => CheatIfOp::Equal,
+                        "1" => CheatIfOp::NotEqual,
+                        "2" => CheatIfOp::LessThan,
+                        "3" => CheatIfOp::GreaterThan,
+                        _ => unreachable!(),
+                    };
+                    cheat_codes.push(CheatCode::If { address, value, op: if_op, is_byte: &cmd[0..1] == "E" });
                 }
                 "10"|"11"|"20"|"21" => {
-                    let address = u32::from_str_radix(&code_value[0][2..], 16).ok();
-                    let value = u32::from_str_radix(&code_value[1], 16).ok();
-                    match (address, value) {
-                        (Some(address), Some(value)) => {
-                            let cheat = Cheat::IncDec { address, value, is_byte: &cmd[0..1] == "2", is_inc: &cmd[1..2] == "0" };
-                            if let Some(Cheat::Patch { nn,bb, dddd, .. }) = patch.take() {
-                                cheats.add(Cheat::Patch { nn, bb, dddd, op: Some(Box::new(cheat)) })
-                            }
-                            else {
-                                cheats.add(cheat);
-                            }
-                        },
-                        _ => {
-                            error!("Syntax error on cheat code: {}", code);
-                        }
+                    let cheat = CheatCode::IncDec { address, value, is_byte: &cmd[0..1] == "2", is_inc: &cmd[1..2] == "0" };
+                    if let Some(CheatCode::Patch { nn,bb, dddd, .. }) = patch.take() {
+                        cheat_codes.push(CheatCode::Patch { nn, bb, dddd, op: Some(Box::new(cheat)) })
+                    }
+                    else {
+                        cheat_codes.push(cheat);
                     }
                 }
                 "50" => {
-                    let nn = u8::from_str_radix(&code_value[0][4..6], 16).ok();
-                    let bb = u8::from_str_radix(&code_value[0][6..], 16).ok();
-                    let dddd = u32::from_str_radix(&code_value[1], 16).ok();
-                    match (nn, bb, dddd) {
-                        (Some(nn), Some(bb), Some(dddd)) => {
-                            patch = Some(Cheat::Patch { nn, bb,dddd, op: None   });
-                        },
-                        _ => {
-                            error!("Syntax error on cheat code: {}", code);
-                        }
+                    patch = Some(CheatCode::Patch { nn: (address >> 8) as u8, bb: address as u8, dddd: value, op: None });
+                }
+                "C0" => cheat_codes.push(CheatCode::ActivateIf { address, value }),
+                "C1"|"00" => {
+                    if value != 0 {
+                        cheat_codes.push(CheatCode::Delay { frames: value });
                     }
                 }
+                "C2" => {
+                    copy = Some(CheatCode::Copy { source: address, destination: 0, len: value });
+                }
+                "D4" => cheat_codes.push(CheatCode::JoypadIf { buttons: value as u16 }),
+                "D5" => cheat_codes.push(CheatCode::JoypadOn { buttons: value as u16 }),
+                "D6" => cheat_codes.push(CheatCode::JoypadOff { buttons: value as u16 }),
                 _ => {
                     error!("Unsupported cheat code's command: {}", code);
                 }
             }
         }
-        cheats
+        if patch.is_some() || copy.is_some() {
+            error!("Missing last line of slide or copy cheat code");
+        }
+        cheat_codes
+    }
+
+    /*
+    Loads a libretro cheat file:
+      cheats = 2
+      cheat0_desc = "Infinite lives"
+      cheat0_code = "800A1234 0009+800A1236 0000"
+      cheat0_enable = true
+      ...
+    Codes are separated by '+' (address and value can be separated by '+' too).
+    A description like "Group\Name" puts the cheat into a group.
+     */
+    pub fn load_cht(path:&Path) -> Result<Self,String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read cheat file '{}': {}",path.display(),e))?;
+        let mut entries : Vec<(Option<String>,Option<String>,bool)> = Vec::new();
+        for (line_number,line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key,value)) = line.split_once('=') else {
+                warn!("Cheat file '{}' line {}: syntax error",path.display(),line_number + 1);
+                continue;
+            };
+            let key = key.trim();
+            let value = value.trim().trim_matches('"');
+            let Some((index,property)) = key.strip_prefix("cheat").and_then(|k| k.split_once('_')).and_then(|(i,p)| i.parse::<usize>().ok().map(|i| (i,p))) else {
+                continue;
+            };
+            if index >= entries.len() {
+                entries.resize(index + 1,(None,None,false));
+            }
+            match property {
+                "desc" => entries[index].0 = Some(value.to_string()),
+                "code" => entries[index].1 = Some(value.to_string()),
+                "enable" => entries[index].2 = value.eq_ignore_ascii_case("true"),
+                _ => {}
+            }
+        }
+
+        let mut cheats = Cheats::new();
+        for (index,(desc,code,enabled)) in entries.into_iter().enumerate() {
+            let Some(code) = code else { continue };
+            let tokens = code.split(|c:char| c == '+' || c.is_whitespace()).filter(|t| !t.is_empty()).collect::<Vec<&str>>();
+            let codes = tokens.chunks(2).map(|c| c.join(" ")).collect::<Vec<String>>();
+            let codes = Self::parse_codes(&codes);
+            if codes.is_empty() {
+                continue;
+            }
+            let desc = desc.unwrap_or_else(|| format!("Cheat {}",index));
+            let (group,name) = match desc.split_once('\\') {
+                Some((group,name)) => (Some(group.to_string()),name.to_string()),
+                None => (None,desc),
+            };
+            cheats.add(Cheat::new(name,group,enabled,codes));
+        }
+        info!("Loaded {} cheats from '{}'",cheats.cheats.len(),path.display());
+        Ok(cheats)
     }
 
     pub fn add(&mut self, cheat: Cheat) {
         self.cheats.push(cheat);
     }
 
-    pub fn apply<M : Memory>(&self,mem:&mut M) {
-        let mut index = 0;
-        while index < self.cheats.len() {
-            let delta_index = self.apply_cheat(mem,self.cheats.get(index).unwrap());
-            index += delta_index;
+    pub fn append(&mut self, cheats: Cheats) {
+        self.cheats.extend(cheats.cheats);
+    }
+
+    fn labels(&self) -> Vec<&str> {
+        let mut labels : Vec<&str> = Vec::new();
+        for cheat in self.cheats.iter() {
+            if !labels.contains(&cheat.label()) {
+                labels.push(cheat.label());
+            }
         }
+        labels
+    }
+
+    /*
+    Restarts the delays and the joypad switches, i.e. when cheats are switched on
+     */
+    pub fn reset(&mut self) {
+        self.cheats.iter_mut().for_each(Cheat::reset);
+    }
+
+    pub fn enabled_count(&self) -> usize {
+        self.cheats.iter().filter(|c| c.enabled).count()
     }
 
-    fn apply_cheat<M : Memory>(&self,mem:&mut M,cheat: &Cheat) -> usize {
-        //println!("Apply cheat: {:?}",cheat);
+    fn label_enabled(&self,label:&str) -> bool {
+        self.cheats.iter().any(|c| c.enabled && c.label() == label)
+    }
+
+    /*
+    Switches on/off the cheats with the given label (group or name): returns the number of cheats changed
+     */
+    pub fn set_enabled(&mut self,label:&str,enabled:bool) -> usize {
+        let mut count = 0;
+        for cheat in self.cheats.iter_mut().filter(|c| c.label() == label) {
+            if cheat.enabled != enabled {
+                cheat.enabled = enabled;
+                cheat.reset();
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /*
+    Selects the next label: returns it with its state
+     */
+    pub fn select_next(&mut self) -> Option<(String,bool)> {
+        let labels = self.labels().len();
+        if labels == 0 {
+            return None;
+        }
+        self.selected = Some(self.selected.map_or(0,|selected| (selected + 1) % labels));
+        self.get_selected()
+    }
+
+    pub fn get_selected(&self) -> Option<(String,bool)> {
+        self.labels().get(self.selected.unwrap_or(0)).map(|label| (label.to_string(),self.label_enabled(label)))
+    }
+
+    /*
+    Switches on/off the selected label: returns it with its new state
+     */
+    pub fn toggle_selected(&mut self) -> Option<(String,bool)> {
+        let (label,enabled) = self.get_selected()?;
+        self.set_enabled(&label,!enabled);
+        Some((label,!enabled))
+    }
+
+    /*
+    Applies the enabled cheats: joypad_buttons are the pressed buttons of controller #1 (1 << ControllerButton)
+     */
+    pub fn apply<M : Memory>(&mut self,mem:&mut M,joypad_buttons:u16) {
+        // cheat codes use the bytes of the controller response swapped
+        let joypad_buttons = joypad_buttons.swap_bytes();
+        for cheat in self.cheats.iter_mut().filter(|c| c.enabled) {
+            cheat.frames = cheat.frames.saturating_add(1);
+            let mut index = 0;
+            while index < cheat.codes.len() {
+                let code = &cheat.codes[index];
+                index += match code {
+                    CheatCode::ActivateIf { address, value } => {
+                        if Self::read_memory(mem,*address,16) != Self::adjust_value(*value,16) {
+                            break;
+                        }
+                        1
+                    }
+                    CheatCode::Delay { frames } => {
+                        if cheat.frames <= *frames {
+                            break;
+                        }
+                        1
+                    }
+                    CheatCode::JoypadIf { buttons } => {
+                        if *buttons == joypad_buttons { 1 } else { 2 }
+                    }
+                    CheatCode::JoypadOn { buttons } | CheatCode::JoypadOff { buttons } => {
+                        if *buttons == joypad_buttons {
+                            cheat.joypad_on = matches!(code,CheatCode::JoypadOn { .. });
+                        }
+                        // a D5/D6 sequence is checked as a whole
+                        let next_is_switch = matches!(cheat.codes.get(index + 1),Some(CheatCode::JoypadOn { .. } | CheatCode::JoypadOff { .. }));
+                        if !next_is_switch && !cheat.joypad_on {
+                            break;
+                        }
+                        1
+                    }
+                    _ => Self::apply_code(mem,code),
+                };
+            }
+        }
+    }
+
+    fn apply_code<M : Memory>(mem:&mut M,cheat: &CheatCode) -> usize {
         match cheat {
-            Cheat::WriteMemory { address, value, is_byte } => {
-                //println!("Writing {:08X} to {:08X} [{is_byte}]",Self::adjust_value(*value,*is_byte),Self::get_address(*address));
-                Self::write_memory(mem,*address,Self::adjust_value(*value,*is_byte),*is_byte);
+            CheatCode::WriteMemory { address, value, size } => {
+                Self::write_memory(mem,*address,Self::adjust_value(*value,*size),*size);
                 1
             }
-            Cheat::If { address, value, op, is_byte } => {
-                if Self::compare(Self::read_memory(mem,*address,*is_byte),op,Self::adjust_value(*value,*is_byte)) {
+            CheatCode::If { address, value, op, is_byte } => {
+                let size = Self::size(*is_byte);
+                if Self::compare(Self::read_memory(mem,*address,size),op,Self::adjust_value(*value,size)) {
                     1
                 }
                 else {
                     2
                 }
             }
-            Cheat::IncDec { address, value, is_byte, is_inc } => {
-                let read_value = Self::read_memory(mem,*address,*is_byte);
+            CheatCode::IncDec { address, value, is_byte, is_inc } => {
+                let size = Self::size(*is_byte);
+                let read_value = Self::read_memory(mem,*address,size);
                 let write_value = if *is_inc {
-                    Self::adjust_value(read_value + Self::adjust_value(*value,*is_byte),*is_byte)
+                    Self::adjust_value(read_value + Self::adjust_value(*value,size),size)
                 }
                 else {
-                    Self::adjust_value(read_value.saturating_sub(Self::adjust_value(*value,*is_byte)),*is_byte)
+                    Self::adjust_value(read_value.saturating_sub(Self::adjust_value(*value,size)),size)
                 };
-                Self::write_memory(mem,*address,write_value,*is_byte);
+                Self::write_memory(mem,*address,write_value,size);
                 1
             }
-            Cheat::Patch { nn, bb, dddd, op } => {
+            CheatCode::Patch { nn, bb, dddd, op } => {
                 let cheat = op.as_ref().unwrap();
                 for i in 0..*nn {
-                    let delta_address = i as u32 * (*bb as u32);
+                    // slides wrap around, as on the GameShark
+                    let delta_address = (i as u32).wrapping_mul(*bb as u32);
                     match cheat.as_ref() {
-                        Cheat::WriteMemory { address, value, is_byte } => {
-                            let delta_value =  i as u32 * *value;
-                            self.apply_cheat(mem,&Cheat::WriteMemory { address: *address + delta_address, value: *dddd + delta_value, is_byte: *is_byte });
+                        CheatCode::WriteMemory { address, value, size } => {
+                            let delta_value = (i as u32).wrapping_mul(*value);
+                            Self::apply_code(mem,&CheatCode::WriteMemory { address: address.wrapping_add(delta_address), value: dddd.wrapping_add(delta_value), size: *size });
                         }
-                        Cheat::IncDec { address, value, is_byte, is_inc } => {
-                            let delta_value =  i as u32 * *value;
-                            self.apply_cheat(mem,&Cheat::IncDec { address: *address + delta_address, value: *dddd + delta_value, is_byte: *is_byte, is_inc: *is_inc });
+                        CheatCode::IncDec { address, value, is_byte, is_inc } => {
+                            let delta_value = (i as u32).wrapping_mul(*value);
+                            Self::apply_code(mem,&CheatCode::IncDec { address: address.wrapping_add(delta_address), value: dddd.wrapping_add(delta_value), is_byte: *is_byte, is_inc: *is_inc });
                         }
                         _ => {/*ignored*/}
                     }
                 }
                 1
             }
+            CheatCode::Copy { source, destination, len } => {
+                for i in 0..*len {
+                    let value = Self::read_memory(mem,source.wrapping_add(i),8);
+                    Self::write_memory(mem,destination.wrapping_add(i),value,8);
+                }
+                1
+            }
+            // handled by apply
+            CheatCode::ActivateIf { .. } | CheatCode::Delay { .. } | CheatCode::JoypadIf { .. } | CheatCode::JoypadOn { .. } | CheatCode::JoypadOff { .. } => 1,
         }
     }
 
     #[inline(always)]
-    fn adjust_value(value:u32,is_byte:bool) -> u32 {
-        if is_byte {
-            value & 0xFF
-        }
-        else {
-            value & 0xFFFF
+    fn size(is_byte:bool) -> u8 {
+        if is_byte { 8 } else { 16 }
+    }
+
+    #[inline(always)]
+    fn adjust_value(value:u32,size:u8) -> u32 {
+        match size {
+            8 => value & 0xFF,
+            16 => value & 0xFFFF,
+            _ => value,
         }
     }
 
     #[inline(always)]
-    fn write_memory<M : Memory>(mem:&mut M,address:u32,value:u32,is_byte:bool) {
+    fn write_memory<M : Memory>(mem:&mut M,address:u32,value:u32,size:u8) {
         let address = Self::get_address(address);
-        if is_byte {
-            mem.write::<8>(address,value);
-        }
-        else {
-            mem.write::<16>(address,value);
+        match size {
+            8 => { mem.write::<8>(address,value); }
+            16 => { mem.write::<16>(address,value); }
+            _ => { mem.write::<32>(address,value); }
         }
     }
 
     #[inline(always)]
-    fn read_memory<M : Memory>(mem:&mut M,address:u32,is_byte:bool) -> u32 {
+    fn read_memory<M : Memory>(mem:&mut M,address:u32,size:u8) -> u32 {
         let address = Self::get_address(address);
-        let read = if is_byte {
-            mem.read::<8>(address,false)
-        }
-        else {
-            mem.read::<16>(address,false)
+        let read = match size {
+            8 => mem.read::<8>(address,false),
+            16 => mem.read::<16>(address,false),
+            _ => mem.read::<32>(address,false),
         };
 
         match read  {
@@ -271,4 +525,4 @@ impl Cheats {
     fn get_address(a:u32) -> u32 {
         a & 0xFFFFFF | 0x80000000
     }
-}
\ No newline at end of file
+}
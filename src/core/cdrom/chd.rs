@@ -0,0 +1,659 @@
+use crate::core::cdrom::disc::{TrackFile, SECTOR_SIZE};
+use crate::core::cdrom::flac::{self, BitReader};
+use crate::core::cdrom::sector::{ecc_generate, CD_SYNC_HEADER};
+use flate2::{Decompress, FlushDecompress};
+use lzma_rust2::LzmaReader;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tracing::{debug, info};
+
+/*
+CHD (MAME Compressed Hunks of Data) v5 reader.
+The image is split in hunks, each one compressed with one of the (up to 4) codecs declared in the header.
+For CD images every hunk holds a number of frames of 2448 bytes: 2352 bytes of sector data + 96 bytes of subcode.
+Hunks are decompressed on demand when a sector is read: the last decompressed hunk is cached.
+ */
+
+const CHD_TAG : &[u8;8] = b"MComprHD";
+const CHD_V5_HEADER_SIZE : usize = 124;
+const CD_FRAME_SIZE : usize = 2448;
+const CD_SECTOR_SIZE : usize = SECTOR_SIZE as usize;
+const CD_TRACK_PADDING : u32 = 4;
+
+const fn fourcc(tag:&[u8;4]) -> u32 {
+    u32::from_be_bytes(*tag)
+}
+
+const CODEC_NONE : u32 = 0;
+const CODEC_ZLIB : u32 = fourcc(b"zlib");
+const CODEC_LZMA : u32 = fourcc(b"lzma");
+const CODEC_FLAC : u32 = fourcc(b"flac");
+const CODEC_CD_ZLIB : u32 = fourcc(b"cdzl");
+const CODEC_CD_LZMA : u32 = fourcc(b"cdlz");
+const CODEC_CD_FLAC : u32 = fourcc(b"cdfl");
+
+const CDROM_TRACK_METADATA_TAG : u32 = fourcc(b"CHTR");
+const CDROM_TRACK_METADATA2_TAG : u32 = fourcc(b"CHT2");
+
+// compressed map entry types
+const COMPRESSION_TYPE_0 : u8 = 0;
+const COMPRESSION_TYPE_3 : u8 = 3;
+const COMPRESSION_NONE : u8 = 4;
+const COMPRESSION_SELF : u8 = 5;
+const COMPRESSION_PARENT : u8 = 6;
+const COMPRESSION_RLE_SMALL : u8 = 7;
+const COMPRESSION_RLE_LARGE : u8 = 8;
+const COMPRESSION_SELF_0 : u8 = 9;
+const COMPRESSION_SELF_1 : u8 = 10;
+const COMPRESSION_PARENT_SELF : u8 = 11;
+const COMPRESSION_PARENT_0 : u8 = 12;
+const COMPRESSION_PARENT_1 : u8 = 13;
+
+#[derive(Debug,Clone,Copy)]
+enum HunkEntry {
+    Compressed { codec: u8, offset: u64, length: u32 },
+    Uncompressed(u64),
+    SelfRef(u32),
+    Parent,
+    Zero,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub(super) enum ChdTrackType {
+    Audio,
+    Mode1Raw,
+    Mode2Raw,
+    Other(String),
+}
+
+/*
+A CD track as described by the CHT2/CHTR metadata.
+Track frames are padded to a multiple of 4 inside the image.
+ */
+#[derive(Debug,Clone)]
+pub(super) struct ChdTrack {
+    pub number: u8,
+    pub track_type: ChdTrackType,
+    pub frames: u32,
+    pub pregap: u32,
+    pub pregap_in_file: bool,
+    pub postgap: u32,
+    pub first_frame: u32,
+}
+
+pub(super) struct ChdFile {
+    file: BufReader<File>,
+    path: PathBuf,
+    compressors: [u32;4],
+    hunk_bytes: u32,
+    unit_bytes: u32,
+    map: Vec<HunkEntry>,
+    meta_offset: u64,
+    cached_hunk: Option<u32>,
+    hunk: Vec<u8>,
+    compressed: Vec<u8>,
+    scratch: Vec<u8>,
+}
+
+impl fmt::Debug for ChdFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,"ChdFile({})",self.path.display())
+    }
+}
+
+fn be16(b:&[u8]) -> u16 {
+    u16::from_be_bytes([b[0],b[1]])
+}
+fn be32(b:&[u8]) -> u32 {
+    u32::from_be_bytes([b[0],b[1],b[2],b[3]])
+}
+fn be48(b:&[u8]) -> u64 {
+    ((be16(b) as u64) << 32) | be32(&b[2..]) as u64
+}
+fn be64(b:&[u8]) -> u64 {
+    ((be32(b) as u64) << 32) | be32(&b[4..]) as u64
+}
+
+impl ChdFile {
+    pub fn open(path:&Path) -> Result<Self,String> {
+        let file = File::open(path).map_err(|e| format!("Cannot open CHD file '{}': {}",path.display(),e))?;
+        let mut file = BufReader::new(file);
+        let mut header = [0u8;CHD_V5_HEADER_SIZE];
+        file.read_exact(&mut header).map_err(|e| format!("Cannot read CHD header: {}",e))?;
+        if &header[0..8] != CHD_TAG {
+            return Err(format!("'{}' is not a CHD file",path.display()));
+        }
+        let version = be32(&header[12..]);
+        if version != 5 {
+            return Err(format!("Unsupported CHD version {}: only v5 is supported",version));
+        }
+        let mut compressors = [0u32;4];
+        for (i,c) in compressors.iter_mut().enumerate() {
+            *c = be32(&header[16 + i * 4..]);
+        }
+        let logical_bytes = be64(&header[32..]);
+        let map_offset = be64(&header[40..]);
+        let meta_offset = be64(&header[48..]);
+        let hunk_bytes = be32(&header[56..]);
+        let unit_bytes = be32(&header[60..]);
+        // parent SHA1
+        if header[104..124].iter().any(|&b| b != 0) {
+            return Err("CHD files with a parent are not supported".to_string());
+        }
+        if hunk_bytes == 0 || unit_bytes as usize != CD_FRAME_SIZE || !hunk_bytes.is_multiple_of(unit_bytes) {
+            return Err(format!("CHD file '{}' is not a CD image (hunk size {}, unit size {})",path.display(),hunk_bytes,unit_bytes));
+        }
+        for &c in compressors.iter().filter(|&&c| c != CODEC_NONE) {
+            if !matches!(c,CODEC_ZLIB | CODEC_LZMA | CODEC_FLAC | CODEC_CD_ZLIB | CODEC_CD_LZMA | CODEC_CD_FLAC) {
+                return Err(format!("Unsupported CHD codec '{}'",String::from_utf8_lossy(&c.to_be_bytes())));
+            }
+        }
+        let hunk_count = logical_bytes.div_ceil(hunk_bytes as u64) as u32;
+
+        let mut chd = ChdFile {
+            file,
+            path: path.to_path_buf(),
+            compressors,
+            hunk_bytes,
+            unit_bytes,
+            map: Vec::new(),
+            meta_offset,
+            cached_hunk: None,
+            hunk: vec![0;hunk_bytes as usize],
+            compressed: Vec::new(),
+            scratch: Vec::new(),
+        };
+        chd.map = if compressors[0] == CODEC_NONE {
+            chd.read_raw_map(map_offset,hunk_count)?
+        }
+        else {
+            chd.read_compressed_map(map_offset,hunk_count)?
+        };
+        info!("CHD file '{}' opened: {} hunks of {} bytes, codecs [{}]",path.display(),hunk_count,hunk_bytes,
+            compressors.iter().filter(|&&c| c != CODEC_NONE).map(|c| String::from_utf8_lossy(&c.to_be_bytes()).to_string()).collect::<Vec<_>>().join(","));
+        Ok(chd)
+    }
+
+    fn read_bytes_at(&mut self,offset:u64,len:usize) -> Result<Vec<u8>,String> {
+        let mut buffer = vec![0u8;len];
+        self.file.seek(SeekFrom::Start(offset)).and_then(|_| self.file.read_exact(&mut buffer)).map_err(|e| format!("Cannot read CHD file at offset {}: {}",offset,e))?;
+        Ok(buffer)
+    }
+
+    fn read_raw_map(&mut self,map_offset:u64,hunk_count:u32) -> Result<Vec<HunkEntry>,String> {
+        let raw = self.read_bytes_at(map_offset,hunk_count as usize * 4)?;
+        Ok(raw.chunks_exact(4).map(|e| {
+            match be32(e) {
+                0 => HunkEntry::Zero,
+                block => HunkEntry::Uncompressed(block as u64 * self.hunk_bytes as u64)
+            }
+        }).collect())
+    }
+
+    fn read_compressed_map(&mut self,map_offset:u64,hunk_count:u32) -> Result<Vec<HunkEntry>,String> {
+        let header = self.read_bytes_at(map_offset,16)?;
+        let map_bytes = be32(&header[0..]) as usize;
+        let first_offset = be48(&header[4..]);
+        let length_bits = header[12] as u32;
+        let self_bits = header[13] as u32;
+        let parent_bits = header[14] as u32;
+        let data = self.read_bytes_at(map_offset + 16,map_bytes)?;
+        let mut br = BitReader::new(&data);
+
+        // first pass: compression types, Huffman + RLE encoded
+        let huffman = Huffman::import_rle(&mut br)?;
+        let mut types = vec![0u8;hunk_count as usize];
+        let mut last_type = 0u8;
+        let mut repeat = 0u32;
+        for t in types.iter_mut() {
+            if repeat > 0 {
+                *t = last_type;
+                repeat -= 1;
+                continue;
+            }
+            match huffman.decode(&mut br) {
+                COMPRESSION_RLE_SMALL => {
+                    *t = last_type;
+                    repeat = 2 + huffman.decode(&mut br) as u32;
+                }
+                COMPRESSION_RLE_LARGE => {
+                    *t = last_type;
+                    repeat = 2 + 16 + ((huffman.decode(&mut br) as u32) << 4);
+                    repeat += huffman.decode(&mut br) as u32;
+                }
+                value => {
+                    *t = value;
+                    last_type = value;
+                }
+            }
+        }
+
+        // second pass: offsets and lengths
+        let mut map = Vec::with_capacity(hunk_count as usize);
+        let mut current_offset = first_offset;
+        let mut last_self = 0u32;
+        for (hunk,&t) in types.iter().enumerate() {
+            let entry = match t {
+                COMPRESSION_TYPE_0..=COMPRESSION_TYPE_3 => {
+                    let length = br.read(length_bits);
+                    let entry = HunkEntry::Compressed { codec: t, offset: current_offset, length };
+                    current_offset += length as u64;
+                    br.skip(16); // crc
+                    entry
+                }
+                COMPRESSION_NONE => {
+                    let entry = HunkEntry::Uncompressed(current_offset);
+                    current_offset += self.hunk_bytes as u64;
+                    br.skip(16); // crc
+                    entry
+                }
+                COMPRESSION_SELF => {
+                    last_self = br.read(self_bits);
+                    HunkEntry::SelfRef(last_self)
+                }
+                COMPRESSION_SELF_0 => HunkEntry::SelfRef(last_self),
+                COMPRESSION_SELF_1 => {
+                    last_self += 1;
+                    HunkEntry::SelfRef(last_self)
+                }
+                COMPRESSION_PARENT => {
+                    br.skip(parent_bits);
+                    HunkEntry::Parent
+                }
+                COMPRESSION_PARENT_SELF | COMPRESSION_PARENT_0 | COMPRESSION_PARENT_1 => HunkEntry::Parent,
+                _ => return Err(format!("Invalid CHD map entry type {} for hunk {}",t,hunk))
+            };
+            map.push(entry);
+        }
+        if br.is_overflow() {
+            return Err("CHD map truncated".to_string());
+        }
+        Ok(map)
+    }
+
+    /*
+    Reads the CD track list from the image metadata
+     */
+    pub fn read_tracks(&mut self) -> Result<Vec<ChdTrack>,String> {
+        let mut tracks = Vec::new();
+        let mut offset = self.meta_offset;
+        while offset != 0 {
+            let header = self.read_bytes_at(offset,16)?;
+            let tag = be32(&header[0..]);
+            let length = (be32(&header[4..]) & 0x00FF_FFFF) as usize;
+            let next = be64(&header[8..]);
+            if tag == CDROM_TRACK_METADATA2_TAG || tag == CDROM_TRACK_METADATA_TAG {
+                let data = self.read_bytes_at(offset + 16,length)?;
+                let text = String::from_utf8_lossy(&data).trim_end_matches('\0').to_string();
+                debug!("CHD track metadata: {}",text);
+                tracks.push(Self::parse_track_metadata(&text)?);
+            }
+            offset = next;
+        }
+        if tracks.is_empty() {
+            return Err(format!("CHD file '{}' does not contain CD track metadata",self.path.display()));
+        }
+        tracks.sort_by_key(|t| t.number);
+        let mut first_frame = 0;
+        for track in tracks.iter_mut() {
+            track.first_frame = first_frame;
+            first_frame += track.frames.div_ceil(CD_TRACK_PADDING) * CD_TRACK_PADDING;
+        }
+        Ok(tracks)
+    }
+
+    /*
+    CHT2: TRACK:%d TYPE:%s SUBTYPE:%s FRAMES:%d PREGAP:%d PGTYPE:%s PGSUB:%s POSTGAP:%d
+    CHTR: TRACK:%d TYPE:%s SUBTYPE:%s FRAMES:%d
+     */
+    fn parse_track_metadata(text:&str) -> Result<ChdTrack,String> {
+        let mut track = ChdTrack { number: 0, track_type: ChdTrackType::Other(String::new()), frames: 0, pregap: 0, pregap_in_file: false, postgap: 0, first_frame: 0 };
+        let parse_num = |v:&str| v.parse::<u32>().map_err(|_| format!("Invalid number '{}' in CHD track metadata '{}'",v,text));
+        for field in text.split_whitespace() {
+            let Some((key,value)) = field.split_once(':') else { continue };
+            match key {
+                "TRACK" => track.number = parse_num(value)? as u8,
+                "TYPE" => track.track_type = match value {
+                    "AUDIO" => ChdTrackType::Audio,
+                    "MODE1_RAW" => ChdTrackType::Mode1Raw,
+                    "MODE2_RAW" => ChdTrackType::Mode2Raw,
+                    other => ChdTrackType::Other(other.to_string())
+                },
+                "FRAMES" => track.frames = parse_num(value)?,
+                "PREGAP" => track.pregap = parse_num(value)?,
+                "PGTYPE" => track.pregap_in_file = value.starts_with('V'),
+                "POSTGAP" => track.postgap = parse_num(value)?,
+                _ => {}
+            }
+        }
+        if track.number == 0 {
+            return Err(format!("Invalid CHD track metadata '{}'",text));
+        }
+        Ok(track)
+    }
+
+    /*
+    Copies the 2352 bytes of sector data of the given frame into buffer
+     */
+    pub fn read_frame(&mut self,frame:u32,buffer:&mut [u8]) -> Result<(),String> {
+        let frames_per_hunk = self.hunk_bytes / self.unit_bytes;
+        let hunk = frame / frames_per_hunk;
+        self.load_hunk(hunk)?;
+        let offset = (frame % frames_per_hunk) as usize * CD_FRAME_SIZE;
+        buffer.copy_from_slice(&self.hunk[offset..offset + CD_SECTOR_SIZE]);
+        Ok(())
+    }
+
+    fn load_hunk(&mut self,hunk:u32) -> Result<(),String> {
+        if self.cached_hunk == Some(hunk) {
+            return Ok(());
+        }
+        self.cached_hunk = None;
+        let entry = *self.map.get(hunk as usize).ok_or_else(|| format!("CHD hunk {} out of range",hunk))?;
+        match entry {
+            HunkEntry::Compressed { codec, offset, length } => {
+                let mut compressed = std::mem::take(&mut self.compressed);
+                compressed.resize(length as usize,0);
+                let read = self.file.seek(SeekFrom::Start(offset)).and_then(|_| self.file.read_exact(&mut compressed));
+                let result = read.map_err(|e| format!("Cannot read CHD hunk {}: {}",hunk,e))
+                    .and_then(|_| self.decompress_hunk(self.compressors[codec as usize],&compressed));
+                self.compressed = compressed;
+                result.map_err(|e| format!("Cannot decompress CHD hunk {}: {}",hunk,e))?;
+            }
+            HunkEntry::Uncompressed(offset) => {
+                self.file.seek(SeekFrom::Start(offset)).and_then(|_| self.file.read_exact(&mut self.hunk)).map_err(|e| format!("Cannot read CHD hunk {}: {}",hunk,e))?;
+            }
+            HunkEntry::SelfRef(other) => {
+                if other >= hunk {
+                    return Err(format!("Invalid CHD self reference from hunk {} to hunk {}",hunk,other));
+                }
+                self.load_hunk(other)?;
+            }
+            HunkEntry::Parent => return Err(format!("CHD hunk {} references a parent image",hunk)),
+            HunkEntry::Zero => self.hunk.fill(0),
+        }
+        self.cached_hunk = Some(hunk);
+        Ok(())
+    }
+
+    fn decompress_hunk(&mut self,codec:u32,src:&[u8]) -> Result<(),String> {
+        let hunk_bytes = self.hunk_bytes as usize;
+        match codec {
+            CODEC_ZLIB => inflate(src,&mut self.hunk),
+            CODEC_LZMA => lzma_decompress(src,&mut self.hunk,self.hunk_bytes),
+            CODEC_FLAC => {
+                let big_endian = match src.first() {
+                    Some(b'L') => false,
+                    Some(b'B') => true,
+                    _ => return Err("Invalid FLAC endianness marker".to_string())
+                };
+                flac::decode_interleaved(&src[1..],&mut self.hunk,big_endian).map(|_| ())
+            }
+            CODEC_CD_ZLIB | CODEC_CD_LZMA | CODEC_CD_FLAC => {
+                let frames = hunk_bytes / CD_FRAME_SIZE;
+                let mut sectors = std::mem::take(&mut self.scratch);
+                sectors.resize(frames * CD_SECTOR_SIZE,0);
+                let ecc_bytes = frames.div_ceil(8);
+                let result = if codec == CODEC_CD_FLAC {
+                    // audio samples are stored big endian, subcode is not needed
+                    flac::decode_interleaved(src,&mut sectors,true).map(|_| ())
+                }
+                else {
+                    let complen_bytes = if hunk_bytes < 65536 { 2 } else { 3 };
+                    let header_bytes = ecc_bytes + complen_bytes;
+                    if src.len() < header_bytes {
+                        return Err("CD hunk too short".to_string());
+                    }
+                    let mut base_len = be16(&src[ecc_bytes..]) as usize;
+                    if complen_bytes > 2 {
+                        base_len = (base_len << 8) | src[ecc_bytes + 2] as usize;
+                    }
+                    match src.get(header_bytes..header_bytes + base_len) {
+                        Some(base) if codec == CODEC_CD_ZLIB => inflate(base,&mut sectors),
+                        Some(base) => lzma_decompress(base,&mut sectors,self.hunk_bytes),
+                        None => Err("CD hunk base data out of range".to_string())
+                    }
+                };
+                if result.is_ok() {
+                    for (f,sector) in sectors.chunks_exact_mut(CD_SECTOR_SIZE).enumerate() {
+                        // ECC was stripped from this sector: sync header and ECC must be regenerated
+                        if codec != CODEC_CD_FLAC && src[f / 8] & (1 << (f % 8)) != 0 {
+                            sector[0..12].copy_from_slice(&CD_SYNC_HEADER);
+                            ecc_generate(sector);
+                        }
+                        let frame = &mut self.hunk[f * CD_FRAME_SIZE..(f + 1) * CD_FRAME_SIZE];
+                        frame[..CD_SECTOR_SIZE].copy_from_slice(sector);
+                        frame[CD_SECTOR_SIZE..].fill(0);
+                    }
+                }
+                self.scratch = sectors;
+                result
+            }
+            _ => Err(format!("Unsupported codec '{}'",String::from_utf8_lossy(&codec.to_be_bytes())))
+        }
+    }
+}
+
+pub(super) fn inflate(src:&[u8],dest:&mut [u8]) -> Result<(),String> {
+    let mut inflater = Decompress::new(false);
+    inflater.decompress(src,dest,FlushDecompress::Finish).map_err(|e| format!("zlib error: {}",e))?;
+    if inflater.total_out() as usize != dest.len() {
+        return Err(format!("zlib error: expected {} bytes, got {}",dest.len(),inflater.total_out()));
+    }
+    Ok(())
+}
+
+/*
+CHD uses raw LZMA streams with lc=3, lp=0, pb=2 and a dictionary sized on the hunk
+ */
+fn lzma_decompress(src:&[u8],dest:&mut [u8],hunk_bytes:u32) -> Result<(),String> {
+    let mut reader = LzmaReader::new(src,dest.len() as u64,3,0,2,hunk_bytes.max(4096),None).map_err(|e| format!("lzma error: {}",e))?;
+    reader.read_exact(dest).map_err(|e| format!("lzma error: {}",e))
+}
+
+/*
+Huffman decoder used by the compressed map: 16 codes, max 8 bits per code
+ */
+const HUFFMAN_CODES : usize = 16;
+const HUFFMAN_MAX_BITS : u32 = 8;
+
+struct Huffman {
+    lookup: Vec<u16>,
+}
+
+impl Huffman {
+    fn import_rle(br:&mut BitReader) -> Result<Self,String> {
+        let mut code_bits = [0u8;HUFFMAN_CODES];
+        let num_bits = 4; // max bits = 8
+        let mut node = 0;
+        while node < HUFFMAN_CODES {
+            let bits = br.read(num_bits) as u8;
+            if bits != 1 {
+                code_bits[node] = bits;
+                node += 1;
+                continue;
+            }
+            let bits = br.read(num_bits) as u8;
+            if bits == 1 {
+                code_bits[node] = bits;
+                node += 1;
+                continue;
+            }
+            let repeat = br.read(num_bits) as usize + 3;
+            if node + repeat > HUFFMAN_CODES {
+                return Err("Invalid CHD map Huffman tree".to_string());
+            }
+            code_bits[node..node + repeat].fill(bits);
+            node += repeat;
+        }
+
+        // canonical codes
+        let mut histogram = [0u32;33];
+        for &bits in code_bits.iter() {
+            if bits as u32 > HUFFMAN_MAX_BITS {
+                return Err("Invalid CHD map Huffman code length".to_string());
+            }
+            histogram[bits as usize] += 1;
+        }
+        let mut start = 0u32;
+        for len in (1..=32).rev() {
+            let next = (start + histogram[len]) >> 1;
+            if len != 1 && next * 2 != start + histogram[len] {
+                return Err("Invalid CHD map Huffman tree".to_string());
+            }
+            histogram[len] = start;
+            start = next;
+        }
+
+        let mut lookup = vec![0u16;1 << HUFFMAN_MAX_BITS];
+        for (index,&bits) in code_bits.iter().enumerate() {
+            if bits == 0 {
+                continue;
+            }
+            let code = histogram[bits as usize];
+            histogram[bits as usize] += 1;
+            let shift = HUFFMAN_MAX_BITS - bits as u32;
+            let value = ((index as u16) << 5) | bits as u16;
+            let first = (code << shift) as usize;
+            let last = (((code + 1) << shift) - 1) as usize;
+            lookup[first..=last].fill(value);
+        }
+        Ok(Self { lookup })
+    }
+
+    fn decode(&self,br:&mut BitReader) -> u8 {
+        let value = self.lookup[br.peek(HUFFMAN_MAX_BITS) as usize];
+        br.skip((value & 0x1F) as u32);
+        (value >> 5) as u8
+    }
+}
+
+/*
+A track of a CHD image seen as a flat sequence of 2352 bytes sectors.
+Audio samples are stored big endian in CHD images: they are swapped to little endian as bin files.
+ */
+#[derive(Debug)]
+pub(super) struct ChdTrackFile {
+    chd: Rc<RefCell<ChdFile>>,
+    first_frame: u32,
+    audio: bool,
+}
+
+impl ChdTrackFile {
+    pub fn new(chd:Rc<RefCell<ChdFile>>,first_frame:u32,audio:bool) -> Self {
+        Self { chd, first_frame, audio }
+    }
+}
+
+impl TrackFile for ChdTrackFile {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> std::io::Result<()> {
+        let mut sector = [0u8;CD_SECTOR_SIZE];
+        let mut offset = offset;
+        let mut done = 0;
+        let mut chd = self.chd.borrow_mut();
+        while done < buffer.len() {
+            let frame = self.first_frame + (offset / CD_SECTOR_SIZE as u64) as u32;
+            let start = (offset % CD_SECTOR_SIZE as u64) as usize;
+            chd.read_frame(frame,&mut sector).map_err(std::io::Error::other)?;
+            if self.audio {
+                for sample in sector.chunks_exact_mut(2) {
+                    sample.swap(0,1);
+                }
+            }
+            let len = (CD_SECTOR_SIZE - start).min(buffer.len() - done);
+            buffer[done..done + len].copy_from_slice(&sector[start..start + len]);
+            done += len;
+            offset += len as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+    tests/chd/codecs.chd is built by tests/chd/make_codecs_chd.py (see tests/chd/README.md): one frame per hunk, compressors
+    zlib, lzma, cdzl and cdfl, every map entry kind but the parent ones. The digests are those of the source frames.
+     */
+    const CODECS_CHD : &str = concat!(env!("CARGO_MANIFEST_DIR"),"/tests/chd/codecs.chd");
+    const HUNK_MD5 : [&str;36] = [
+        "4e8208433fab0935fe1dd91d71277e09", // zlib
+        "b8ea480f19742df0ebce486dcf5d86aa", // lzma
+        "4da91a119a97efb011696e1aa5206048", // cdzl, Mode 1 sector with ECC to regenerate
+        "2cdff28720cc43b5a057c6cc629ca753", // cdfl, 4 FLAC frames with every stereo mode
+        "9e004dcced377e04c89762a5708ffda9", // uncompressed
+        "4e8208433fab0935fe1dd91d71277e09", // self reference to hunk 0
+        "4e8208433fab0935fe1dd91d71277e09", // same self reference as the last one
+        "b8ea480f19742df0ebce486dcf5d86aa", // last self reference + 1
+        "ca3e85ad085078a14c62252462d5142c", // zlib, RLE small
+        "d4a35a8e80784b17e7fb7b2364bc6940",
+        "b62e31ae31525f7491b7e5903f81f2f7",
+        "2a2759a9b36ab9b4db739e7ec4c4b9aa",
+        "9100fc894c7af8c0b7d82be4e1bb8d17", // lzma, RLE large
+        "6956a951f38176fd8a6908b4f38c9b6f",
+        "b9e421101450a5e3ef1e772bfca6873b",
+        "2a792417d270a7fa9d395ed20486d7af",
+        "bbd95cbd11ad249cc3e93eb94dbf621e",
+        "5e74e8c8b8f75bb72d4e78a9bc507d3f",
+        "8e76cee45c13dd1108186ee1cb1a5d79",
+        "7d6e9655d995a3493201bf1de198ba50",
+        "ccdbc380930401ee6dccd1ce7dd38610",
+        "65df997c97657cf381cb05c075490654",
+        "485885c2b2ad932355c1d7da4f5ef619",
+        "fe50f09a488aeaa604c346394dd1fce7",
+        "25b7c87b8e8456b491431b79aa4f3af4",
+        "47da107eded9fc8befc158c8d7137846",
+        "b8fb22b063acceb55475ba56b8a50f69",
+        "c728620474df27b7dd209db7e4caaafa",
+        "27529afe90261ef544dc2614514d05a8",
+        "18d84c6e6abb8589035bd45a67547638",
+        "350c591b2e56ce4698096bb57aa7a0ba",
+        "dcccc8e0b577a73964e6653c7bb082cf",
+        "d04839517a28b90ac15b0295c027481d",
+        "85a38f927893b477f34d7d3b898aaed0",
+        "43098c03b07f742a402df3328f783688",
+        "a5db5611e76ab90d5c3e3cd68327ac25",
+    ];
+
+    #[test]
+    fn compressed_map() {
+        let chd = ChdFile::open(Path::new(CODECS_CHD)).unwrap();
+        assert_eq!(chd.map.len(),HUNK_MD5.len());
+        let codecs : Vec<_> = chd.map.iter().filter_map(|e| match e { HunkEntry::Compressed { codec, .. } => Some(*codec), _ => None }).collect();
+        assert_eq!(codecs,[[0,1,2,3].as_slice(),&[0;4],&[1;24]].concat());
+        // compressed and uncompressed hunks are stored one after the other, from the end of the header
+        let mut offset = CHD_V5_HEADER_SIZE as u64;
+        for entry in chd.map.iter() {
+            match *entry {
+                HunkEntry::Compressed { offset: o, length, .. } => { assert_eq!(o,offset); offset += length as u64; }
+                HunkEntry::Uncompressed(o) => { assert_eq!(o,offset); offset += chd.hunk_bytes as u64; }
+                _ => {}
+            }
+        }
+        assert!(matches!(chd.map[4],HunkEntry::Uncompressed(_)));
+        assert!(matches!(chd.map[5..8],[HunkEntry::SelfRef(0),HunkEntry::SelfRef(0),HunkEntry::SelfRef(1)]));
+    }
+
+    #[test]
+    fn hunks() {
+        let mut chd = ChdFile::open(Path::new(CODECS_CHD)).unwrap();
+        for (hunk,expected) in HUNK_MD5.iter().enumerate() {
+            chd.load_hunk(hunk as u32).unwrap();
+            assert_eq!(format!("{:x}",md5::compute(&chd.hunk)),*expected,"hunk {}",hunk);
+        }
+    }
+
+    #[test]
+    fn track_metadata() {
+        let mut chd = ChdFile::open(Path::new(CODECS_CHD)).unwrap();
+        let tracks = chd.read_tracks().unwrap();
+        assert_eq!(tracks.len(),1);
+        assert_eq!((tracks[0].number,&tracks[0].track_type,tracks[0].frames),(1,&ChdTrackType::Mode1Raw,HUNK_MD5.len() as u32));
+    }
+}
@@ -1,7 +1,8 @@
-use crate::core::cdrom::disc::{AudioLeftRight, DiscTime, TrackType, BCD};
-use crate::core::cdrom::{CDRom, Command, CommandState, DriveState, PendingIrq, Region, CDROM_VER};
+use crate::core::cdrom::disc::{AudioLeftRight, DiscTime, Track, TrackType, BCD};
+use crate::core::cdrom::timing::{delay_cycles_44100, GET_ID_SECOND_RESPONSE_CYCLES};
+use crate::core::cdrom::{CDRom, Command, CommandState, DriveState, PendingIrq, Region, CDROM_DECODER_ID, CDROM_SERVO_AMPLIFIER_ID, CDROM_SIGNAL_PROCESSOR_ID, CDROM_VER};
 use crate::core::interrupt::IrqHandler;
-use std::process::exit;
+use crate::core::unimplemented;
 use tracing::{debug, error, info, warn};
 
 pub(super) const INT1 : u8 = 0x01;
@@ -13,16 +14,12 @@ pub(super) const INT5 : u8 = 0x05;
 pub(super) const STAT_NO_ERR : Option<(bool,bool,bool)> = Some((false,false,false));
 pub(super) const STAT_NO_DATA : &[u8] = &[];
 
+// the first response delay is set by process_command (see CDRom::first_response_cycles): this is the delay of the responses sent at once
 pub(super) const FIRST_RESPONSE_IRQ_DELAY_44100 : usize = 1;
 pub(super) const STD_SECOND_RESPONSE_IRQ_DELAY_44100 : usize = delay_cycles_44100(0x4A73);
 pub(super) const INIT_SECOND_RESPONSE_IRQ_DELAY_44100 : usize = 2000;
-pub(super) const GET_ID_SECOND_RESPONSE_IRQ_DELAY_44100: usize = 2000;
 pub(super) const READ_TOC_SECOND_RESPONSE_IRQ_DELAY_44100: usize = 2000;
 
-const fn delay_cycles_44100(cycles:usize) -> usize {
-    cycles / (33_868_800 / 44_100)
-}
-
 impl CDRom {
     pub(super) fn change_drive_state(&mut self, new_state: DriveState) {
         //info!("CDROM drive state changed to {:?}",new_state);
@@ -113,17 +110,23 @@ impl CDRom {
         debug!("CDROM processing command {:02X}",cmd);
         match Command::from_u8(cmd) {
             Some(command) => {
-                if !command.parameters_number().contains(&self.parameter_fifo.len()) {
+                let first_response_cycles = self.first_response_cycles(command);
+                let state = if !command.parameters_number().contains(&self.parameter_fifo.len()) {
                     error!("CDROM invalid parameters for command {:?}, found {} expected {:?}",command,self.parameter_fifo.len(),command.parameters_number());
                     self.make_bad_parameter_response(command)
+                } else if self.is_shell_opened() && command.needs_disc() {
+                    warn!("CDROM command {:?} rejected: shell is open",command);
+                    self.make_error_response(command, 0x80, (false, false, true)) // cannot response yet
                 } else {
                     self.execute_command(command, false)
-                }
+                };
+                Self::with_first_response_delay(state,first_response_cycles)
             }
             None => {
-                error!("CDROM unknown command {:02X}",cmd);
-                // TODO
-                exit(1);
+                // i.e. the SCPH-5903 (Video CD) and debug only commands, or the secret unlock ones
+                unimplemented::report("CDROM",&format!("command {cmd:02X}"));
+                self.parameter_fifo.clear();
+                self.make_error_response(Command::Unknown, 0x40, (false, false, true)) // InvalidCommand
             }
         }
     }
@@ -165,7 +168,7 @@ impl CDRom {
             Command::GetID => self.command_get_id(second_response),
             Command::ReadTOC => self.command_read_toc(second_response),
             Command::SetMode => self.command_setmode(),
-            Command::Read => self.command_read(second_response),
+            Command::Read|Command::ReadS => self.command_read(cmd,second_response),
             Command::Pause => self.command_pause(second_response),
             Command::Init => self.command_init(second_response),
             Command::Demute => self.command_demute(),
@@ -178,6 +181,10 @@ impl CDRom {
             Command::Play => self.command_play(),
             Command::MotorOn => self.command_motor_on(second_response),
             Command::Mute => self.command_mute(),
+            Command::GetQ => self.command_get_q(),
+            Command::SetSession => self.command_set_session(second_response),
+            Command::VideoCD => self.command_video_cd(),
+            Command::Unknown => self.make_error_response(cmd, 0x40, (false, false, true)), // InvalidCommand
         }
     }
 
@@ -237,19 +244,6 @@ impl CDRom {
         )
     }
 
-    fn get_approx_seek_cycles_44100(from: &DiscTime, target: &DiscTime) -> usize {
-        const MIN_SEEK_TIME_44100: usize = 50;
-        let distance = (from.to_lba() as i32 - target.to_lba() as i32).abs() as u64;
-        let seek_time_ms = 1000.0 * distance as f32 / (75.0 * 60.0 * 80.0); // 1000ms per minute, 75 frames per second, 80 sectors per frame
-        Self::get_cycles_per_ms_44100(seek_time_ms).max(MIN_SEEK_TIME_44100)
-
-    }
-
-    #[inline(always)]
-    fn get_cycles_per_ms_44100(ms: f32) -> usize {
-        ((44100.0 / 1000.0 * ms) as usize).max(1)
-    }
-
     // =============================================================================================
     // Nop - Command 01h --> INT3(stat)
     fn command_nop(&mut self) -> CommandState {
@@ -278,25 +272,24 @@ impl CDRom {
         self.activate_motor(true);
         let first_sector_cycles = Self::get_cycles_per_ms_44100(self.get_speed().get_read_sector_ms());
 
-        if let Some(disc) = self.disc.as_mut() {
-            let mut seeking_cycles = 0usize;
+        if let Some(disc) = self.disc.as_ref() {
             let mut track = BCD::decode(self.parameter_fifo.pop_front().unwrap_or(0));
-            if track == 0 {
-                match self.pending_setloc.take() {
-                    Some(loc) => {
-                        seeking_cycles = Self::get_approx_seek_cycles_44100(&disc.get_head_position(), &loc);
-                        debug!("CDROM seeking before playing {}",loc);
-                        disc.seek_sector(loc);
-                    }
-                    _ => {}
-                }
+            let target = if track == 0 {
+                self.pending_setloc.take()
             }
             else {
                 let t = disc.get_track_by_number(track).unwrap();
                 track = t.track_number();
-                let start_time = t.effective_start_time();
-                disc.seek_sector(start_time);
-
+                Some(t.effective_start_time())
+            };
+            let seeking_cycles = match target {
+                Some(loc) => self.seek_cycles_44100(&disc.get_head_position(), &loc),
+                None => self.spindle_cycles,
+            };
+            let disc = self.disc.as_mut().unwrap();
+            if let Some(loc) = target {
+                debug!("CDROM seeking before playing {}",loc);
+                disc.seek_sector(loc);
             }
             if !matches!(self.drive_state,DriveState::Playing { .. }) { // fixes motorhead
                 self.drive_state = DriveState::Playing { first_sector_cycles, sample_index: 0, report_counter: 1, report_absolute: true, seeking_cycles };
@@ -318,6 +311,7 @@ impl CDRom {
                     disc.seek_sector(loc);
                 }
             }
+            self.update_subq();
             self.change_drive_state(DriveState::Idle);
             self.make_stat_response(INT2, Command::SeekL)
         } else {
@@ -325,7 +319,7 @@ impl CDRom {
             self.activate_motor(true);
             let seek_cycles = match (self.disc.as_ref(), self.pending_setloc.as_ref()) {
                 (Some(disc), Some(loc)) => {
-                    let cycles = Self::get_approx_seek_cycles_44100(&disc.get_head_position(), &loc);
+                    let cycles = self.seek_cycles_44100(&disc.get_head_position(), loc);
                     debug!("CDROM seekl from {} to {} approx cycles={}",disc.get_head_position(),loc,cycles);
                     cycles
                 },
@@ -348,42 +342,67 @@ impl CDRom {
             )
         }
     }
-    // 19h,20h --> INT3(yy,mm,dd,ver)
+    /*
+    Test - Command 19h,subfunction --> INT3(...)
+      00h..02h  force motor on (clockwise, anti-clockwise, anti-clockwise super-fast) --> INT3(stat)
+      03h       force motor off                            --> INT3(stat)
+      04h       start SCEx reading and reset the counters  --> INT3(stat)
+      05h       stop SCEx reading and get the counters     --> INT3(total,success)
+      10h..1Ah  servo/focus/tracking/sled adjustments      --> INT3(stat) (no effect on a disc image)
+      20h       controller BIOS date and version           --> INT3(yy,mm,dd,ver)
+      21h       drive switches (bit0=POS0 head at inner position, bit1=DOOR open) --> INT3(flags)
+      22h       region ID string                           --> INT3("for U/C")
+      23h       servo amplifier chip ID                    --> INT3("CXA1782BR")
+      24h       signal processor chip ID                   --> INT3("CXD2545Q")
+      25h       decoder chip ID                            --> INT3("CXD1815Q")
+    Other sub-functions fail with INT5(stat|1,10h)
+     */
     fn command_test(&mut self) -> CommandState {
         let sub_function = self.parameter_fifo.pop_front().unwrap();
-        match sub_function {
+        debug!("CDROM test sub function {:02X}",sub_function);
+        let data : Vec<u8> = match sub_function {
+            0x00..=0x02 => {
+                self.activate_motor(true);
+                return self.make_stat_response(INT3,Command::Test)
+            }
+            0x03 => {
+                self.activate_motor(false);
+                return self.make_stat_response(INT3,Command::Test)
+            }
             0x04 => {
-                debug!("CDROM test sub function 0x04");
                 self.activate_motor(true);
-                self.make_stat_response(INT3,Command::Test)
+                self.scex_counters = Some((0,0));
+                return self.make_stat_response(INT3,Command::Test)
             }
             0x05 => {
-                debug!("CDROM test sub function 0x05");
-                self.make_response(
-                    Command::Test,
-                    INT3,
-                    FIRST_RESPONSE_IRQ_DELAY_44100,
-                    &[0x00,0x00],
-                    STAT_NO_ERR,
-                    CommandState::Idle
-                )
+                let (total,success) = self.scex_counters.take().unwrap_or((0,0));
+                debug!("CDROM SCEx counters total={total} success={success}");
+                vec![total,success]
             }
-            0x20 => {
-                debug!("CDROM test sub function 0x20: sending {:?}",CDROM_VER);
-                self.make_response(
-                    Command::Test,
-                    INT3,
-                    FIRST_RESPONSE_IRQ_DELAY_44100,
-                    CDROM_VER.as_slice(), // Invalid sub-function
-                    None,
-                    CommandState::Idle
-                )
+            0x10..=0x1A => return self.make_stat_response(INT3,Command::Test),
+            0x20 => CDROM_VER.to_vec(),
+            0x21 => {
+                let head_at_pos0 = self.disc.as_ref().is_none_or(|disc| disc.get_head_position().to_lba() <= DiscTime::new(0,2,0).to_lba());
+                vec![(head_at_pos0 as u8) | ((self.is_shell_opened() as u8) << 1)]
             }
+            0x22 => self.console_region.to_controller_region_id().to_vec(),
+            0x23 => CDROM_SERVO_AMPLIFIER_ID.to_vec(),
+            0x24 => CDROM_SIGNAL_PROCESSOR_ID.to_vec(),
+            0x25 => CDROM_DECODER_ID.to_vec(),
             _ => {
-                warn!("Unsupported test command sub function {}",sub_function);
-                self.make_error_response(Command::Test, 0x10, (false, false, true)) // Invalid sub-function
+                warn!("Unsupported test command sub function {:02X}",sub_function);
+                return self.make_error_response(Command::Test, 0x10, (false, false, true)) // Invalid sub-function
             }
-        }
+        };
+        debug!("CDROM test sub function {:02X}: sending {:02X?}",sub_function,data);
+        self.make_response(
+            Command::Test,
+            INT3,
+            FIRST_RESPONSE_IRQ_DELAY_44100,
+            &data,
+            None,
+            CommandState::Idle
+        )
     }
     /*
         GetID - Command 1Ah --> INT3(stat) --> INT2/5 (stat,flags,type,atip,"SCEx")
@@ -443,7 +462,7 @@ impl CDRom {
                 STAT_NO_ERR,
                 CommandState::Delay {
                     cmd: Command::GetID,
-                    delay_cycles: GET_ID_SECOND_RESPONSE_IRQ_DELAY_44100,
+                    delay_cycles: GET_ID_SECOND_RESPONSE_CYCLES,
                     next_state: Box::new(
                         CommandState::Response2 { cmd: Command::GetID }
                     )
@@ -496,38 +515,48 @@ impl CDRom {
             // preserve last sector size bit
             self.mode = (self.mode & !(1 << 5)) | (prev_mode & (1 << 5));
         }
+        // the spindle changes speed only if the speed bit changed and the motor is spinning: setting the same speed again doesn't wait
+        if ((self.mode ^ prev_mode) & 0x80) != 0 && self.motor_on {
+            self.start_spindle(false);
+        }
         debug!("CDROM set mode to {:02X}, speed={:?} sector size={:?} ignore_bit={ignore_bit} cd-da:{} report={}",self.mode,self.get_speed(),self.get_sector_size(),(self.mode & 1) != 0,(self.mode & 4) != 0);
         self.make_stat_response(INT3, Command::SetMode)
     }
-    // ReadN/S - Command 06h --> INT3(stat) --> INT1(stat) --> datablock
-    fn command_read(&mut self,second_response:bool) -> CommandState {
+    /*
+    ReadN - Command 06h --> INT3(stat) --> INT1(stat) --> datablock
+    ReadS - Command 1Bh --> INT3(stat) --> INT1(stat) --> datablock
+    ReadN retries the sectors that cannot be read, ReadS doesn't (it's meant for streaming XA-ADPCM and video)
+     */
+    fn command_read(&mut self,cmd:Command,second_response:bool) -> CommandState {
         if second_response {
             let mut seeking_cycles = 0usize;
-            match (self.disc.as_mut(),self.pending_setloc.take()) {
-                (Some(disc),Some(loc)) => {
-                    seeking_cycles = Self::get_approx_seek_cycles_44100(&disc.get_head_position(), &loc);
-                    debug!("CDROM seeking before reading {} -> {}",disc.get_head_position(),loc);
-                    disc.seek_sector(loc);
-                }
-                _ => {}
+            let target = self.pending_setloc.take();
+            if let (Some(disc),Some(loc)) = (self.disc.as_ref(),target) {
+                seeking_cycles = self.seek_cycles_44100(&disc.get_head_position(), &loc);
+            }
+            if let (Some(disc),Some(loc)) = (self.disc.as_mut(),target) {
+                debug!("CDROM seeking before reading {} -> {}",disc.get_head_position(),loc);
+                disc.seek_sector(loc);
             }
-            let next_sector_cycles = Self::get_cycles_per_ms_44100(self.get_speed().get_read_sector_ms());
+            let next_sector_cycles = self.read_sector_cycles_44100();
             debug!("CDROM reading next sector in {next_sector_cycles} cycles seeking cycles {seeking_cycles} [{} ms]",self.get_speed().get_read_sector_ms());
             self.change_drive_state(DriveState::Reading { next_sector_cycles, seeking_cycles: 0 }); // seeking cycles prevent running gran turismo 2
             CommandState::Idle
         }
         else if self.is_disk_inserted() {
             self.activate_motor(true);
+            self.read_retry = matches!(cmd,Command::Read);
+            self.read_retries = 0;
             self.make_response(
-                Command::Read,
+                cmd,
                 INT3,
                 FIRST_RESPONSE_IRQ_DELAY_44100,
                 STAT_NO_DATA,
                 STAT_NO_ERR,
-                CommandState::Response2 { cmd : Command::Read }
+                CommandState::Response2 { cmd }
             )
         } else {
-            self.make_error_response(Command::Read, 0x80, (false, false, true)) // cannot response yet
+            self.make_error_response(cmd, 0x80, (false, false, true)) // cannot response yet
         }
     }
 
@@ -540,8 +569,13 @@ impl CDRom {
         }
 
         let send_int1 = self.read_data_sector(irq_handler);
-        let next_sector_cycles = Self::get_cycles_per_ms_44100(self.get_speed().get_read_sector_ms());
-        if send_int1 {
+        let next_sector_cycles = self.read_sector_cycles_44100();
+        if send_int1 && self.is_int1_pending() {
+            // sector buffer overrun: the game didn't acknowledge the INT1 of the previous sector in time. The previous sector
+            // has been replaced in the buffer by the new one, the pending INT1 now refers to it
+            debug!("CDROM sector buffer overrun: sector not read in time");
+        }
+        else if send_int1 {
             debug!("CDROM reading sending INT1");
             self.apply_irq_and_result(Command::Read,INT1, vec![self.get_stat(false, false, false)], irq_handler);
         }
@@ -555,6 +589,7 @@ impl CDRom {
             debug!("CDROM executing pause from drive state: {:?}",self.drive_state);
             self.make_stat_response(INT2, Command::Pause)
         } else {
+            let paused = matches!(self.drive_state,DriveState::Idle);
             let response = self.make_response(
                 Command::Pause,
                 INT3,
@@ -563,7 +598,7 @@ impl CDRom {
                 STAT_NO_ERR,
                 CommandState::Delay {
                     cmd: Command::Pause,
-                    delay_cycles: 5 * Self::get_cycles_per_ms_44100(self.get_speed().get_read_sector_ms()), // time equals to about 5 sectors
+                    delay_cycles: self.pause_second_response_cycles(paused),
                     next_state: Box::new(
                         CommandState::Response2 { cmd: Command::Pause }
                     )
@@ -638,7 +673,7 @@ impl CDRom {
                 STAT_NO_ERR,
                 CommandState::Delay {
                     cmd: Command::Stop,
-                    delay_cycles: STD_SECOND_RESPONSE_IRQ_DELAY_44100,
+                    delay_cycles: self.stop_second_response_cycles(),
                     next_state: Box::new(
                         CommandState::Response2 { cmd: Command::Stop }
                     )
@@ -663,27 +698,16 @@ impl CDRom {
             CommandState::Idle
         )
     }
-    // GetlocP - Command 11h - INT3(track,index,mm,ss,sect,amm,ass,asect)
+    /*
+    GetlocP - Command 11h - INT3(track,index,mm,ss,sect,amm,ass,asect)
+    Returns the last Q subchannel read with a valid CRC: LibCrypt sectors are skipped.
+     */
     fn command_get_locp(&mut self) -> CommandState {
+        let q = self.last_subq;
         let mut locp = [0u8; 8];
-        if let Some(disc) = self.disc.as_ref() {
-            if let Some(track) = disc.get_current_track() {
-                locp[0] = track.track_number();
-                let absolute_time = disc.get_head_position();
-                locp[1] = (absolute_time >= track.effective_start_time()).into();
-                let track_relative_time = disc.get_head_position().sub(&track.start_time());
-                locp[2] = absolute_time.m();
-                locp[3] = absolute_time.s();
-                locp[4] = absolute_time.f();
-                locp[5] = track_relative_time.m();
-                locp[6] = track_relative_time.s();
-                locp[7] = track_relative_time.f();
-                debug!("CDROM getlocp absolute={} relative={} index={}",absolute_time,track_relative_time,locp[1]);
-            }
-            for e in locp.iter_mut() {
-                *e = BCD::encode(*e);
-            }
-        }
+        locp[0..5].copy_from_slice(&q[1..6]);
+        locp[5..8].copy_from_slice(&q[7..10]);
+        debug!("CDROM getlocp {:02X?}",locp);
         self.make_response(
             Command::GetLocP,
             INT3,
@@ -737,7 +761,65 @@ impl CDRom {
         }
         self.make_error_response(Command::GetTD, 0x10, (false, false, true)) // Invalid sub-function
     }
-    // MotorOn - Command 07h --> INT3(stat) --> INT2(stat)
+    /*
+    GetQ - Command 1Dh,adr,point --> INT3(stat) --> INT2(10bytesSubQ,peak_lo)
+    Reads the Q subchannel of the given TOC point from the lead-in area:
+      A0h  pmin=first track, psec=disc type (00h=CD-DA/CD-ROM, 20h=CD-ROM XA)
+      A1h  pmin=last track
+      A2h  pmin:psec:pframe=lead-out start
+      01h-99h track start
+    Only adr 1 (position) is supported.
+     */
+    fn command_get_q(&mut self) -> CommandState {
+        let adr = self.parameter_fifo.pop_front().unwrap();
+        let point = self.parameter_fifo.pop_front().unwrap();
+        let toc_q = match self.disc.as_ref() {
+            Some(disc) if adr & 0x0F == 1 => {
+                let tracks = disc.get_tracks();
+                let (first,last) = (&tracks[0],&tracks[tracks.len() - 1]);
+                let control = |track:&Track| if matches!(track.track_type(),TrackType::Audio) { 0x01 } else { 0x41 };
+                let bcd_time = |time:&DiscTime| [BCD::encode(time.m()),BCD::encode(time.s()),BCD::encode(time.f())];
+                let entry = match point {
+                    0xA0 => {
+                        let disc_type = if matches!(first.track_type(),TrackType::Data(2,_)) { 0x20 } else { 0x00 };
+                        Some((control(first),[BCD::encode(first.track_number()),disc_type,0x00]))
+                    }
+                    0xA1 => Some((control(last),[BCD::encode(last.track_number()),0x00,0x00])),
+                    0xA2 => Some((control(last),bcd_time(last.end_time()))),
+                    0x00 => None,
+                    _ => disc.get_track_by_number(BCD::decode(point)).map(|track| (control(track),bcd_time(&track.effective_start_time())))
+                };
+                entry.map(|(control,[pmin,psec,pframe])| [control,0x00,point,0x00,0x00,0x00,0x00,pmin,psec,pframe])
+            }
+            _ => None
+        };
+        match toc_q {
+            Some(q) => {
+                debug!("CDROM getq adr={adr:02X} point={point:02X}: {:02X?}",q);
+                let mut response = q.to_vec();
+                response.push(0x00); // peak_lo
+                self.make_response(
+                    Command::GetQ,
+                    INT3,
+                    FIRST_RESPONSE_IRQ_DELAY_44100,
+                    STAT_NO_DATA,
+                    STAT_NO_ERR,
+                    CommandState::Delay {
+                        cmd: Command::GetQ,
+                        delay_cycles: STD_SECOND_RESPONSE_IRQ_DELAY_44100,
+                        next_state: Box::new(
+                            CommandState::Response { cmd: Command::GetQ, irq: INT2, delay_cycles: FIRST_RESPONSE_IRQ_DELAY_44100, response, next_state: Box::new(CommandState::Idle) }
+                        )
+                    }
+                )
+            }
+            None => {
+                warn!("CDROM getq adr={adr:02X} point={point:02X} not found");
+                self.make_error_response(Command::GetQ, 0x10, (false, false, true)) // Invalid sub-function
+            }
+        }
+    }
+    // MotorOn (Standby) - Command 07h --> INT3(stat) --> INT2(stat)
     // Activates the drive motor, works ONLY if the motor was off (otherwise fails with INT5(stat,20h);
     // that error code would normally indicate "wrong number of parameters", but means "motor already on" in this case).
     fn command_motor_on(&mut self,second_response:bool) -> CommandState {
@@ -750,7 +832,7 @@ impl CDRom {
                 self.make_bad_parameter_response(Command::MotorOn)
             }
             else {
-                self.motor_on = true;
+                self.activate_motor(true);
                 self.make_response(
                     Command::MotorOn,
                     INT3,
@@ -759,7 +841,7 @@ impl CDRom {
                     STAT_NO_ERR,
                     CommandState::Delay {
                         cmd: Command::MotorOn,
-                        delay_cycles: STD_SECOND_RESPONSE_IRQ_DELAY_44100,
+                        delay_cycles: self.spin_up_second_response_cycles(),
                         next_state: Box::new(
                             CommandState::Response2 { cmd: Command::MotorOn }
                         )
@@ -772,7 +854,63 @@ impl CDRom {
     fn command_mute(&mut self) -> CommandState {
         debug!("CDROM mute on");
         self.audio_mute = true;
-        self.make_stat_response(INT3, Command::MotorOn)
+        self.make_stat_response(INT3, Command::Mute)
+    }
+    /*
+    SetSession - Command 12h,session --> INT3(stat) --> INT2(stat)
+    Seeks to the beginning of the given session. Disc images have a single session:
+      session 00h       --> INT5(stat|1,10h)
+      session 01h       --> INT3(stat) --> INT2(stat)
+      other sessions    --> INT3(stat) --> INT5(stat|4,40h)
+     */
+    fn command_set_session(&mut self,second_response:bool) -> CommandState {
+        if second_response {
+            if let Some(disc) = self.disc.as_mut() {
+                let start_time = disc.get_tracks()[0].effective_start_time();
+                disc.seek_sector(start_time);
+                debug!("CDROM set session 1 completed. Seeked to track1 start time {:?}",start_time);
+            }
+            self.update_subq();
+            self.change_drive_state(DriveState::Idle);
+            self.make_stat_response(INT2, Command::SetSession)
+        }
+        else {
+            let session = self.parameter_fifo.pop_front().unwrap();
+            if session == 0 {
+                return self.make_error_response(Command::SetSession, 0x10, (false, false, true)) // Invalid sub-function
+            }
+            self.activate_motor(true);
+            let seek_cycles = match self.disc.as_ref() {
+                Some(disc) => self.seek_cycles_44100(&disc.get_head_position(), &disc.get_tracks()[0].effective_start_time()),
+                None => FIRST_RESPONSE_IRQ_DELAY_44100
+            };
+            let next_state = if session == 1 {
+                self.change_drive_state(DriveState::Seeking);
+                CommandState::Response2 { cmd: Command::SetSession }
+            }
+            else {
+                warn!("CDROM set session {session}: session not found");
+                self.make_response(Command::SetSession, INT5, FIRST_RESPONSE_IRQ_DELAY_44100, &[0x40], Some((false, true, false)), CommandState::Idle)
+            };
+            self.make_response(
+                Command::SetSession,
+                INT3,
+                FIRST_RESPONSE_IRQ_DELAY_44100,
+                STAT_NO_DATA,
+                STAT_NO_ERR,
+                CommandState::Delay {
+                    cmd: Command::SetSession,
+                    delay_cycles: seek_cycles,
+                    next_state: Box::new(next_state)
+                }
+            )
+        }
+    }
+    // VideoCD - Command 1Fh,... --> INT5(stat|1,40h): supported by the SCPH-5903 only
+    fn command_video_cd(&mut self) -> CommandState {
+        debug!("CDROM VideoCD command not supported by this controller");
+        self.parameter_fifo.clear();
+        self.make_error_response(Command::VideoCD, 0x40, (false, false, true)) // InvalidCommand
     }
 
     // =========================================
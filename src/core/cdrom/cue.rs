@@ -32,7 +32,9 @@ pub struct Track {
 
 #[derive(Debug, Clone)]
 pub enum TrackType {
+    Mode1_2048,
     Mode1_2352,
+    Mode2_2336,
     Mode2_2352,
     Audio,
     Unknown(String),
@@ -51,6 +53,23 @@ pub struct Msf {
     pub frame: u8,
 }
 
+impl CueSheet {
+    /*
+    Cue sheet of an image without one (iso, lone bin): a single track starting at the beginning of the file
+     */
+    pub fn single_track(path:PathBuf,track_type:TrackType) -> Self {
+        let track = Track {
+            number: 1,
+            track_type,
+            pregap: None,
+            indices: vec![Index { number: 1, time: Msf { minute: 0, second: 0, frame: 0 } }],
+        };
+        Self {
+            files: vec![CueFile { path, file_type: CueFileType::Binary, tracks: vec![track] }],
+        }
+    }
+}
+
 pub fn parse_cue<P: AsRef<Path>>(path: P) -> std::io::Result<CueSheet> {
     let file = File::open(&path)?;
     let reader = BufReader::new(file);
@@ -189,7 +208,9 @@ fn parse_file_type(s: &str) -> CueFileType {
 
 fn parse_track_type(s: &str) -> TrackType {
     match s {
+        "MODE1/2048" => TrackType::Mode1_2048,
         "MODE1/2352" => TrackType::Mode1_2352,
+        "MODE2/2336" => TrackType::Mode2_2336,
         "MODE2/2352" => TrackType::Mode2_2352,
         "AUDIO" => TrackType::Audio,
         _ => TrackType::Unknown(s.to_string()),
@@ -1,10 +1,20 @@
+use crate::core::cdrom::chd::{ChdFile, ChdTrackFile, ChdTrackType};
+use crate::core::cdrom::ecm::EcmFile;
+use crate::core::cdrom::pbp::{PbpFile, PbpTrackFile};
+use crate::core::cdrom::pbp;
+use crate::core::cdrom::sector::{generate_mode1, generate_mode2_form1, write_header, MODE2_FORM1_SUBHEADER};
+use crate::core::cdrom::subq::{self, SubQ, SUBQ_SIZE};
 use crate::core::cdrom::{cue, util, Region};
+use crate::core::savestate::{impl_state_struct, SaveState, StateSync};
 use crate::core::Resettable;
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use tempfile::TempDir;
 use tracing::{debug, error, info, warn};
 use zip::ZipArchive;
@@ -13,7 +23,63 @@ pub(super) const SECTOR_SIZE : u16 = 2352;
 
 const FILE_BUFFER_SIZE : usize = 1024 * 1024;
 
-#[derive(Copy,Clone,Debug)]
+/*
+Source of the raw 2352 bytes sectors of one or more tracks: a bin file or a track of a compressed image
+ */
+pub(super) trait TrackFile : fmt::Debug {
+    fn read_at(&mut self,offset:u64,buffer:&mut [u8]) -> std::io::Result<()>;
+}
+
+impl TrackFile for BufReader<File> {
+    fn read_at(&mut self,offset:u64,buffer:&mut [u8]) -> std::io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(buffer)
+    }
+}
+
+/*
+Bin files with cooked sectors: 2048 bytes of user data (iso, MODE1/2048) or 2336 bytes without sync and header (MODE2/2336).
+The missing parts are rebuilt on the fly, the header time is the absolute time of the sector.
+2048 bytes sectors are rebuilt as mode 1 sectors (header, EDC and ECC), 2336 bytes sectors only get sync and a mode 2 header.
+ */
+#[derive(Debug)]
+struct CookedTrackFile {
+    file: Box<dyn TrackFile>,
+    sector_size: u16,
+    // absolute time of the first sector of the file
+    start: DiscTime,
+}
+
+impl TrackFile for CookedTrackFile {
+    fn read_at(&mut self,offset:u64,buffer:&mut [u8]) -> std::io::Result<()> {
+        let mut sector = [0u8;SECTOR_SIZE as usize];
+        let mut offset = offset;
+        let mut done = 0;
+        while done < buffer.len() {
+            let index = offset / SECTOR_SIZE as u64;
+            let start = (offset % SECTOR_SIZE as u64) as usize;
+            sector.fill(0);
+            let msf = self.start.add(&DiscTime::from_lba(index as u32));
+            let file_offset = index * self.sector_size as u64;
+            if self.sector_size == 2048 {
+                write_header(&mut sector,msf,1);
+                self.file.read_at(file_offset,&mut sector[16..16 + 2048])?;
+                generate_mode1(&mut sector);
+            }
+            else {
+                write_header(&mut sector,msf,2);
+                self.file.read_at(file_offset,&mut sector[16..])?;
+            }
+            let len = (SECTOR_SIZE as usize - start).min(buffer.len() - done);
+            buffer[done..done + len].copy_from_slice(&sector[start..start + len]);
+            done += len;
+            offset += len as u64;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy,Clone,Debug,Default)]
 pub struct DiscTime {
     minutes:u8, // 00 - 99
     seconds:u8, // 00 - 59
@@ -61,10 +127,6 @@ impl DiscTime {
         Self { minutes, seconds, frames }
     }
 
-    pub fn from_file_length(file_length:u32) -> Self {
-        Self::from_lba(file_length / SECTOR_SIZE as u32)
-    }
-
     pub fn add(&self,other:&Self) -> Self {
         Self::from_lba(self.to_lba() + other.to_lba())
     }
@@ -74,6 +136,8 @@ impl DiscTime {
     }
 }
 
+impl_state_struct!(DiscTime { minutes, seconds, frames });
+
 impl fmt::Display for DiscTime {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{:02}:{:02}:{:02}", self.minutes, self.seconds,self.frames)
@@ -119,9 +183,16 @@ impl BCD {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug,Default)]
 pub struct AudioLeftRight(pub i16,pub i16);
 
+impl SaveState for AudioLeftRight {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.0);
+        s.sync(&mut self.1);
+    }
+}
+
 #[derive(Debug)]
 pub enum TrackType {
     Audio,
@@ -155,6 +226,10 @@ impl DataSector {
       7   End of File (EOF) (or end of Directory/PathTable/VolumeTerminator)
      */
     pub fn is_audio_adpcm(&self) -> bool {
+        // mode 1 sectors have no subheader: user data starts at 16
+        if self.get_data_mode() != 2 {
+            return false
+        }
         let sub_mode = self.sector[18];
         (sub_mode & ((1 << 2) | (1 << 6))) == (1 << 2) | (1 << 6)
     }
@@ -189,8 +264,9 @@ impl DataSector {
     22      1     Submode (copy)
     23      1     Coding Info (copy)
      */
-    pub fn get_mode2_user_data(&self, data_size:&TrackSectorDataSize) -> &[u8] {
+    pub fn get_user_data(&self, data_size:&TrackSectorDataSize) -> &[u8] {
         match data_size {
+            TrackSectorDataSize::DataOnly if self.get_data_mode() == 1 => &self.sector[16..16 + 2048],
             TrackSectorDataSize::DataOnly => &self.sector[24..24 + 2048],
             TrackSectorDataSize::WholeSectorExceptSyncBytes => &self.sector[12..]
         }
@@ -257,7 +333,7 @@ impl Track {
         Self { file_id, number, track_type, start_time, end_time, pre_gap, post_gap, pause_gap }
     }
 
-    fn read_sector_into(&mut self,file:&mut BufReader<File>, msf:DiscTime, buffer: &mut [u8]) -> std::io::Result<bool> {
+    fn read_sector_into(&mut self,file:&mut dyn TrackFile, msf:DiscTime, buffer: &mut [u8]) -> std::io::Result<bool> {
         let relative_msf = msf.sub(&self.start_time);
         if relative_msf < self.pre_gap || relative_msf > self.end_time.sub(&self.start_time).sub(&self.post_gap) {
             self.fill_fake_sector(msf, buffer);
@@ -265,10 +341,7 @@ impl Track {
         }
 
         let offset : u64 = relative_msf.sub(&self.pre_gap).to_lba() as u64 * SECTOR_SIZE as u64;
-        if let Err(_) = file.seek(SeekFrom::Start(offset)) {
-            return Ok(false);
-        }
-        file.read_exact(buffer)?;
+        file.read_at(offset,buffer)?;
 
         Ok(true)
     }
@@ -279,8 +352,17 @@ impl Track {
             TrackType::Audio => {
                 buffer.fill(0);
             }
-            TrackType::Data(_,_) => {
-                todo!("fill fake data sector");
+            // pregap/postgap data sectors: zeroed user data with a valid header (mode 2 as form 1), as on the pressed discs
+            TrackType::Data(mode,_) => {
+                buffer.fill(0);
+                write_header(buffer,msf,mode);
+                if mode == 1 {
+                    generate_mode1(buffer);
+                }
+                else {
+                    buffer[16..24].copy_from_slice(&MODE2_FORM1_SUBHEADER);
+                    generate_mode2_form1(buffer);
+                }
             }
         }
     }
@@ -298,12 +380,16 @@ pub(super) enum SectorReadResult {
 pub struct Disc {
     cue_file_name:String,
     tracks:Vec<Track>,
-    files:Vec<(BufReader<File>,PathBuf)>,
+    files:Vec<(Box<dyn TrackFile>,PathBuf)>,
     region: Option<Region>,
+    // boot executable name from SYSTEM.CNF, i.e. SLUS_005.94
+    serial: Option<String>,
     head_position: DiscTime,
     track_number: u8,
     tracks_start_times: Vec<DiscTime>,
     temp_dir: Option<TempDir>,
+    // Q subchannel of the sectors patched by a .sbi/.sub file, by absolute LBA
+    subq_replacements: HashMap<u32,SubQ>,
 }
 
 impl Resettable for Disc {
@@ -314,6 +400,16 @@ impl Resettable for Disc {
     }
 }
 
+/*
+Only the head position is saved: the disc contents are reloaded from the image file
+ */
+impl SaveState for Disc {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.head_position);
+        s.sync(&mut self.track_number);
+    }
+}
+
 impl Drop for Disc {
     fn drop(&mut self) {
         info!("Closing disc {} ...",self.cue_file_name);
@@ -358,7 +454,76 @@ impl Disc {
         }
     }
 
+    fn empty(cue_file_name:String,temp_dir:Option<TempDir>) -> Self {
+        Disc {
+            cue_file_name,
+            tracks: Vec::new(),
+            files: Vec::new(),
+            region: None,
+            serial: None,
+            head_position: DiscTime::new(0,0,0),
+            track_number: 0,
+            tracks_start_times: Vec::new(),
+            temp_dir,
+            subq_replacements: HashMap::new(),
+        }
+    }
+
+    /*
+    Opens a bin file referenced by the cue sheet, returning the file and its length in bytes: ECM files are decoded on the fly.
+     */
+    fn open_track_file(bin_path:&Path) -> Result<(Box<dyn TrackFile>,u64),String> {
+        if EcmFile::is_ecm(bin_path) {
+            let ecm = EcmFile::open(bin_path)?;
+            let len = ecm.len();
+            return Ok((Box::new(ecm),len));
+        }
+        match File::open(bin_path) {
+            Ok(file) => {
+                let file_len = file.metadata().map_err(|e| format!("Failed to read file '{}': {}",bin_path.display(),e))?.len();
+                Ok((Box::new(BufReader::with_capacity(FILE_BUFFER_SIZE,file)),file_len))
+            }
+            Err(e) => Err(format!("Failed to open file '{}': {}",bin_path.display(),e))
+        }
+    }
+
+    /*
+    The disc paths of a multi-disc image (PBP files only), None for the other images
+     */
+    pub fn multi_disc_paths(image_path:&str) -> Option<Vec<String>> {
+        let (pbp_path,_) = pbp::split_disc_number(image_path);
+        if !pbp_path.to_uppercase().ends_with(".PBP") {
+            return None;
+        }
+        match PbpFile::disc_count(Path::new(pbp_path)) {
+            Ok(count) if count > 1 => Some((1..=count).map(|number| pbp::disc_path(pbp_path,number)).collect()),
+            _ => None
+        }
+    }
+
+    /*
+    The image file of a disc path, without the disc number of multi-disc PBP files
+     */
+    pub fn image_path(disc_path:&str) -> &str {
+        pbp::split_disc_number(disc_path).0
+    }
+
     pub fn new(cue_file_name:&String) -> Result<Self,String> {
+        let upper_name = Self::image_path(cue_file_name).to_uppercase();
+        let mut disc = if upper_name.ends_with(".CHD") {
+            Self::new_chd(cue_file_name)?
+        }
+        else if upper_name.ends_with(".PBP") {
+            Self::new_pbp(cue_file_name)?
+        }
+        else {
+            Self::new_cue(cue_file_name)?
+        };
+        disc.load_subchannel();
+        Ok(disc)
+    }
+
+    fn new_cue(cue_file_name:&String) -> Result<Self,String> {
         let (temp_dir,file_name) = if cue_file_name.to_uppercase().ends_with(".ZIP") {
             match Self::get_cue_path_from_zip(cue_file_name) {
                 Some((tmp_dir,file_name)) => (Some(tmp_dir) ,file_name),
@@ -368,49 +533,59 @@ impl Disc {
         else {
             (None,cue_file_name.clone())
         };
-        let cue = match cue::parse_cue(file_name.clone()) {
-            Ok(cue) => cue,
-            Err(e) => return Err(format!("Failed to parse cue sheet '{}': {}",file_name,e))
+        // images without cue sheet: iso files hold 2048 bytes sectors, lone bin (or ecm) files a single data track
+        let image_name = PathBuf::from(Path::new(&file_name).file_name().unwrap_or_default());
+        let upper_name = file_name.to_uppercase();
+        let cue = if upper_name.ends_with(".ISO") {
+            cue::CueSheet::single_track(image_name,cue::TrackType::Mode1_2048)
+        }
+        else if upper_name.ends_with(".BIN") || upper_name.ends_with(".ECM") {
+            cue::CueSheet::single_track(image_name,cue::TrackType::Mode2_2352)
+        }
+        else {
+            match cue::parse_cue(file_name.clone()) {
+                Ok(cue) => cue,
+                Err(e) => return Err(format!("Failed to parse cue sheet '{}': {}",file_name,e))
+            }
         };
         let file_path_dir = Path::new(&file_name).parent().unwrap();
 
-        let mut disc = Disc {
-            cue_file_name: file_name.clone(),
-            tracks: Vec::new(),
-            files: Vec::new(),
-            region: None,
-            head_position: DiscTime::new(0,0,0),
-            track_number: 0,
-            tracks_start_times: Vec::new(),
-            temp_dir
-        };
+        let mut disc = Disc::empty(file_name.clone(),temp_dir);
 
         let mut absolute_start_time = DiscTime::ZERO_TIME;
 
         let mut file_id = 0u8;
         for file in cue.files.iter() {
-            let bin_path = file_path_dir.join(&file.path);
+            let mut bin_path = file_path_dir.join(&file.path);
             if !bin_path.exists() {
-                return Err(format!("File '{:?}' referenced in cue sheet '{}' does not exist",file.path,cue_file_name));
+                // the bin file could have been compressed with ECM
+                let ecm_path = PathBuf::from(format!("{}.ecm",bin_path.display()));
+                if !ecm_path.exists() {
+                    return Err(format!("File '{:?}' referenced in cue sheet '{}' does not exist",file.path,cue_file_name));
+                }
+                bin_path = ecm_path;
             }
-            let file_bytes = match File::open(bin_path.clone()) {
-                Ok(file) => {
-                    let file_len = file.metadata().unwrap().len() as u32;
-                    disc.files.push((BufReader::with_capacity(FILE_BUFFER_SIZE,file),bin_path.clone()));
-                    file_len
-                },
-                Err(e) =>
-                    return Err(format!("Failed to open file '{}': {}",bin_path.display(),e))
-            };
+            let (track_file,file_bytes) = Self::open_track_file(&bin_path)?;
+            let mut file_sector_size = None;
+            let mut file_start_time = absolute_start_time;
 
             for i in 0..file.tracks.len() {
                 let track = &file.tracks[i];
                 let track_type = match track.track_type {
                     cue::TrackType::Audio => TrackType::Audio,
+                    cue::TrackType::Mode1_2048  => TrackType::Data(1,2048),
                     cue::TrackType::Mode1_2352  => TrackType::Data(1,SECTOR_SIZE),
+                    cue::TrackType::Mode2_2336  => TrackType::Data(2,2336),
                     cue::TrackType::Mode2_2352  => TrackType::Data(2,SECTOR_SIZE),
                     _ => return Err(format!("Unsupported track type {:?}",track.track_type))
                 };
+                let sector_size = match track_type {
+                    TrackType::Data(_,size) => size,
+                    TrackType::Audio => SECTOR_SIZE,
+                };
+                if *file_sector_size.get_or_insert(sector_size) != sector_size {
+                    return Err(format!("Tracks with different sector sizes in file '{}' are not supported",bin_path.display()));
+                }
                 let pregap_len = match track_type {
                     TrackType::Data(_,_) => {
                         // Data tracks always have a 2-second pregap
@@ -427,7 +602,7 @@ impl Disc {
                 });
                 let is_last_track_in_file = i == file.tracks.len() - 1;
                 let data_end_time = if is_last_track_in_file {
-                    DiscTime::from_file_length(file_bytes)
+                    DiscTime::from_lba((file_bytes / sector_size as u64) as u32)
                 }
                 else {
                     let next_track = &file.tracks[i + 1];
@@ -441,6 +616,9 @@ impl Disc {
                 };
                 let track_len = data_end_time.sub(&track_start);
                 let padded_track_len = pregap_len.add(&pause_len).add(&track_len).add(&postgap_len);
+                if i == 0 {
+                    file_start_time = absolute_start_time.add(&pregap_len);
+                }
 
                 let track = Track::new(
                     file_id,
@@ -456,15 +634,126 @@ impl Disc {
                 disc.tracks_start_times.push(absolute_start_time);
                 absolute_start_time = absolute_start_time.add(&padded_track_len);
             }
+            let track_file : Box<dyn TrackFile> = match file_sector_size {
+                Some(sector_size) if sector_size != SECTOR_SIZE => Box::new(CookedTrackFile { file: track_file, sector_size, start: file_start_time }),
+                _ => track_file
+            };
+            disc.files.push((track_file,bin_path));
             file_id += 1;
         }
 
-        disc.tracks.iter().for_each(|t| info!("  Track [{:?}] {}: {}/{} - {}",t.track_type(),t.track_number(),t.start_time(),t.effective_start_time(),t.end_time()));
+        disc.detect_region();
+        Ok(disc)
+    }
+
+    /*
+    CHD images: each track gets its own file entry that reads sectors lazily from the shared image.
+    CHD pregaps marked with a V type are stored in the image, the others must be generated.
+     */
+    fn new_chd(chd_file_name:&String) -> Result<Self,String> {
+        let chd_path = PathBuf::from(chd_file_name);
+        let mut chd = ChdFile::open(&chd_path)?;
+        let chd_tracks = chd.read_tracks()?;
+        let chd = Rc::new(RefCell::new(chd));
+
+        let mut disc = Disc::empty(chd_file_name.clone(),None);
+
+        let mut absolute_start_time = DiscTime::ZERO_TIME;
+        for (file_id,track) in chd_tracks.iter().enumerate() {
+            let track_type = match track.track_type {
+                ChdTrackType::Audio => TrackType::Audio,
+                ChdTrackType::Mode1Raw => TrackType::Data(1,SECTOR_SIZE),
+                ChdTrackType::Mode2Raw => TrackType::Data(2,SECTOR_SIZE),
+                ChdTrackType::Other(ref t) => return Err(format!("Unsupported CHD track type {} for track {}",t,track.number))
+            };
+            let stored_pregap = if track.pregap_in_file { track.pregap } else { 0 };
+            let (pregap_len,postgap_len) = match track_type {
+                // Data tracks always have a 2-second pregap
+                TrackType::Data(_,_) => (DiscTime::_2_SEC_TIME,DiscTime::_2_SEC_TIME),
+                TrackType::Audio => (DiscTime::from_lba(track.pregap - stored_pregap),DiscTime::from_lba(track.postgap)),
+            };
+            let pause_len = DiscTime::from_lba(stored_pregap);
+            let track_len = DiscTime::from_lba(track.frames - stored_pregap);
+            let padded_track_len = pregap_len.add(&pause_len).add(&track_len).add(&postgap_len);
+
+            let audio = matches!(track_type,TrackType::Audio);
+            disc.files.push((Box::new(ChdTrackFile::new(chd.clone(),track.first_frame,audio)),chd_path.clone()));
+            disc.tracks.push(Track::new(
+                file_id as u8,
+                track.number,
+                track_type,
+                absolute_start_time,
+                absolute_start_time.add(&padded_track_len),
+                pregap_len,
+                postgap_len,
+                pause_len,
+            ));
+            disc.tracks_start_times.push(absolute_start_time);
+            absolute_start_time = absolute_start_time.add(&padded_track_len);
+        }
+
+        disc.detect_region();
+        Ok(disc)
+    }
+
+    /*
+    PBP images: the whole disc is stored contiguously with its gaps, so only the 2 seconds before the first track are generated.
+    Each track gets its own file entry that reads sectors lazily from the shared image.
+     */
+    fn new_pbp(pbp_file_name:&str) -> Result<Self,String> {
+        let (pbp_path,disc_number) = pbp::split_disc_number(pbp_file_name);
+        let pbp_path = PathBuf::from(pbp_path);
+        let pbp = PbpFile::open(&pbp_path,disc_number)?;
+        let pbp_tracks : Vec<(u8,bool,DiscTime)> = pbp.tracks().iter().map(|t| (t.number,t.audio,t.start)).collect();
+        let lead_out = pbp.lead_out();
+        let pbp = Rc::new(RefCell::new(pbp));
+
+        let mut disc = Disc::empty(pbp_file_name.to_string(),None);
+
+        for (file_id,&(number,audio,start)) in pbp_tracks.iter().enumerate() {
+            let end_time = pbp_tracks.get(file_id + 1).map_or(lead_out,|t| t.2);
+            let track_type = if audio { TrackType::Audio } else { TrackType::Data(2,SECTOR_SIZE) };
+            let (start_time,pregap_len) = if file_id == 0 { (DiscTime::ZERO_TIME,start) } else { (start,DiscTime::ZERO_TIME) };
+            disc.files.push((Box::new(PbpTrackFile::new(pbp.clone(),start)),pbp_path.clone()));
+            disc.tracks.push(Track::new(
+                file_id as u8,
+                number,
+                track_type,
+                start_time,
+                end_time,
+                pregap_len,
+                DiscTime::ZERO_TIME,
+                DiscTime::ZERO_TIME,
+            ));
+            disc.tracks_start_times.push(start_time);
+        }
 
-        disc.region = util::get_cd_region(&disc.files[0].1).or_else(|| Disc::try_find_region_by_file_name(&disc.cue_file_name));
+        disc.detect_region();
         Ok(disc)
     }
 
+    /*
+    LibCrypt protected discs need the .sbi or .sub file with the same name as the image (for zip files, inside the archive)
+     */
+    fn load_subchannel(&mut self) {
+        let image_path = PathBuf::from(&self.cue_file_name);
+        match subq::load_replacements(&image_path,|msf| self.generate_subq(msf)) {
+            Ok(replacements) => self.subq_replacements = replacements,
+            Err(e) => warn!("Subchannel data ignored: {}",e),
+        }
+    }
+
+    fn detect_region(&mut self) {
+        self.tracks.iter().for_each(|t| info!("  Track [{:?}] {}: {}/{} - {}",t.track_type(),t.track_number(),t.start_time(),t.effective_start_time(),t.end_time()));
+
+        let (file,path) = &mut self.files[0];
+        self.serial = util::get_boot_serial(file.as_mut(),path);
+        if let Some(serial) = &self.serial {
+            info!("Disc serial: {}",serial);
+        }
+        self.region = self.serial.as_deref().and_then(util::get_serial_region).or_else(|| Disc::try_find_region_by_file_name(&self.cue_file_name));
+    }
+
     fn try_find_region_by_file_name(file_name:&String) -> Option<Region> {
         let name = file_name.to_uppercase();
         if name.contains("(JAPAN)") {
@@ -481,8 +770,8 @@ impl Disc {
         }
     }
 
-    fn find_track(&mut self,msf:DiscTime) -> Option<(&mut Track,&mut BufReader<File>,PathBuf)> {
-        let track_index = match self.tracks_start_times.binary_search(&msf) {
+    fn find_track_index(&self,msf:DiscTime) -> Option<usize> {
+        match self.tracks_start_times.binary_search(&msf) {
             Ok(i) => {
                 Some(i)
             },
@@ -500,8 +789,11 @@ impl Disc {
                     }
                 }
             }
-        };
-        track_index.map(|i| {
+        }
+    }
+
+    fn find_track(&mut self,msf:DiscTime) -> Option<(&mut Track,&mut Box<dyn TrackFile>,PathBuf)> {
+        self.find_track_index(msf).map(|i| {
             let track = &mut self.tracks[i];
             let file_id = track.get_file_id() as usize;
             let file_path = self.files[file_id].1.clone();
@@ -518,6 +810,10 @@ impl Disc {
         self.region
     }
 
+    pub fn get_serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
     pub fn force_region(&mut self,region:Region) {
         info!("Forcing region to {:?}",region);
         self.region = Some(region);
@@ -539,7 +835,7 @@ impl Disc {
                 track_number = Some(track.track_number());
                 debug!("Reading sector {} from track {} in '{}'",msf,track.track_number(),file_path.display());
                 let mut sector = DataSector::empty(track.track_number());
-                match track.read_sector_into(file, msf, &mut sector.sector) {
+                match track.read_sector_into(file.as_mut(), msf, &mut sector.sector) {
                     Ok(true) => SectorReadResult::SectorReadOk(sector),
                     Ok(false) => SectorReadResult::EndOfTrack,
                     Err(e) => {
@@ -597,4 +893,42 @@ impl Disc {
         }
         self.tracks.iter().find(|t| t.track_number() == number)
     }
-}
\ No newline at end of file
+
+    /*
+    Q subchannel of the given sector: the one patched by the .sbi/.sub file, or the one of an unprotected disc
+     */
+    pub fn get_subq(&self,msf:DiscTime) -> SubQ {
+        match self.subq_replacements.get(&msf.to_lba()) {
+            Some(q) => *q,
+            None => self.generate_subq(msf),
+        }
+    }
+
+    fn generate_subq(&self,msf:DiscTime) -> SubQ {
+        let mut q = [0u8;SUBQ_SIZE];
+        let lead_out = self.tracks.last().map_or(DiscTime::ZERO_TIME,|t| *t.end_time());
+        let (track,index,relative) = match self.find_track_index(msf) {
+            Some(i) if msf < lead_out => {
+                let track = &self.tracks[i];
+                let index_start = track.effective_start_time();
+                if msf < index_start {
+                    (track,0,index_start.sub(&msf))
+                }
+                else {
+                    (track,1,msf.sub(&index_start))
+                }
+            }
+            _ => match self.tracks.last() {
+                Some(track) => (track,1,msf.sub(&lead_out)),
+                None => return q,
+            }
+        };
+        q[0] = match track.track_type { TrackType::Audio => 0x01, TrackType::Data(_,_) => 0x41 };
+        q[1] = if msf < lead_out { BCD::encode(track.track_number()) } else { 0xAA };
+        q[2] = BCD::encode(index);
+        q[3..6].copy_from_slice(&[BCD::encode(relative.m()),BCD::encode(relative.s()),BCD::encode(relative.f())]);
+        q[7..10].copy_from_slice(&[BCD::encode(msf.m()),BCD::encode(msf.s()),BCD::encode(msf.f())]);
+        subq::set_crc(&mut q);
+        q
+    }
+}
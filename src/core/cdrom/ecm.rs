@@ -0,0 +1,208 @@
+use crate::core::cdrom::disc::{TrackFile, SECTOR_SIZE};
+use crate::core::cdrom::sector::{generate_mode1, generate_mode2_form1, generate_mode2_form2, CD_SYNC_HEADER};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use tracing::info;
+
+/*
+ECM (Error Code Modeler) image reader.
+ECM removes the sync pattern, EDC and ECC from the sectors of a bin file: they are rebuilt on the fly when read.
+After the "ECM\0" magic the file is a sequence of records, each one starting with a variable length type/count:
+  bits 0-1 type, bits 2-6 count (low bits), bit 7 more count bytes follow (7 bits each); count 0xFFFFFFFF ends the stream
+  Type 0: count raw bytes
+  Type 1: count Mode1 sectors, 3 bytes address + 2048 bytes of data -> 2352 bytes
+  Type 2: count Mode2 Form1 sectors, 4 bytes subheader + 2048 bytes of data -> 2336 bytes (without sync and header)
+  Type 3: count Mode2 Form2 sectors, 4 bytes subheader + 2324 bytes of data -> 2336 bytes (without sync and header)
+An index of the records is built when the file is opened, so sectors can be read randomly.
+ */
+
+const ECM_MAGIC : &[u8;4] = b"ECM\0";
+const MODE2_SECTOR_SIZE : u64 = 2336;
+const FILE_BUFFER_SIZE : usize = 256 * 1024;
+
+#[derive(Debug)]
+struct EcmRecord {
+    record_type: u8,
+    count: u32,
+    in_offset: u64,
+    out_offset: u64,
+}
+
+impl EcmRecord {
+    fn in_unit_size(&self) -> u64 {
+        match self.record_type {
+            0 => 1,
+            1 => 3 + 2048,
+            2 => 4 + 2048,
+            _ => 4 + 2324,
+        }
+    }
+
+    fn out_unit_size(&self) -> u64 {
+        match self.record_type {
+            0 => 1,
+            1 => SECTOR_SIZE as u64,
+            _ => MODE2_SECTOR_SIZE,
+        }
+    }
+
+    fn out_size(&self) -> u64 {
+        self.count as u64 * self.out_unit_size()
+    }
+}
+
+pub(super) struct EcmFile {
+    file: BufReader<File>,
+    records: Vec<EcmRecord>,
+    len: u64,
+    sector: [u8;SECTOR_SIZE as usize],
+    // record index and sector index of the sector buffer
+    cached_sector: Option<(usize,u32)>,
+}
+
+impl fmt::Debug for EcmFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,"EcmFile(records={},len={})",self.records.len(),self.len)
+    }
+}
+
+impl EcmFile {
+    pub fn is_ecm(path:&Path) -> bool {
+        path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ecm"))
+    }
+
+    pub fn open(path:&Path) -> Result<Self,String> {
+        let file = File::open(path).map_err(|e| format!("Cannot open ECM file '{}': {}",path.display(),e))?;
+        let file_len = file.metadata().map_err(|e| e.to_string())?.len();
+        let mut file = BufReader::with_capacity(FILE_BUFFER_SIZE,file);
+        let io_error = |e:std::io::Error| format!("Error reading ECM file '{}': {}",path.display(),e);
+
+        let mut magic = [0u8;4];
+        file.read_exact(&mut magic).map_err(io_error)?;
+        if &magic != ECM_MAGIC {
+            return Err(format!("'{}' is not an ECM file",path.display()));
+        }
+
+        let mut records = Vec::new();
+        let mut in_offset = 4u64;
+        let mut out_offset = 0u64;
+        loop {
+            let mut byte = [0u8;1];
+            file.read_exact(&mut byte).map_err(io_error)?;
+            in_offset += 1;
+            let mut c = byte[0];
+            let record_type = c & 3;
+            let mut count = ((c >> 2) & 0x1F) as u32;
+            let mut bits = 5;
+            while c & 0x80 != 0 {
+                if bits > 31 {
+                    return Err(format!("Invalid ECM record count at offset {}",in_offset));
+                }
+                file.read_exact(&mut byte).map_err(io_error)?;
+                in_offset += 1;
+                c = byte[0];
+                count |= ((c & 0x7F) as u32) << bits;
+                bits += 7;
+            }
+            if count == 0xFFFF_FFFF {
+                break;
+            }
+            let record = EcmRecord { record_type, count: count + 1, in_offset, out_offset };
+            let in_size = record.count as u64 * record.in_unit_size();
+            in_offset += in_size;
+            if in_offset > file_len {
+                return Err(format!("ECM file '{}' is truncated",path.display()));
+            }
+            out_offset += record.out_size();
+            file.seek_relative(in_size as i64).map_err(io_error)?;
+            records.push(record);
+        }
+
+        info!("ECM file '{}': {} records, {} bytes decoded",path.display(),records.len(),out_offset);
+        Ok(Self { file, records, len: out_offset, sector: [0;SECTOR_SIZE as usize], cached_sector: None })
+    }
+
+    // decoded length
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /*
+    Decodes the given sector of a sector record into the sector buffer: the returned range is the part written to the bin file
+     */
+    fn decode_sector(&mut self,record_index:usize,sector_index:u32) -> std::io::Result<std::ops::Range<usize>> {
+        let record = &self.records[record_index];
+        let range = match record.record_type {
+            1 => 0..SECTOR_SIZE as usize,
+            _ => 0x10..SECTOR_SIZE as usize,
+        };
+        if self.cached_sector == Some((record_index,sector_index)) {
+            return Ok(range);
+        }
+        let record_type = record.record_type;
+        self.file.seek(SeekFrom::Start(record.in_offset + sector_index as u64 * record.in_unit_size()))?;
+        let sector = &mut self.sector;
+        sector.fill(0);
+        sector[0..12].copy_from_slice(&CD_SYNC_HEADER);
+        match record_type {
+            1 => {
+                self.file.read_exact(&mut sector[0x0C..0x0F])?;
+                sector[0x0F] = 1;
+                self.file.read_exact(&mut sector[0x10..0x810])?;
+                generate_mode1(sector);
+            }
+            2 => {
+                sector[0x0F] = 2;
+                self.file.read_exact(&mut sector[0x10..0x14])?;
+                sector.copy_within(0x10..0x14,0x14);
+                self.file.read_exact(&mut sector[0x18..0x818])?;
+                generate_mode2_form1(sector);
+            }
+            _ => {
+                sector[0x0F] = 2;
+                self.file.read_exact(&mut sector[0x10..0x14])?;
+                sector.copy_within(0x10..0x14,0x14);
+                self.file.read_exact(&mut sector[0x18..0x92C])?;
+                generate_mode2_form2(sector);
+            }
+        }
+        self.cached_sector = Some((record_index,sector_index));
+        Ok(range)
+    }
+}
+
+impl TrackFile for EcmFile {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> std::io::Result<()> {
+        let mut offset = offset;
+        let mut done = 0;
+        while done < buffer.len() {
+            if offset >= self.len {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+            }
+            let record_index = self.records.partition_point(|r| r.out_offset + r.out_size() <= offset);
+            let record = &self.records[record_index];
+            let record_offset = offset - record.out_offset;
+            let len = if record.record_type == 0 {
+                let len = ((record.out_size() - record_offset) as usize).min(buffer.len() - done);
+                self.file.seek(SeekFrom::Start(record.in_offset + record_offset))?;
+                self.file.read_exact(&mut buffer[done..done + len])?;
+                len
+            }
+            else {
+                let unit_size = record.out_unit_size();
+                let sector_index = (record_offset / unit_size) as u32;
+                let start = (record_offset % unit_size) as usize;
+                let range = self.decode_sector(record_index,sector_index)?;
+                let data = &self.sector[range][start..];
+                let len = data.len().min(buffer.len() - done);
+                buffer[done..done + len].copy_from_slice(&data[..len]);
+                len
+            };
+            done += len;
+            offset += len as u64;
+        }
+        Ok(())
+    }
+}
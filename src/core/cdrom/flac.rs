@@ -0,0 +1,348 @@
+/*
+Minimal FLAC frame decoder used by the CHD flac/cdfl codecs.
+CHD hunks contain a sequence of raw FLAC frames (no stream header): STREAMINFO values are implied
+(44100Hz, 2 channels, 16 bits per sample).
+ */
+
+/*
+MSB-first bit reader. Reading past the end returns zeros and sets the overflow flag.
+ */
+pub(super) struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    overflow: bool,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data:&'a [u8]) -> Self {
+        Self { data, pos: 0, overflow: false }
+    }
+
+    pub fn is_overflow(&self) -> bool {
+        self.overflow
+    }
+
+    pub fn byte_position(&self) -> usize {
+        self.pos.div_ceil(8)
+    }
+
+    pub fn align(&mut self) {
+        self.pos = (self.pos + 7) & !7;
+    }
+
+    pub fn peek(&self,bits:u32) -> u32 {
+        if bits == 0 {
+            return 0;
+        }
+        let byte = self.pos >> 3;
+        let mut window = 0u64;
+        for i in 0..8 {
+            window = (window << 8) | *self.data.get(byte + i).unwrap_or(&0) as u64;
+        }
+        ((window << (self.pos & 7)) >> (64 - bits)) as u32
+    }
+
+    pub fn skip(&mut self,bits:u32) {
+        self.pos += bits as usize;
+        if self.pos > self.data.len() << 3 {
+            self.overflow = true;
+        }
+    }
+
+    pub fn read(&mut self,bits:u32) -> u32 {
+        let value = self.peek(bits);
+        self.skip(bits);
+        value
+    }
+
+    pub fn read_signed(&mut self,bits:u32) -> i32 {
+        if bits == 0 {
+            return 0;
+        }
+        let shift = 32 - bits;
+        ((self.read(bits) << shift) as i32) >> shift
+    }
+
+    pub fn read_unary(&mut self) -> u32 {
+        let mut count = 0;
+        loop {
+            let window = self.peek(32);
+            if window != 0 {
+                let zeros = window.leading_zeros();
+                self.skip(zeros + 1);
+                return count + zeros;
+            }
+            count += 32;
+            self.skip(32);
+            if self.overflow {
+                return count;
+            }
+        }
+    }
+}
+
+const DEFAULT_BITS_PER_SAMPLE : u32 = 16;
+
+/*
+Decodes FLAC frames from src until dest is full, writing interleaved 16 bits samples.
+Returns the number of source bytes consumed.
+ */
+pub(super) fn decode_interleaved(src:&[u8],dest:&mut [u8],big_endian:bool) -> Result<usize,String> {
+    let mut br = BitReader::new(src);
+    let mut channels : Vec<Vec<i32>> = Vec::new();
+    let mut out = 0;
+    while out < dest.len() {
+        let (block_size,channel_count) = decode_frame(&mut br,&mut channels)?;
+        if br.is_overflow() {
+            return Err("FLAC stream truncated".to_string());
+        }
+        for i in 0..block_size {
+            for ch in channels.iter().take(channel_count) {
+                if out + 2 > dest.len() {
+                    break;
+                }
+                let sample = ch[i] as i16;
+                let bytes = if big_endian { sample.to_be_bytes() } else { sample.to_le_bytes() };
+                dest[out..out + 2].copy_from_slice(&bytes);
+                out += 2;
+            }
+        }
+    }
+    Ok(br.byte_position())
+}
+
+fn decode_frame(br:&mut BitReader,channels:&mut Vec<Vec<i32>>) -> Result<(usize,usize),String> {
+    // frame header
+    let sync = br.read(14);
+    if sync != 0x3FFE {
+        return Err(format!("Invalid FLAC frame sync {:04X}",sync));
+    }
+    br.skip(2); // reserved + blocking strategy
+    let block_size_code = br.read(4);
+    let sample_rate_code = br.read(4);
+    let channel_assignment = br.read(4);
+    let sample_size_code = br.read(3);
+    br.skip(1);
+    // UTF-8 coded frame/sample number
+    let first = br.read(8) as u8;
+    let extra_bytes = first.leading_ones();
+    if extra_bytes > 1 {
+        br.skip((extra_bytes - 1) * 8);
+    }
+    let block_size = match block_size_code {
+        1 => 192,
+        2..=5 => 576 << (block_size_code - 2),
+        6 => br.read(8) as usize + 1,
+        7 => br.read(16) as usize + 1,
+        8..=15 => 256 << (block_size_code - 8),
+        _ => return Err("Invalid FLAC block size".to_string())
+    };
+    match sample_rate_code {
+        12 => br.skip(8),
+        13 | 14 => br.skip(16),
+        15 => return Err("Invalid FLAC sample rate".to_string()),
+        _ => {}
+    }
+    let bits_per_sample = match sample_size_code {
+        0 => DEFAULT_BITS_PER_SAMPLE,
+        1 => 8,
+        2 => 12,
+        4 => 16,
+        5 => 20,
+        6 => 24,
+        7 => 32,
+        _ => return Err("Invalid FLAC sample size".to_string())
+    };
+    br.skip(8); // CRC-8
+    let channel_count = match channel_assignment {
+        0..=7 => channel_assignment as usize + 1,
+        8..=10 => 2,
+        _ => return Err(format!("Invalid FLAC channel assignment {}",channel_assignment))
+    };
+
+    if channels.len() < channel_count {
+        channels.resize(channel_count,Vec::new());
+    }
+    for (ch,samples) in channels.iter_mut().take(channel_count).enumerate() {
+        // side channel has one extra bit
+        let side = match channel_assignment {
+            8 | 10 => ch == 1,
+            9 => ch == 0,
+            _ => false
+        };
+        samples.resize(block_size,0);
+        decode_subframe(br,samples,bits_per_sample + side as u32)?;
+    }
+
+    // decorrelation
+    if channel_count == 2 {
+        let (left,right) = channels.split_at_mut(1);
+        let (left,right) = (&mut left[0],&mut right[0]);
+        match channel_assignment {
+            8 => { // left/side
+                for i in 0..block_size {
+                    right[i] = left[i].wrapping_sub(right[i]);
+                }
+            }
+            9 => { // side/right
+                for i in 0..block_size {
+                    left[i] = left[i].wrapping_add(right[i]);
+                }
+            }
+            10 => { // mid/side
+                for i in 0..block_size {
+                    let side = right[i];
+                    let mid = (left[i] << 1) | (side & 1);
+                    left[i] = mid.wrapping_add(side) >> 1;
+                    right[i] = mid.wrapping_sub(side) >> 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    br.align();
+    br.skip(16); // CRC-16
+    Ok((block_size,channel_count))
+}
+
+fn decode_subframe(br:&mut BitReader,samples:&mut [i32],bits_per_sample:u32) -> Result<(),String> {
+    if br.read(1) != 0 {
+        return Err("Invalid FLAC subframe padding".to_string());
+    }
+    let subframe_type = br.read(6);
+    let wasted_bits = if br.read(1) == 1 { br.read_unary() + 1 } else { 0 };
+    if wasted_bits >= bits_per_sample {
+        return Err("Invalid FLAC wasted bits".to_string());
+    }
+    let bps = bits_per_sample - wasted_bits;
+    if bps > 32 {
+        return Err(format!("Unsupported FLAC sample size {}",bps));
+    }
+
+    match subframe_type {
+        0 => { // constant
+            let value = br.read_signed(bps);
+            samples.fill(value);
+        }
+        1 => { // verbatim
+            for s in samples.iter_mut() {
+                *s = br.read_signed(bps);
+            }
+        }
+        8..=12 => { // fixed
+            let order = (subframe_type - 8) as usize;
+            decode_warmup(br,samples,order,bps)?;
+            decode_residual(br,samples,order)?;
+            for i in order..samples.len() {
+                let s = |k:usize| samples[i - k] as i64;
+                let prediction = match order {
+                    0 => 0,
+                    1 => s(1),
+                    2 => 2 * s(1) - s(2),
+                    3 => 3 * s(1) - 3 * s(2) + s(3),
+                    _ => 4 * s(1) - 6 * s(2) + 4 * s(3) - s(4),
+                };
+                samples[i] = (samples[i] as i64 + prediction) as i32;
+            }
+        }
+        32..=63 => { // LPC
+            let order = (subframe_type - 31) as usize;
+            decode_warmup(br,samples,order,bps)?;
+            let precision = br.read(4) + 1;
+            if precision == 16 {
+                return Err("Invalid FLAC LPC precision".to_string());
+            }
+            let shift = br.read_signed(5);
+            if shift < 0 {
+                return Err("Negative FLAC LPC shift".to_string());
+            }
+            let mut coefficients = [0i64;32];
+            for c in coefficients.iter_mut().take(order) {
+                *c = br.read_signed(precision) as i64;
+            }
+            decode_residual(br,samples,order)?;
+            for i in order..samples.len() {
+                let mut sum = 0i64;
+                for (j,c) in coefficients.iter().take(order).enumerate() {
+                    sum += c * samples[i - 1 - j] as i64;
+                }
+                samples[i] = (samples[i] as i64 + (sum >> shift)) as i32;
+            }
+        }
+        _ => return Err(format!("Invalid FLAC subframe type {}",subframe_type))
+    }
+
+    if wasted_bits > 0 {
+        for s in samples.iter_mut() {
+            *s <<= wasted_bits;
+        }
+    }
+    Ok(())
+}
+
+fn decode_warmup(br:&mut BitReader,samples:&mut [i32],order:usize,bps:u32) -> Result<(),String> {
+    if order > samples.len() {
+        return Err("FLAC predictor order greater than block size".to_string());
+    }
+    for s in samples.iter_mut().take(order) {
+        *s = br.read_signed(bps);
+    }
+    Ok(())
+}
+
+fn decode_residual(br:&mut BitReader,samples:&mut [i32],order:usize) -> Result<(),String> {
+    let (param_bits,escape) = match br.read(2) {
+        0 => (4,15),
+        1 => (5,31),
+        m => return Err(format!("Invalid FLAC residual coding method {}",m))
+    };
+    let partition_order = br.read(4);
+    let partitions = 1usize << partition_order;
+    let partition_size = samples.len() >> partition_order;
+    if partition_size < order {
+        return Err("Invalid FLAC residual partition size".to_string());
+    }
+    let mut index = order;
+    for p in 0..partitions {
+        let count = if p == 0 { partition_size - order } else { partition_size };
+        let param = br.read(param_bits);
+        if param == escape {
+            let bits = br.read(5);
+            for s in samples[index..index + count].iter_mut() {
+                *s = br.read_signed(bits);
+            }
+        }
+        else {
+            for s in samples[index..index + count].iter_mut() {
+                let q = br.read_unary() as u64;
+                let u = ((q << param) | br.read(param) as u64) as u32;
+                *s = ((u >> 1) as i32) ^ -((u & 1) as i32);
+            }
+        }
+        index += count;
+        if br.is_overflow() {
+            return Err("FLAC residual truncated".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+    Example 1 of the FLAC specification (RFC 9639, appendix D.1) without its stream header: a frame of one stereo sample,
+    with verbatim subframes and wasted bits.
+     */
+    #[test]
+    fn rfc_example_frame() {
+        let frame = [0xFF,0xF8,0x69,0x18,0x00,0x00,0xBF,0x03,0x58,0xFD,0x03,0x12,0x8B,0xAA,0x9A];
+        let mut samples = [0u8;4];
+        assert_eq!(decode_interleaved(&frame,&mut samples,false),Ok(frame.len()));
+        assert_eq!(samples,[25588i16.to_le_bytes(),10416i16.to_le_bytes()].concat().as_slice());
+        decode_interleaved(&frame,&mut samples,true).unwrap();
+        assert_eq!(samples,[25588i16.to_be_bytes(),10416i16.to_be_bytes()].concat().as_slice());
+    }
+}
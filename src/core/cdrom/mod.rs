@@ -1,17 +1,27 @@
 pub mod disc;
 pub mod util;
+mod chd;
 mod cue;
+mod ecm;
+mod flac;
+mod pbp;
+pub mod playlist;
 mod commands;
 mod read_sector;
+mod sector;
+mod subq;
+mod timing;
 mod xaadpcm;
 
-use crate::core::cdrom::commands::INT5;
+use crate::core::cdrom::commands::{INT1, INT5};
 use crate::core::cdrom::disc::{AudioLeftRight, Disc, DiscTime, TrackSectorDataSize};
+use crate::core::cdrom::subq::{SubQ, SUBQ_SIZE};
 use crate::core::cdrom::xaadpcm::XaAdpcmState;
 use crate::core::clock::Clock;
 use crate::core::dma::DmaDevice;
 use crate::core::interrupt::{InterruptType, IrqHandler};
-use crate::core::Resettable;
+use crate::core::savestate::{impl_state_enum, SaveState, StateSync};
+use crate::core::{unimplemented, Resettable};
 use std::collections::VecDeque;
 use std::ops::RangeInclusive;
 use tracing::{debug, info, warn};
@@ -36,6 +46,12 @@ Indicates the date (Year-month-day, in BCD format) and version of the HC05 CDROM
       (unknown)        ;PS2,   xx xxx xxxx, late PS2 models...?
  */
 const CDROM_VER : [u8;4] = [0x95, 0x07, 0x24, 0xC1]; // 95h,07h,24h,C1h  ;PSX (LATE-PU-8)          24 Jul 1995, version vC1 (b)
+/*
+19h,23h..25h --> INT3("...") chip IDs of the LATE-PU-8 board: servo amplifier, signal processor and decoder (psx-spx)
+ */
+const CDROM_SERVO_AMPLIFIER_ID : &[u8] = b"CXA1782BR";
+const CDROM_SIGNAL_PROCESSOR_ID : &[u8] = b"CXD2545Q";
+const CDROM_DECODER_ID : &[u8] = b"CXD1815Q";
 
 const PARAMETER_FIFO_LEN : usize = 16;
 
@@ -70,6 +86,16 @@ impl Region {
             Region::Europe => 'E',
         }
     }
+    /*
+    Region string returned by Test 22h: the one of the controller BIOS of the console, not the disc one
+     */
+    fn to_controller_region_id(self) -> &'static [u8] {
+        match self {
+            Region::Japan => b"for Japan",
+            Region::USA => b"for U/C",
+            Region::Europe => b"for Europe",
+        }
+    }
     pub fn get_crt_total_lines(&self) -> usize {
         match self {
             Region::Japan | Region::USA => 263,
@@ -100,6 +126,11 @@ enum Command {
     Test,
     GetID,
     ReadTOC,
+    GetQ,
+    ReadS,
+    SetSession,
+    VideoCD,
+    Unknown,
 }
 
 impl Command {
@@ -108,7 +139,8 @@ impl Command {
             0x01 => Some(Command::Nop),
             0x02 => Some(Command::Setloc),
             0x03 => Some(Command::Play),
-            0x06|0x1B => Some(Command::Read),
+            0x06 => Some(Command::Read), // ReadN
+            0x1B => Some(Command::ReadS),
             0x07 => Some(Command::MotorOn),
             0x08 => Some(Command::Stop),
             0x09 => Some(Command::Pause),
@@ -119,13 +151,16 @@ impl Command {
             0x0E => Some(Command::SetMode),
             0x10 => Some(Command::GetLocL),
             0x11 => Some(Command::GetLocP),
+            0x12 => Some(Command::SetSession),
             0x13 => Some(Command::GetTN),
             0x14 => Some(Command::GetTD),
             0x15 => Some(Command::SeekL),
             0x16 => Some(Command::SeekP),
             0x19 => Some(Command::Test),
             0x1A => Some(Command::GetID),
+            0x1D => Some(Command::GetQ),
             0x1E => Some(Command::ReadTOC),
+            0x1F => Some(Command::VideoCD),
             _ => None
         }
     }
@@ -139,11 +174,51 @@ impl Command {
             Command::SetMode => 1..=1,
             Command::GetTD => 1..=1,
             Command::Test => 1..=1,
+            Command::GetQ => 2..=2,
+            Command::SetSession => 1..=1,
+            Command::VideoCD => 0..=PARAMETER_FIFO_LEN,
             _ => 0..=0,
         }
     }
+
+    /*
+    Commands that cannot be executed while the shell is open: they fail with INT5(11h,80h)
+     */
+    fn needs_disc(&self) -> bool {
+        matches!(self,Command::Play | Command::Read | Command::ReadS | Command::SetSession | Command::SeekL | Command::SeekP | Command::GetID | Command::ReadTOC |
+                      Command::GetLocL | Command::GetLocP | Command::GetTN | Command::GetTD | Command::MotorOn | Command::GetQ)
+    }
 }
 
+impl_state_enum!(Command {
+    Command::Nop,
+    Command::Setloc,
+    Command::Play,
+    Command::Read,
+    Command::MotorOn,
+    Command::Stop,
+    Command::Pause,
+    Command::Init,
+    Command::Mute,
+    Command::Demute,
+    Command::SetMode,
+    Command::SetFilter,
+    Command::GetLocL,
+    Command::GetLocP,
+    Command::GetTN,
+    Command::GetTD,
+    Command::SeekL,
+    Command::SeekP,
+    Command::Test,
+    Command::GetID,
+    Command::ReadTOC,
+    Command::GetQ,
+    Command::ReadS,
+    Command::SetSession,
+    Command::VideoCD,
+    Command::Unknown,
+});
+
 #[derive(Debug,Clone)]
 enum CommandState {
     Idle,
@@ -161,6 +236,89 @@ enum DriveState {
     Reading { next_sector_cycles: usize, seeking_cycles: usize },
 }
 
+impl SaveState for CommandState {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let mut index : u8 = match self {
+            CommandState::Idle => 0,
+            CommandState::WaitingIrqAck(_) => 1,
+            CommandState::ToProcess(_) => 2,
+            CommandState::Response { .. } => 3,
+            CommandState::Delay { .. } => 4,
+            CommandState::Response2 { .. } => 5,
+        };
+        s.sync(&mut index);
+        if s.is_loading() {
+            *self = match index {
+                0 => CommandState::Idle,
+                1 => CommandState::WaitingIrqAck(0),
+                2 => CommandState::ToProcess(0),
+                3 => CommandState::Response { cmd: Command::Nop, irq: 0, delay_cycles: 0, response: vec![], next_state: Box::new(CommandState::Idle) },
+                4 => CommandState::Delay { cmd: Command::Nop, delay_cycles: 0, next_state: Box::new(CommandState::Idle) },
+                5 => CommandState::Response2 { cmd: Command::Nop },
+                _ => {
+                    s.set_error(format!("Invalid CDROM command state {index}"));
+                    return;
+                }
+            };
+        }
+        match self {
+            CommandState::Idle => {}
+            CommandState::WaitingIrqAck(irq) | CommandState::ToProcess(irq) => s.sync(irq),
+            CommandState::Response { cmd, irq, delay_cycles, response, next_state } => {
+                s.sync(cmd);
+                s.sync(irq);
+                s.sync(delay_cycles);
+                s.sync(response);
+                s.sync(next_state);
+            }
+            CommandState::Delay { cmd, delay_cycles, next_state } => {
+                s.sync(cmd);
+                s.sync(delay_cycles);
+                s.sync(next_state);
+            }
+            CommandState::Response2 { cmd } => s.sync(cmd),
+        }
+    }
+}
+
+impl SaveState for DriveState {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let mut index : u8 = match self {
+            DriveState::Idle => 0,
+            DriveState::Playing { .. } => 1,
+            DriveState::Seeking => 2,
+            DriveState::Reading { .. } => 3,
+        };
+        s.sync(&mut index);
+        if s.is_loading() {
+            *self = match index {
+                0 => DriveState::Idle,
+                1 => DriveState::Playing { first_sector_cycles: 0, sample_index: 0, report_counter: 0, report_absolute: false, seeking_cycles: 0 },
+                2 => DriveState::Seeking,
+                3 => DriveState::Reading { next_sector_cycles: 0, seeking_cycles: 0 },
+                _ => {
+                    s.set_error(format!("Invalid CDROM drive state {index}"));
+                    return;
+                }
+            };
+        }
+        match self {
+            DriveState::Playing { first_sector_cycles, sample_index, report_counter, report_absolute, seeking_cycles } => {
+                s.sync(first_sector_cycles);
+                s.sync(sample_index);
+                s.sync(report_counter);
+                s.sync(report_absolute);
+                s.sync(seeking_cycles);
+            }
+            DriveState::Reading { next_sector_cycles, seeking_cycles } => {
+                s.sync(next_sector_cycles);
+                s.sync(seeking_cycles);
+            }
+            _ => {}
+        }
+    }
+}
+
 impl DriveState {
     fn to_u8(&self) -> u8 {
         match self {
@@ -191,6 +349,20 @@ struct PendingIrq {
     response: Vec<u8>,
 }
 
+impl Default for PendingIrq {
+    fn default() -> Self {
+        Self { cmd: Command::Nop, irq: 0, response: vec![] }
+    }
+}
+
+impl SaveState for PendingIrq {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.cmd);
+        s.sync(&mut self.irq);
+        s.sync(&mut self.response);
+    }
+}
+
 const CHANGE_DISK_CYCLES: usize = 44_100 * 2;
 
 pub struct CDRom {
@@ -204,6 +376,8 @@ pub struct CDRom {
     result_fifo: VecDeque<u8>,
     data_buffer: VecDeque<u8>,
     last_sector_header: Vec<u8>,
+    // last Q subchannel read with a valid CRC
+    last_subq: SubQ,
     last_audio_sector: Vec<AudioLeftRight>,
     cd_to_spu_volume: [[u8; 2]; 2],
     pending_cd_to_spu_volume: [[u8; 2]; 2],
@@ -219,6 +393,17 @@ pub struct CDRom {
     changing_disk_cycles: usize,
     pending_disc: Option<Disc>,
     pending_irq: Option<PendingIrq>,
+    // ReadN retries the sectors that cannot be read, ReadS skips them
+    read_retry: bool,
+    read_retries: u8,
+    // SCEx strings read (total,success) since Test 04h, until Test 05h
+    scex_counters: Option<(u8,u8)>,
+    // region of the console, reported by Test 22h
+    console_region: Region,
+    // cycles before the spindle reaches the requested speed (spin-up or speed change)
+    spindle_cycles: usize,
+    // fast CD mode read speed multiplier
+    fast_cd: Option<usize>,
 }
 
 impl DmaDevice for CDRom {
@@ -229,7 +414,8 @@ impl DmaDevice for CDRom {
         true
     }
     fn dma_write(&mut self, _word: u32, _clock: &mut Clock, _irq_handler: &mut IrqHandler) {
-        todo!()
+        // the CD-ROM DMA channel only reads from the drive: words sent to it are ignored
+        unimplemented::report("CDROM","DMA write (RAM to CD-ROM)");
     }
     fn dma_read(&mut self) -> u32 {
         //info!("CDROM dma read");
@@ -260,6 +446,7 @@ impl Resettable for CDRom {
         self.result_fifo.clear();
         self.data_buffer.clear();
         self.last_sector_header.clear();
+        self.last_subq = [0;SUBQ_SIZE];
         self.last_audio_sector.clear();
         self.cd_to_spu_volume = [[0x80,0],[0x80,0]];
         self.pending_cd_to_spu_volume = [[0x80,0],[0x80,0]];
@@ -275,10 +462,67 @@ impl Resettable for CDRom {
         self.changing_disk_cycles = 0;
         self.pending_disc = None;
         self.pending_irq = None;
+        self.read_retry = false;
+        self.read_retries = 0;
+        self.scex_counters = None;
+        self.spindle_cycles = 0;
         info!("CDROM reset done");
     }
 }
 
+/*
+The disc image is not part of the state: the caller must insert the same disc (see set_disc) before loading.
+If the state has been saved while the shell was open the disc is moved back to the pending one.
+ */
+impl SaveState for CDRom {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.section("CDROM");
+        s.sync(&mut self.drive_state);
+        s.sync(&mut self.bank_address);
+        s.sync(&mut self.hintmsk_reg);
+        s.sync(&mut self.hintsts_reg);
+        s.sync(&mut self.hchpctl);
+        s.sync(&mut self.parameter_fifo);
+        s.sync(&mut self.result_fifo);
+        s.sync(&mut self.data_buffer);
+        s.sync(&mut self.last_sector_header);
+        s.sync(&mut self.last_subq);
+        s.sync(&mut self.last_audio_sector);
+        s.sync(&mut self.cd_to_spu_volume);
+        s.sync(&mut self.pending_cd_to_spu_volume);
+        s.sync(&mut self.audio_mute);
+        s.sync(&mut self.audio_sample);
+        s.sync(&mut self.command_state);
+        s.sync(&mut self.shell_once_opened);
+        s.sync(&mut self.send_int5_shell_opened);
+        s.sync(&mut self.motor_on);
+        s.sync(&mut self.pending_setloc);
+        s.sync(&mut self.mode);
+        s.sync(&mut self.adpcm);
+        s.sync(&mut self.changing_disk_cycles);
+        s.sync(&mut self.pending_irq);
+        s.sync(&mut self.read_retry);
+        s.sync(&mut self.read_retries);
+        s.sync(&mut self.scex_counters);
+        s.sync(&mut self.spindle_cycles);
+
+        if !s.is_loading() && self.disc.is_none() && let Some(pending) = self.pending_disc.take() {
+            self.disc = Some(pending);
+        }
+        let mut disc_present = self.disc.is_some();
+        s.sync(&mut disc_present);
+        match self.disc.as_mut() {
+            Some(disc) if disc_present => s.sync(disc),
+            None if !disc_present => {}
+            _ if s.is_loading() => s.set_error(format!("CDROM disc mismatch: state has disc {disc_present}")),
+            _ => {}
+        }
+        if self.changing_disk_cycles > 0 && self.pending_disc.is_none() {
+            self.pending_disc = self.disc.take();
+        }
+    }
+}
+
 impl CDRom {
     pub fn new() -> Self {
         Self {
@@ -292,6 +536,7 @@ impl CDRom {
             result_fifo: Default::default(),
             data_buffer: Default::default(),
             last_sector_header: Vec::with_capacity(disc::SECTOR_SIZE as usize),
+            last_subq: [0;SUBQ_SIZE],
             last_audio_sector: Vec::with_capacity(disc::SECTOR_SIZE as usize),
             cd_to_spu_volume: [[0x80,0],[0x80,0]],
             pending_cd_to_spu_volume: [[0x80,0],[0x80,0]],
@@ -307,14 +552,29 @@ impl CDRom {
             changing_disk_cycles: 0,
             pending_disc: None,
             pending_irq: None,
+            read_retry: false,
+            read_retries: 0,
+            scex_counters: None,
+            console_region: Region::USA,
+            spindle_cycles: 0,
+            fast_cd: None,
         }
     }
 
+    pub fn set_console_region(&mut self,region:Region) {
+        self.console_region = region;
+    }
+
+    /*
+    If a disc is already present the shell is opened: the drive stops, the motor is turned off and INT5 is raised.
+    The new disc is inserted when the shell is closed, after CHANGE_DISK_CYCLES.
+     */
     pub fn insert_disk(&mut self,disc:Disc) {
-        if let Some(old_disc) = self.disc.take() {
-            info!("CDROM removing disk '{}'. Inserting disk '{}'",old_disc.get_cue_file_name(),disc.get_cue_file_name());
+        if let Some(old_disc) = self.disc.take().or(self.pending_disc.take()) {
+            info!("CDROM opening shell: removing disk '{}'. Inserting disk '{}'",old_disc.get_cue_file_name(),disc.get_cue_file_name());
             self.drive_state = DriveState::Idle;
             self.command_state = CommandState::Idle;
+            self.motor_on = false;
 
             self.pending_disc = Some(disc);
             self.changing_disk_cycles = CHANGE_DISK_CYCLES;
@@ -327,17 +587,30 @@ impl CDRom {
         }
     }
 
+    /*
+    Inserts the disc immediately, without opening the shell: used when restoring a state.
+    Returns the previous disc, if any
+     */
+    pub fn set_disc(&mut self,disc:Option<Disc>) -> Option<Disc> {
+        let old_disc = self.disc.take().or(self.pending_disc.take());
+        self.disc = disc;
+        old_disc
+    }
+
     pub fn clock_44100hz(&mut self,irq_handler: &mut IrqHandler) -> CDOperation {
         if self.changing_disk_cycles > 0 {
             if self.send_int5_shell_opened {
                 self.send_int5_shell_opened = false;
-                self.apply_irq_and_result(Command::Nop,INT5,vec![self.get_stat(false, false, false)],irq_handler);
+                // Drive door became opened
+                self.apply_irq_and_result(Command::Nop,INT5,vec![self.get_stat(false, false, true),0x08],irq_handler);
             }
             self.changing_disk_cycles -= 1;
             if self.changing_disk_cycles == 0 {
                 if let Some(disc) = self.pending_disc.take() {
                     self.disc = Some(disc);
-                    info!("CDROM inserted disk '{}'",self.disc.as_ref().unwrap().get_cue_file_name());
+                    // shell closed: the drive spins up again, ShellOpen stat bit remains set until the next Nop
+                    self.activate_motor(true);
+                    info!("CDROM shell closed, inserted disk '{}'",self.disc.as_ref().unwrap().get_cue_file_name());
                 }
             }
         }
@@ -347,6 +620,7 @@ impl CDRom {
         }
         
         self.audio_sample = AudioLeftRight(0,0);
+        self.clock_spindle();
         self.check_drive_state(irq_handler);
         self.check_command_state(irq_handler);
         
@@ -450,9 +724,14 @@ impl CDRom {
     }
 
     fn activate_motor(&mut self,enabled:bool) {
-        self.motor_on = true;
+        if enabled && !self.motor_on {
+            self.start_spindle(true);
+        }
+        else if !enabled {
+            self.spindle_cycles = 0;
+        }
+        self.motor_on = enabled;
         debug!("CDROM motor activated: {enabled}");
-        // TODO
     }
 
     /*
@@ -683,6 +962,11 @@ impl CDRom {
     fn is_irq_pending(&self) -> bool {
         (self.hintsts_reg & 7) != 0
     }
+    // an INT1 (sector ready) not acknowledged yet or waiting to be sent
+    #[inline]
+    fn is_int1_pending(&self) -> bool {
+        (self.hintsts_reg & 7) == INT1 || self.pending_irq.as_ref().is_some_and(|pending| pending.irq == INT1)
+    }
 
     /*
     0x1f801803 (write, bank 0): HCHPCTL
@@ -0,0 +1,255 @@
+use crate::core::cdrom::chd::inflate;
+use crate::core::cdrom::disc::{DiscTime, TrackFile, BCD, SECTOR_SIZE};
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::rc::Rc;
+use tracing::info;
+
+/*
+PSP eboot (PBP) reader for PS1 images converted with popstation-like tools.
+PBP header: "\0PBP" magic, version and the offsets of the 8 embedded files: the disc image is in DATA.PSAR (the last one).
+DATA.PSAR starts with "PSISOIMG0000" for single disc images or "PSTITLEIMG000000" for multi-disc images,
+in the latter case the offsets of the PSISOIMG headers of each disc are stored at 200h.
+PSISOIMG header:
+  400h  disc id (i.e. _SLUS_00594)
+  800h  TOC: 10 bytes Q subchannel entries (A0h/A1h/A2h points, then one entry per track with BCD absolute start time)
+  4000h block table: 32 bytes entries (offset, size, ...) of 16 sectors blocks compressed with raw deflate (stored if size is 9300h)
+  100000h blocks data
+The image contains the whole disc starting at 00:02:00, gaps included.
+The discs of a multi-disc image after the first one are referenced as <pbp path>#<disc number> (i.e. game.pbp#2).
+ */
+
+const PBP_MAGIC : &[u8;4] = b"\0PBP";
+const PSAR_OFFSET : usize = 0x24;
+const SINGLE_DISC_MAGIC : &[u8] = b"PSISOIMG0000";
+const MULTI_DISC_MAGIC : &[u8] = b"PSTITLEIMG000000";
+const MULTI_DISC_OFFSETS : u64 = 0x200;
+const MAX_DISCS : usize = 5;
+const TOC_OFFSET : u64 = 0x800;
+const TOC_ENTRY_SIZE : usize = 10;
+const TOC_MAX_ENTRIES : usize = 3 + 99;
+const BLOCK_TABLE_OFFSET : u64 = 0x4000;
+const BLOCK_TABLE_ENTRY_SIZE : usize = 32;
+const BLOCKS_DATA_OFFSET : u64 = 0x100000;
+const BLOCK_SECTORS : u32 = 16;
+const BLOCK_SIZE : usize = BLOCK_SECTORS as usize * SECTOR_SIZE as usize;
+const LEAD_IN_SECTORS : u32 = 150;
+const DISC_NUMBER_SEPARATOR : char = '#';
+
+#[derive(Debug)]
+pub(super) struct PbpTrack {
+    pub number: u8,
+    pub audio: bool,
+    // absolute start time (index 01)
+    pub start: DiscTime,
+}
+
+pub(super) struct PbpFile {
+    file: BufReader<File>,
+    blocks_offset: u64,
+    blocks: Vec<(u32,u16)>,
+    tracks: Vec<PbpTrack>,
+    lead_out: DiscTime,
+    block: Vec<u8>,
+    cached_block: Option<usize>,
+}
+
+impl fmt::Debug for PbpFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,"PbpFile(blocks={},tracks={})",self.blocks.len(),self.tracks.len())
+    }
+}
+
+fn le32(b:&[u8]) -> u32 {
+    u32::from_le_bytes([b[0],b[1],b[2],b[3]])
+}
+
+fn bcd_time(b:&[u8]) -> DiscTime {
+    DiscTime::new(BCD::decode(b[0]),BCD::decode(b[1]),BCD::decode(b[2]))
+}
+
+/*
+The PBP file and the disc number (from 1) referenced by a disc path: the path without disc number is the first disc
+ */
+pub fn split_disc_number(disc_path:&str) -> (&str,usize) {
+    match disc_path.rsplit_once(DISC_NUMBER_SEPARATOR) {
+        Some((pbp,number)) if pbp.to_uppercase().ends_with(".PBP") => match number.parse::<usize>() {
+            Ok(number) if number >= 1 => (pbp,number),
+            _ => (disc_path,1)
+        }
+        _ => (disc_path,1)
+    }
+}
+
+pub fn disc_path(pbp_path:&str,number:usize) -> String {
+    if number == 1 { pbp_path.to_string() } else { format!("{}{}{}",pbp_path,DISC_NUMBER_SEPARATOR,number) }
+}
+
+fn read_at(file:&mut BufReader<File>,path:&Path,offset:u64,len:usize) -> Result<Vec<u8>,String> {
+    let io_error = |e:std::io::Error| format!("Error reading PBP file '{}': {}",path.display(),e);
+    let mut buffer = vec![0u8;len];
+    file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+    file.read_exact(&mut buffer).map_err(io_error)?;
+    Ok(buffer)
+}
+
+/*
+Offsets of the PSISOIMG headers of the discs in the file
+ */
+fn disc_offsets(file:&mut BufReader<File>,path:&Path) -> Result<Vec<u64>,String> {
+    let header = read_at(file,path,0,0x28)?;
+    if &header[0..4] != PBP_MAGIC {
+        return Err(format!("'{}' is not a PBP file",path.display()));
+    }
+    let psar_offset = le32(&header[PSAR_OFFSET..]) as u64;
+    let psar_magic = read_at(file,path,psar_offset,16)?;
+    if psar_magic.starts_with(SINGLE_DISC_MAGIC) {
+        Ok(vec![psar_offset])
+    }
+    else if psar_magic.starts_with(MULTI_DISC_MAGIC) {
+        let offsets = read_at(file,path,psar_offset + MULTI_DISC_OFFSETS,MAX_DISCS * 4)?;
+        Ok(offsets.chunks_exact(4).map(le32).take_while(|o| *o != 0).map(|o| psar_offset + o as u64).collect())
+    }
+    else {
+        Err(format!("Unsupported PBP file '{}': DATA.PSAR is not a PS1 image (encrypted?)",path.display()))
+    }
+}
+
+fn open_file(path:&Path) -> Result<BufReader<File>,String> {
+    let file = File::open(path).map_err(|e| format!("Cannot open PBP file '{}': {}",path.display(),e))?;
+    Ok(BufReader::new(file))
+}
+
+impl PbpFile {
+    pub fn disc_count(path:&Path) -> Result<usize,String> {
+        let mut file = open_file(path)?;
+        Ok(disc_offsets(&mut file,path)?.len())
+    }
+
+    /*
+    Opens the given disc (from 1) of the file
+     */
+    pub fn open(path:&Path,disc_number:usize) -> Result<Self,String> {
+        let mut file = open_file(path)?;
+        let read_at = |file:&mut BufReader<File>,offset:u64,len:usize| read_at(file,path,offset,len);
+
+        let discs = disc_offsets(&mut file,path)?;
+        let iso_offset = match discs.get(disc_number.wrapping_sub(1)) {
+            Some(offset) => *offset,
+            None => return Err(format!("PBP file '{}' has {} discs, cannot load disc {}",path.display(),discs.len(),disc_number)),
+        };
+        let iso_magic = read_at(&mut file,iso_offset,SINGLE_DISC_MAGIC.len())?;
+        if iso_magic != SINGLE_DISC_MAGIC {
+            return Err(format!("Invalid PBP disc header in '{}'",path.display()));
+        }
+
+        // TOC
+        let toc = read_at(&mut file,iso_offset + TOC_OFFSET,TOC_MAX_ENTRIES * TOC_ENTRY_SIZE)?;
+        let mut tracks = Vec::new();
+        let mut lead_out = None;
+        for entry in toc.chunks_exact(TOC_ENTRY_SIZE) {
+            let (control,point) = (entry[0],entry[2]);
+            if control == 0 && point == 0 {
+                break;
+            }
+            match point {
+                0xA2 => lead_out = Some(bcd_time(&entry[7..10])),
+                0xA0 | 0xA1 => {}
+                _ => tracks.push(PbpTrack { number: BCD::decode(point), audio: control & 0x40 == 0, start: bcd_time(&entry[7..10]) })
+            }
+        }
+        if tracks.is_empty() {
+            return Err(format!("No tracks found in PBP file '{}'",path.display()));
+        }
+        tracks.sort_by_key(|t| t.number);
+
+        // block table
+        let table = read_at(&mut file,iso_offset + BLOCK_TABLE_OFFSET,(BLOCKS_DATA_OFFSET - BLOCK_TABLE_OFFSET) as usize)?;
+        let blocks : Vec<(u32,u16)> = table.chunks_exact(BLOCK_TABLE_ENTRY_SIZE)
+            .map(|e| (le32(e),u16::from_le_bytes([e[4],e[5]])))
+            .take_while(|(_,size)| *size != 0)
+            .collect();
+        if blocks.is_empty() {
+            return Err(format!("Empty PBP image '{}'",path.display()));
+        }
+        let image_end = DiscTime::from_lba(blocks.len() as u32 * BLOCK_SECTORS + LEAD_IN_SECTORS);
+        let lead_out = lead_out.unwrap_or(image_end).min(image_end);
+
+        info!("PBP file '{}' disc {}/{}: {} tracks, {} blocks, lead-out at {}",path.display(),disc_number,discs.len(),tracks.len(),blocks.len(),lead_out);
+        Ok(Self {
+            file,
+            blocks_offset: iso_offset + BLOCKS_DATA_OFFSET,
+            blocks,
+            tracks,
+            lead_out,
+            block: vec![0;BLOCK_SIZE],
+            cached_block: None,
+        })
+    }
+
+    pub fn tracks(&self) -> &[PbpTrack] {
+        &self.tracks
+    }
+
+    pub fn lead_out(&self) -> DiscTime {
+        self.lead_out
+    }
+
+    // sector 0 is at 00:02:00
+    fn read_sector(&mut self,sector:u32,buffer:&mut [u8]) -> Result<(),String> {
+        let block_index = (sector / BLOCK_SECTORS) as usize;
+        if self.cached_block != Some(block_index) {
+            let (offset,size) = *self.blocks.get(block_index).ok_or_else(|| format!("Sector {} out of PBP image",sector))?;
+            let mut data = vec![0u8;size as usize];
+            self.file.seek(SeekFrom::Start(self.blocks_offset + offset as u64)).map_err(|e| e.to_string())?;
+            self.file.read_exact(&mut data).map_err(|e| e.to_string())?;
+            if data.len() == BLOCK_SIZE {
+                self.block.copy_from_slice(&data);
+            }
+            else {
+                inflate(&data,&mut self.block)?;
+            }
+            self.cached_block = Some(block_index);
+        }
+        let start = (sector % BLOCK_SECTORS) as usize * SECTOR_SIZE as usize;
+        buffer.copy_from_slice(&self.block[start..start + SECTOR_SIZE as usize]);
+        Ok(())
+    }
+}
+
+/*
+Sectors of a PBP image track: the image is shared by all the tracks
+ */
+#[derive(Debug)]
+pub(super) struct PbpTrackFile {
+    pbp: Rc<RefCell<PbpFile>>,
+    first_sector: u32,
+}
+
+impl PbpTrackFile {
+    pub fn new(pbp:Rc<RefCell<PbpFile>>,start:DiscTime) -> Self {
+        Self { pbp, first_sector: start.to_lba().saturating_sub(LEAD_IN_SECTORS) }
+    }
+}
+
+impl TrackFile for PbpTrackFile {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> std::io::Result<()> {
+        let mut sector = [0u8;SECTOR_SIZE as usize];
+        let mut offset = offset;
+        let mut done = 0;
+        let mut pbp = self.pbp.borrow_mut();
+        while done < buffer.len() {
+            let index = self.first_sector + (offset / SECTOR_SIZE as u64) as u32;
+            let start = (offset % SECTOR_SIZE as u64) as usize;
+            pbp.read_sector(index,&mut sector).map_err(std::io::Error::other)?;
+            let len = (SECTOR_SIZE as usize - start).min(buffer.len() - done);
+            buffer[done..done + len].copy_from_slice(&sector[start..start + len]);
+            done += len;
+            offset += len as u64;
+        }
+        Ok(())
+    }
+}
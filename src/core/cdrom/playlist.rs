@@ -0,0 +1,85 @@
+use crate::core::cdrom::disc::Disc;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/*
+Multi-disc games playlist (.m3u): one disc image per line, relative paths are resolved against the playlist directory.
+Empty lines and lines starting with # are ignored.
+The discs of a multi-disc PBP image make a playlist too.
+ */
+#[derive(Debug, Clone)]
+pub struct Playlist {
+    discs: Vec<String>,
+    current: usize,
+}
+
+impl Playlist {
+    pub fn is_playlist(path:&str) -> bool {
+        path.to_uppercase().ends_with(".M3U")
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self,String> {
+        let file = File::open(&path).map_err(|e| format!("Cannot open playlist '{}': {}",path.as_ref().display(),e))?;
+        let base_dir = path.as_ref().parent().unwrap_or(Path::new(""));
+        let mut discs = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("Cannot read playlist '{}': {}",path.as_ref().display(),e))?;
+            let line = line.trim().trim_start_matches('\u{feff}');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let disc_path = base_dir.join(line);
+            if Self::is_playlist(line) {
+                return Err(format!("Nested playlist '{}' not supported",disc_path.display()));
+            }
+            discs.push(disc_path.to_string_lossy().to_string());
+        }
+        if discs.is_empty() {
+            return Err(format!("Playlist '{}' is empty",path.as_ref().display()));
+        }
+        Ok(Self { discs, current: 0 })
+    }
+
+    /*
+    The playlist of the discs of a multi-disc image, positioned on the given disc: None for single disc images
+     */
+    pub fn from_multi_disc_image(disc_path:&str) -> Option<Self> {
+        let mut playlist = Self { discs: Disc::multi_disc_paths(disc_path)?, current: 0 };
+        playlist.select_disc(disc_path);
+        Some(playlist)
+    }
+
+    pub fn current_disc(&self) -> &String {
+        &self.discs[self.current]
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn disc_count(&self) -> usize {
+        self.discs.len()
+    }
+
+    /*
+    The disc after (or before) the current one, wrapping around: the playlist moves to it with select_disc, once it's loaded
+     */
+    pub fn next_disc(&self,next:bool) -> &String {
+        let len = self.discs.len();
+        &self.discs[if next { (self.current + 1) % len } else { (self.current + len - 1) % len }]
+    }
+
+    /*
+    Selects the given disc if it belongs to the playlist: used when a disc of the playlist is loaded, or referenced by a state
+     */
+    pub fn select_disc(&mut self,disc_path:&str) -> bool {
+        match self.discs.iter().position(|d| d == disc_path) {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false
+        }
+    }
+}
@@ -1,15 +1,41 @@
 use crate::core::cdrom::commands::{INT1, INT4};
 use crate::core::cdrom::disc::{Disc, SectorReadResult, BCD};
+use crate::core::cdrom::subq::is_crc_valid;
 use crate::core::cdrom::{CDRom, Command, DriveState};
 use crate::core::interrupt::IrqHandler;
 use std::process::exit;
 use tracing::{debug, error, info, warn};
 
+// times a sector that cannot be read is read again by ReadN before skipping it
+const MAX_READ_RETRIES : u8 = 8;
+
 impl CDRom {
+    /*
+    Latches the Q subchannel of the sector under the head, unless its CRC is wrong (LibCrypt sectors)
+     */
+    pub(super) fn update_subq(&mut self) {
+        if let Some(disc) = self.disc.as_ref() {
+            let q = disc.get_subq(disc.get_head_position());
+            if is_crc_valid(&q) {
+                self.last_subq = q;
+            }
+            else {
+                debug!("CDROM sector {} has an invalid Q subchannel CRC: {:02X?}",disc.get_head_position(),q);
+            }
+        }
+    }
+
+    /*
+    Reads the sector under the head. A sector that cannot be read is read again by ReadN (up to MAX_READ_RETRIES times),
+    while ReadS skips it: with disc images this happens only for I/O errors.
+     */
     pub(super) fn read_data_sector(&mut self,irq_handler:&mut IrqHandler) -> bool {
+        self.update_subq();
+        self.count_scex();
         let sector_size = self.get_sector_size();
         let mut send_int1 = false;
         let mut end_of_track = false;
+        let mut read_error = false;
         if let Some(disc) = self.disc.as_mut() {
             let read_result = disc.read_sector();
             match read_result {
@@ -35,7 +61,7 @@ impl CDRom {
                             debug!("Reading next sector at {} with old bytes not read: {}",disc.get_head_position(),self.data_buffer.len());
                         }
                         send_int1 = true;
-                        let data = sector.get_mode2_user_data(&sector_size);
+                        let data = sector.get_user_data(&sector_size);
                         self.data_buffer.clear();
                         self.data_buffer.extend(data);
                     }
@@ -45,11 +71,19 @@ impl CDRom {
                 }
                 SectorReadResult::SectorReadError(e) => {
                     error!("CDROM read_data_sector at loc {:?} failed due to {:?}",disc.get_head_position(),e);
+                    read_error = true;
                 }
                 SectorReadResult::TrackNotFound => {
                     error!("CDROM read_data_sector at loc {:?} failed: track not found",disc.get_head_position());
+                    read_error = true;
                 }
             }
+            if read_error && self.read_retry && self.read_retries < MAX_READ_RETRIES {
+                self.read_retries += 1;
+                warn!("CDROM ReadN retrying sector {} [{}/{MAX_READ_RETRIES}]",disc.get_head_position(),self.read_retries);
+                return false;
+            }
+            self.read_retries = 0;
             // go to next sector
             if disc.set_next_sector_head_position() {
                 end_of_track = true;
@@ -66,14 +100,30 @@ impl CDRom {
             let last_track_number = disc.get_tracks().last().map(|track| track.track_number()).unwrap_or(0);
             if current_track.track_number() == last_track_number {
                 info!("Reached end of disc, stopping...");
-                self.activate_motor(false);
+                // the drive stops but the motor keeps spinning
                 self.change_drive_state(DriveState::Idle);
                 self.apply_irq_and_result(Command::Play,INT4,vec![],irq_handler);
             }
         }
     }
 
+    /*
+    SCEx strings are counted for every sector passed under the head, while enabled by Test 04h:
+    only a licensed disc gives a valid one
+     */
+    fn count_scex(&mut self) {
+        let licensed = self.disc.as_ref().is_some_and(|disc| !disc.is_audio_cd() && disc.get_region().is_some());
+        if let Some((total,success)) = self.scex_counters.as_mut() {
+            *total = total.saturating_add(1);
+            if licensed {
+                *success = success.saturating_add(1);
+            }
+        }
+    }
+
     pub(super) fn read_audio_sector(&mut self,send_report_flag:bool,report_absolute:bool,irq_handler:&mut IrqHandler) {
+        self.update_subq();
+        self.count_scex();
         let stat = self.get_stat(false,false,false);
         let mut report = [0u8; 8];
         let mut send_report = false;
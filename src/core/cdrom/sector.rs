@@ -0,0 +1,125 @@
+use crate::core::cdrom::disc::{DiscTime, BCD, SECTOR_SIZE};
+
+/*
+Raw sector helpers, used to rebuild the 2352 bytes sectors of images that store only a part of them:
+sync pattern, header, EDC (CRC32 with 0xD8018001 polynomial) and ECC (P and Q parity).
+
+Mode1           Mode2 Form1        Mode2 Form2
+000h Sync       000h Sync          000h Sync
+00Ch Header     00Ch Header        00Ch Header
+010h Data       010h Subheader     010h Subheader
+810h EDC        018h Data          018h Data
+814h Zero       818h EDC           92Ch EDC
+81Ch ECC        81Ch ECC
+ */
+pub(super) const CD_SYNC_HEADER : [u8;12] = [0x00,0xFF,0xFF,0xFF,0xFF,0xFF,0xFF,0xFF,0xFF,0xFF,0xFF,0x00];
+pub(super) const MODE2_FORM1_SUBHEADER : [u8;8] = [0x00,0x00,0x08,0x00,0x00,0x00,0x08,0x00];
+
+const EDC_LUT : [u32;256] = {
+    let mut lut = [0u32;256];
+    let mut i = 0;
+    while i < 256 {
+        let mut edc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            edc = (edc >> 1) ^ if edc & 1 != 0 { 0xD8018001 } else { 0 };
+            bit += 1;
+        }
+        lut[i] = edc;
+        i += 1;
+    }
+    lut
+};
+
+pub(super) fn edc_compute(data:&[u8]) -> u32 {
+    data.iter().fold(0u32,|edc,b| (edc >> 8) ^ EDC_LUT[((edc ^ *b as u32) & 0xFF) as usize])
+}
+
+/*
+CD-ROM ECC (Reed-Solomon product code) generation
+ */
+const ECC_F_LUT : [u8;256] = {
+    let mut lut = [0u8;256];
+    let mut i = 0;
+    while i < 256 {
+        lut[i] = ((i << 1) ^ if i & 0x80 != 0 { 0x11D } else { 0 }) as u8;
+        i += 1;
+    }
+    lut
+};
+const ECC_B_LUT : [u8;256] = {
+    let mut lut = [0u8;256];
+    let mut i = 0;
+    while i < 256 {
+        lut[i ^ ECC_F_LUT[i] as usize] = i as u8;
+        i += 1;
+    }
+    lut
+};
+
+fn ecc_compute_block(src:&[u8],major_count:usize,minor_count:usize,major_mult:usize,minor_inc:usize,dest:&mut [u8]) {
+    let size = major_count * minor_count;
+    for major in 0..major_count {
+        let mut index = (major >> 1) * major_mult + (major & 1);
+        let mut ecc_a = 0u8;
+        let mut ecc_b = 0u8;
+        for _ in 0..minor_count {
+            let temp = src[index];
+            index += minor_inc;
+            if index >= size {
+                index -= size;
+            }
+            ecc_a ^= temp;
+            ecc_b ^= temp;
+            ecc_a = ECC_F_LUT[ecc_a as usize];
+        }
+        ecc_a = ECC_B_LUT[(ECC_F_LUT[ecc_a as usize] ^ ecc_b) as usize];
+        dest[major] = ecc_a;
+        dest[major + major_count] = ecc_a ^ ecc_b;
+    }
+}
+
+pub(super) fn ecc_generate(sector:&mut [u8]) {
+    let mut p = [0u8;2 * 86];
+    ecc_compute_block(&sector[0x0C..],86,24,2,86,&mut p);
+    sector[0x81C..0x81C + p.len()].copy_from_slice(&p);
+    let mut q = [0u8;2 * 52];
+    ecc_compute_block(&sector[0x0C..],52,43,86,88,&mut q);
+    sector[0x8C8..0x8C8 + q.len()].copy_from_slice(&q);
+}
+
+/*
+Writes sync pattern and header (BCD absolute time and mode)
+ */
+pub(super) fn write_header(sector:&mut [u8],msf:DiscTime,mode:u8) {
+    sector[0..12].copy_from_slice(&CD_SYNC_HEADER);
+    sector[12] = BCD::encode(msf.m());
+    sector[13] = BCD::encode(msf.s());
+    sector[14] = BCD::encode(msf.f());
+    sector[15] = mode;
+}
+
+pub(super) fn generate_mode1(sector:&mut [u8]) {
+    let edc = edc_compute(&sector[0..0x810]);
+    sector[0x810..0x814].copy_from_slice(&edc.to_le_bytes());
+    sector[0x814..0x81C].fill(0);
+    ecc_generate(sector);
+}
+
+/*
+Mode2 ECC does not cover the header: it's computed as if the header was zeroed
+ */
+pub(super) fn generate_mode2_form1(sector:&mut [u8]) {
+    let edc = edc_compute(&sector[0x10..0x818]);
+    sector[0x818..0x81C].copy_from_slice(&edc.to_le_bytes());
+    let mut header = [0u8;4];
+    header.copy_from_slice(&sector[0x0C..0x10]);
+    sector[0x0C..0x10].fill(0);
+    ecc_generate(sector);
+    sector[0x0C..0x10].copy_from_slice(&header);
+}
+
+pub(super) fn generate_mode2_form2(sector:&mut [u8]) {
+    let edc = edc_compute(&sector[0x10..0x92C]);
+    sector[0x92C..SECTOR_SIZE as usize].copy_from_slice(&edc.to_le_bytes());
+}
@@ -0,0 +1,131 @@
+use crate::core::cdrom::disc::{DiscTime, BCD};
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{info, warn};
+
+/*
+Subchannel Q: 12 bytes stored with each sector (outside the 2352 bytes of user data)
+  00h  control/adr (41h=data track, 01h=audio track)
+  01h  track number (BCD, AAh=lead-out)
+  02h  index number (BCD, 00h=pregap)
+  03h  mm:ss:ff relative time within the track (BCD, counting down in the pregap)
+  06h  zero
+  07h  amm:ass:aff absolute time (BCD)
+  0Ah  CRC-16 (polynomial 1021h, inverted, big endian)
+The controller keeps the last Q with a valid CRC: GetlocP returns it.
+
+LibCrypt protected discs have a few sectors with modified Q data and a wrong CRC, which bin images cannot hold:
+they are provided by a .sbi file (the patched sectors only) or by a .sub file (the raw subchannels of the whole disc).
+SBI: "SBI\0", then for each sector 3 bytes BCD absolute time, 1 byte type and the data
+  type 1: 10 bytes of Q (the CRC is not stored)
+  type 2: 3 bytes relative time
+  type 3: 3 bytes absolute time
+SUB: 96 bytes per sector (P..W deinterleaved, 12 bytes each) starting at 00:02:00, Q at 0Ch.
+ */
+pub(super) const SUBQ_SIZE : usize = 12;
+pub(super) type SubQ = [u8;SUBQ_SIZE];
+
+const SBI_MAGIC : &[u8;4] = b"SBI\0";
+const SUB_SECTOR_SIZE : usize = 96;
+const SUB_Q_OFFSET : usize = 12;
+
+const CRC16_LUT : [u16;256] = {
+    let mut lut = [0u16;256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = (crc << 1) ^ if crc & 0x8000 != 0 { 0x1021 } else { 0 };
+            bit += 1;
+        }
+        lut[i] = crc;
+        i += 1;
+    }
+    lut
+};
+
+fn crc16(data:&[u8]) -> u16 {
+    !data.iter().fold(0u16,|crc,b| (crc << 8) ^ CRC16_LUT[((crc >> 8) as u8 ^ *b) as usize])
+}
+
+pub(super) fn set_crc(q:&mut SubQ) {
+    let crc = crc16(&q[0..10]);
+    q[10..12].copy_from_slice(&crc.to_be_bytes());
+}
+
+pub(super) fn is_crc_valid(q:&SubQ) -> bool {
+    crc16(&q[0..10]).to_be_bytes() == q[10..12]
+}
+
+fn bcd_time(b:&[u8]) -> Option<DiscTime> {
+    if !b.iter().all(|v| BCD::is_valid(*v)) {
+        return None
+    }
+    DiscTime::new_checked(BCD::decode(b[0]),BCD::decode(b[1]),BCD::decode(b[2]))
+}
+
+/*
+Loads the .sbi or .sub file next to the disc image, if any: returns the Q data of the sectors (by absolute LBA) that differ from the generated ones.
+generate_q gives the Q data a sector would have without protection.
+ */
+pub(super) fn load_replacements(image_path:&Path,generate_q:impl Fn(DiscTime) -> SubQ) -> Result<HashMap<u32,SubQ>,String> {
+    let sbi_path = image_path.with_extension("sbi");
+    let sub_path = image_path.with_extension("sub");
+    let path = if sbi_path.exists() { sbi_path } else if sub_path.exists() { sub_path } else { return Ok(HashMap::new()) };
+    let data = std::fs::read(&path).map_err(|e| format!("Cannot read subchannel file '{}': {}",path.display(),e))?;
+    let replacements = if path.extension().is_some_and(|ext| ext == "sbi") {
+        load_sbi(&data,&generate_q)
+    }
+    else {
+        load_sub(&data,&generate_q)
+    };
+    let replacements = replacements.map_err(|e| format!("Invalid subchannel file '{}': {}",path.display(),e))?;
+    info!("Loaded subchannel file '{}': {} patched sectors",path.display(),replacements.len());
+    Ok(replacements)
+}
+
+fn load_sbi(data:&[u8],generate_q:&impl Fn(DiscTime) -> SubQ) -> Result<HashMap<u32,SubQ>,String> {
+    if data.len() < SBI_MAGIC.len() || &data[0..4] != SBI_MAGIC {
+        return Err("bad SBI header".to_string());
+    }
+    let mut replacements = HashMap::new();
+    let mut offset = SBI_MAGIC.len();
+    while offset < data.len() {
+        let Some(entry) = data.get(offset..offset + 4) else { return Err(format!("truncated entry at offset {offset}")) };
+        let msf = bcd_time(&entry[0..3]).ok_or_else(|| format!("invalid time {:02X?} at offset {offset}",&entry[0..3]))?;
+        let (range,len) = match entry[3] {
+            1 => (0..10,10),
+            2 => (3..6,3),
+            3 => (7..10,3),
+            t => return Err(format!("unknown entry type {t} at offset {offset}"))
+        };
+        offset += 4;
+        let Some(patch) = data.get(offset..offset + len) else { return Err(format!("truncated entry at offset {offset}")) };
+        offset += len;
+        let mut q = generate_q(msf);
+        q[range].copy_from_slice(patch);
+        // the CRC is not stored: LibCrypt sectors have a wrong one, so the valid CRC is inverted
+        set_crc(&mut q);
+        q[10] ^= 0xFF;
+        q[11] ^= 0xFF;
+        replacements.insert(msf.to_lba(),q);
+    }
+    Ok(replacements)
+}
+
+fn load_sub(data:&[u8],generate_q:&impl Fn(DiscTime) -> SubQ) -> Result<HashMap<u32,SubQ>,String> {
+    if !data.len().is_multiple_of(SUB_SECTOR_SIZE) {
+        warn!("Subchannel file size {} is not a multiple of {} bytes",data.len(),SUB_SECTOR_SIZE);
+    }
+    let mut replacements = HashMap::new();
+    for (sector,sub) in data.chunks_exact(SUB_SECTOR_SIZE).enumerate() {
+        let msf = DiscTime::from_lba(sector as u32).add(&DiscTime::_2_SEC_TIME);
+        let mut q = [0u8;SUBQ_SIZE];
+        q.copy_from_slice(&sub[SUB_Q_OFFSET..SUB_Q_OFFSET + SUBQ_SIZE]);
+        if q != generate_q(msf) {
+            replacements.insert(msf.to_lba(),q);
+        }
+    }
+    Ok(replacements)
+}
@@ -0,0 +1,157 @@
+use crate::core::cdrom::commands::FIRST_RESPONSE_IRQ_DELAY_44100;
+use crate::core::cdrom::disc::DiscTime;
+use crate::core::cdrom::{CDRom, Command, CommandState};
+use tracing::info;
+
+/*
+CD-ROM timing model. All the times are in 44100Hz cycles, the clock of the controller (see CDRom::clock_44100hz).
+
+Response times measured on a real console (CPU cycles at 33.8688MHz), from nocash psx-spx:
+  Command                   Average
+  1st response (INT3)       000C4E1h
+  1st response, motor off   0005CF4h
+  Init 1st response         0013CCEh
+  GetID 2nd response        0004A00h
+  Pause, single speed       021181Ch  (about 5 sectors)
+  Pause, double speed       010BD93h
+  Pause, when paused        0001DF2h
+  Stop, single speed        0D38ACAh  (the disc slows down)
+  Stop, double speed        18A6076h
+  Stop, when stopped        0001D7Bh
+Seeks are modelled physically:
+- forward seeks of a few sectors are done waiting for the sectors to pass under the head
+- longer seeks move the sled: a fixed time (track jump, focus and tracking settling, subchannel lock) plus the time to move the
+  sled by the radial distance between the two positions (the disc is read at constant linear velocity, so the radius grows with
+  the square root of the sector number)
+- the spindle must be at the requested speed before seeking or playing: after the motor is turned on (spin-up) or the speed is
+  changed with Setmode, the seek lasts at least until the spindle is ready. ReadN/ReadS start reading without waiting for the seek
+  (a seek time there prevents running Gran Turismo 2)
+ */
+pub(super) const fn delay_cycles_44100(cycles:usize) -> usize {
+    cycles / (33_868_800 / 44_100)
+}
+
+const FIRST_RESPONSE_CYCLES : usize = delay_cycles_44100(0xC4E1);
+const FIRST_RESPONSE_MOTOR_OFF_CYCLES : usize = delay_cycles_44100(0x5CF4);
+const INIT_FIRST_RESPONSE_CYCLES : usize = delay_cycles_44100(0x13CCE);
+pub(super) const GET_ID_SECOND_RESPONSE_CYCLES : usize = delay_cycles_44100(0x4A00);
+const PAUSE_SECOND_RESPONSE_CYCLES : [usize;2] = [delay_cycles_44100(0x21181C),delay_cycles_44100(0x10BD93)];
+const PAUSE_WHEN_PAUSED_SECOND_RESPONSE_CYCLES : usize = delay_cycles_44100(0x1DF2);
+const STOP_SECOND_RESPONSE_CYCLES : [usize;2] = [delay_cycles_44100(0xD38ACA),delay_cycles_44100(0x18A6076)];
+const STOP_WHEN_STOPPED_SECOND_RESPONSE_CYCLES : usize = delay_cycles_44100(0x1D7B);
+
+const SPIN_UP_MS : f32 = 1000.0;
+const SPEED_CHANGE_MS : f32 = 650.0;
+
+// forward seeks up to this distance don't move the sled
+const SHORT_SEEK_SECTORS : u32 = 24;
+const SLED_SEEK_BASE_MS : f32 = 30.0;
+const SLED_SEEK_MS_PER_MM : f32 = 10.0;
+// radius of the first and last sector of an 80 minutes disc
+const INNER_RADIUS_MM : f32 = 25.0;
+const OUTER_RADIUS_MM : f32 = 58.0;
+const DISC_SECTORS : f32 = 80.0 * 60.0 * 75.0;
+
+const MIN_FAST_CD_READ_SPEED : usize = 2;
+const MAX_FAST_CD_READ_SPEED : usize = 8;
+
+impl CDRom {
+    /*
+    Fast CD mode: seeks, spin-up and speed changes are instant and data sectors are read read_speed times faster
+    (but for XA-ADPCM streaming, that must be played in real time). Cuts load times, but some games rely on the real timings.
+     */
+    pub fn set_fast_cd(&mut self,enabled:bool,read_speed:usize) {
+        let fast_cd = enabled.then(|| read_speed.clamp(MIN_FAST_CD_READ_SPEED,MAX_FAST_CD_READ_SPEED));
+        if fast_cd != self.fast_cd {
+            info!("CDROM fast mode: {}",fast_cd.map(|speed| format!("read speed x{speed}")).unwrap_or_else(|| "off".to_string()));
+        }
+        self.fast_cd = fast_cd;
+        if fast_cd.is_some() {
+            self.spindle_cycles = 0;
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn get_cycles_per_ms_44100(ms: f32) -> usize {
+        ((44100.0 / 1000.0 * ms) as usize).max(1)
+    }
+
+    /*
+    The spindle motor is turned on, or it changes speed: seeking must wait until it's ready.
+    A speed change during the spin-up doesn't shorten the remaining spin-up time.
+     */
+    pub(super) fn start_spindle(&mut self,spin_up:bool) {
+        if self.fast_cd.is_none() {
+            let cycles = Self::get_cycles_per_ms_44100(if spin_up { SPIN_UP_MS } else { SPEED_CHANGE_MS });
+            self.spindle_cycles = if spin_up { cycles } else { self.spindle_cycles.max(cycles) };
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn clock_spindle(&mut self) {
+        self.spindle_cycles = self.spindle_cycles.saturating_sub(1);
+    }
+
+    /*
+    Time to read a data sector at the current speed
+     */
+    pub(super) fn read_sector_cycles_44100(&self) -> usize {
+        let cycles = Self::get_cycles_per_ms_44100(self.get_speed().get_read_sector_ms());
+        match self.fast_cd {
+            Some(read_speed) if (self.mode & 0x40) == 0 => (cycles / read_speed).max(1),
+            _ => cycles
+        }
+    }
+
+    /*
+    Time to move the head from the sector from to the sector target, ready to read it
+     */
+    pub(super) fn seek_cycles_44100(&self,from:&DiscTime,target:&DiscTime) -> usize {
+        if self.fast_cd.is_some() {
+            return FIRST_RESPONSE_IRQ_DELAY_44100;
+        }
+        let (from,target) = (from.to_lba(),target.to_lba());
+        let seek_cycles = if target >= from && target - from <= SHORT_SEEK_SECTORS {
+            (target - from).max(1) as usize * self.read_sector_cycles_44100()
+        }
+        else {
+            let radius = |lba:u32| (INNER_RADIUS_MM.powi(2) + (OUTER_RADIUS_MM.powi(2) - INNER_RADIUS_MM.powi(2)) * (lba as f32 / DISC_SECTORS)).sqrt();
+            let distance_mm = (radius(target) - radius(from)).abs();
+            Self::get_cycles_per_ms_44100(SLED_SEEK_BASE_MS + SLED_SEEK_MS_PER_MM * distance_mm)
+        };
+        seek_cycles.max(self.spindle_cycles)
+    }
+
+    /*
+    The first response of every command is sent after the time taken by the controller to process it
+     */
+    pub(super) fn first_response_cycles(&self,cmd:Command) -> usize {
+        match cmd {
+            Command::Init => INIT_FIRST_RESPONSE_CYCLES,
+            _ if !self.motor_on => FIRST_RESPONSE_MOTOR_OFF_CYCLES,
+            _ => FIRST_RESPONSE_CYCLES,
+        }
+    }
+
+    pub(super) fn with_first_response_delay(state:CommandState,delay_cycles:usize) -> CommandState {
+        match state {
+            CommandState::Response { cmd, irq, response, next_state, .. } => CommandState::Response { cmd, irq, delay_cycles, response, next_state },
+            state => state
+        }
+    }
+
+    pub(super) fn pause_second_response_cycles(&self,paused:bool) -> usize {
+        if paused { PAUSE_WHEN_PAUSED_SECOND_RESPONSE_CYCLES } else { PAUSE_SECOND_RESPONSE_CYCLES[self.get_speed() as usize] }
+    }
+
+    pub(super) fn stop_second_response_cycles(&self) -> usize {
+        if !self.motor_on { STOP_WHEN_STOPPED_SECOND_RESPONSE_CYCLES } else { STOP_SECOND_RESPONSE_CYCLES[self.get_speed() as usize] }
+    }
+
+    /*
+    MotorOn completes when the spindle has spun up
+     */
+    pub(super) fn spin_up_second_response_cycles(&self) -> usize {
+        self.spindle_cycles.max(FIRST_RESPONSE_IRQ_DELAY_44100)
+    }
+}
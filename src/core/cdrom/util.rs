@@ -1,46 +1,36 @@
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Result};
-use std::path::PathBuf;
+use std::io::Result;
+use std::path::Path;
 use regex::Regex;
 use tracing::error;
+use crate::core::cdrom::disc::TrackFile;
 use crate::core::cdrom::Region;
 
-const SECTOR_SIZE: u64 = 2352;
-const USER_DATA_OFFSET: u64 = 24;
+const SECTOR_SIZE: usize = 2352;
 const USER_DATA_SIZE: usize = 2048;
 
-fn read_sector(file: &mut File, lba: u32, buffer: &mut [u8; USER_DATA_SIZE]) -> Result<()> {
-    let offset = (lba as u64) * SECTOR_SIZE + USER_DATA_OFFSET;
-    file.seek(SeekFrom::Start(offset))?;
-    file.read_exact(buffer)?;
+/*
+User data of a data sector: after the header (mode byte at 15) for Mode 1 sectors (iso images), after the subheader for Mode 2
+ */
+fn read_sector(file: &mut dyn TrackFile, lba: u32, buffer: &mut [u8; USER_DATA_SIZE]) -> Result<()> {
+    let mut sector = [0u8; SECTOR_SIZE];
+    file.read_at(lba as u64 * SECTOR_SIZE as u64, &mut sector)?;
+    let user_data_offset = if sector[15] == 1 { 16 } else { 24 };
+    buffer.copy_from_slice(&sector[user_data_offset..user_data_offset + USER_DATA_SIZE]);
     Ok(())
 }
 
 /*
-SxPx - Japan (NTSC)
-SxEx - Europe (PAL)
-SxUx - USA (NTSC)
+Boot executable name from SYSTEM.CNF (BOOT=cdrom:\SLUS_005.94;1), used as disc serial.
+Some discs boot from a subdirectory or omit the backslash: only the file name is kept.
  */
-pub fn get_cd_region(disc_path: &PathBuf) -> Option<Region> {
-    match read_system_cnf(disc_path) {
+pub(super) fn get_boot_serial(file: &mut dyn TrackFile, disc_path: &Path) -> Option<String> {
+    match read_system_cnf(file) {
         Ok(cnf) => {
-            let boot_re = Regex::new(r"\s*BOOT\s*=\s*cdrom:\\(.*);.*").unwrap();
-            match boot_re.captures(cnf.as_str()) {
-                Some(file) => {
-                    let upper = file[1].to_uppercase();
-                    let file = upper.as_bytes();
-                    if file.len() < 4 || file[0] != b'S' {
-                        return None;
-                    }
-                    match file[2] {
-                        b'P' => Some(Region::Japan),
-                        b'U' => Some(Region::USA),
-                        b'E' => Some(Region::Europe),
-                        _ => None
-                    }
-                }
-                None => None
-            }
+            let boot_re = Regex::new(r"(?i)\s*BOOT\s*=\s*cdrom0?:\\?([^;\r\n]*)").unwrap();
+            boot_re.captures(cnf.as_str()).and_then(|boot| {
+                let serial = boot[1].trim().rsplit(['\\',':']).next().unwrap_or_default().to_uppercase();
+                if serial.is_empty() { None } else { Some(serial) }
+            })
         }
         Err(e) => {
             error!("Error reading SYSTEM.CNF from {:?}: {}",disc_path,e);
@@ -49,12 +39,29 @@ pub fn get_cd_region(disc_path: &PathBuf) -> Option<Region> {
     }
 }
 
-pub fn read_system_cnf(path: &PathBuf) -> Result<String> {
-    let mut file = File::open(path)?;
+/*
+SxPx - Japan (NTSC)
+SxEx - Europe (PAL)
+SxUx - USA (NTSC)
+ */
+pub(super) fn get_serial_region(serial: &str) -> Option<Region> {
+    let file = serial.as_bytes();
+    if file.len() < 4 || file[0] != b'S' {
+        return None;
+    }
+    match file[2] {
+        b'P' => Some(Region::Japan),
+        b'U' => Some(Region::USA),
+        b'E' => Some(Region::Europe),
+        _ => None
+    }
+}
+
+pub(super) fn read_system_cnf(file: &mut dyn TrackFile) -> Result<String> {
 
     // Step 1: Read Primary Volume Descriptor (sector 16)
     let mut sector = [0u8; USER_DATA_SIZE];
-    read_sector(&mut file, 16, &mut sector)?;
+    read_sector(file, 16, &mut sector)?;
 
     // Root directory record starts at offset 156
     let root_dir_lba = u32::from_le_bytes([
@@ -72,7 +79,7 @@ pub fn read_system_cnf(path: &PathBuf) -> Result<String> {
 
     for i in 0..num_sectors {
         let mut buf = [0u8; USER_DATA_SIZE];
-        read_sector(&mut file, root_dir_lba + i as u32, &mut buf)?;
+        read_sector(file, root_dir_lba + i as u32, &mut buf)?;
         dir_data[i * USER_DATA_SIZE..(i + 1) * USER_DATA_SIZE].copy_from_slice(&buf);
     }
 
@@ -112,7 +119,7 @@ pub fn read_system_cnf(path: &PathBuf) -> Result<String> {
 
             for i in 0..num_sectors {
                 let mut buf = [0u8; USER_DATA_SIZE];
-                read_sector(&mut file, lba + i as u32, &mut buf)?;
+                read_sector(file, lba + i as u32, &mut buf)?;
                 file_data[i * USER_DATA_SIZE..(i + 1) * USER_DATA_SIZE]
                     .copy_from_slice(&buf);
             }
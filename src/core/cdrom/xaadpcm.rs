@@ -31,6 +31,7 @@
 //! contain the 28th sample from each block.
 
 use tracing::warn;
+use crate::core::savestate::{impl_state_enum, impl_state_struct};
 use crate::core::spu::adpcm;
 use crate::core::spu::adpcm::{FILTER_0_TABLE, FILTER_1_TABLE};
 
@@ -68,6 +69,8 @@ struct ResampleRingBuffer {
     idx: usize,
 }
 
+impl_state_struct!(ResampleRingBuffer { buffer, idx });
+
 impl ResampleRingBuffer {
     fn new() -> Self {
         Self { buffer: [0; 32], idx: 0 }
@@ -98,6 +101,9 @@ enum SampleRate {
     Half,
 }
 
+impl_state_enum!(ChannelMode { ChannelMode::Stereo, ChannelMode::Mono });
+impl_state_enum!(SampleRate { SampleRate::Normal, SampleRate::Half });
+
 #[derive(Debug, Clone)]
 pub struct XaAdpcmState {
     pub file: u8,
@@ -117,6 +123,24 @@ pub struct XaAdpcmState {
     sample_rate: SampleRate,
 }
 
+impl_state_struct!(XaAdpcmState {
+    file,
+    channel,
+    muted,
+    filter_enabled,
+    adpcm_buffer_l,
+    adpcm_buffer_r,
+    output_buffer_l,
+    output_buffer_r,
+    output_idx,
+    block_buffer_l,
+    block_buffer_r,
+    resample_ring_buffer_l,
+    resample_ring_buffer_r,
+    channel_mode,
+    sample_rate,
+});
+
 impl XaAdpcmState {
     pub fn new() -> Self {
         Self {
@@ -1,6 +1,7 @@
 use std::collections::BinaryHeap;
 use std::cmp::Ordering;
 use tracing::info;
+use crate::core::savestate::{impl_state_enum, SaveState, StateSync};
 use crate::core::Resettable;
 
 // Tipo di evento
@@ -97,6 +98,50 @@ pub struct Clock {
     clock_config: ClockConfig,
 }
 
+impl_state_enum!(EventType {
+    EventType::HBlankStart,
+    EventType::HBlankEnd,
+    EventType::RasterLineEnd,
+    EventType::Timer0,
+    EventType::Timer1,
+    EventType::Timer2,
+    EventType::SIO0Byte,
+    EventType::SIO0Ack,
+    EventType::GPUCommandCompleted,
+    EventType::Audio44100,
+});
+
+impl SaveState for Clock {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.section("CLOCK");
+        s.sync(&mut self.current_time);
+        let mut pal = self.clock_config.gpu_hz == ClockConfig::PAL_GPU_CLOCK;
+        s.sync(&mut pal);
+        let mut events_count = self.events.len();
+        s.sync(&mut events_count);
+        if s.is_loading() {
+            self.clock_config = if pal { ClockConfig::PAL } else { ClockConfig::NTSC };
+            self.events.clear();
+            for _ in 0..events_count {
+                let mut event = ClockEvent { event_type: EventType::HBlankStart, timestamp: 0 };
+                s.sync(&mut event.event_type);
+                s.sync(&mut event.timestamp);
+                if s.has_error() {
+                    break;
+                }
+                self.events.push(event);
+            }
+        }
+        else {
+            for event in self.events.iter() {
+                let (mut event_type,mut timestamp) = (event.event_type,event.timestamp);
+                s.sync(&mut event_type);
+                s.sync(&mut timestamp);
+            }
+        }
+    }
+}
+
 impl Clock {
     pub fn new(clock_config: ClockConfig) -> Self {
         Self {
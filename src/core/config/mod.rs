@@ -1,8 +1,8 @@
 use crate::core::controllers::ControllerButton;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
-use tracing::error;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
 use winit::keyboard::KeyCode;
 
 pub fn parse_keycode(s: &str) -> Option<KeyCode> {
@@ -179,6 +179,8 @@ pub struct KeyMapping {
     pub r2: String,
     pub l3: Option<String>,
     pub r3: Option<String>,
+    #[serde(default)]
+    pub analog: Option<String>,
     pub start: String,
     pub select: String,
     pub dpad_up: String,
@@ -204,6 +206,7 @@ impl KeyMapping {
             // Analog sticks
             l3: None,
             r3: None,
+            analog: None,
 
             // Start/Select
             start: "Enter".to_string(),
@@ -232,6 +235,7 @@ impl KeyMapping {
             // Analog sticks
             l3: None,
             r3: None,
+            analog: None,
 
             // Start/Select
             start: "Digit0".to_string(),
@@ -320,6 +324,10 @@ impl ControllerKeyMapping {
                 key_map.insert(key, ControllerButton::R3);
             }
         }
+        // DualShock analog mode button
+        if let Some(ref analog_key) = config.analog && let Some(key) = parse_keycode(analog_key) {
+            key_map.insert(key, ControllerButton::Analog);
+        }
 
         // Start/Select
         if let Some(key) = parse_keycode(&config.start) {
@@ -427,6 +435,16 @@ impl Default for AudioConfig {
 pub struct MemoryConfig {
     pub cpu_write_queue_enabled: bool,
 }
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum CpuBackend {
+    #[default]
+    Interpreter,
+    CachedInterpreter,
+}
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CpuConfig {
+    pub cpu_backend: CpuBackend,
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogConfig {
     pub log_file: Option<PathBuf>,
@@ -441,11 +459,34 @@ impl Default for LogConfig {
         }
     }
 }
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum DeinterlaceMode {
+    Weave,
+    Bob,
+    Blend,
+    #[default]
+    Adaptive,
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GPUConfig {
     pub command_delay_enabled: bool,
     pub rendering_type: Option<String>,
     pub start_full_screen: bool,
+    // internal resolution multiplier: 1 (native), 2, 4 or 8
+    pub internal_resolution: usize,
+    // PGXP: sub-pixel precision for the vertices projected by the GTE
+    pub pgxp_enabled: bool,
+    // PGXP: perspective correct texture mapping
+    pub pgxp_perspective_correct: bool,
+    // GTE widescreen hack: 3D scenes are projected for 16:9 and displayed at 16:9
+    pub widescreen_hack: bool,
+    // displays at 16:9 the games that render anamorphic 16:9 by themselves
+    pub widescreen_anamorphic: bool,
+    // GPU texture cache emulation: stale texels while drawing a primitive, slower
+    pub texture_cache_enabled: bool,
+    // how the two fields of the 480 lines interlaced modes are combined in the displayed frame
+    pub deinterlace_mode: DeinterlaceMode,
 }
 
 impl Default for GPUConfig {
@@ -454,33 +495,178 @@ impl Default for GPUConfig {
             command_delay_enabled: false,
             rendering_type: Some("Bilinear".to_string()),
             start_full_screen: false,
+            internal_resolution: 1,
+            pgxp_enabled: false,
+            pgxp_perspective_correct: false,
+            widescreen_hack: false,
+            widescreen_anamorphic: false,
+            texture_cache_enabled: false,
+            deinterlace_mode: DeinterlaceMode::default(),
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CdromConfig {
     pub show_cdrom_access: bool,
+    // instant seeks and faster data reads: shorter load times, but some games need the real timings
+    pub fast_cd_enabled: bool,
+    // data read speed multiplier in fast CD mode: 2..8
+    pub fast_cd_read_speed: usize,
 }
 
 impl Default for CdromConfig {
     fn default() -> Self {
         Self {
             show_cdrom_access: true,
+            fast_cd_enabled: false,
+            fast_cd_read_speed: 4,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CheatsConfig {
     pub cheats_enabled: bool,
     pub cheats_codes: Vec<String>,
+    // libretro .cht file
+    pub cheats_file: Option<String>,
+    // cheat database: <cheats_path>/<serial>.cht files
+    pub cheats_path: String,
+}
+
+impl Default for CheatsConfig {
+    fn default() -> Self {
+        Self {
+            cheats_enabled: false,
+            cheats_codes: Vec::new(),
+            cheats_file: None,
+            cheats_path: "cheats".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveStateConfig {
+    pub states_path: String,
+}
+
+impl Default for SaveStateConfig {
+    fn default() -> Self {
+        Self {
+            states_path: "states".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotConfig {
+    pub snapshots_path: String,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            snapshots_path: "snapshots".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewindConfig {
+    pub rewind_enabled: bool,
+    pub snapshot_interval_in_frames: u32,
+    pub depth_in_snapshots: usize,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        Self {
+            rewind_enabled: false,
+            snapshot_interval_in_frames: 5,
+            depth_in_snapshots: 120,
+        }
+    }
+}
+
+/*
+GDB remote serial protocol stub, listening on localhost
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GdbConfig {
+    pub gdb_enabled: bool,
+    pub gdb_port: u16,
+}
+
+impl Default for GdbConfig {
+    fn default() -> Self {
+        Self {
+            gdb_enabled: false,
+            gdb_port: 2345,
+        }
+    }
+}
+
+/*
+Per-game overrides, see Config::with_game_overrides
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameConfig {
+    pub games_path: String,
+    pub compatibility_db_enabled: bool,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            games_path: "games".to_string(),
+            compatibility_db_enabled: true,
+        }
+    }
+}
+
+/*
+Built-in compatibility database: overrides checked on the games, by disc serial (for now the games requiring a DualShock)
+ */
+static GAMES_DB: &str = include_str!("../../../resources/games_db.yaml");
+
+#[derive(Debug, Deserialize)]
+struct GameDbEntry {
+    title: String,
+    config: serde_yaml::Value,
+}
+
+/*
+Headless mode options, set from command line only
+ */
+#[derive(Debug, Clone, Default)]
+pub struct HeadlessConfig {
+    pub max_frames: Option<u64>,
+    pub max_cycles: Option<u64>,
+    pub tty_stop_string: Option<String>,
+    pub dump_frames: Vec<u64>,
+    pub dump_vram: bool,
+}
+
+/*
+Input movie to record or to play, set from command line only
+ */
+#[derive(Debug, Clone)]
+pub enum MovieConfig {
+    Record(PathBuf),
+    Play(PathBuf),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize,Default)]
 pub struct Config {
     #[serde(skip)]
     pub file_config: Option<PathBuf>,
+    #[serde(skip)]
+    pub headless_config: Option<HeadlessConfig>,
+    #[serde(skip)]
+    pub movie_config: Option<MovieConfig>,
     pub disc_path: Option<String>,
     pub bios_path: Option<String>,
     pub region_policy: RegionPolicyConfig,
@@ -493,6 +679,35 @@ pub struct Config {
     pub gpu_config: GPUConfig,
     pub cdrom_config: CdromConfig,
     pub cheats_config: CheatsConfig,
+    #[serde(default)]
+    pub save_state_config: SaveStateConfig,
+    #[serde(default)]
+    pub snapshot_config: SnapshotConfig,
+    #[serde(default)]
+    pub game_config: GameConfig,
+    #[serde(default)]
+    pub rewind_config: RewindConfig,
+    #[serde(default)]
+    pub gdb_config: GdbConfig,
+    #[serde(default)]
+    pub cpu_config: CpuConfig,
+}
+
+/*
+Overlay values replace the base ones, except mappings that are merged key by key
+ */
+fn merge_yaml(base:&mut serde_yaml::Value,overlay:serde_yaml::Value) {
+    match (base,overlay) {
+        (serde_yaml::Value::Mapping(base),serde_yaml::Value::Mapping(overlay)) => {
+            for (key,value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_yaml(base_value,value),
+                    None => { base.insert(key,value); }
+                }
+            }
+        }
+        (base,overlay) => *base = overlay,
+    }
 }
 
 impl Config {
@@ -511,6 +726,64 @@ impl Config {
             })
     }
 
+    /*
+    Returns the configuration to use for the disc with the given serial (i.e. SLUS_005.94):
+    the built-in compatibility database entry, if enabled, and then the user overlay <games_path>/<serial>.yaml
+    are deep-merged onto this configuration. An overlay is a partial configuration, i.e.
+      controllers:
+        controller_1:
+          controller_type: Analog
+    An invalid overlay is ignored.
+     */
+    pub fn with_game_overrides(&self,serial:&str) -> Config {
+        let mut overlays = Vec::new();
+        if self.game_config.compatibility_db_enabled {
+            match serde_yaml::from_str::<HashMap<String,GameDbEntry>>(GAMES_DB) {
+                Ok(mut db) => {
+                    if let Some(entry) = db.remove(serial) {
+                        overlays.push((format!("compatibility database entry '{}'",entry.title),entry.config));
+                    }
+                }
+                Err(e) => error!("Invalid compatibility database: {}",e),
+            }
+        }
+        let game_path = Path::new(&self.game_config.games_path).join(format!("{}.yaml",serial));
+        if game_path.exists() {
+            match std::fs::read_to_string(&game_path).map_err(|e| e.to_string()).and_then(|text| serde_yaml::from_str(&text).map_err(|e| e.to_string())) {
+                Ok(overlay) => overlays.push((format!("game configuration '{}'",game_path.display()),overlay)),
+                Err(e) => error!("Cannot read game configuration '{}': {}",game_path.display(),e),
+            }
+        }
+        if overlays.is_empty() {
+            return self.clone();
+        }
+
+        let mut merged = match serde_yaml::to_value(self) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("Error serializing configuration: {}",e);
+                return self.clone();
+            }
+        };
+        let mut config = self.clone();
+        for (name,overlay) in overlays {
+            let mut value = merged.clone();
+            merge_yaml(&mut value,overlay);
+            match serde_yaml::from_value::<Config>(value.clone()) {
+                Ok(game_config) => {
+                    info!("Applied {} for {}",name,serial);
+                    config = game_config;
+                    merged = value;
+                }
+                Err(e) => error!("Ignored invalid {} for {}: {}",name,serial,e),
+            }
+        }
+        config.file_config = self.file_config.clone();
+        config.headless_config = self.headless_config.clone();
+        config.movie_config = self.movie_config.clone();
+        config
+    }
+
     pub fn save(&self, path: &PathBuf) -> Result<(), String> {
         match serde_yaml::to_string(self) {
             Ok(text) => {
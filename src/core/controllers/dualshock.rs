@@ -0,0 +1,251 @@
+use crate::core::savestate::{impl_state_enum, impl_state_struct};
+use tracing::{debug, info};
+
+const DIGITAL_ID : u8 = 0x41;
+const ANALOG_ID : u8 = 0x73;
+const CONFIG_ID : u8 = 0xF3;
+const DISABLED_MOTOR : u8 = 0xFF;
+
+#[derive(Copy, Clone, Debug)]
+pub enum ControllerAxis {
+    RightX,
+    RightY,
+    LeftX,
+    LeftY,
+}
+
+/*
+DualShock (SCPH-1200) protocol, byte 0 (01h) is handled by the controller.
+  Send Reply Comment
+  01h  Hi-Z  Controller address
+  4xh  idlo  Command: 41h=digital, 73h=analog, F3h=config mode
+  00h  5Ah   idhi
+  ...  ...   Command parameters / reply (2 bytes in digital mode, 6 bytes otherwise)
+
+Commands
+  42h  Read buttons (and sticks in analog mode), parameters are the rumble motors values
+  43h  Enter/Exit config mode (param 01h=enter, 00h=exit). Outside config mode it replies like 42h
+  44h  Set LED state (param 00h=digital, 01h=analog, second param 03h=lock the analog button) (config mode only)
+  45h  Get LED state (config mode only), first byte 01h=DualShock (03h on DualShock 2)
+  46h  Get variable response A (config mode only)
+  47h  Get whatever values (config mode only)
+  4Ch  Get variable response B (config mode only)
+  4Dh  Set rumble mapping: each parameter byte maps a 42h parameter to a motor (00h=small, 01h=large, FFh=disabled)
+ */
+#[derive(Debug)]
+pub(super) struct DualShock {
+    analog_mode: bool,
+    analog_locked: bool,
+    config_mode: bool,
+    rumble_map: [u8;6],
+    // small, large
+    motors: [u8;2],
+    rumble_changed: bool,
+    // right x, right y, left x, left y: 00h=left/up, 80h=center, FFh=right/down
+    axes: [u8;4],
+    command: u8,
+    reply_id: u8,
+    index: usize,
+    params: [u8;6],
+}
+
+impl_state_enum!(ControllerAxis { ControllerAxis::RightX, ControllerAxis::RightY, ControllerAxis::LeftX, ControllerAxis::LeftY });
+impl_state_struct!(DualShock { analog_mode, analog_locked, config_mode, rumble_map, motors, axes, command, reply_id, index, params });
+
+impl DualShock {
+    // a DualShock powers on in digital mode
+    pub fn new() -> Self {
+        Self {
+            analog_mode: false,
+            analog_locked: false,
+            config_mode: false,
+            rumble_map: [DISABLED_MOTOR;6],
+            motors: [0;2],
+            rumble_changed: false,
+            axes: [0x80;4],
+            command: 0,
+            reply_id: DIGITAL_ID,
+            index: 0,
+            params: [0;6],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        let axes = self.axes;
+        *self = Self::new();
+        self.axes = axes;
+        self.rumble_changed = true;
+    }
+
+    pub fn is_analog_mode(&self) -> bool {
+        self.analog_mode
+    }
+
+    pub fn toggle_analog_mode(&mut self) {
+        if self.analog_locked {
+            debug!("DualShock analog button is locked by the game");
+            return;
+        }
+        self.analog_mode = !self.analog_mode;
+        info!("DualShock analog mode {}",if self.analog_mode { "on" } else { "off" });
+    }
+
+    pub fn set_axis(&mut self,axis:ControllerAxis,value:u8) {
+        self.axes[axis as usize] = value;
+    }
+
+    /*
+    Returns the (small,large) motors values if they changed since the last call
+     */
+    pub fn take_rumble(&mut self) -> Option<(u8,u8)> {
+        if self.rumble_changed {
+            self.rumble_changed = false;
+            Some((self.motors[0],self.motors[1]))
+        }
+        else {
+            None
+        }
+    }
+
+    fn id(&self) -> u8 {
+        if self.config_mode {
+            CONFIG_ID
+        }
+        else if self.analog_mode {
+            ANALOG_ID
+        }
+        else {
+            DIGITAL_ID
+        }
+    }
+
+    fn reply_len(&self) -> usize {
+        ((self.reply_id & 0x0F) as usize) << 1
+    }
+
+    /*
+    Transfers the command byte following the address byte.
+    Returns the reply and true if the transfer continues.
+     */
+    pub fn transfer(&mut self,cmd:u8,buttons:u16) -> (u8,bool) {
+        match self.index {
+            0 => {
+                self.command = cmd;
+                self.reply_id = self.id();
+                self.params = [0;6];
+                self.index = 1;
+                let valid = match cmd {
+                    0x42 | 0x43 => true,
+                    0x40..=0x4F => self.config_mode,
+                    _ => false
+                };
+                if !valid {
+                    debug!("DualShock unsupported command {:02X} (config mode = {})",cmd,self.config_mode);
+                    self.index = 0;
+                }
+                (self.reply_id,valid)
+            }
+            1 => {
+                self.index = 2;
+                (0x5A,true)
+            }
+            _ => {
+                let pos = self.index - 2;
+                self.params[pos] = cmd;
+                let reply = self.reply(pos,cmd,buttons);
+                let last = pos + 1 >= self.reply_len();
+                if last {
+                    self.end_command();
+                    self.index = 0;
+                }
+                else {
+                    self.index += 1;
+                }
+                (reply,!last)
+            }
+        }
+    }
+
+    fn poll_reply(&self,pos:usize,buttons:u16) -> u8 {
+        match pos {
+            0 => buttons as u8,
+            1 => (buttons >> 8) as u8,
+            _ => self.axes[pos - 2],
+        }
+    }
+
+    fn reply(&mut self,pos:usize,cmd:u8,buttons:u16) -> u8 {
+        match self.command {
+            0x42 => {
+                self.set_motor(pos,cmd);
+                self.poll_reply(pos,buttons)
+            }
+            0x43 if self.reply_id != CONFIG_ID => self.poll_reply(pos,buttons),
+            0x44 => {
+                match pos {
+                    0 if cmd <= 0x01 => {
+                        self.analog_mode = cmd == 0x01;
+                        info!("DualShock analog mode set to {} by the game",self.analog_mode);
+                    }
+                    1 => self.analog_locked = cmd == 0x03,
+                    _ => {}
+                }
+                0x00
+            }
+            0x45 => [0x01,0x02,self.analog_mode as u8,0x02,0x01,0x00][pos],
+            0x46 => match self.params[0] {
+                0x00 => [0x00,0x00,0x01,0x02,0x00,0x0A][pos],
+                0x01 => [0x00,0x00,0x01,0x01,0x01,0x14][pos],
+                _ => 0x00
+            }
+            0x47 => [0x00,0x00,0x02,0x00,0x01,0x00][pos],
+            0x4C => match (pos,self.params[0]) {
+                (3,0x00) => 0x04,
+                (3,0x01) => 0x07,
+                _ => 0x00
+            }
+            0x4D => {
+                let old = self.rumble_map[pos];
+                self.rumble_map[pos] = cmd;
+                old
+            }
+            _ => 0x00
+        }
+    }
+
+    fn set_motor(&mut self,pos:usize,value:u8) {
+        let motor = match self.rumble_map[pos] {
+            0x00 => 0,
+            0x01 => 1,
+            _ => return
+        };
+        // the small motor is only on/off
+        let value = if motor == 0 { if value & 1 != 0 { 0xFF } else { 0x00 } } else { value };
+        if self.motors[motor] != value {
+            self.motors[motor] = value;
+            self.rumble_changed = true;
+        }
+    }
+
+    fn end_command(&mut self) {
+        match self.command {
+            0x43 => {
+                let config_mode = self.params[0] == 0x01;
+                if config_mode != self.config_mode {
+                    debug!("DualShock config mode {}",if config_mode { "entered" } else { "exited" });
+                }
+                self.config_mode = config_mode;
+            }
+            0x4D => {
+                // motors not mapped anymore are stopped
+                for (motor,id) in [0x00u8,0x01].iter().enumerate() {
+                    if !self.rumble_map.contains(id) && self.motors[motor] != 0 {
+                        self.motors[motor] = 0;
+                        self.rumble_changed = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
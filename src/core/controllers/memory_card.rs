@@ -3,6 +3,7 @@ use std::io;
 use std::io::{ErrorKind, Read, Write};
 use tracing::{info, warn};
 use crate::core::controllers::MemoryCardCommand;
+use crate::core::savestate::{SaveState, StateSync};
 
 #[derive(Debug)]
 pub struct MemoryCard {
@@ -17,6 +18,20 @@ pub struct MemoryCard {
     modified: bool,
 }
 
+/*
+The memory card contents are not part of the state: they belong to the card file and must survive a state loading.
+Only the protocol status is saved.
+ */
+impl SaveState for MemoryCard {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.memory_card_command);
+        s.sync(&mut self.flag);
+        s.sync(&mut self.sector_number);
+        s.sync(&mut self.checksum);
+        s.sync(&mut self.bytes_count);
+    }
+}
+
 impl MemoryCard {
     const MEM_SIZE : usize = 128 * 1024;
 
@@ -0,0 +1,525 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/*
+Memory card filesystem: 16 blocks of 8KB (64 frames of 128 bytes each), block 0 holds the directory.
+Block 0
+  Frame 0      Header: "MC", zero filled, checksum at 7Fh (all the previous bytes XORed)
+  Frame 1..15  Directory entries, one per block 1..15
+    00h-03h  Block allocation state
+               000000A0h free
+               00000051h/00000052h/00000053h in use: first/middle/last block of a save
+               000000A1h/000000A2h/000000A3h deleted: first/middle/last block of a save
+    04h-07h  Save size in bytes (first block only, 2000h per block)
+    08h-09h  Next block (0..14) or FFFFh for the last block
+    0Ah-1Eh  File name, zero terminated (i.e. BASLUS-00594GAME0: region, product code, identifier)
+    7Fh      Checksum
+  Frame 16..35 Broken sectors list (FFFFFFFFh=none)
+  Frame 36..62 Broken sectors replacement data and unused frames
+  Frame 63     Write test: same as frame 0
+Block 1..15, first block of a save
+  Frame 0      Title frame
+    00h-01h  "SC"
+    02h      Icon display flag: 11h..13h = 1..3 animation frames
+    03h      Block count
+    04h-43h  Title in Shift-JIS (max 32 characters, zero terminated)
+    60h-7Fh  Icon palette (16 colors, 16bit)
+  Frame 1..3   Icon bitmaps (16x16, 4bit)
+  Frame 4..    Save data
+Unused blocks are FFh filled.
+
+Single save formats
+  .mcs  Directory entry frame followed by the save blocks
+  .psv  PS3 virtual memory card export: 84h bytes header (file name at 64h, size at 40h, data offset at 44h) followed by the save blocks.
+        The signature is not generated, so exported files must be resigned to be used on a PS3.
+  .gme  DexDrive card: F40h bytes header (directory states/next pointers and 256 bytes comments per entry) followed by a whole card,
+        some tools append padding after the card
+ */
+pub const CARD_SIZE : usize = 128 * 1024;
+pub const BLOCK_SIZE : usize = 8 * 1024;
+pub const DIRECTORY_ENTRIES : usize = 15;
+const FRAME_SIZE : usize = 128;
+const FILE_NAME_OFFSET : usize = 0x0A;
+const FILE_NAME_LEN : usize = 20;
+const TITLE_OFFSET : usize = 0x04;
+const TITLE_LEN : usize = 64;
+const BROKEN_SECTORS_FRAMES : std::ops::Range<usize> = 16..36;
+const WRITE_TEST_FRAME : usize = 63;
+const NO_NEXT_BLOCK : u16 = 0xFFFF;
+
+const STATE_FREE : u32 = 0xA0;
+const STATE_FIRST : u32 = 0x51;
+const STATE_MIDDLE : u32 = 0x52;
+const STATE_LAST : u32 = 0x53;
+// first/middle/last deleted block: A1h..A3h
+const DELETED_STATE_OFFSET : u32 = 0x50;
+
+const GME_MAGIC : &[u8] = b"123-456-STD";
+const GME_HEADER_SIZE : usize = 0xF40;
+const GME_COMMENTS_OFFSET : usize = 0x40;
+const GME_COMMENT_SIZE : usize = 256;
+
+const PSV_MAGIC : &[u8;4] = b"\0VSP";
+const PSV_HEADER_SIZE : usize = 0x84;
+const PSV_PS1_TYPE : u32 = 1;
+
+#[derive(Debug,Clone)]
+pub struct SaveEntry {
+    // directory entry of the first block: 1..15
+    pub slot: usize,
+    pub blocks: Vec<usize>,
+    pub deleted: bool,
+    // false if the chain is broken (blocks of a deleted save reused by another one)
+    pub complete: bool,
+    pub file_name: String,
+    pub title: String,
+    pub icon_frames: u8,
+}
+
+impl SaveEntry {
+    pub fn product_code(&self) -> &str {
+        self.file_name.get(2..12).unwrap_or("")
+    }
+
+    pub fn identifier(&self) -> &str {
+        self.file_name.get(12..).unwrap_or("")
+    }
+
+    pub fn region(&self) -> &str {
+        match self.file_name.get(0..2) {
+            Some("BA") => "USA",
+            Some("BE") => "Europe",
+            Some("BI") => "Japan",
+            _ => "?"
+        }
+    }
+}
+
+/*
+A save extracted from a card: its directory entry and its blocks
+ */
+#[derive(Debug,Clone)]
+pub struct SaveFile {
+    directory_frame: [u8;FRAME_SIZE],
+    data: Vec<u8>,
+}
+
+#[derive(Debug,Clone)]
+pub struct MemoryCardImage {
+    memory: Vec<u8>,
+}
+
+fn le32(b:&[u8]) -> u32 {
+    u32::from_le_bytes([b[0],b[1],b[2],b[3]])
+}
+
+fn frame_checksum(frame:&[u8]) -> u8 {
+    frame[..FRAME_SIZE - 1].iter().fold(0,|c,b| c ^ b)
+}
+
+fn has_extension(path:&Path,ext:&str) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case(ext))
+}
+
+fn write_file(path:&Path,data:&[u8]) -> Result<(),String> {
+    File::create(path).and_then(|mut f| f.write_all(data)).map_err(|e| format!("Cannot write '{}': {}",path.display(),e))
+}
+
+impl MemoryCardImage {
+    pub fn new_formatted() -> Self {
+        let mut card = Self { memory: vec![0;CARD_SIZE] };
+        card.format();
+        card
+    }
+
+    /*
+    Raw images (mcd, mcr, ...) or DexDrive (gme) images
+     */
+    pub fn open(path:&Path) -> Result<Self,String> {
+        let data = std::fs::read(path).map_err(|e| format!("Cannot read memory card '{}': {}",path.display(),e))?;
+        Self::from_bytes(&data).map_err(|e| format!("Invalid memory card '{}': {}",path.display(),e))
+    }
+
+    fn from_bytes(data:&[u8]) -> Result<Self,String> {
+        let memory = if data.len() >= GME_HEADER_SIZE + CARD_SIZE && data.starts_with(GME_MAGIC) {
+            &data[GME_HEADER_SIZE..GME_HEADER_SIZE + CARD_SIZE]
+        }
+        else if data.len() == CARD_SIZE {
+            data
+        }
+        else {
+            return Err(format!("unexpected size {} bytes",data.len()));
+        };
+        if &memory[0..2] != b"MC" {
+            return Err("missing MC header".to_string());
+        }
+        Ok(Self { memory: memory.to_vec() })
+    }
+
+    pub fn save(&self,path:&Path) -> Result<(),String> {
+        if has_extension(path,"gme") {
+            write_file(path,&self.to_gme())
+        }
+        else {
+            write_file(path,&self.memory)
+        }
+    }
+
+    fn to_gme(&self) -> Vec<u8> {
+        let mut gme = vec![0u8;GME_HEADER_SIZE];
+        gme[..GME_MAGIC.len()].copy_from_slice(GME_MAGIC);
+        gme[0x12] = 0x01;
+        gme[0x14] = 0x01;
+        gme[0x15] = b'M';
+        for slot in 1..=DIRECTORY_ENTRIES {
+            let frame = self.frame(0,slot);
+            gme[0x15 + slot] = frame[0];
+            gme[0x25 + slot] = frame[8];
+        }
+        for save in self.saves().iter().filter(|s| !s.deleted) {
+            let comment = GME_COMMENTS_OFFSET + (save.slot - 1) * GME_COMMENT_SIZE;
+            let title = save.title.as_bytes();
+            let len = title.len().min(GME_COMMENT_SIZE - 1);
+            gme[comment..comment + len].copy_from_slice(&title[..len]);
+        }
+        gme.extend(&self.memory);
+        gme
+    }
+
+    fn frame(&self,block:usize,frame:usize) -> &[u8] {
+        let offset = block * BLOCK_SIZE + frame * FRAME_SIZE;
+        &self.memory[offset..offset + FRAME_SIZE]
+    }
+
+    fn frame_mut(&mut self,block:usize,frame:usize) -> &mut [u8] {
+        let offset = block * BLOCK_SIZE + frame * FRAME_SIZE;
+        &mut self.memory[offset..offset + FRAME_SIZE]
+    }
+
+    fn block_mut(&mut self,block:usize) -> &mut [u8] {
+        &mut self.memory[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE]
+    }
+
+    fn update_checksum(&mut self,block:usize,frame:usize) {
+        let frame = self.frame_mut(block,frame);
+        frame[FRAME_SIZE - 1] = frame_checksum(frame);
+    }
+
+    fn entry_state(&self,slot:usize) -> u32 {
+        le32(self.frame(0,slot))
+    }
+
+    fn set_entry_state(&mut self,slot:usize,state:u32) {
+        self.frame_mut(0,slot)[0..4].copy_from_slice(&state.to_le_bytes());
+        self.update_checksum(0,slot);
+    }
+
+    fn entry_next_pointer(&self,slot:usize) -> u16 {
+        let frame = self.frame(0,slot);
+        u16::from_le_bytes([frame[8],frame[9]])
+    }
+
+    /*
+    Next block of the chain: pointers 0..14 for the blocks 1..15, none for FFFFh and for corrupt values
+     */
+    fn entry_next(&self,slot:usize) -> Option<usize> {
+        match self.entry_next_pointer(slot) as usize {
+            next if next < DIRECTORY_ENTRIES => Some(next + 1),
+            _ => None,
+        }
+    }
+
+    pub fn format(&mut self) {
+        self.memory.fill(0);
+        self.frame_mut(0,0)[0..2].copy_from_slice(b"MC");
+        self.update_checksum(0,0);
+        for slot in 1..=DIRECTORY_ENTRIES {
+            self.clear_entry(slot);
+        }
+        for frame in BROKEN_SECTORS_FRAMES {
+            let f = self.frame_mut(0,frame);
+            f[0..4].fill(0xFF);
+            f[8..10].fill(0xFF);
+            self.update_checksum(0,frame);
+        }
+        self.memory[BLOCK_SIZE..].fill(0xFF);
+        let header = self.frame(0,0).to_vec();
+        self.frame_mut(0,WRITE_TEST_FRAME).copy_from_slice(&header);
+    }
+
+    fn clear_entry(&mut self,slot:usize) {
+        let frame = self.frame_mut(0,slot);
+        frame.fill(0);
+        frame[0..4].copy_from_slice(&STATE_FREE.to_le_bytes());
+        frame[8..10].copy_from_slice(&NO_NEXT_BLOCK.to_le_bytes());
+        self.update_checksum(0,slot);
+    }
+
+    pub fn free_blocks(&self) -> usize {
+        (1..=DIRECTORY_ENTRIES).filter(|slot| self.is_entry_free(*slot)).count()
+    }
+
+    // deleted blocks can be overwritten
+    fn is_entry_free(&self,slot:usize) -> bool {
+        let state = self.entry_state(slot);
+        state == STATE_FREE || (STATE_FIRST + DELETED_STATE_OFFSET..=STATE_LAST + DELETED_STATE_OFFSET).contains(&state)
+    }
+
+    /*
+    Saves found following the block chains of the directory, deleted ones included
+     */
+    pub fn saves(&self) -> Vec<SaveEntry> {
+        let mut saves = Vec::new();
+        for slot in 1..=DIRECTORY_ENTRIES {
+            let deleted = match self.entry_state(slot) {
+                STATE_FIRST => false,
+                s if s == STATE_FIRST + DELETED_STATE_OFFSET => true,
+                _ => continue
+            };
+            let offset = if deleted { DELETED_STATE_OFFSET } else { 0 };
+            let mut blocks = vec![slot];
+            let mut complete = false;
+            let mut current = slot;
+            loop {
+                let Some(next) = self.entry_next(current) else {
+                    complete = self.entry_next_pointer(current) == NO_NEXT_BLOCK && (blocks.len() == 1 || self.entry_state(current) == STATE_LAST + offset);
+                    break;
+                };
+                if blocks.contains(&next) {
+                    break;
+                }
+                let state = self.entry_state(next);
+                if state != STATE_MIDDLE + offset && state != STATE_LAST + offset {
+                    break;
+                }
+                blocks.push(next);
+                current = next;
+            }
+            let dir = self.frame(0,slot);
+            let name_bytes = &dir[FILE_NAME_OFFSET..FILE_NAME_OFFSET + FILE_NAME_LEN];
+            let name_len = name_bytes.iter().position(|b| *b == 0).unwrap_or(FILE_NAME_LEN);
+            let title_frame = self.frame(slot,0);
+            let icon_frames = if &title_frame[0..2] == b"SC" { title_frame[2] & 0x03 } else { 0 };
+            saves.push(SaveEntry {
+                slot,
+                blocks,
+                deleted,
+                complete,
+                file_name: String::from_utf8_lossy(&name_bytes[..name_len]).to_string(),
+                title: decode_shift_jis(&title_frame[TITLE_OFFSET..TITLE_OFFSET + TITLE_LEN]),
+                icon_frames,
+            });
+        }
+        saves
+    }
+
+    fn find_save(&self,slot:usize) -> Result<SaveEntry,String> {
+        self.saves().into_iter().find(|s| s.slot == slot).ok_or_else(|| format!("No save starts at slot {}",slot))
+    }
+
+    pub fn export_save(&self,slot:usize) -> Result<SaveFile,String> {
+        let save = self.find_save(slot)?;
+        let mut directory_frame = [0u8;FRAME_SIZE];
+        directory_frame.copy_from_slice(self.frame(0,slot));
+        directory_frame[0..4].copy_from_slice(&STATE_FIRST.to_le_bytes());
+        directory_frame[4..8].copy_from_slice(&((save.blocks.len() * BLOCK_SIZE) as u32).to_le_bytes());
+        directory_frame[8..10].copy_from_slice(&NO_NEXT_BLOCK.to_le_bytes());
+        directory_frame[FRAME_SIZE - 1] = frame_checksum(&directory_frame);
+        let data = save.blocks.iter().flat_map(|b| self.memory[b * BLOCK_SIZE..(b + 1) * BLOCK_SIZE].iter().copied()).collect();
+        Ok(SaveFile { directory_frame, data })
+    }
+
+    /*
+    Writes the save in the first free (or deleted) blocks: returns the slot of its first block
+     */
+    pub fn import_save(&mut self,save:&SaveFile) -> Result<usize,String> {
+        let name = save.file_name();
+        if self.saves().iter().any(|s| !s.deleted && s.file_name == name) {
+            return Err(format!("A save named '{}' already exists",name));
+        }
+        let blocks_needed = save.data.len() / BLOCK_SIZE;
+        // never used blocks first, so that deleted saves can still be restored
+        let free : Vec<usize> = (1..=DIRECTORY_ENTRIES).filter(|slot| self.entry_state(*slot) == STATE_FREE)
+            .chain((1..=DIRECTORY_ENTRIES).filter(|slot| self.entry_state(*slot) != STATE_FREE && self.is_entry_free(*slot)))
+            .take(blocks_needed)
+            .collect();
+        if free.len() < blocks_needed {
+            return Err(format!("Not enough free blocks: {} needed, {} available",blocks_needed,self.free_blocks()));
+        }
+        let mut blocks = free;
+        blocks.sort();
+        for (i,&block) in blocks.iter().enumerate() {
+            let state = match i {
+                0 => STATE_FIRST,
+                _ if i == blocks.len() - 1 => STATE_LAST,
+                _ => STATE_MIDDLE,
+            };
+            let next = blocks.get(i + 1).map_or(NO_NEXT_BLOCK,|b| (*b - 1) as u16);
+            let frame = self.frame_mut(0,block);
+            if i == 0 {
+                frame.copy_from_slice(&save.directory_frame);
+            }
+            else {
+                frame.fill(0);
+            }
+            frame[0..4].copy_from_slice(&state.to_le_bytes());
+            frame[8..10].copy_from_slice(&next.to_le_bytes());
+            self.update_checksum(0,block);
+            self.block_mut(block).copy_from_slice(&save.data[i * BLOCK_SIZE..(i + 1) * BLOCK_SIZE]);
+        }
+        Ok(blocks[0])
+    }
+
+    pub fn delete_save(&mut self,slot:usize) -> Result<SaveEntry,String> {
+        let save = self.find_save(slot)?;
+        if save.deleted {
+            return Err(format!("Save at slot {} is already deleted",slot));
+        }
+        for block in save.blocks.iter() {
+            let state = self.entry_state(*block);
+            self.set_entry_state(*block,state + DELETED_STATE_OFFSET);
+        }
+        Ok(save)
+    }
+
+    pub fn undelete_save(&mut self,slot:usize) -> Result<SaveEntry,String> {
+        let save = self.find_save(slot)?;
+        if !save.deleted {
+            return Err(format!("Save at slot {} is not deleted",slot));
+        }
+        if self.saves().iter().any(|s| !s.deleted && s.file_name == save.file_name) {
+            return Err(format!("A save named '{}' already exists",save.file_name));
+        }
+        if !save.complete {
+            return Err(format!("Save at slot {} cannot be restored: its blocks have been reused",slot));
+        }
+        for block in save.blocks.iter() {
+            let state = self.entry_state(*block);
+            self.set_entry_state(*block,state - DELETED_STATE_OFFSET);
+        }
+        Ok(save)
+    }
+}
+
+impl SaveFile {
+    pub fn file_name(&self) -> String {
+        let name = &self.directory_frame[FILE_NAME_OFFSET..FILE_NAME_OFFSET + FILE_NAME_LEN];
+        let len = name.iter().position(|b| *b == 0).unwrap_or(FILE_NAME_LEN);
+        String::from_utf8_lossy(&name[..len]).to_string()
+    }
+
+    pub fn blocks(&self) -> usize {
+        self.data.len() / BLOCK_SIZE
+    }
+
+    fn new(file_name:&[u8],data:Vec<u8>) -> Result<Self,String> {
+        if data.is_empty() || !data.len().is_multiple_of(BLOCK_SIZE) || data.len() > DIRECTORY_ENTRIES * BLOCK_SIZE {
+            return Err(format!("invalid save size {} bytes",data.len()));
+        }
+        let mut directory_frame = [0u8;FRAME_SIZE];
+        directory_frame[0..4].copy_from_slice(&STATE_FIRST.to_le_bytes());
+        directory_frame[4..8].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        directory_frame[8..10].copy_from_slice(&NO_NEXT_BLOCK.to_le_bytes());
+        let len = file_name.iter().position(|b| *b == 0).unwrap_or(file_name.len()).min(FILE_NAME_LEN);
+        directory_frame[FILE_NAME_OFFSET..FILE_NAME_OFFSET + len].copy_from_slice(&file_name[..len]);
+        directory_frame[FRAME_SIZE - 1] = frame_checksum(&directory_frame);
+        Ok(Self { directory_frame, data })
+    }
+
+    /*
+    Reads a single save file (mcs, psv) or all the saves of a card image (gme, mcd, ...)
+     */
+    pub fn load(path:&Path) -> Result<Vec<SaveFile>,String> {
+        let data = std::fs::read(path).map_err(|e| format!("Cannot read '{}': {}",path.display(),e))?;
+        let invalid = |e:String| format!("Invalid save file '{}': {}",path.display(),e);
+        if has_extension(path,"mcs") {
+            if data.len() < FRAME_SIZE {
+                return Err(invalid(format!("size {} bytes",data.len())));
+            }
+            let save = Self::new(&data[FILE_NAME_OFFSET..FILE_NAME_OFFSET + FILE_NAME_LEN],data[FRAME_SIZE..].to_vec()).map_err(invalid)?;
+            Ok(vec![save])
+        }
+        else if has_extension(path,"psv") {
+            if data.len() < PSV_HEADER_SIZE || &data[0..4] != PSV_MAGIC || le32(&data[0x3C..]) != PSV_PS1_TYPE {
+                return Err(invalid("not a PS1 PSV save".to_string()));
+            }
+            let size = le32(&data[0x40..]) as usize;
+            let offset = le32(&data[0x44..]) as usize;
+            let save_data = data.get(offset..offset + size).ok_or_else(|| invalid(format!("truncated data, {} bytes at {:X}h",size,offset)))?;
+            let save = Self::new(&data[0x64..0x64 + FILE_NAME_LEN],save_data.to_vec()).map_err(invalid)?;
+            Ok(vec![save])
+        }
+        else {
+            let card = MemoryCardImage::from_bytes(&data).map_err(invalid)?;
+            card.saves().iter().filter(|s| !s.deleted).map(|s| card.export_save(s.slot)).collect()
+        }
+    }
+
+    /*
+    Writes the save as mcs, psv or as a gme card holding only this save
+     */
+    pub fn save(&self,path:&Path) -> Result<(),String> {
+        if has_extension(path,"mcs") {
+            let mut out = self.directory_frame.to_vec();
+            out.extend(&self.data);
+            write_file(path,&out)
+        }
+        else if has_extension(path,"psv") {
+            let mut out = vec![0u8;PSV_HEADER_SIZE];
+            out[0..4].copy_from_slice(PSV_MAGIC);
+            out[0x38..0x3C].copy_from_slice(&0x14u32.to_le_bytes());
+            out[0x3C..0x40].copy_from_slice(&PSV_PS1_TYPE.to_le_bytes());
+            out[0x40..0x44].copy_from_slice(&(self.data.len() as u32).to_le_bytes());
+            out[0x44..0x48].copy_from_slice(&(PSV_HEADER_SIZE as u32).to_le_bytes());
+            out[0x48..0x4C].copy_from_slice(&0x200u32.to_le_bytes());
+            out[0x5C..0x60].copy_from_slice(&(BLOCK_SIZE as u32).to_le_bytes());
+            out[0x64..0x64 + FILE_NAME_LEN].copy_from_slice(&self.directory_frame[FILE_NAME_OFFSET..FILE_NAME_OFFSET + FILE_NAME_LEN]);
+            out.extend(&self.data);
+            write_file(path,&out)
+        }
+        else if has_extension(path,"gme") {
+            let mut card = MemoryCardImage::new_formatted();
+            card.import_save(self)?;
+            card.save(path)
+        }
+        else {
+            Err(format!("Unsupported save file format '{}': use mcs, psv or gme",path.display()))
+        }
+    }
+}
+
+/*
+Titles are Shift-JIS encoded, usually with full width ASCII characters: these are converted to ASCII.
+Only symbols, kana and full width ASCII are decoded, other characters (kanji) are shown as '?'.
+ */
+const SJIS_SYMBOLS_8140 : &str = " 、。,.・:;?!゛゜´`¨^￣_ヽヾゝゞ〃仝々〆〇ー―‐/\\~∥|…‥‘’“”()〔〕[]{}〈〉《》「」『』【】+-±×";
+const SJIS_SYMBOLS_8180 : &str = "÷=≠<>≦≧∞∴♂♀°′″℃¥$¢£%#&*@§☆★○●◎◇";
+
+fn decode_shift_jis(bytes:&[u8]) -> String {
+    let mut title = String::new();
+    let mut i = 0;
+    while i < bytes.len() && bytes[i] != 0 {
+        let b = bytes[i];
+        if b < 0x80 || !(0x81..=0x9F).contains(&b) && !(0xE0..=0xEF).contains(&b) {
+            title.push(if b < 0x80 { b as char } else { '?' });
+            i += 1;
+            continue;
+        }
+        let code = ((b as u16) << 8) | bytes.get(i + 1).copied().unwrap_or(0) as u16;
+        i += 2;
+        let c = match code {
+            0x8140..=0x817E => SJIS_SYMBOLS_8140.chars().nth((code - 0x8140) as usize),
+            0x8180..=0x819E => SJIS_SYMBOLS_8180.chars().nth((code - 0x8180) as usize),
+            0x824F..=0x8258 => char::from_u32((code - 0x824F) as u32 + '0' as u32),
+            0x8260..=0x8279 => char::from_u32((code - 0x8260) as u32 + 'A' as u32),
+            0x8281..=0x829A => char::from_u32((code - 0x8281) as u32 + 'a' as u32),
+            0x829F..=0x82F1 => char::from_u32((code - 0x829F) as u32 + 0x3041),
+            0x8340..=0x837E => char::from_u32((code - 0x8340) as u32 + 0x30A1),
+            0x8380..=0x8396 => char::from_u32((code - 0x8380) as u32 + 0x30E0),
+            _ => None
+        };
+        title.push(c.unwrap_or('?'));
+    }
+    title.trim_end().to_string()
+}
@@ -1,9 +1,15 @@
 mod memory_card;
+mod dualshock;
+pub mod memory_card_fs;
 
 use crate::core::config::ControllerConfig;
 use crate::core::controllers::memory_card::MemoryCard;
+use crate::core::controllers::dualshock::DualShock;
+use crate::core::savestate::{impl_state_enum, impl_state_struct, SaveState, StateSync};
 use crate::core::Resettable;
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
+
+pub use dualshock::ControllerAxis;
 
 #[derive(Copy, Clone, Debug)]
 pub enum ControllerButton {
@@ -23,6 +29,8 @@ pub enum ControllerButton {
     Circle,
     Cross,
     Square,
+    // DualShock analog mode button: not part of the switches
+    Analog,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -32,7 +40,7 @@ pub enum MouseInfo {
     DXYMotion(i8,i8),
 }
 
-#[derive(Debug,Default,Copy, Clone)]
+#[derive(Debug,Default,Copy, Clone, PartialEq)]
 pub enum ControllerType {
     #[default]
     Digital,
@@ -76,10 +84,7 @@ enum ControllerState {
     IdHi,
     SwLo,
     SwHi,
-    Analog0,
-    Analog1,
-    Analog2,
-    Analog3,
+    DualShock,
     // memory card
     MemCommand,
     MemId1,
@@ -123,18 +128,71 @@ impl Resettable for Controller {
         self.memory_card.reset();
         self.memory_card_selected = false;
         self.digital_switches = 0xFFFF;
-        self.analog_switches = 0;
+        self.dualshock.reset();
         self.last_cmd = 0;
         self.write_checksum = 0;
     }
 }
 
+impl SaveState for ControllerState {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        use ControllerState::*;
+        // MemGetIdEpilogue is the only variant carrying data: it is stored with index 0xFF followed by its value
+        let variants = [
+            Init, IdLo, IdHi, SwLo, SwHi, DualShock,
+            MemCommand, MemId1, MemId2, MemLSB, MemMSB, MemAck1, MemAck2, MemConfirmedLSB, MemConfirmedMSB,
+            MemReadDataSector, MemSendDataSector, MemChecksum, MemEndByteRead, MemEndByteWrite,
+            MouseButtonsLo, MouseButtonsHi, MouseMovementLo, MouseMovementHi,
+        ];
+        let mut index = match self {
+            MemGetIdEpilogue(_) => 0xFF,
+            _ => variants.iter().position(|v| std::mem::discriminant(v) == std::mem::discriminant(self)).unwrap_or(0) as u8,
+        };
+        s.sync(&mut index);
+        if index == 0xFF {
+            let mut count = if let MemGetIdEpilogue(count) = self { *count } else { 0 };
+            s.sync(&mut count);
+            *self = MemGetIdEpilogue(count);
+        }
+        else if s.is_loading() {
+            match variants.into_iter().nth(index as usize) {
+                Some(state) => *self = state,
+                None => s.set_error(format!("Invalid controller state index {}",index)),
+            }
+        }
+    }
+}
+
+impl_state_enum!(ControllerButton {
+    ControllerButton::Select, ControllerButton::L3, ControllerButton::R3, ControllerButton::Start,
+    ControllerButton::Up, ControllerButton::Right, ControllerButton::Down, ControllerButton::Left,
+    ControllerButton::L2, ControllerButton::R2, ControllerButton::L1, ControllerButton::R1,
+    ControllerButton::Triangle, ControllerButton::Circle, ControllerButton::Cross, ControllerButton::Square,
+    ControllerButton::Analog,
+});
+impl_state_enum!(MemoryCardCommand { MemoryCardCommand::Read, MemoryCardCommand::Write, MemoryCardCommand::GetId });
+impl_state_struct!(MouseSwitches { right_button, left_button, dx_motion, dy_motion });
+
+impl SaveState for Controller {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.digital_switches);
+        s.sync(&mut self.dualshock);
+        s.sync(&mut self.mouse_switches);
+        s.sync(&mut self.state);
+        s.sync(&mut self.memory_card);
+        s.sync(&mut self.memory_card_selected);
+        s.sync(&mut self.memory_card_sector);
+        s.sync(&mut self.last_cmd);
+        s.sync(&mut self.write_checksum);
+    }
+}
+
 #[derive(Debug)]
 pub struct Controller {
     id: u8,
     controller_type: ControllerType,
     digital_switches: u16,
-    analog_switches: u32,
+    dualshock: DualShock,
     mouse_switches: MouseSwitches,
     state: ControllerState,
     connected: bool,
@@ -152,7 +210,7 @@ impl Controller {
             id,
             controller_type,
             digital_switches: 0xFFFF,
-            analog_switches: 0,
+            dualshock: DualShock::new(),
             mouse_switches: MouseSwitches::default(),
             state: ControllerState::Init,
             connected,
@@ -168,6 +226,16 @@ impl Controller {
     pub fn get_type(&self) -> &ControllerType {
         &self.controller_type
     }
+
+    pub fn set_type(&mut self,controller_type:ControllerType) {
+        if self.controller_type != controller_type {
+            info!("Controller #{} type changed to {:?}",self.id,controller_type);
+            self.controller_type = controller_type;
+            self.dualshock = DualShock::new();
+            self.mouse_switches = MouseSwitches::default();
+            self.state = ControllerState::Init;
+        }
+    }
     
     pub fn save(&mut self) {
         self.memory_card.save();
@@ -186,6 +254,12 @@ impl Controller {
     }
 
     pub fn on_controller_event(&mut self, key:ControllerButton, pressed:bool) {
+        if let ControllerButton::Analog = key {
+            if pressed && self.connected && self.controller_type.is_analog() {
+                self.dualshock.toggle_analog_mode();
+            }
+            return;
+        }
         if self.connected {
             if pressed {
                 self.digital_switches &= !(1 << (key as u16));
@@ -194,6 +268,35 @@ impl Controller {
             }
         }
     }
+    pub fn on_axis_event(&mut self, axis:ControllerAxis, value:u8) {
+        if self.connected && self.controller_type.is_analog() {
+            self.dualshock.set_axis(axis,value);
+        }
+    }
+
+    /*
+    Pressed buttons, bit n set for ControllerButton n
+     */
+    pub fn get_pressed_buttons(&self) -> u16 {
+        !self.digital_switches
+    }
+
+    pub fn is_analog_mode(&self) -> bool {
+        self.controller_type.is_analog() && self.dualshock.is_analog_mode()
+    }
+
+    /*
+    DualShock small and large motors values, returned only when changed
+     */
+    pub fn take_rumble(&mut self) -> Option<(u8,u8)> {
+        if self.controller_type.is_analog() {
+            self.dualshock.take_rumble()
+        }
+        else {
+            None
+        }
+    }
+
     pub fn on_mouse_event(&mut self, event:MouseInfo) {
         match event {
             MouseInfo::RightButton(pressed) => {
@@ -440,7 +543,12 @@ impl Controller {
             ControllerState::Init => {
                 if cmd == 0x01 && self.connected {
                     self.memory_card_selected = false;
-                    self.state = ControllerState::IdLo;
+                    self.state = if self.controller_type.is_analog() {
+                        ControllerState::DualShock
+                    }
+                    else {
+                        ControllerState::IdLo
+                    };
                 }
                 else if cmd == 0x81 && self.memory_card.is_present() {
                     self.memory_card_selected = true;
@@ -482,58 +590,16 @@ impl Controller {
                 self.digital_switches as u8
             }
             ControllerState::SwHi => {
-                self.state = if self.controller_type.is_digital() {
-                    ControllerState::Init
-                }
-                else {
-                    ControllerState::Analog0
-                };
+                self.state = ControllerState::Init;
                 //println!("Digital switches: {:04X}",self.digital_switches);
                 (self.digital_switches >> 8) as u8
             }
-            ControllerState::Analog0 => {
-                if cmd == 0x00 {
-                    self.state = ControllerState::Analog1;
-                    (self.analog_switches & 0xFF) as u8
-                }
-                else {
-                    warn!("Unexpected controller command on state {:?}: {:02X}",self.state,cmd);
+            ControllerState::DualShock => {
+                let (byte,next) = self.dualshock.transfer(cmd,self.digital_switches);
+                if !next {
                     self.state = ControllerState::Init;
-                    0xFF
-                }
-            }
-            ControllerState::Analog1 => {
-                if cmd == 0x00 {
-                    self.state = ControllerState::Analog2;
-                    ((self.analog_switches >> 8) & 0xFF) as u8
-                }
-                else {
-                    warn!("Unexpected controller command on state {:?}: {:02X}",self.state,cmd);
-                    self.state = ControllerState::Init;
-                    0xFF
-                }
-            }
-            ControllerState::Analog2 => {
-                if cmd == 0x00 {
-                    self.state = ControllerState::Analog3;
-                    ((self.analog_switches >> 16) & 0xFF) as u8
-                }
-                else {
-                    warn!("Unexpected controller command on state {:?}: {:02X}",self.state,cmd);
-                    self.state = ControllerState::Init;
-                    0xFF
-                }
-            }
-            ControllerState::Analog3 => {
-                if cmd == 0x00 {
-                    self.state = ControllerState::Init;
-                    ((self.analog_switches >> 24) & 0xFF) as u8
-                }
-                else {
-                    warn!("Unexpected controller command on state {:?}: {:02X}",self.state,cmd);
-                    self.state = ControllerState::Init;
-                    0xFF
                 }
+                byte
             }
             /*
             __Halfword 1 (Mouse Buttons)__________________
@@ -0,0 +1,186 @@
+use crate::core::cpu::instruction::{Instruction, Opcode};
+use crate::core::cpu::{Cpu, OpFunction};
+use crate::core::memory;
+use crate::core::memory::bus::{Bus, CODE_PAGE_SHIFT};
+use crate::core::memory::{Memory, MemorySection};
+use std::mem;
+use std::rc::Rc;
+
+// upper bound of the instructions of a block, to return to the main loop from time to time
+const MAX_BLOCK_INSTRUCTIONS : usize = 256;
+
+const RAM_ENTRIES : usize = 0x20_0000 >> 2;
+const BIOS_ENTRIES : usize = 0x8_0000 >> 2;
+const BIOS_START : u32 = 0x1FC0_0000;
+const PAGE_ENTRIES_SHIFT : u32 = CODE_PAGE_SHIFT - 2;
+const PAGES : usize = (RAM_ENTRIES + BIOS_ENTRIES) >> PAGE_ENTRIES_SHIFT;
+
+/*
+An instruction decoded once when its block is built: the word it was decoded from, its opcode and the handler executing it
+ */
+pub(super) struct CachedInstruction {
+    pub word: u32,
+    pub opcode: Opcode,
+    pub handler: OpFunction,
+}
+
+type Block = Rc<[CachedInstruction]>;
+
+/*
+Cached interpreter: blocks of decoded instructions, by the physical address (RAM mirrors folded) of their first instruction.
+A block runs sequentially up to a branch or jump and its delay slot, a SYSCALL/BREAK, a cop write or the end of its 4K page.
+Blocks are dropped:
+- by page, when the bus reports a write (CPU, DMA, cheats, EXE or state loading) to a RAM page holding blocks
+- all of them, when the i-cache isolation ends: the code written with the cache isolated is usually followed by a FlushCache
+ */
+pub(super) struct BlockCache {
+    blocks: Vec<Option<Block>>,
+    pages: [u64;PAGES.div_ceil(64)],
+    cache_isolated: bool,
+}
+
+impl BlockCache {
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![None;RAM_ENTRIES + BIOS_ENTRIES],
+            pages: [0;PAGES.div_ceil(64)],
+            cache_isolated: false,
+        }
+    }
+
+    #[inline(always)]
+    fn index(pc:u32) -> Option<usize> {
+        let address = pc & 0x1FFF_FFFF;
+        if address < 0x80_0000 {
+            Some(((address & 0x1F_FFFF) >> 2) as usize)
+        }
+        else if (BIOS_START..BIOS_START + 0x8_0000).contains(&address) {
+            Some(RAM_ENTRIES + ((address - BIOS_START) >> 2) as usize)
+        }
+        else {
+            None
+        }
+    }
+
+    fn invalidate_page(&mut self,page:usize) {
+        let bit = 1u64 << (page & 63);
+        if self.pages[page >> 6] & bit != 0 {
+            self.pages[page >> 6] &= !bit;
+            self.blocks[page << PAGE_ENTRIES_SHIFT..(page + 1) << PAGE_ENTRIES_SHIFT].fill(None);
+        }
+    }
+
+    fn invalidate_pages(&mut self,pages:&[u64]) {
+        for (word_index,&word) in pages.iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                self.invalidate_page(word_index * 64 + word.trailing_zeros() as usize);
+                word &= word - 1;
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        let pages = self.pages;
+        self.invalidate_pages(&pages);
+    }
+
+    /*
+    The block starting at pc, built if needed. None if pc is not in RAM or BIOS, or if its first instruction is unknown:
+    the interpreter path handles them.
+     */
+    fn block(&mut self,pc:u32,memory:&mut Bus,op_functions:&[OpFunction]) -> Option<Block> {
+        if memory.get_cop0().is_cache_isolated() {
+            self.cache_isolated = true;
+            return None;
+        }
+        if mem::take(&mut self.cache_isolated) {
+            self.flush();
+        }
+        if let Some(dirty_pages) = memory.take_dirty_code_pages() {
+            self.invalidate_pages(&dirty_pages);
+        }
+        if pc & 3 != 0 {
+            return None;
+        }
+        let index = Self::index(pc)?;
+        if let Some(block) = &self.blocks[index] {
+            return Some(block.clone());
+        }
+
+        let mut instructions = Vec::new();
+        let mut address = pc;
+        let mut ends_with_delay_slot = false;
+        while instructions.len() < MAX_BLOCK_INSTRUCTIONS {
+            let word = memory.peek::<32>(address).unwrap_or_default();
+            let opcode = Opcode::from_instruction(&Instruction(word));
+            if opcode == Opcode::UNKNOWN {
+                break;
+            }
+            instructions.push(CachedInstruction { word, opcode, handler: op_functions[opcode as usize] });
+            address = address.wrapping_add(4);
+            if ends_with_delay_slot || matches!(opcode,Opcode::SYSCALL | Opcode::BREAK | Opcode::MTCn | Opcode::CTCn) || address & ((1 << CODE_PAGE_SHIFT) - 1) == 0 {
+                break;
+            }
+            ends_with_delay_slot = is_branch_or_jump(opcode);
+        }
+        if instructions.is_empty() {
+            return None;
+        }
+
+        let page = index >> PAGE_ENTRIES_SHIFT;
+        self.pages[page >> 6] |= 1 << (page & 63);
+        if index < RAM_ENTRIES {
+            memory.set_code_page(index << 2);
+        }
+        let block : Block = instructions.into();
+        self.blocks[index] = Some(block.clone());
+        Some(block)
+    }
+}
+
+fn is_branch_or_jump(opcode:Opcode) -> bool {
+    use Opcode::*;
+    matches!(opcode,J|JAL|JR|JALR|BEQ|BNE|BLEZ|BGTZ|BLTZ|BGEZ|BLTZAL|BGEZAL|BCnF|BCnT)
+}
+
+impl Cpu {
+    /*
+    Cached interpreter, execution side: runs the block starting at the current PC, advancing the clock after every instruction but
+    the last one, whose cycles are returned to the main loop as for execute_next_instruction.
+    The block is left early before anything the main loop must see between two instructions:
+    - the next clock event would be reached
+    - the PC didn't move to the next instruction (taken branch or jump, exception, interrupt, stall)
+    - an access outside RAM, scratchpad and BIOS: I/O ports can start a DMA, raise an IRQ or schedule an event
+    Without a block for the PC, a single instruction is interpreted.
+    To be used only when no DMA is in progress (the CPU/DMA bus arbitration is done by the main loop at every instruction).
+     */
+    pub fn execute_block(&mut self,memory:&mut Bus) -> usize {
+        let Some(block) = self.block_cache.as_mut().and_then(|cache| cache.block(self.pc,memory,&self.op_functions)) else {
+            return self.execute_next_instruction(memory,false);
+        };
+        let next_event_time = memory.get_clock().next_event_time().unwrap_or(u64::MAX);
+        let last = block.len() - 1;
+        for (n,instruction) in block.iter().enumerate() {
+            let pc = self.pc;
+            let cycles = self.execute_instruction(memory,false,Some(instruction));
+
+            if n == last || self.pc != pc.wrapping_add(4) || self.is_device_access() {
+                return cycles;
+            }
+            let clock = memory.get_clock_mut();
+            if clock.current_time() + cycles as u64 >= next_event_time {
+                return cycles;
+            }
+            clock.advance_time(cycles as u64);
+        }
+        unreachable!()
+    }
+
+    #[inline(always)]
+    fn is_device_access(&self) -> bool {
+        [self.last_mem_read_address,self.last_mem_write_address].iter().flatten().any(|&address| {
+            !matches!(memory::get_memory_section(address),MemorySection::MainRAM | MemorySection::ScratchPad | MemorySection::BIOSRom)
+        })
+    }
+}
@@ -1,6 +1,7 @@
 use tracing::{debug, info, warn};
 use crate::core::cpu::CpuException;
 use crate::core::cpu::{Coprocessor, CopResult};
+use crate::core::savestate::impl_state_struct;
 use crate::core::Resettable;
 
 pub static COP0_REGISTER_ALIASES: [ &str; 32 ] = [
@@ -135,6 +136,8 @@ impl Resettable for Cop0 {
     }
 }
 
+impl_state_struct!(Cop0 { regs, pending_int_on_next_opcode });
+
 impl Cop0 {
     pub fn new() -> Self {
         let mut cop0 = Cop0 {
@@ -151,6 +154,10 @@ impl Cop0 {
         &self.regs
     }
 
+    pub fn get_regs_mut(&mut self) -> &mut [u32;32] {
+        &mut self.regs
+    }
+
     pub fn is_in_kernel_mode(&self) -> bool {
         (self.regs[Cop0Reg::SR as usize] & 2) == 0 // KUc = 0
     }
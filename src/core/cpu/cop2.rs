@@ -1,4 +1,6 @@
+use crate::core::cpu::pgxp::PgxpVertex;
 use crate::core::cpu::{CopResult, Coprocessor};
+use crate::core::savestate::{impl_state_struct, SaveState, StateSync};
 use crate::core::Resettable;
 use std::cmp;
 use tracing::{debug, error, info};
@@ -185,6 +187,11 @@ pub struct Cop2 {
     mac: [i32; 4],
     lzcs: i32,
     lzcr: i32,
+
+    // PGXP: precise vertices of the SXY FIFO, not saved in states
+    sxy_precise: [Option<PgxpVertex>; 3],
+    // widescreen hack: the projected X is narrowed by 3/4
+    widescreen_hack: bool,
 }
 
 impl Coprocessor for Cop2 {
@@ -286,18 +293,22 @@ impl Coprocessor for Cop2 {
             12 => {
                 self.sxy_fifo[0].x = value as i16;
                 self.sxy_fifo[0].y = (value >> 16) as i16;
+                self.sxy_precise[0] = None;
             }
             13 => {
                 self.sxy_fifo[1].x = value as i16;
                 self.sxy_fifo[1].y = (value >> 16) as i16;
+                self.sxy_precise[1] = None;
             }
             14 => {
                 self.sxy_fifo[2].x = value as i16;
                 self.sxy_fifo[2].y = (value >> 16) as i16;
+                self.sxy_precise[2] = None;
             }
             15 => {
                 self.push_sx(value as i16);
                 self.push_sy((value >> 16) as i16);
+                self.push_sxy_precise(None);
             }
             16 => self.sz_fifo[0] = value as u16,
             17 => self.sz_fifo[1] = value as u16,
@@ -560,10 +571,35 @@ impl Resettable for Cop2 {
         self.mac = [0; 4];
         self.lzcs = 0;
         self.lzcr = 0;
+        self.sxy_precise = [None; 3];
         info!("Cop2 reset done");
     }
 }
 
+impl_state_struct!(Matrix3x3 { m11, m12, m13, m21, m22, m23, m31, m32, m33 });
+impl_state_struct!(RGB { r, g, b, c });
+
+impl<T:SaveState> SaveState for Vec2<T> {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.x);
+        s.sync(&mut self.y);
+    }
+}
+
+impl<T:SaveState> SaveState for Vec3<T> {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.x);
+        s.sync(&mut self.y);
+        s.sync(&mut self.z);
+    }
+}
+
+impl_state_struct!(Cop2 {
+    sf, mx, sv, cv, lm,
+    rotation, tr, light, bk, colour, fc, ofx, ofy, h, dqa, dqb, zsf3, zsf4, flags,
+    v, rgb, otz, ir, sxy_fifo, sz_fifo, rgb_fifo, res1, mac, lzcs, lzcr
+});
+
 impl Cop2 {
     pub fn new() -> Cop2 {
         let mut cop2 = Cop2 {
@@ -600,12 +636,45 @@ impl Cop2 {
             mac: [0; 4],
             lzcs: 0,
             lzcr: 0,
+            sxy_precise: [None; 3],
+            widescreen_hack: false,
         };
 
         cop2.init_commands();
         cop2
     }
 
+    /*
+    Widescreen hack: RTPS/RTPT project X with 3/4 of the horizontal scale, so a 16:9 view is squeezed in the 4:3 frame,
+    that is displayed stretched to 16:9
+     */
+    pub fn set_widescreen_hack(&mut self, enabled: bool) {
+        self.widescreen_hack = enabled;
+    }
+
+    /*
+    PGXP: the precise vertex of the SXY data register (12-15), if any
+     */
+    pub fn precise_sxy(&self, index: usize) -> Option<PgxpVertex> {
+        match index {
+            12 | 13 => self.sxy_precise[index - 12],
+            14 | 15 => self.sxy_precise[2],
+            _ => None,
+        }
+    }
+
+    /*
+    PGXP: attaches the precise vertex to the SXY data register (12-15) just written, if it belongs to the written value
+     */
+    pub fn set_precise_sxy(&mut self, index: usize, vertex: Option<PgxpVertex>) {
+        if !(12..=15).contains(&index) {
+            return;
+        }
+        let CopResult(value, _) = self.read_data_register(index);
+        let slot = if index == 15 { 2 } else { index - 12 };
+        self.sxy_precise[slot] = PgxpVertex::matching(vertex, value);
+    }
+
     fn init_commands(&mut self) {
         for cmd in 0..0x40 {
             let (command,penalty) : (fn(&mut Cop2),usize) = match cmd {
@@ -1252,7 +1321,11 @@ impl Cop2 {
         let ir1 = self.ir[1] as i64;
         let ir2 = self.ir[2] as i64;
 
-        let sx2 = self.ofx as i64 + ir1 * h_div_sz as i64;
+        let sx2 = if self.widescreen_hack {
+            self.ofx as i64 + ((ir1 * h_div_sz as i64 * 3) >> 2)
+        } else {
+            self.ofx as i64 + ir1 * h_div_sz as i64
+        };
         let sx2_f = self.f(sx2) >> 16;
         let sx2_f_g = self.lm_g(1, sx2_f as i32);
 
@@ -1264,6 +1337,20 @@ impl Cop2 {
 
         self.push_sy(sy2_f_g);
 
+        // PGXP: the same projection without the integer truncation, discarded if it doesn't agree with the integer result
+        let z = tr_z as f64 / 4096.0;
+        let h_div_z = if sz3 > (self.h / 2) { self.h as f64 / z } else { h_div_sz as f64 / 65536.0 };
+        let h_div_z_x = if self.widescreen_hack { h_div_z * 0.75 } else { h_div_z };
+        let precise_x = ((self.ofx as f64 / 65536.0) + (temp[0] as f64 / 4096.0) * h_div_z_x).clamp(-1024.0, 1023.999);
+        let precise_y = ((self.ofy as f64 / 65536.0) + (temp[1] as f64 / 4096.0) * h_div_z).clamp(-1024.0, 1023.999);
+        let precise = if (precise_x - sx2_f_g as f64).abs() < 2.0 && (precise_y - sy2_f_g as f64).abs() < 2.0 {
+            let value = (sx2_f_g as u16 as u32) | ((sy2_f_g as u16 as u32) << 16);
+            Some(PgxpVertex { value, x: precise_x as f32, y: precise_y as f32, z: z as f32 })
+        } else {
+            None
+        };
+        self.push_sxy_precise(precise);
+
         if dq {
             let depth = self.dqb as i64 + self.dqa as i64 * h_div_sz as i64;
             self.mac[0] = self.f(depth) as i32;
@@ -1832,6 +1919,12 @@ impl Cop2 {
         self.sxy_fifo[2].y = sy;
     }
     #[inline]
+    fn push_sxy_precise(&mut self, vertex: Option<PgxpVertex>) {
+        self.sxy_precise[0] = self.sxy_precise[1];
+        self.sxy_precise[1] = self.sxy_precise[2];
+        self.sxy_precise[2] = vertex;
+    }
+    #[inline]
     fn push_sz(&mut self, sz: u16) {
         self.sz_fifo[0] = self.sz_fifo[1];
         self.sz_fifo[1] = self.sz_fifo[2];
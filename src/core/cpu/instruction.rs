@@ -3,7 +3,7 @@ pub struct Instruction(pub u32);
 static OPCODE_CACHE: [fn(&Instruction) -> Opcode;64] = init_opcode_cache();
 static OPCODE_CACHE_SPECIAL: [fn(&Instruction) -> Opcode;64] = init_opcode_special_cache();
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,PartialEq,Clone,Copy)]
 pub enum Opcode {
     // SPECIAL
     NOP,
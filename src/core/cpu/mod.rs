@@ -1,9 +1,11 @@
-use crate::core::config::Config;
+use crate::core::config::{Config, CpuBackend};
 use crate::core::cpu::cop2::Cop2;
+use crate::core::cpu::pgxp::PgxpVertex;
 use crate::core::cpu::instruction::{Instruction, Opcode};
 use crate::core::memory::bus::Bus;
 use crate::core::memory::{Memory, MemoryMap, MemorySection, ReadMemoryAccess, WriteMemoryAccess};
-use crate::core::{memory, Resettable};
+use crate::core::savestate::{impl_state_struct, SaveState, StateSync};
+use crate::core::{memory, unimplemented, Resettable};
 use std::mem;
 use tracing::{debug, error, info};
 
@@ -11,6 +13,8 @@ pub mod instruction;
 pub mod disassembler;
 pub mod cop0;
 mod cop2;
+pub mod pgxp;
+mod cached;
 
 pub const RESET_ADDRESS : u32 = 0xBFC00000;
 const MUL_AVERAGE_CYCLES : usize = 9;
@@ -115,6 +119,9 @@ impl Resettable for ICache {
     }
 }
 
+impl_state_struct!(CacheLine { valid, tag, line });
+impl_state_struct!(ICache { lines, cache_miss, requests });
+
 impl ICache {
     fn new() -> Self {
         // let mut lines = Vec::with_capacity(256);
@@ -299,8 +306,12 @@ impl WriteQueue {
     }
 }
 
+impl_state_struct!(WriteQueue { queue, head, tail, len });
+
+type OpFunction = fn(&mut Cpu,&mut Bus,&Instruction,bool) -> OperationException;
+
 pub struct Cpu {
-    op_functions: [fn(&mut Cpu,&mut Bus,&Instruction,bool) -> OperationException; 80],
+    op_functions: [OpFunction; 80],
     cop2: Cop2,
     i_cache: ICache,
     write_queue: WriteQueue,
@@ -318,12 +329,19 @@ pub struct Cpu {
     write_queue_elapsed: usize,
     bios_tty_capture_enabled: bool,
     bios_tty_buffer: String,
+    bios_tty_watch: Option<String>,
+    bios_tty_watch_found: bool,
     last_mem_read_address : Option<u32>,
     last_mem_write_address : Option<u32>,
     last_mem_rw_value: u32,
     last_opcode: u32,
     cop2_remaining_cycles: usize,
     write_queue_enabled: bool,
+    // PGXP: precise vertices held by the general purpose registers
+    pgxp_enabled: bool,
+    pgxp_regs: [Option<PgxpVertex>; 32],
+    // cached interpreter backend
+    block_cache: Option<cached::BlockCache>,
 }
 
 impl Resettable for Cpu {
@@ -337,15 +355,45 @@ impl Resettable for Cpu {
         self.hi = 0;
         self.lo = 0;
         self.regs.fill(0);
+        self.pgxp_regs.fill(None);
         self.delayed_load = (0,0);
         self.delayed_load_next = (0,0);
         self.regs.fill(0);
         self.branch_taken = false;
         self.cop2_remaining_cycles = 0;
+        if let Some(cache) = self.block_cache.as_mut() {
+            cache.flush();
+        }
         info!("CPU reset done");
     }
 }
 
+impl SaveState for Cpu {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.section("CPU");
+        s.sync(&mut self.cop2);
+        s.sync(&mut self.i_cache);
+        s.sync(&mut self.write_queue);
+        s.sync(&mut self.pc);
+        s.sync(&mut self.regs);
+        s.sync(&mut self.hi);
+        s.sync(&mut self.lo);
+        s.sync(&mut self.delayed_load);
+        s.sync(&mut self.delayed_load_next);
+        s.sync(&mut self.branch_address);
+        s.sync(&mut self.branch_taken);
+        s.sync(&mut self.in_branch_delay_slot);
+        s.sync(&mut self.mul_div_pending_cycles);
+        s.sync(&mut self.op_cycles);
+        s.sync(&mut self.write_queue_elapsed);
+        s.sync(&mut self.last_opcode);
+        s.sync(&mut self.cop2_remaining_cycles);
+        if s.is_loading() && let Some(cache) = self.block_cache.as_mut() {
+            cache.flush();
+        }
+    }
+}
+
 impl Cpu {
     pub fn new(config:&Config) -> Self {
         let mut cpu = Cpu {
@@ -367,15 +415,24 @@ impl Cpu {
             write_queue_elapsed: 0,
             bios_tty_capture_enabled: false,
             bios_tty_buffer: String::new(),
+            bios_tty_watch: None,
+            bios_tty_watch_found: false,
             last_mem_read_address: None,
             last_mem_write_address: None,
             last_mem_rw_value: 0,
             last_opcode: 0,
             cop2_remaining_cycles: 0,
             write_queue_enabled: config.memory_config.cpu_write_queue_enabled,
+            pgxp_enabled: config.gpu_config.pgxp_enabled,
+            pgxp_regs: [None; 32],
+            block_cache: match config.cpu_config.cpu_backend {
+                CpuBackend::Interpreter => None,
+                CpuBackend::CachedInterpreter => Some(cached::BlockCache::new()),
+            },
         };
 
         cpu.init_op_functions();
+        cpu.set_widescreen_hack(config.gpu_config.widescreen_hack);
 
         cpu
     }
@@ -493,6 +550,24 @@ impl Cpu {
         self.bios_tty_capture_enabled = bios_tty_capture_enabled;
     }
 
+    pub fn set_widescreen_hack(&mut self, enabled: bool) {
+        self.cop2.set_widescreen_hack(enabled);
+    }
+
+    pub fn set_pgxp_enabled(&mut self, enabled: bool) {
+        self.pgxp_enabled = enabled;
+        self.pgxp_regs.fill(None);
+    }
+
+    pub fn set_bios_tty_watch(&mut self, watch: Option<String>) {
+        self.bios_tty_watch = watch;
+        self.bios_tty_watch_found = false;
+    }
+
+    pub fn is_bios_tty_watch_found(&self) -> bool {
+        self.bios_tty_watch_found
+    }
+
     pub fn get_last_elapsed_cycles(&self) -> usize {
         self.op_cycles
     }
@@ -525,6 +600,14 @@ impl Cpu {
         self.hi
     }
 
+    pub fn set_lo(&mut self, lo:u32) {
+        self.lo = lo;
+    }
+
+    pub fn set_hi(&mut self, hi:u32) {
+        self.hi = hi;
+    }
+
     pub fn get_last_opcode(&self) -> u32 {
         self.last_opcode
     }
@@ -546,6 +629,15 @@ impl Cpu {
     }
 
     pub fn execute_next_instruction(&mut self,memory: &mut Bus,dma_in_progress:bool) -> usize {
+        self.execute_instruction(memory,dma_in_progress,None)
+    }
+
+    /*
+    With the cached interpreter, cached is the instruction of the current block at PC: the fetch is still done (i-cache timings,
+    code changed in the i-cache only), and the cached opcode and handler are used when the fetched word is the one they were decoded from
+     */
+    #[inline(always)]
+    fn execute_instruction(&mut self,memory: &mut Bus,dma_in_progress:bool,cached:Option<&cached::CachedInstruction>) -> usize {
         // check pending operations during last op_cycles
         self.update_pending_operations(memory,dma_in_progress);
         // reset cycle count
@@ -609,7 +701,13 @@ impl Cpu {
         let mut use_write_cache = false;
 
         // exit conditions
-        let opcode = Opcode::from_instruction(&i);
+        let (opcode,handler) = match cached {
+            Some(cached) if cached.word == i.0 => (cached.opcode,cached.handler),
+            _ => {
+                let opcode = Opcode::from_instruction(&i);
+                (opcode,self.op_functions[opcode as usize])
+            }
+        };
         // 1) unknown instruction
         if opcode == Opcode::UNKNOWN {
             error!("Unknown opcode during fetching: {:08X} at address: {:08X}",self.last_opcode,self.pc);
@@ -672,6 +770,9 @@ impl Cpu {
                 else {
                     if let Some(ch) = char::from_u32(self.regs[4]) {
                         self.bios_tty_buffer.push(ch);
+                        if let Some(watch) = self.bios_tty_watch.as_ref() && self.bios_tty_buffer.contains(watch.as_str()) {
+                            self.bios_tty_watch_found = true;
+                        }
                     }
                 }
             }
@@ -696,7 +797,7 @@ impl Cpu {
         };
 
         // execute instruction
-        if let Err(ex) = self.op_functions[opcode as usize](self,memory,&i,use_write_cache) {
+        if let Err(ex) = handler(self,memory,&i,use_write_cache) {
             if matches!(ex,CpuException::ReadWriteWait) {
                 self.in_branch_delay_slot = was_in_branch_delay_slot;
                 // stay on the same instruction and wait for the memory operation to complete, we will re-attempt to execute the same instruction on the next call to execute_next_instruction
@@ -1249,6 +1350,9 @@ impl Cpu {
         }
         let read = self.read_data_memory::<32>(memory,target)?;
         self.write_delayed_reg(instr.rt(),read);
+        if self.pgxp_enabled {
+            self.pgxp_regs[instr.rt()] = memory.get_pgxp().lookup(target,read);
+        }
         Ok(())
     }
 
@@ -1349,6 +1453,9 @@ impl Cpu {
             return Err(CpuException::AddressErrorStore(target))
         }
         let rt_w = self.regs[instr.rt()];
+        if self.pgxp_enabled {
+            memory.get_pgxp_mut().store(target,PgxpVertex::matching(self.pgxp_regs[instr.rt()],rt_w));
+        }
         self.write_data_memory::<32>(memory,target,rt_w,use_write_cache)
     }
 
@@ -1462,6 +1569,9 @@ impl Cpu {
                     // the CPU will hold until the instruction has finished.
                     let CopResult(read,penalty) = self.cop2.read_data_register(instr.rd());
                     self.write_delayed_reg(instr.rt(),read);
+                    if self.pgxp_enabled {
+                        self.pgxp_regs[instr.rt()] = self.cop2.precise_sxy(instr.rd());
+                    }
                     self.op_cycles += penalty + self.cop2_remaining_cycles;
                     self.cop2_remaining_cycles = 0;
                 }
@@ -1506,6 +1616,9 @@ impl Cpu {
                 }
                 else {
                     let CopResult(_,penalty) = self.cop2.write_data_register(instr.rd(), self.regs[instr.rt()]);
+                    if self.pgxp_enabled {
+                        self.cop2.set_precise_sxy(instr.rd(),self.pgxp_regs[instr.rt()]);
+                    }
                     self.op_cycles += penalty;
                 }
                 Ok(())
@@ -1546,8 +1659,25 @@ impl Cpu {
         }
     }
 
-    fn op_bcn_ft<const FALSE:bool>(&mut self,_memory:&mut Bus,_instr: &Instruction,_use_write_cache:bool) -> OperationException {
-        todo!{"Implement BCnF/BCnT instructions"}
+    /*
+    BCnF/BCnT branch on the condition input of coprocessor n. On the PS1 no coprocessor drives it (it's unknown what real
+    hardware reads there): the condition is taken as false, so BCnF always branches and BCnT never does.
+     */
+    fn op_bcn_ft<const FALSE:bool>(&mut self,memory:&mut Bus,instr: &Instruction,_use_write_cache:bool) -> OperationException {
+        let n = (instr.op() & 3) as usize;
+        let cop0 = memory.get_cop0();
+        let accessible = match n {
+            0 => cop0.is_accessible::<0>(),
+            1 => cop0.is_accessible::<1>(),
+            2 => cop0.is_accessible::<2>(),
+            _ => cop0.is_accessible::<3>(),
+        };
+        if !accessible {
+            return Err(CpuException::CoprocessorUnusable(n))
+        }
+        unimplemented::report("CPU",&format!("BC{}{} condition",n,if FALSE { "F" } else { "T" }));
+        self.op_branch::<_,false>(instr,|_,_| FALSE);
+        Ok(())
     }
 
     fn op_lwc<const N:usize>(&mut self,memory:&mut Bus,instr: &Instruction,_use_write_cache:bool) -> OperationException {
@@ -1570,6 +1700,9 @@ impl Cpu {
         }
 
         let CopResult(_,penalty) = self.cop2.write_data_register(instr.rt(), read);
+        if self.pgxp_enabled {
+            self.cop2.set_precise_sxy(instr.rt(),memory.get_pgxp().lookup(target,read));
+        }
         self.op_cycles += penalty;
         Ok(())
     }
@@ -1594,6 +1727,9 @@ impl Cpu {
 
         let CopResult(data,penalty) = self.cop2.read_data_register(instr.rt());
         self.op_cycles += penalty;
+        if self.pgxp_enabled {
+            memory.get_pgxp_mut().store(target,self.cop2.precise_sxy(instr.rt()));
+        }
         self.write_data_memory::<32>(memory,target,data,use_write_cache)
 
     }
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+/*
+PGXP (precision geometry transform pipeline): sub-pixel precision for the vertices projected by the GTE.
+RTPS/RTPT output integer screen coordinates (SXY) and the lost fractional part is the cause of the polygon wobble.
+The GTE keeps the precise projected coordinates and depth alongside the integer SXY; they follow the integer value:
+- to the CPU registers (MFC2, LW) and back to the GTE (MTC2, LWC2)
+- to memory (SWC2, SW), where they are tracked by physical word address
+When the GPU receives a word (DMA or GP0 port) the precise vertex stored at its address goes with it, and the GPU matches it
+with the polygon vertices by integer value.
+Every precise vertex carries the integer value it belongs to: if the word has been changed meanwhile (the game moved or clipped a vertex)
+the values don't match anymore and the integer coordinates are used.
+ */
+#[derive(Clone,Copy,Debug,Default)]
+pub struct PgxpVertex {
+    pub value: u32, // integer SXY word: x in the low 16 bits, y in the high ones
+    pub x: f32,
+    pub y: f32,
+    pub z: f32, // view depth, used by the perspective correct texturing
+}
+
+impl PgxpVertex {
+    // the precise vertex if it still belongs to the given integer word
+    #[inline(always)]
+    pub fn matching(vertex:Option<PgxpVertex>,value:u32) -> Option<PgxpVertex> {
+        vertex.filter(|v| v.value == value)
+    }
+}
+
+/*
+Precise vertices stored in memory, by physical word address (RAM mirrors folded)
+ */
+#[derive(Default)]
+pub struct PgxpMemory {
+    enabled: bool,
+    vertices: HashMap<u32,PgxpVertex>,
+}
+
+impl PgxpMemory {
+    pub fn new(enabled:bool) -> Self {
+        Self { enabled, vertices: HashMap::new() }
+    }
+
+    #[inline(always)]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self,enabled:bool) {
+        self.enabled = enabled;
+        self.vertices.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    #[inline(always)]
+    fn physical_address(address:u32) -> u32 {
+        let address = address & 0x1FFF_FFFC;
+        if address < 0x80_0000 { address & 0x1F_FFFC } else { address }
+    }
+
+    #[inline]
+    pub fn store(&mut self,address:u32,vertex:Option<PgxpVertex>) {
+        if !self.enabled {
+            return;
+        }
+        match vertex {
+            Some(vertex) => { self.vertices.insert(Self::physical_address(address),vertex); }
+            None if !self.vertices.is_empty() => { self.vertices.remove(&Self::physical_address(address)); }
+            None => {}
+        }
+    }
+
+    /*
+    The precise vertex at the given address, if it belongs to the word read there
+     */
+    #[inline]
+    pub fn lookup(&self,address:u32,value:u32) -> Option<PgxpVertex> {
+        if !self.enabled || self.vertices.is_empty() {
+            return None;
+        }
+        PgxpVertex::matching(self.vertices.get(&Self::physical_address(address)).copied(),value)
+    }
+}
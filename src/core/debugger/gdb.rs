@@ -0,0 +1,431 @@
+use crate::core::debugger::{BreakKind, BreakPoint, BreakPoints, CpuRegister, DebuggerCommand, DebuggerResponse, RunMode};
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/*
+GDB remote serial protocol stub, see https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+It drives the emulator through the same commands of the CLI debugger: when GDB connects the CPU is stopped in step by step mode,
+continue switches to break mode (free mode without breakpoints) and Ctrl-C stops it again.
+Registers use the GDB MIPS layout (no target description):
+  0-31 GPR, 32 SR, 33 LO, 34 HI, 35 BadVaddr, 36 CAUSE, 37 PC, 38-71 FPU (unavailable)
+Software and hardware breakpoints are both execute breakpoints, watchpoints are read/write breakpoints on every byte of the watched range.
+ */
+const GDB_REGISTERS : usize = 72;
+const GDB_PC_REGISTER : usize = 37;
+const MAX_MEMORY_PACKET : usize = 0x800;
+const RESPONSE_TIMEOUT : Duration = Duration::from_secs(2);
+const INTERRUPT_POLL : Duration = Duration::from_millis(50);
+const SIGINT : u8 = 2;
+const SIGTRAP : u8 = 5;
+
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+/*
+Packets framing: $<data>#<checksum>, acknowledged with +
+ */
+struct GdbConnection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl GdbConnection {
+    fn new(stream:TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, buffer: Vec::new() })
+    }
+
+    // reads available data: false if nothing arrived before the timeout (None = wait forever)
+    fn fill(&mut self,timeout:Option<Duration>) -> io::Result<bool> {
+        self.stream.set_read_timeout(timeout)?;
+        let mut data = [0u8;1024];
+        match self.stream.read(&mut data) {
+            Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof,"GDB disconnected")),
+            Ok(n) => {
+                self.buffer.extend_from_slice(&data[..n]);
+                Ok(true)
+            }
+            Err(e) if matches!(e.kind(),io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn next_packet(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            match self.buffer.first() {
+                None => return Ok(None),
+                Some(0x03) => {
+                    self.buffer.remove(0);
+                    return Ok(Some(Packet::Interrupt));
+                }
+                Some(b'$') => {
+                    let Some(end) = self.buffer.iter().position(|b| *b == b'#') else { return Ok(None) };
+                    if self.buffer.len() < end + 3 {
+                        return Ok(None);
+                    }
+                    let data = self.buffer[1..end].to_vec();
+                    let checksum = std::str::from_utf8(&self.buffer[end + 1..end + 3]).ok().and_then(|cs| u8::from_str_radix(cs,16).ok());
+                    self.buffer.drain(..end + 3);
+                    if checksum != Some(data.iter().fold(0u8,|cs,b| cs.wrapping_add(*b))) {
+                        warn!("GDB packet with wrong checksum");
+                        self.stream.write_all(b"-")?;
+                        continue;
+                    }
+                    self.stream.write_all(b"+")?;
+                    return Ok(Some(Packet::Command(String::from_utf8_lossy(&data).to_string())));
+                }
+                Some(_) => {
+                    // acks and garbage
+                    self.buffer.remove(0);
+                }
+            }
+        }
+    }
+
+    fn read_packet(&mut self) -> io::Result<Packet> {
+        loop {
+            if let Some(packet) = self.next_packet()? {
+                return Ok(packet);
+            }
+            self.fill(None)?;
+        }
+    }
+
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        if self.fill(Some(INTERRUPT_POLL))? {
+            while let Some(packet) = self.next_packet()? {
+                match packet {
+                    Packet::Interrupt => return Ok(true),
+                    Packet::Command(command) => debug!("GDB packet '{}' ignored while running",command),
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn send_packet(&mut self,data:&str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8,|cs,b| cs.wrapping_add(b));
+        self.stream.write_all(format!("${}#{:02x}",data,checksum).as_bytes())
+    }
+}
+
+// registers are sent in target (little endian) byte order
+fn hex_u32(value:u32) -> String {
+    format!("{:08x}",value.swap_bytes())
+}
+
+fn parse_hex_u32(hex:&str) -> Option<u32> {
+    u32::from_str_radix(hex,16).ok().map(u32::swap_bytes)
+}
+
+fn parse_hex_bytes(hex:&str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?,16).ok()).collect()
+}
+
+// <address>,<length> or <address>,<kind>
+fn parse_address_length(args:&str) -> Option<(u32,usize)> {
+    let (address,length) = args.split_once(',')?;
+    Some((u32::from_str_radix(address,16).ok()?,usize::from_str_radix(length,16).ok()?))
+}
+
+fn gdb_register(index:usize) -> Option<CpuRegister> {
+    match index {
+        0..=31 => Some(CpuRegister::Gpr(index)),
+        32 => Some(CpuRegister::Cop0(12)),
+        33 => Some(CpuRegister::Lo),
+        34 => Some(CpuRegister::Hi),
+        35 => Some(CpuRegister::Cop0(8)),
+        36 => Some(CpuRegister::Cop0(13)),
+        GDB_PC_REGISTER => Some(CpuRegister::Pc),
+        _ => None,
+    }
+}
+
+pub struct GdbStub {
+    receiver: Receiver<DebuggerResponse>,
+    sender: Sender<DebuggerCommand>,
+    port: u16,
+    break_points: BreakPoints,
+    // watchpoints as set by GDB (address, length, Z packet type): overlapping ones share the watched bytes
+    watch_points: Vec<(u32,usize,u8)>,
+}
+
+impl GdbStub {
+    pub fn new(receiver: Receiver<DebuggerResponse>, sender: Sender<DebuggerCommand>, port: u16) -> Self {
+        Self { receiver, sender, port, break_points: BreakPoints::new(), watch_points: Vec::new() }
+    }
+
+    pub fn execute(&mut self) {
+        let listener = match TcpListener::bind(("127.0.0.1",self.port)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Cannot start GDB stub on port {}: {}",self.port,e);
+                return;
+            }
+        };
+        info!("GDB stub listening on 127.0.0.1:{}",self.port);
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    info!("GDB connected from {:?}",stream.peer_addr());
+                    if let Err(e) = self.session(stream) {
+                        info!("GDB session ended: {}",e);
+                    }
+                    // the emulation goes on without the debugger
+                    self.break_points = BreakPoints::new();
+                    self.watch_points.clear();
+                    if self.sender.send(DebuggerCommand::RunModeChanged(RunMode::FreeMode)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => warn!("GDB connection error: {}",e),
+            }
+        }
+    }
+
+    fn session(&mut self,stream:TcpStream) -> io::Result<()> {
+        let mut connection = GdbConnection::new(stream)?;
+        self.break_points = BreakPoints::new();
+        self.watch_points.clear();
+        // stops the CPU
+        self.drain_responses();
+        self.send(DebuggerCommand::Step)?;
+        self.wait_stop(&mut connection,false)?;
+
+        loop {
+            let command = match connection.read_packet()? {
+                Packet::Interrupt => continue, // already stopped
+                Packet::Command(command) => command,
+            };
+            debug!("GDB packet '{}'",command);
+            let reply = match command.as_bytes().first() {
+                Some(b'?') => format!("S{:02x}",SIGTRAP),
+                Some(b'g') => self.read_registers()?,
+                Some(b'G') => self.write_registers(&command[1..])?,
+                Some(b'p') => self.read_register(&command[1..])?,
+                Some(b'P') => self.write_register(&command[1..])?,
+                Some(b'm') => self.read_memory(&command[1..])?,
+                Some(b'M') => self.write_memory(&command[1..])?,
+                Some(b'Z') => self.set_break_point(&command[1..],true),
+                Some(b'z') => self.set_break_point(&command[1..],false),
+                Some(b's') => {
+                    self.send(DebuggerCommand::Step)?;
+                    self.wait_stop(&mut connection,false)?
+                }
+                Some(b'c') => self.resume(&mut connection)?,
+                Some(b'D') => {
+                    connection.send_packet("OK")?;
+                    return Ok(());
+                }
+                Some(b'k') => return Ok(()),
+                Some(b'H') | Some(b'T') => String::from("OK"),
+                _ => self.query(&command),
+            };
+            connection.send_packet(&reply)?;
+        }
+    }
+
+    fn query(&self,command:&str) -> String {
+        match command.split(':').next().unwrap_or_default() {
+            "qSupported" => format!("PacketSize={:x}",MAX_MEMORY_PACKET * 2 + 16),
+            "qAttached" => String::from("1"),
+            "qC" => String::from("QC1"),
+            "qfThreadInfo" => String::from("m1"),
+            "qsThreadInfo" => String::from("l"),
+            _ => String::new(), // unsupported
+        }
+    }
+
+    fn send(&self,command:DebuggerCommand) -> io::Result<()> {
+        self.sender.send(command).map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe,"emulator stopped"))
+    }
+
+    fn drain_responses(&self) {
+        while self.receiver.try_recv().is_ok() {}
+    }
+
+    fn response(&self) -> io::Result<DebuggerResponse> {
+        self.receiver.recv_timeout(RESPONSE_TIMEOUT).map_err(|_| io::Error::new(io::ErrorKind::TimedOut,"no response from emulator"))
+    }
+
+    /*
+    Waits until the CPU stops (the emulator sends the registers when entering step by step mode) and returns the stop reply.
+    While running a Ctrl-C from GDB stops the CPU.
+     */
+    fn wait_stop(&self,connection:&mut GdbConnection,interruptible:bool) -> io::Result<String> {
+        let mut signal = SIGTRAP;
+        let mut watch = None;
+        loop {
+            match self.receiver.recv_timeout(if interruptible { INTERRUPT_POLL } else { RESPONSE_TIMEOUT }) {
+                Ok(DebuggerResponse::BreakAt(address,kind)) => {
+                    info!("GDB: break on {:?} at {:08X}",kind,address);
+                    watch = match kind {
+                        BreakKind::Read => Some(format!("rwatch:{:x};",address)),
+                        BreakKind::Write => Some(format!("watch:{:x};",address)),
+                        _ => None,
+                    };
+                }
+                Ok(DebuggerResponse::CpuRegs(..)) => {
+                    return Ok(match watch {
+                        Some(watch) => format!("T{:02x}{}",signal,watch),
+                        None => format!("S{:02x}",signal),
+                    });
+                }
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) if interruptible => {
+                    if connection.poll_interrupt()? {
+                        signal = SIGINT;
+                        self.send(DebuggerCommand::Step)?;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(io::Error::new(io::ErrorKind::TimedOut,"no response from emulator")),
+                Err(RecvTimeoutError::Disconnected) => return Err(io::Error::new(io::ErrorKind::BrokenPipe,"emulator stopped")),
+            }
+        }
+    }
+
+    fn resume(&self,connection:&mut GdbConnection) -> io::Result<String> {
+        // a breakpoint on the current instruction would stop the CPU immediately: the instruction is executed first
+        if let Some(pc) = self.get_registers()?.get(GDB_PC_REGISTER).copied() && self.break_points.execute.contains_key(&pc) {
+            self.send(DebuggerCommand::Step)?;
+            self.wait_stop(connection,false)?;
+        }
+        self.drain_responses();
+        let mode = if self.break_points.is_empty() { RunMode::FreeMode } else { RunMode::BreakMode(self.break_points.clone()) };
+        self.send(DebuggerCommand::RunModeChanged(mode))?;
+        self.wait_stop(connection,true)
+    }
+
+    /*
+    The 38 available registers in GDB order
+     */
+    fn get_registers(&self) -> io::Result<Vec<u32>> {
+        self.drain_responses();
+        self.send(DebuggerCommand::ReqCpuRegs)?;
+        let DebuggerResponse::CpuRegs(_,cpu,_) = self.response()? else {
+            return Err(io::Error::other("unexpected response to registers request"));
+        };
+        self.send(DebuggerCommand::ReqCop0Regs)?;
+        let DebuggerResponse::Cop0Regs(cop0) = self.response()? else {
+            return Err(io::Error::other("unexpected response to COP0 registers request"));
+        };
+        let mut registers = cpu.regs.to_vec();
+        registers.extend([cop0.regs[12],cpu.lo,cpu.hi,cop0.regs[8],cop0.regs[13],cpu.pc]);
+        Ok(registers)
+    }
+
+    fn read_registers(&self) -> io::Result<String> {
+        let registers = self.get_registers()?;
+        let mut reply : String = registers.iter().map(|r| hex_u32(*r)).collect();
+        reply.push_str(&"x".repeat((GDB_REGISTERS - registers.len()) * 8));
+        Ok(reply)
+    }
+
+    fn read_register(&self,args:&str) -> io::Result<String> {
+        let Ok(index) = usize::from_str_radix(args,16) else { return Ok(String::from("E01")) };
+        Ok(match self.get_registers()?.get(index) {
+            Some(value) => hex_u32(*value),
+            None => String::from("xxxxxxxx"),
+        })
+    }
+
+    fn write_registers(&self,args:&str) -> io::Result<String> {
+        for index in 0..GDB_REGISTERS {
+            let Some(hex) = args.get(index * 8..index * 8 + 8) else { break };
+            if let (Some(register),Some(value)) = (gdb_register(index),parse_hex_u32(hex)) {
+                self.send(DebuggerCommand::WriteRegister(register,value))?;
+            }
+        }
+        Ok(String::from("OK"))
+    }
+
+    fn write_register(&self,args:&str) -> io::Result<String> {
+        let Some((index,value)) = args.split_once('=') else { return Ok(String::from("E01")) };
+        match (usize::from_str_radix(index,16).ok(),parse_hex_u32(value)) {
+            (Some(index),Some(value)) => {
+                // FPU registers are ignored
+                if let Some(register) = gdb_register(index) {
+                    self.send(DebuggerCommand::WriteRegister(register,value))?;
+                }
+                Ok(String::from("OK"))
+            }
+            _ => Ok(String::from("E01")),
+        }
+    }
+
+    fn read_memory(&self,args:&str) -> io::Result<String> {
+        let Some((address,length)) = parse_address_length(args) else { return Ok(String::from("E01")) };
+        self.drain_responses();
+        self.send(DebuggerCommand::ReadMemory(address,length.min(MAX_MEMORY_PACKET),8))?;
+        match self.response()? {
+            // unmapped addresses are read as FF
+            DebuggerResponse::Memory(_,bytes) => Ok(bytes.iter().map(|b| format!("{:02x}",*b as u8)).collect()),
+            _ => Ok(String::from("E02")),
+        }
+    }
+
+    fn write_memory(&self,args:&str) -> io::Result<String> {
+        let Some((range,data)) = args.split_once(':') else { return Ok(String::from("E01")) };
+        match (parse_address_length(range),parse_hex_bytes(data)) {
+            (Some((address,length)),Some(bytes)) if bytes.len() == length => {
+                self.send(DebuggerCommand::WriteMemory(address,bytes))?;
+                Ok(String::from("OK"))
+            }
+            _ => Ok(String::from("E01")),
+        }
+    }
+
+    /*
+    Z0/Z1 software/hardware breakpoint, Z2 write watchpoint, Z3 read watchpoint, Z4 access watchpoint.
+    A watchpoint is removed only by the same address, length and type it was set with.
+     */
+    fn set_break_point(&mut self,args:&str,add:bool) -> String {
+        let Some((kind,args)) = args.split_once(',') else { return String::from("E01") };
+        let Some((address,length)) = parse_address_length(args.split(';').next().unwrap_or_default()) else { return String::from("E01") };
+        let kind = match kind {
+            "0" | "1" => {
+                if add {
+                    self.break_points.execute.insert(address,BreakPoint::default());
+                }
+                else {
+                    self.break_points.execute.remove(&address);
+                }
+                return String::from("OK");
+            }
+            "2" => 2,
+            "3" => 3,
+            "4" => 4,
+            _ => return String::new(),
+        };
+        if add {
+            self.watch_points.push((address,length,kind));
+        }
+        else {
+            let Some(index) = self.watch_points.iter().position(|w| *w == (address,length,kind)) else { return String::from("E01") };
+            self.watch_points.remove(index);
+        }
+        self.break_points.read.clear();
+        self.break_points.write.clear();
+        for &(address,length,kind) in self.watch_points.iter() {
+            // half/word accesses are reported with their aligned address
+            for address in (address & !3)..address.wrapping_add(length.max(1) as u32) {
+                if kind != 2 {
+                    self.break_points.read.insert(address,BreakPoint::default());
+                }
+                if kind != 3 {
+                    self.break_points.write.insert(address,BreakPoint::default());
+                }
+            }
+        }
+        String::from("OK")
+    }
+}
@@ -0,0 +1,206 @@
+/*
+RAM search (cheat finder) over the 2MB of main RAM.
+A search starts with a snapshot of the RAM: every aligned address of the chosen width is a candidate.
+Each filter compares the current value of the candidates with the value they had at the previous step
+(or with a given value) and keeps only the matching ones, then the current values become the previous ones.
+ */
+
+const MAX_LISTED_CANDIDATES : usize = 1000;
+// after a filter the candidates are listed if they are no more than this
+const AUTO_LISTED_CANDIDATES : usize = 10;
+
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub enum SearchWidth {
+    #[default]
+    Byte,
+    Half,
+    Word,
+}
+
+impl SearchWidth {
+    pub fn from_bits(bits:usize) -> Option<Self> {
+        match bits {
+            8 => Some(SearchWidth::Byte),
+            16 => Some(SearchWidth::Half),
+            32 => Some(SearchWidth::Word),
+            _ => None,
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        match self {
+            SearchWidth::Byte => 1,
+            SearchWidth::Half => 2,
+            SearchWidth::Word => 4,
+        }
+    }
+}
+
+#[derive(Debug,Clone,Copy)]
+pub enum SearchFilter {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value(i64),
+}
+
+#[derive(Debug)]
+pub enum MemorySearchCommand {
+    Start(SearchWidth,bool), // width, signed
+    Filter(SearchFilter),
+    List(usize),
+    AddCheat(u32,Option<i64>), // address, value (current one if none)
+}
+
+#[derive(Debug,Clone)]
+pub struct SearchCandidate {
+    pub address: u32,
+    pub previous: i64,
+    pub current: i64,
+}
+
+/*
+Result of a search command, sent to the debugger
+ */
+#[derive(Debug)]
+pub struct SearchReport {
+    pub width: SearchWidth,
+    pub signed: bool,
+    pub total: usize,
+    pub candidates: Vec<SearchCandidate>,
+    pub cheat: Option<String>,
+    pub error: Option<String>,
+}
+
+impl SearchReport {
+    pub fn dump(&self) -> String {
+        if let Some(error) = &self.error {
+            return format!("Search error: {}\n",error);
+        }
+        if let Some(cheat) = &self.cheat {
+            return format!("Cheat added: {} (F4 switches cheats on/off)\n",cheat);
+        }
+        let mut dump = format!("{} candidates ({}-bit {})\n",self.total,self.width.bytes() * 8,if self.signed { "signed" } else { "unsigned" });
+        for candidate in self.candidates.iter() {
+            dump.push_str(&format!("{:08X} {:>11} (previous {})\n",candidate.address,candidate.current,candidate.previous));
+        }
+        if self.total > self.candidates.len() && !self.candidates.is_empty() {
+            dump.push_str(&format!("... {} more\n",self.total - self.candidates.len()));
+        }
+        dump
+    }
+}
+
+#[derive(Default)]
+pub struct MemorySearch {
+    width: SearchWidth,
+    signed: bool,
+    // RAM offsets of the candidates and their values at the previous step
+    addresses: Vec<u32>,
+    previous: Vec<i64>,
+    started: bool,
+}
+
+impl MemorySearch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self,ram:&[u8],offset:u32) -> i64 {
+        let offset = offset as usize;
+        match (self.width,self.signed) {
+            (SearchWidth::Byte,false) => ram[offset] as i64,
+            (SearchWidth::Byte,true) => ram[offset] as i8 as i64,
+            (SearchWidth::Half,false) => u16::from_le_bytes([ram[offset],ram[offset + 1]]) as i64,
+            (SearchWidth::Half,true) => i16::from_le_bytes([ram[offset],ram[offset + 1]]) as i64,
+            (SearchWidth::Word,false) => u32::from_le_bytes([ram[offset],ram[offset + 1],ram[offset + 2],ram[offset + 3]]) as i64,
+            (SearchWidth::Word,true) => i32::from_le_bytes([ram[offset],ram[offset + 1],ram[offset + 2],ram[offset + 3]]) as i64,
+        }
+    }
+
+    /*
+    Values are given as typed by the user: they are adjusted to the search width and signedness
+     */
+    fn normalize(&self,value:i64) -> i64 {
+        match (self.width,self.signed) {
+            (SearchWidth::Byte,false) => value as u8 as i64,
+            (SearchWidth::Byte,true) => value as i8 as i64,
+            (SearchWidth::Half,false) => value as u16 as i64,
+            (SearchWidth::Half,true) => value as i16 as i64,
+            (SearchWidth::Word,false) => value as u32 as i64,
+            (SearchWidth::Word,true) => value as i32 as i64,
+        }
+    }
+
+    // RAM offset of a KUSEG/KSEG0/KSEG1 address (mirrors included)
+    fn ram_offset(address:u32,ram_len:usize) -> u32 {
+        (address & 0x1FFFFFFF) % ram_len as u32
+    }
+
+    pub fn execute(&mut self,command:MemorySearchCommand,ram:&[u8]) -> SearchReport {
+        let mut report = SearchReport { width: self.width, signed: self.signed, total: 0, candidates: Vec::new(), cheat: None, error: None };
+        let mut list_count = 0;
+        match command {
+            MemorySearchCommand::Start(width,signed) => {
+                self.width = width;
+                self.signed = signed;
+                self.addresses = (0..ram.len() as u32).step_by(width.bytes()).collect();
+                self.previous = self.addresses.iter().map(|a| self.read(ram,*a)).collect();
+                self.started = true;
+                report.width = width;
+                report.signed = signed;
+            }
+            MemorySearchCommand::Filter(filter) if self.started => {
+                let value = if let SearchFilter::Value(value) = filter { self.normalize(value) } else { 0 };
+                let mut addresses = Vec::new();
+                let mut previous = Vec::new();
+                for (address,prev) in self.addresses.iter().zip(self.previous.iter()) {
+                    let current = self.read(ram,*address);
+                    let keep = match filter {
+                        SearchFilter::Equal => current == *prev,
+                        SearchFilter::Changed => current != *prev,
+                        SearchFilter::Increased => current > *prev,
+                        SearchFilter::Decreased => current < *prev,
+                        SearchFilter::Value(_) => current == value,
+                    };
+                    if keep {
+                        addresses.push(*address);
+                        previous.push(current);
+                    }
+                }
+                self.addresses = addresses;
+                self.previous = previous;
+                if self.addresses.len() <= AUTO_LISTED_CANDIDATES {
+                    list_count = AUTO_LISTED_CANDIDATES;
+                }
+            }
+            MemorySearchCommand::List(count) if self.started => {
+                list_count = count.min(MAX_LISTED_CANDIDATES);
+            }
+            MemorySearchCommand::AddCheat(address,value) if self.started => {
+                let offset = Self::ram_offset(address,ram.len());
+                if !(offset as usize).is_multiple_of(self.width.bytes()) {
+                    report.error = Some(format!("Address {:08X} is not aligned to {} bytes",address,self.width.bytes()));
+                }
+                else {
+                    let value = self.normalize(value.unwrap_or_else(|| self.read(ram,offset)));
+                    report.cheat = Some(match self.width {
+                        SearchWidth::Byte => format!("30{:06X} {:04X}",offset,value as u8),
+                        SearchWidth::Half => format!("80{:06X} {:04X}",offset,value as u16),
+                        SearchWidth::Word => format!("90{:06X} {:08X}",offset,value as u32),
+                    });
+                }
+            }
+            _ => {
+                report.error = Some(String::from("no search started"));
+            }
+        }
+        report.candidates = self.addresses.iter().zip(self.previous.iter())
+            .take(list_count)
+            .map(|(address,previous)| SearchCandidate { address: 0x80000000 | address, previous: *previous, current: self.read(ram,*address) })
+            .collect();
+        report.total = self.addresses.len();
+        report
+    }
+}
@@ -1,10 +1,27 @@
-use crate::core::cpu::disassembler::Disassembled;
-use std::collections::HashSet;
+use crate::core::cpu::disassembler::{register_alias, Disassembled};
+use crate::core::cpu::instruction::Opcode;
+use crate::core::debugger::memory_search::{MemorySearchCommand, SearchFilter, SearchReport, SearchWidth};
+use crate::core::debugger::symbols::SymbolTable;
+use crate::core::unimplemented;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::io;
 use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+
+pub mod memory_search;
+pub mod gdb;
+pub mod symbols;
 
 const DUMP_MEMORY_COLUMNS : usize = 16;
+const SEARCH_LIST_DEFAULT : usize = 20;
+const DISASSEMBLY_DEFAULT : usize = 16;
+// jr $ra
+const RETURN_OPCODE : u32 = 0x03E00008;
 
 #[derive(Debug,PartialEq)]
 pub enum RunMode {
@@ -13,29 +30,229 @@ pub enum RunMode {
     BreakMode(BreakPoints),
 }
 
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ConditionOperand {
+    Register(CpuRegister),
+    Memory(u32,usize), // address, 8/16/32
+}
+
+/*
+Breakpoint condition: <register|[address]> <op> <value>, values are compared as unsigned
+ */
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct BreakCondition {
+    pub operand: ConditionOperand,
+    pub op: CompareOp,
+    pub value: u32,
+}
+
+impl BreakCondition {
+    /*
+    Operands: r0-r31 or register aliases (a0, sp, ...), pc, lo, hi, sr, cause, epc, badvaddr
+    or memory [hex address] (word), w[..] h[..] b[..]
+     */
+    fn parse(args:&[&str]) -> Result<Self,String> {
+        let [operand,op,value] = args else { return Err(String::from("Wrong condition: expected <register|[hex address]> <op> <value>")) };
+        let operand = operand.to_lowercase();
+        let memory = |prefix:&str,size:usize| -> Option<Result<ConditionOperand,String>> {
+            let address = operand.strip_prefix(prefix)?.strip_suffix(']')?;
+            Some(u32::from_str_radix(address,16).map(|address| ConditionOperand::Memory(address,size)).map_err(|_| format!("Invalid address '{}'",address)))
+        };
+        let operand = match memory("[",32).or_else(|| memory("w[",32)).or_else(|| memory("h[",16)).or_else(|| memory("b[",8)) {
+            Some(operand) => operand?,
+            None => ConditionOperand::Register(parse_register(&operand).ok_or_else(|| format!("Unknown register '{}'",operand))?),
+        };
+        let op = match *op {
+            "==" => CompareOp::Equal,
+            "!=" => CompareOp::NotEqual,
+            "<" => CompareOp::Less,
+            "<=" => CompareOp::LessOrEqual,
+            ">" => CompareOp::Greater,
+            ">=" => CompareOp::GreaterOrEqual,
+            _ => return Err(format!("Unknown operator '{}': expected == != < <= > >=",op)),
+        };
+        Ok(Self { operand, op, value: parse_value(value)? as u32 })
+    }
+
+    pub fn is_true(&self,operand_value:u32) -> bool {
+        match self.op {
+            CompareOp::Equal => operand_value == self.value,
+            CompareOp::NotEqual => operand_value != self.value,
+            CompareOp::Less => operand_value < self.value,
+            CompareOp::LessOrEqual => operand_value <= self.value,
+            CompareOp::Greater => operand_value > self.value,
+            CompareOp::GreaterOrEqual => operand_value >= self.value,
+        }
+    }
+}
+
+impl Display for BreakCondition {
+    fn fmt(&self,f:&mut Formatter<'_>) -> std::fmt::Result {
+        let operand = match self.operand {
+            ConditionOperand::Register(CpuRegister::Gpr(r)) => register_alias(r),
+            ConditionOperand::Register(CpuRegister::Cop0(r)) => crate::core::cpu::disassembler::cop_register_alias(0,r,false),
+            ConditionOperand::Register(register) => format!("{:?}",register).to_lowercase(),
+            ConditionOperand::Memory(address,size) => format!("{}[{:08X}]",match size { 8 => "b", 16 => "h", _ => "w" },address),
+        };
+        let op = match self.op {
+            CompareOp::Equal => "==",
+            CompareOp::NotEqual => "!=",
+            CompareOp::Less => "<",
+            CompareOp::LessOrEqual => "<=",
+            CompareOp::Greater => ">",
+            CompareOp::GreaterOrEqual => ">=",
+        };
+        write!(f,"{} {} {:X}",operand,op,self.value)
+    }
+}
+
+fn parse_register(name:&str) -> Option<CpuRegister> {
+    let name = name.trim_start_matches('$');
+    match name {
+        "pc" => Some(CpuRegister::Pc),
+        "lo" => Some(CpuRegister::Lo),
+        "hi" => Some(CpuRegister::Hi),
+        "badvaddr" => Some(CpuRegister::Cop0(8)),
+        "sr" => Some(CpuRegister::Cop0(12)),
+        "cause" => Some(CpuRegister::Cop0(13)),
+        "epc" => Some(CpuRegister::Cop0(14)),
+        _ => {
+            if let Some(index) = name.strip_prefix('r').and_then(|r| r.parse::<usize>().ok()) && index < 32 {
+                return Some(CpuRegister::Gpr(index));
+            }
+            (0..32).find(|r| register_alias(*r).trim_start_matches('$') == name || (name == "s8" && *r == 30)).map(CpuRegister::Gpr)
+        }
+    }
+}
+
+// decimal, or hex with 0x, optionally negative
+fn parse_value(v:&str) -> Result<i64,String> {
+    let (negative,digits) = match v.strip_prefix('-') { Some(digits) => (true,digits), None => (false,v) };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex,16),
+        None => digits.parse::<i64>(),
+    }.map_err(|_| format!("Invalid value '{}'",v))?;
+    Ok(if negative { -value } else { value })
+}
+
+/*
+A breakpoint with an optional condition: it breaks from the from_hit-th time the condition is true.
+The hits are counted by the emulator and shared with the debugger's copy, so they can be listed.
+ */
+#[derive(Debug,Clone,Default)]
+pub struct BreakPoint {
+    pub condition: Option<BreakCondition>,
+    pub from_hit: u32,
+    hits: Arc<AtomicU32>,
+}
+
+impl PartialEq for BreakPoint {
+    fn eq(&self,other:&Self) -> bool {
+        self.condition == other.condition && self.from_hit == other.from_hit
+    }
+}
+
+impl BreakPoint {
+    pub fn new(condition:Option<BreakCondition>,from_hit:u32) -> Self {
+        Self { condition, from_hit, hits: Arc::new(AtomicU32::new(0)) }
+    }
+
+    // counts a hit: true if the breakpoint must break
+    pub fn hit(&self) -> bool {
+        let hits = self.hits.fetch_add(1,Ordering::Relaxed) + 1;
+        hits >= self.from_hit
+    }
+
+    pub fn hits(&self) -> u32 {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+impl Display for BreakPoint {
+    fn fmt(&self,f:&mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(condition) = &self.condition {
+            write!(f," if {}",condition)?;
+        }
+        if self.from_hit > 1 {
+            write!(f," from hit {}",self.from_hit)?;
+        }
+        write!(f," ({} hits)",self.hits())
+    }
+}
+
 #[derive(Debug,PartialEq,Clone)]
 pub struct BreakPoints {
-    pub execute: HashSet<u32>,
-    pub read: HashSet<u32>,
-    pub write: HashSet<u32>,
+    pub execute: HashMap<u32,BreakPoint>,
+    pub read: HashMap<u32,BreakPoint>,
+    pub write: HashMap<u32,BreakPoint>,
     pub opcode: Option<u32>,
+    // step out: calls depth, the break happens after the return from the current function (see check_step_out)
+    pub step_out: Option<Cell<i32>>,
 }
 
 impl BreakPoints {
     pub fn new() -> Self {
         Self {
-            execute: HashSet::new(),
-            read: HashSet::new(),
-            write: HashSet::new(),
+            execute: HashMap::new(),
+            read: HashMap::new(),
+            write: HashMap::new(),
             opcode: None,
+            step_out: None,
         }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.execute.is_empty() && self.read.is_empty() && self.write.is_empty() && self.opcode.is_none()
+        self.execute.is_empty() && self.read.is_empty() && self.write.is_empty() && self.opcode.is_none() && self.step_out.is_none()
+    }
+
+    /*
+    Called before every instruction with the last executed opcode: calls increase the depth, returns (jr $ra) decrease it.
+    The return from the current function sets the depth to -1: the break happens after its delay slot.
+     */
+    pub fn check_step_out(&self,last_opcode:u32) -> bool {
+        let Some(depth) = &self.step_out else { return false };
+        if depth.get() < 0 {
+            return true;
+        }
+        let is_call = last_opcode >> 26 == 3 || (last_opcode >> 26 == 0 && last_opcode & 0x3F == 9); // jal, jalr
+        if is_call {
+            depth.set(depth.get() + 1);
+        }
+        else if last_opcode == RETURN_OPCODE {
+            depth.set(depth.get() - 1);
+        }
+        false
     }
 }
 
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum BreakKind {
+    Opcode,
+    Execute,
+    Read,
+    Write,
+    StepOut,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum CpuRegister {
+    Gpr(usize),
+    Lo,
+    Hi,
+    Pc,
+    Cop0(usize),
+}
+
 #[derive(Debug)]
 pub struct CpuRegisters {
     pub pc : u32,
@@ -81,14 +298,19 @@ pub enum DebuggerCommand {
     ReqCop0Regs,
     ReqCpuRegs,
     ReadMemory(u32,usize,usize), // address, size, 8/16/32,
+    WriteMemory(u32,Vec<u8>), // address, bytes
+    WriteRegister(CpuRegister,u32),
     Log(String),
+    Snapshot(bool,Option<String>), // whole vram, file name
+    MemorySearch(MemorySearchCommand),
 }
 #[derive(Debug)]
 pub enum DebuggerResponse {
     CpuRegs(Disassembled,CpuRegisters,usize),
     Cop0Regs(Cop0Registers),
     Memory(u32,Vec<u32>),
-    BreakAt(u32), // address,
+    BreakAt(u32,BreakKind), // address, reason
+    MemorySearch(SearchReport),
 }
 
 pub struct Debugger {
@@ -96,11 +318,82 @@ pub struct Debugger {
     sender: Sender<DebuggerCommand>,
     break_points: BreakPoints,
     step_by_step_mode: bool,
+    symbols: SymbolTable,
+    // last instruction and registers received
+    current: Option<(Disassembled,CpuRegisters)>,
 }
 
 impl Debugger {
     pub fn new(receiver: Receiver<DebuggerResponse>, sender: Sender<DebuggerCommand>) -> Self {
-        Self { receiver, sender, break_points: BreakPoints::new(), step_by_step_mode: true }
+        Self { receiver, sender, break_points: BreakPoints::new(), step_by_step_mode: true, symbols: SymbolTable::default(), current: None }
+    }
+
+    fn symbol_suffix(&self,address:u32) -> String {
+        self.symbols.lookup(address).map(|symbol| format!(" <{}>",symbol)).unwrap_or_default()
+    }
+
+    /*
+    Disassembled instruction with the symbol of its address and of the jump/branch target
+     */
+    fn format_instruction(&self,dis:&Disassembled) -> String {
+        let mut line = match self.symbols.lookup(dis.address) {
+            Some(symbol) => format!("{:<24} {}",symbol,dis.formatted),
+            None => format!("{:<24} {}","",dis.formatted),
+        };
+        let is_jump = matches!(dis.opcode,Opcode::J | Opcode::JAL | Opcode::BEQ | Opcode::BNE | Opcode::BLEZ | Opcode::BGTZ | Opcode::BLTZ | Opcode::BGEZ | Opcode::BLTZAL | Opcode::BGEZAL);
+        let target = dis.parameters.rsplit(',').next().map(|t| t.trim().trim_start_matches("0x")).and_then(|t| u32::from_str_radix(t,16).ok());
+        if is_jump && let Some(target) = target {
+            line.push_str(&self.symbol_suffix(target));
+        }
+        line
+    }
+
+    /*
+    Runs in break mode with the given breakpoints (step over/out) and waits for the break
+     */
+    fn run_until_break(&mut self,break_points:BreakPoints) {
+        self.sender.send(DebuggerCommand::RunModeChanged(RunMode::BreakMode(break_points))).unwrap();
+        self.step_by_step_mode = false;
+        self.wait_resp(true);
+    }
+
+    /*
+    break <add|remove> <o|x|r|w> <hex address|opcode> [if <register|[hex address]> <op> <value>] [hit <n>]
+     */
+    fn break_command(&mut self,args:&[&str]) -> Result<(),String> {
+        let usage = "Wrong arguments for 'break' command: expected <add|remove> <o|x|r|w> <hex address|opcode> [if <register|[hex address]> <op> <value>] [hit <n>]";
+        let [action,bp_type,address,options @ ..] = args else { return Err(usage.to_string()) };
+        let address = u32::from_str_radix(address,16).map_err(|_| format!("Invalid address '{}'",address))?;
+        let add = match *action {
+            "add"|"a" => true,
+            "remove"|"r" => false,
+            _ => return Err(format!("Unrecognized breakpoint action: {}",action)),
+        };
+        let (condition,from_hit) = match options {
+            [] => (None,0),
+            ["if",operand,op,value] => (Some(BreakCondition::parse(&[operand,op,value])?),0),
+            ["hit",n] => (None,n.parse::<u32>().map_err(|_| format!("Invalid hit count '{}'",n))?),
+            ["if",operand,op,value,"hit",n] => (Some(BreakCondition::parse(&[operand,op,value])?),n.parse::<u32>().map_err(|_| format!("Invalid hit count '{}'",n))?),
+            _ => return Err(usage.to_string()),
+        };
+        let (break_points,name) = match *bp_type {
+            "o" => {
+                self.break_points.opcode = if add { Some(address) } else { None };
+                return Ok(());
+            }
+            "x" => (&mut self.break_points.execute,"execute"),
+            "r" => (&mut self.break_points.read,"read"),
+            "w" => (&mut self.break_points.write,"write"),
+            _ => return Err(format!("Unrecognized breakpoint type: {}",bp_type)),
+        };
+        if add {
+            break_points.insert(address,BreakPoint::new(condition,from_hit));
+        }
+        else {
+            break_points.remove(&address);
+        }
+        println!("{} {} breakpoint at {:08X}{}",if add {"Add"} else {"Remove"},name,address,self.symbol_suffix(address));
+        Ok(())
     }
 
     fn wait_resp(&mut self,wait:bool) {
@@ -137,6 +430,15 @@ impl Debugger {
                         self.sender.send(DebuggerCommand::Log(args[0].to_string())).unwrap();
                     }
                 },
+                "snap" => {
+                    let args = command_iter.collect::<Vec<&str>>();
+                    if args.is_empty() || args.len() > 2 || (args[0] != "frame" && args[0] != "vram") {
+                        println!("Wrong arguments for 'snap' command: expected <frame|vram> [file]");
+                    }
+                    else {
+                        self.sender.send(DebuggerCommand::Snapshot(args[0] == "vram",args.get(1).map(|f| f.to_string()))).unwrap();
+                    }
+                },
                 cmd@"regs" => {
                     self.sender.send(DebuggerCommand::ReqCpuRegs).unwrap();
                     self.handle_response(self.receiver.recv().unwrap(),cmd);
@@ -154,6 +456,7 @@ impl Debugger {
                 "go" => {
                     if self.break_points.is_empty() {
                         self.sender.send(DebuggerCommand::RunModeChanged(RunMode::FreeMode)).unwrap();
+                        self.step_by_step_mode = false;
                         println!("No breakpoints set, switching to Free Mode");
                     }
                     else {
@@ -197,80 +500,118 @@ impl Debugger {
                 },
                 "break"|"b" => {
                     let args = command_iter.collect::<Vec<&str>>();
-                    if args.len() == 0 {
-                        println!("Break on execute:");
-                        for (i,addr) in self.break_points.execute.iter().enumerate() {
-                            println!("{:02}:{:08X}",i,addr);
-                        }
-                        println!("Break on read:");
-                        for (i,addr) in self.break_points.read.iter().enumerate() {
-                            println!("{:02}:{:08X}",i,addr);
-                        }
-                        println!("Break on write:");
-                        for (i,addr) in self.break_points.write.iter().enumerate() {
-                            println!("{:02}:{:08X}",i,addr);
-                        }
+                    if args.is_empty() {
+                        let list = |name:&str,break_points:&HashMap<u32,BreakPoint>| {
+                            println!("Break on {}:",name);
+                            let mut addresses = break_points.keys().collect::<Vec<&u32>>();
+                            addresses.sort();
+                            for (i,address) in addresses.into_iter().enumerate() {
+                                println!("{:02}:{:08X}{}{}",i,address,self.symbol_suffix(*address),break_points[address]);
+                            }
+                        };
+                        list("execute",&self.break_points.execute);
+                        list("read",&self.break_points.read);
+                        list("write",&self.break_points.write);
                         println!("Break on opcode:");
                         if let Some(opcode) = self.break_points.opcode {
                             println!("{:08X}",opcode);
                         }
                     }
-                    else if args.len() != 3 {
-                        println!("Wrong number of arguments for 'break' command: expected <add/remove> <o|x|r|w> <hex address|opcode>");
+                    else if let Err(e) = self.break_command(&args) {
+                        println!("{}",e);
+                    }
+                }
+                cmd@("d"|"dis") => {
+                    let args = command_iter.collect::<Vec<&str>>();
+                    let address = match args.first() {
+                        Some(address) => u32::from_str_radix(address,16).ok(),
+                        None => self.current.as_ref().map(|(_,regs)| regs.pc),
+                    };
+                    let count = match args.get(1) {
+                        Some(count) => count.parse().ok(),
+                        None => Some(DISASSEMBLY_DEFAULT),
+                    };
+                    match (address,count) {
+                        _ if !self.step_by_step_mode => println!("Not in step mode: press ENTER to step in"),
+                        (Some(address),Some(count)) => {
+                            self.sender.send(DebuggerCommand::ReadMemory(address & !3,count,32)).unwrap();
+                            self.handle_response(self.receiver.recv().unwrap(),cmd);
+                        }
+                        _ => println!("Wrong arguments for 'd' command: expected [hex address] [count] (address is PC if omitted in step mode)"),
+                    }
+                }
+                "n"|"next" => {
+                    match self.current.as_ref() {
+                        Some((dis,regs)) if self.step_by_step_mode => {
+                            if matches!(dis.opcode,Opcode::JAL | Opcode::JALR | Opcode::BLTZAL | Opcode::BGEZAL) {
+                                // runs until the instruction after the delay slot, with the same stack pointer (recursive calls)
+                                let condition = BreakCondition { operand: ConditionOperand::Register(CpuRegister::Gpr(29)), op: CompareOp::Equal, value: regs.regs[29] };
+                                let mut break_points = self.break_points.clone();
+                                break_points.execute.insert(regs.pc.wrapping_add(8),BreakPoint::new(Some(condition),0));
+                                self.run_until_break(break_points);
+                            }
+                            else {
+                                self.sender.send(DebuggerCommand::Step).unwrap();
+                                self.handle_response(self.receiver.recv().unwrap(),"");
+                            }
+                        }
+                        _ => println!("Not in step mode: press ENTER to step in"),
+                    }
+                }
+                "out"|"finish" => {
+                    if self.current.is_some() && self.step_by_step_mode {
+                        let mut break_points = self.break_points.clone();
+                        break_points.step_out = Some(Cell::new(0));
+                        self.run_until_break(break_points);
                     }
                     else {
-                        let bp_type = args[1];
-                        let address = u32::from_str_radix(args[2], 16).unwrap();
-                        let add = match args[0] {
-                            "add"|"a" => true,
-                            "remove"|"r" => false,
-                            _ => {
-                                println!("Unrecognized breakpoint action: {}",args[0]);
-                                continue;
+                        println!("Not in step mode: press ENTER to step in");
+                    }
+                }
+                "sym" => {
+                    let args = command_iter.collect::<Vec<&str>>();
+                    match args[..] {
+                        [] => println!("{} symbols loaded",self.symbols.count()),
+                        ["clear"] => {
+                            self.symbols.clear();
+                            println!("Symbols cleared");
+                        }
+                        _ => {
+                            let file = args.join(" ");
+                            match self.symbols.load(Path::new(&file)) {
+                                Ok(count) => println!("{} symbols loaded from '{}' ({} total)",count,file,self.symbols.count()),
+                                Err(e) => println!("{}",e),
                             }
-                        };
-                        match bp_type {
-                            "o" => {
-                                if add {
-                                    self.break_points.opcode = Some(address)
-                                }
-                                else {
-                                    self.break_points.opcode = None
-                                }
-                            },
-                            "x" => {
-                                if add {
-                                    self.break_points.execute.insert(address);
-                                }
-                                else {
-                                    self.break_points.execute.remove(&address);
-                                }
-                                println!("{} execute breakpoint at {:08X}",if add {"Add"} else {"Remove"},address);
-                            },
-                            "r" => {
-                                if add {
-                                    self.break_points.read.insert(address);
-                                }
-                                else {
-                                    self.break_points.read.remove(&address);
-                                }
-                                println!("{} read breakpoint at {:08X}",if add {"Add"} else {"Remove"},address);
-                            },
-                            "w" => {
-                                if add {
-                                    self.break_points.write.insert(address);
-                                }
-                                else {
-                                    self.break_points.write.remove(&address);
-                                }
-                                println!("{} write breakpoint at {:08X}",if add {"Add"} else {"Remove"},address);
-                            },
-                            _ => {
-                                println!("Unrecognized breakpoint type: {}",bp_type);
+                        }
+                    }
+                }
+                "hw" => {
+                    match command_iter.next() {
+                        Some("clear") => {
+                            unimplemented::clear();
+                            println!("Unimplemented hardware report cleared");
+                        }
+                        _ => {
+                            let entries = unimplemented::entries();
+                            if entries.is_empty() {
+                                println!("No unimplemented hardware accessed");
+                            }
+                            for (access,count) in entries {
+                                println!("{:>8} {}",count,access);
                             }
                         }
                     }
                 }
+                "search" => {
+                    let args = command_iter.collect::<Vec<&str>>();
+                    match Self::parse_search_command(&args) {
+                        Ok(command) => {
+                            self.sender.send(DebuggerCommand::MemorySearch(command)).unwrap();
+                            self.handle_response(self.receiver.recv().unwrap(),cmd);
+                        }
+                        Err(e) => println!("{}",e),
+                    }
+                }
                 cmd => {
                     println!("Unrecognized command {cmd}")
                 }
@@ -278,6 +619,35 @@ impl Debugger {
         }
     }
 
+    /*
+    search start <8|16|32> [s]      snapshot of the RAM (s for signed values): all the addresses are candidates
+    search eq|ne|inc|dec            keeps the candidates unchanged/changed/increased/decreased since the previous step
+    search val <value>              keeps the candidates with the given value (decimal, or hex with 0x)
+    search list [count]             lists the candidates
+    search cheat <hex address> [value] adds a cheat writing the given value (the current one if omitted)
+     */
+    fn parse_search_command(args:&[&str]) -> Result<MemorySearchCommand,String> {
+        match args {
+            ["start",width] | ["start",width,"s"|"u"] => {
+                let width = width.parse::<usize>().ok().and_then(SearchWidth::from_bits).ok_or_else(|| format!("Invalid search width '{}': expected 8, 16 or 32",width))?;
+                Ok(MemorySearchCommand::Start(width,args.get(2) == Some(&"s")))
+            }
+            ["eq"] => Ok(MemorySearchCommand::Filter(SearchFilter::Equal)),
+            ["ne"] => Ok(MemorySearchCommand::Filter(SearchFilter::Changed)),
+            ["inc"] => Ok(MemorySearchCommand::Filter(SearchFilter::Increased)),
+            ["dec"] => Ok(MemorySearchCommand::Filter(SearchFilter::Decreased)),
+            ["val",value] => Ok(MemorySearchCommand::Filter(SearchFilter::Value(parse_value(value)?))),
+            ["list"] => Ok(MemorySearchCommand::List(SEARCH_LIST_DEFAULT)),
+            ["list",count] => Ok(MemorySearchCommand::List(count.parse().map_err(|_| format!("Invalid count '{}'",count))?)),
+            ["cheat",address] | ["cheat",address,_] => {
+                let address = u32::from_str_radix(address,16).map_err(|_| format!("Invalid address '{}'",address))?;
+                let value = match args.get(2) { Some(v) => Some(parse_value(v)?), None => None };
+                Ok(MemorySearchCommand::AddCheat(address,value))
+            }
+            _ => Err(String::from("Wrong arguments for 'search' command: expected start <8|16|32> [s] | eq | ne | inc | dec | val <value> | list [count] | cheat <hex address> [value]")),
+        }
+    }
+
     fn adjust_mem_len(&self,n:usize) -> usize {
         let rem = n % DUMP_MEMORY_COLUMNS;
         if rem == 0 {
@@ -290,8 +660,11 @@ impl Debugger {
 
     fn handle_response(&mut self,resp:DebuggerResponse,cmd:&str) {
         match resp {
-            DebuggerResponse::BreakAt(address) => {
-                println!("Break at {:08X}",address);
+            DebuggerResponse::BreakAt(address,kind) => {
+                match kind {
+                    BreakKind::StepOut => println!("Returned to {:08X}{}",address,self.symbol_suffix(address)),
+                    _ => println!("Break on {:?} at {:08X}{}",kind,address,self.symbol_suffix(address)),
+                }
                 self.step_by_step_mode = true;
                 self.wait_resp(true);
             },
@@ -300,12 +673,27 @@ impl Debugger {
                     println!("CPU Registers [{}]:\n{}",cycles,regs.dump());
                 }
                 if cmd != "regs" {
-                    println!("{}", dis.formatted);
+                    println!("{}", self.format_instruction(&dis));
                 }
+                self.current = Some((dis,regs));
             },
             DebuggerResponse::Cop0Regs(regs) => {
                 println!("Cop0 Registers:\n{}",regs.dump());
             },
+            DebuggerResponse::MemorySearch(report) => {
+                print!("{}",report.dump());
+            },
+            DebuggerResponse::Memory(address,mem) if cmd == "d" || cmd == "dis" => {
+                let pc = self.current.as_ref().map(|(_,regs)| regs.pc);
+                for (i,opcode) in mem.iter().enumerate() {
+                    let address = address.wrapping_add(i as u32 * 4);
+                    if let Some(symbol) = self.symbols.get(address) {
+                        println!("{}:",symbol);
+                    }
+                    let dis = crate::core::cpu::disassembler::disassemble(address,*opcode);
+                    println!("{}{}",if pc == Some(address) { ">" } else { " " },self.format_instruction(&dis));
+                }
+            },
             DebuggerResponse::Memory(address,mem) => {
                 let mut buffer = String::new();
                 let mut ascii = String::new();
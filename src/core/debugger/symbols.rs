@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// an address farther than this from the previous symbol is shown without symbol
+const MAX_SYMBOL_OFFSET : u32 = 0x10000;
+
+/*
+Symbols loaded from text symbol files: every line made of an hex address and a name, in any order, is a symbol.
+This covers no$psx/pcsx .sym files ("80010000 main"), Psy-Q .map files and GNU ld map files ("0x80010000   main"),
+the other lines (sections, object files, assignments) are ignored.
+ */
+#[derive(Default)]
+pub struct SymbolTable {
+    symbols: BTreeMap<u32,String>,
+}
+
+fn parse_address(token:&str) -> Option<u32> {
+    let (hex,prefixed) = match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+        Some(hex) => (hex,true),
+        None => (token,false),
+    };
+    // without prefix only full 32 bit addresses, to not mistake names like "face" for addresses
+    if (!prefixed && hex.len() != 8) || hex.is_empty() || hex.len() > 16 {
+        return None;
+    }
+    u64::from_str_radix(hex,16).ok().map(|address| address as u32)
+}
+
+fn is_symbol_name(token:&str) -> bool {
+    let mut chars = token.chars();
+    // names starting with '.' are sections
+    matches!(chars.next(),Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+}
+
+impl SymbolTable {
+    /*
+    Adds the symbols of the given file, returns the number of symbols read
+     */
+    pub fn load(&mut self,path:&Path) -> Result<usize,String> {
+        let data = std::fs::read(path).map_err(|e| format!("Cannot read symbol file '{}': {}",path.display(),e))?;
+        if data.starts_with(b"MND") {
+            return Err(format!("'{}' is a binary Psy-Q SYM file: convert it to text (i.e. with dumpsym) before loading it",path.display()));
+        }
+        let text = String::from_utf8_lossy(&data);
+        let mut count = 0;
+        for line in text.lines() {
+            let tokens = line.split_whitespace().collect::<Vec<&str>>();
+            let [first,second] = tokens[..] else { continue };
+            let symbol = match (parse_address(first),parse_address(second)) {
+                (Some(address),_) if is_symbol_name(second) => Some((address,second)),
+                (_,Some(address)) if is_symbol_name(first) => Some((address,first)),
+                _ => None,
+            };
+            if let Some((address,name)) = symbol {
+                self.symbols.insert(address,name.to_string());
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn count(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.symbols.clear();
+    }
+
+    // symbol at exactly the given address
+    pub fn get(&self,address:u32) -> Option<&str> {
+        self.symbols.get(&address).map(|name| name.as_str())
+    }
+
+    /*
+    The address as symbol+offset, i.e. main+0x10
+     */
+    pub fn lookup(&self,address:u32) -> Option<String> {
+        let (symbol_address,name) = self.symbols.range(..=address).next_back()?;
+        match address - symbol_address {
+            0 => Some(name.clone()),
+            offset if offset < MAX_SYMBOL_OFFSET => Some(format!("{}+0x{:X}",name,offset)),
+            _ => None,
+        }
+    }
+}
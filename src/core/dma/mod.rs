@@ -1,10 +1,12 @@
 use crate::core::clock::Clock;
+use crate::core::cpu::pgxp::PgxpVertex;
 use crate::core::interrupt::{InterruptType, IrqHandler};
 use crate::core::memory::bus::Bus;
 use crate::core::memory::{Memory, ReadMemoryAccess, WriteMemoryAccess};
 use std::cell::RefCell;
 use std::rc::Rc;
 use tracing::{debug, info, warn};
+use crate::core::savestate::{impl_state_enum, impl_state_struct};
 use crate::core::Resettable;
 
 pub trait DmaDevice {
@@ -14,6 +16,8 @@ pub trait DmaDevice {
     fn dma_request(&self) -> bool;
     // RAM -> device
     fn dma_write(&mut self, word: u32,clock:&mut Clock,irq_handler:&mut IrqHandler);
+    // PGXP: precise vertex of the next word written, used by the GPU only
+    fn dma_pgxp_vertex(&mut self, _vertex: PgxpVertex) {}
     // device -> RAM
     fn dma_read(&mut self) -> u32;
     // tells how many cpu cycles are needed to transfer one word
@@ -178,6 +182,13 @@ impl Resettable for DMAChannel {
     }
 }
 
+impl_state_enum!(SyncMode { SyncMode::Manual, SyncMode::Slice, SyncMode::LinkedList, SyncMode::Reserved });
+impl_state_enum!(TransferDirection { TransferDirection::DeviceToRAM, TransferDirection::RAMToDevice });
+impl_state_struct!(DMAChannel {
+    madr, madr_read, bcr, chcr, enabled, bus_error, sync_mode, transfer_direction, remaining_words, remaining_blocks,
+    waiting_next_block, chopping_window_words, chopping_window_cycles, linked_list_header, header_in_a_row_count, cycles_per_word
+});
+
 impl DMAChannel {
     fn new(id:usize,device: &Rc<RefCell<dyn DmaDevice>>) -> DMAChannel {
         DMAChannel {
@@ -350,7 +361,12 @@ impl DMAChannel {
             }
             TransferDirection::RAMToDevice => {
                 match bus.read::<32>(target, false) {
-                    ReadMemoryAccess::Read(mem_read,_) => dma_device.dma_write(mem_read,bus.get_clock_mut(),irq_handler),
+                    ReadMemoryAccess::Read(mem_read,_) => {
+                        if let Some(vertex) = bus.get_pgxp().lookup(target,mem_read) {
+                            dma_device.dma_pgxp_vertex(vertex);
+                        }
+                        dma_device.dma_write(mem_read,bus.get_clock_mut(),irq_handler)
+                    }
                     _ => {
                         warn!("DMA Bus error while accessing address {:08X}",target);
                         self.bus_error = true
@@ -446,6 +462,9 @@ impl DMAChannel {
         else {
             self.header_in_a_row_count = 0;
             // send word to device
+            if let Some(vertex) = bus.get_pgxp().lookup(target,word) {
+                self.device.borrow_mut().dma_pgxp_vertex(vertex);
+            }
             self.device.borrow_mut().dma_write(word,bus.get_clock_mut(),irq_handler);
             self.linked_list_header = Some((next_node_address, extra_words - 1));
             DMAResult::LeaveBus
@@ -611,6 +630,11 @@ impl Resettable for DMAController {
     }
 }
 
+impl_state_struct!(DMAController, "DMA" {
+    channels, dpcr, dpcr_changed, chcr_changed, dcir, priorities, irq_flags, reg_f8, reg_fc,
+    dma_in_progress_on_channel, dma_enabled, dma_pending_cycles
+});
+
 impl DMAController {
     pub fn new(devices:&[Rc<RefCell<dyn DmaDevice>>;7]) -> Self {
         Self {
@@ -778,6 +802,11 @@ impl DMAController {
         self.reg_fc = value;
     }
 
+    // no transfer in progress or pending: nothing can start until a DMA register is written or a device becomes ready
+    pub fn is_idle(&self) -> bool {
+        self.dma_in_progress_on_channel.is_none() && self.dma_pending_cycles == 0
+    }
+
     pub fn do_dma_for_cpu_cycles(&mut self,mut cpu_cycles:usize,bus:&mut Bus,irq_handler:&mut IrqHandler) -> bool {
         if self.dma_pending_cycles > 0 {
             self.dma_pending_cycles = self.dma_pending_cycles.saturating_sub(cpu_cycles);
@@ -1,15 +1,22 @@
+mod rewind;
+mod movie;
+
 use crate::audio::cpal::CpalAudioDevice;
+use crate::audio::null::NullAudioDevice;
 use crate::audio::{AudioDevice, AudioSample};
-use crate::cheats::Cheats;
+use crate::cheats::{Cheat, Cheats};
 use crate::core::bios::PS1_BIOS_SET;
+use crate::core::cdrom::disc::Disc;
+use crate::core::cdrom::playlist::Playlist;
 use crate::core::cdrom::{CDOperation, CDRom, Region};
 use crate::core::clock::EventType;
 use crate::core::clock::{ClockConfig, Event};
-use crate::core::config::{Config, ControllerType, RegionPolicyConfig};
+use crate::core::config::{Config, ControllerType, CpuBackend, MovieConfig, RegionPolicyConfig};
 use crate::core::controllers::MouseInfo;
 use crate::core::cpu::{disassembler, Cpu};
-use crate::core::debugger::{BreakPoints, DebuggerCommand};
+use crate::core::debugger::{BreakKind, BreakPoint, BreakPoints, ConditionOperand, CpuRegister, DebuggerCommand};
 use crate::core::debugger::{DebuggerResponse, RunMode};
+use crate::core::debugger::memory_search::{MemorySearch, MemorySearchCommand};
 use crate::core::dma::{DMAController, DmaDevice, DummyDMAChannel};
 use crate::core::gpu::{VideoMode, GPU};
 use crate::core::interrupt::IrqHandler;
@@ -17,7 +24,10 @@ use crate::core::mdec::{MDec, MDecIn, MDecOut};
 use crate::core::memory::bus::Bus;
 use crate::core::memory::{ArrayMemory, Memory, ReadMemoryAccess, BIOS_LEN};
 use crate::core::spu::{AdpcmInterpolation, Spu};
-use crate::core::{debugger, Resettable};
+use crate::core::savestate::{StateHeader, StateSync};
+use crate::core::emu::rewind::RewindBuffer;
+use crate::core::emu::movie::{Movie, MovieInput};
+use crate::core::{debugger, unimplemented, Resettable};
 use crate::log::Logger;
 use crate::renderer::{GUIEvent, MouseAccumulator, Renderer};
 use build_time::build_time_local;
@@ -36,6 +46,22 @@ pub const EMU_NAME : &str = env!("CARGO_PKG_NAME");
 pub const EMU_VERSION : &str = env!("CARGO_PKG_VERSION");
 pub const EMU_BUILD_DATE_TIME : &str = build_time_local!("%d/%m/%Y %H:%M:%S");
 
+const STATE_SLOTS : u8 = 10;
+
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum HeadlessStopReason {
+    MaxFrames,
+    MaxCycles,
+    TtyMatched,
+    MovieEnded,
+}
+
+#[derive(Debug,Copy,Clone)]
+enum StateOperation {
+    Save,
+    Load,
+}
+
 struct Perf {
     last_timestamp: Instant,
     last_cycles: u64,
@@ -99,11 +125,28 @@ pub struct Emulator {
     perf: Perf,
     last_cd_op: CDOperation,
     config:Config,
+    // configuration without per-game overrides
+    global_config:Config,
     cheats: Cheats,
     cheats_on: bool,
+    memory_search: MemorySearch,
     shutting_down: bool,
     mouse_accumulator: Arc<MouseAccumulator>,
     mouse_enabled: bool,
+    disc_path: Option<String>,
+    disc_serial: Option<String>,
+    playlist: Option<Playlist>,
+    state_slot: u8,
+    pending_state_op: Option<StateOperation>,
+    rewind: Option<RewindBuffer>,
+    // rewind key held
+    rewinding: bool,
+    // a rewind snapshot/step must be done at the next safe point
+    rewind_pending: bool,
+    // input movie being recorded or played
+    movie: Option<Movie>,
+    frames: u64,
+    headless_stop_reason: Option<HeadlessStopReason>,
 }
 
 impl Resettable for Emulator {
@@ -116,6 +159,10 @@ impl Resettable for Emulator {
         self.dma_in_progress = false;
         self.perf.initialized = false;
         self.last_cd_op = CDOperation::Idle;
+        if hard_reset {
+            self.disc_path = None;
+        }
+        self.clear_rewind();
 
         // send first hblank event
         self.gpu.borrow_mut().send_first_hblank_event(self.bus.get_clock_mut());
@@ -188,29 +235,112 @@ impl Emulator {
             dma_in_progress: false,
             perf: Perf::new(Duration::from_millis(4000)),
             last_cd_op: CDOperation::Idle,
+            global_config: config.clone(),
             config,
             cheats: Cheats::new(),
             cheats_on: false,
+            memory_search: MemorySearch::new(),
             shutting_down: false,
             mouse_accumulator: mouse_acc,
             mouse_enabled: false,
+            disc_path: None,
+            disc_serial: None,
+            playlist: None,
+            state_slot: 0,
+            pending_state_op: None,
+            rewind: None,
+            rewinding: false,
+            rewind_pending: false,
+            movie: None,
+            frames: 0,
+            headless_stop_reason: None,
         };
 
-        // mouse
-        emu.mouse_enabled = matches!(emu.config.controllers.controller_1.controller_type,ControllerType::Mouse) || matches!(emu.config.controllers.controller_2.controller_type,ControllerType::Mouse);
+        emu.update_mouse_enabled();
+        emu.update_usb_analog_sticks();
+        emu.load_cheats(None);
+
+        // rewind
+        if emu.config.rewind_config.rewind_enabled {
+            let rewind_config = &emu.config.rewind_config;
+            info!("Rewind enabled: a snapshot every {} frames, {} snapshots",rewind_config.snapshot_interval_in_frames,rewind_config.depth_in_snapshots);
+            emu.rewind = Some(RewindBuffer::new(rewind_config.snapshot_interval_in_frames,rewind_config.depth_in_snapshots));
+        }
+
+        emu
+    }
 
-        // cheats
-        if emu.config.cheats_config.cheats_enabled {
+    fn update_mouse_enabled(&mut self) {
+        self.mouse_enabled = matches!(self.config.controllers.controller_1.controller_type,ControllerType::Mouse) || matches!(self.config.controllers.controller_2.controller_type,ControllerType::Mouse);
+    }
+
+    /*
+    The USB sticks are forwarded as analog axes to the analog controllers, and mapped to the d-pad for the others
+     */
+    fn update_usb_analog_sticks(&mut self) {
+        let types = [self.config.controllers.controller_1.controller_type,self.config.controllers.controller_2.controller_type];
+        let mut gpu = self.gpu.borrow_mut();
+        for (controller,controller_type) in types.into_iter().enumerate() {
+            gpu.get_renderer_mut().set_analog_sticks(controller,matches!(controller_type,ControllerType::Analog));
+        }
+    }
+
+    /*
+    Cheats of the configuration file, of the cheats_file and of the cheat database entry of the disc
+     */
+    fn load_cheats(&mut self,serial:Option<&str>) {
+        self.cheats = Cheats::new();
+        if self.config.cheats_config.cheats_enabled {
+            let mut cheats = Cheats::parse(&self.config.cheats_config.cheats_codes);
+            let db_file = serial.map(|serial| Path::new(&self.config.cheats_config.cheats_path).join(format!("{}.cht",serial))).filter(|path| path.exists());
+            for path in self.config.cheats_config.cheats_file.iter().map(PathBuf::from).chain(db_file) {
+                match Cheats::load_cht(&path) {
+                    Ok(file_cheats) => cheats.append(file_cheats),
+                    Err(e) => error!("{}",e),
+                }
+            }
             info!("Cheats enabled:");
-            let cheats = Cheats::parse(&emu.config.cheats_config.cheats_codes);
             for cheat in cheats.cheats.iter() {
-                info!("{:?}",cheat);
+                info!("{} [{}]: {:?}",cheat.label(),if cheat.enabled { "on" } else { "off" },cheat.codes);
             }
-            
-            emu.cheats = cheats;
+
+            self.cheats = cheats;
         }
+    }
 
-        emu
+    /*
+    Switches to the configuration of the disc with the given serial (global configuration + per-game overrides)
+    and applies the settings that can change at disc load
+     */
+    fn apply_game_config(&mut self,serial:Option<&str>) {
+        self.config = match serial {
+            Some(serial) => self.global_config.with_game_overrides(serial),
+            None => self.global_config.clone(),
+        };
+        self.gpu.borrow_mut().set_command_delay_enabled(self.config.gpu_config.command_delay_enabled);
+        self.gpu.borrow_mut().set_internal_resolution(self.config.gpu_config.internal_resolution);
+        self.gpu.borrow_mut().set_texture_cache_enabled(self.config.gpu_config.texture_cache_enabled);
+        self.gpu.borrow_mut().set_deinterlace_mode(self.config.gpu_config.deinterlace_mode);
+        self.set_pgxp_enabled(self.config.gpu_config.pgxp_enabled,self.config.gpu_config.pgxp_perspective_correct);
+        self.cpu.set_widescreen_hack(self.config.gpu_config.widescreen_hack);
+        self.cdrom.borrow_mut().set_fast_cd(self.config.cdrom_config.fast_cd_enabled,self.config.cdrom_config.fast_cd_read_speed);
+        let widescreen = self.config.gpu_config.widescreen_hack || self.config.gpu_config.widescreen_anamorphic;
+        self.gpu.borrow_mut().get_renderer_mut().set_widescreen(widescreen);
+        let sio0 = self.bus.get_sio0_mut();
+        sio0.set_tx_rx_cycles(self.config.controllers.tx_rx_cycles);
+        sio0.get_controller_mut(0).set_type(crate::core::controllers::ControllerType::from(self.config.controllers.controller_1.controller_type));
+        sio0.get_controller_mut(1).set_type(crate::core::controllers::ControllerType::from(self.config.controllers.controller_2.controller_type));
+        self.update_mouse_enabled();
+        self.update_usb_analog_sticks();
+        self.load_cheats(serial);
+    }
+
+    fn set_pgxp_enabled(&mut self,enabled:bool,perspective_correct:bool) {
+        if enabled != self.bus.get_pgxp().is_enabled() {
+            self.cpu.set_pgxp_enabled(enabled);
+            self.bus.get_pgxp_mut().set_enabled(enabled);
+        }
+        self.gpu.borrow_mut().set_pgxp(enabled,perspective_correct);
     }
 
     fn load_exe(&self,file_name:&str) -> Result<Vec<u8>,String> {
@@ -228,12 +358,47 @@ impl Emulator {
         }
     }
 
-    fn load_disc(&mut self,disc_path:&String,allow_exe:bool) {
+    /*
+    Loads the given disc, EXE (only if allow_exe) or playlist, returning true if loaded.
+    The per-game configuration and the console region are applied only by the first disc loaded (the one booted):
+    a disc swapped in later doesn't change the settings of the running game.
+     */
+    fn load_disc(&mut self,disc_path:&String,allow_exe:bool) -> bool {
         // check if the disc path exists
-        if !Path::new(&disc_path).exists() {
+        if !Path::new(Disc::image_path(disc_path)).exists() {
             error!("Disc path '{}' does not exist",disc_path);
-            return;
+            return false;
         }
+        if Playlist::is_playlist(disc_path) {
+            return match Playlist::load(disc_path) {
+                Ok(playlist) => {
+                    info!("Loaded playlist '{}' with {} discs",disc_path,playlist.disc_count());
+                    let first_disc = playlist.current_disc().clone();
+                    self.playlist = Some(playlist);
+                    let loaded = self.load_disc(&first_disc,false);
+                    if !loaded {
+                        self.playlist = None;
+                    }
+                    loaded
+                }
+                Err(e) => {
+                    error!("Error while loading playlist: {}",e);
+                    false
+                }
+            };
+        }
+        let loaded = self.load_disc_image(disc_path,allow_exe);
+        // a disc outside the current playlist replaces it, with the playlist of its image if it's a multi-disc one
+        if loaded && !self.playlist.as_mut().is_some_and(|playlist| playlist.select_disc(disc_path)) {
+            self.playlist = Playlist::from_multi_disc_image(disc_path);
+            if let Some(playlist) = self.playlist.as_ref() {
+                info!("Multi-disc image with {} discs",playlist.disc_count());
+            }
+        }
+        loaded
+    }
+
+    fn load_disc_image(&mut self,disc_path:&String,allow_exe:bool) -> bool {
         let load_exe_pending = allow_exe && disc_path.to_uppercase().ends_with("EXE");
 
         if load_exe_pending {
@@ -249,9 +414,11 @@ impl Emulator {
                 Ok(exe) => {
                     info!("Loading EXE file {} ...",exe_path);
                     self.bus.load_exe(exe,&mut self.cpu);
+                    true
                 }
                 Err(error) => {
                     error!("Error while loading EXE file {} : {}",exe_path,error);
+                    false
                 }
             }
         }
@@ -260,31 +427,19 @@ impl Emulator {
             if disc_path.to_uppercase().ends_with(".ZIP") {
                 self.gpu.borrow_mut().get_renderer_mut().set_last_cd_access(CDOperation::DiscUnzippingStart(disc_path.clone()));
             }
-            match crate::core::cdrom::disc::Disc::new(&disc_path) {
+            match Disc::new(disc_path) {
                 Ok(disc) => {
-                    let region = match self.config.region_policy {
-                        RegionPolicyConfig::Auto => {
-                            if disc.get_region().is_none() {
-                                warn!("Cannot found region on disc {}, default to USA",disc.get_cue_file_name());
-                            }
-                            disc.get_region().unwrap_or(Region::USA)
-                        },
-                        RegionPolicyConfig::Usa => Region::USA,
-                        RegionPolicyConfig::Japan => Region::Japan,
-                        RegionPolicyConfig::Europe => Region::Europe,
-                    };
-
-                    let (clock_config,video_mode) = match region {
-                        Region::USA | Region::Japan => (ClockConfig::NTSC,VideoMode::Ntsc),
-                        Region::Europe => (ClockConfig::PAL,VideoMode::Pal),
-                    };
-                    info!("Setting region to {:?} and clock to {:?}",region,clock_config);
-                    self.bus.get_clock_mut().set_clock_config(clock_config);
-                    self.gpu.borrow_mut().get_renderer_mut().set_region(region);
-                    self.gpu.borrow_mut().set_video_mode(video_mode);
+                    if self.disc_path.is_none() {
+                        self.apply_boot_disc_config(&disc);
+                    }
+                    else {
+                        info!("Disc swapped: the configuration of the running game is kept");
+                    }
 
                     let real_disc_name = disc.get_cue_file_name().clone();
                     self.cdrom.borrow_mut().insert_disk(disc);
+                    self.clear_rewind();
+                    self.disc_path = Some(disc_path.clone());
                     let disc_name = PathBuf::from(real_disc_name);
                     let name = Path::new(&disc_name)
                         .file_stem()
@@ -294,17 +449,74 @@ impl Emulator {
                     let re = Regex::new(r"\([^)]*\)").unwrap();
                     let name_without_parenthesis = re.replace_all(&name, "").to_string();
                     self.gpu.borrow_mut().get_renderer_mut().set_last_cd_access(CDOperation::DiscLoading(name_without_parenthesis));
+                    true
                 }
                 Err(e) => {
                     error!("Error while loading disc: {:?}",e);
                     self.gpu.borrow_mut().get_renderer_mut().set_last_cd_access(CDOperation::DiscUnzippingStop);
+                    false
                 }
             }
         }
     }
 
+    /*
+    Per-game configuration and console region of the booted disc
+     */
+    fn apply_boot_disc_config(&mut self,disc:&Disc) {
+        self.apply_game_config(disc.get_serial());
+        self.disc_serial = disc.get_serial().map(String::from);
+        let region = match self.config.region_policy {
+            RegionPolicyConfig::Auto => {
+                if disc.get_region().is_none() {
+                    warn!("Cannot found region on disc {}, default to USA",disc.get_cue_file_name());
+                }
+                disc.get_region().unwrap_or(Region::USA)
+            },
+            RegionPolicyConfig::Usa => Region::USA,
+            RegionPolicyConfig::Japan => Region::Japan,
+            RegionPolicyConfig::Europe => Region::Europe,
+        };
+
+        let (clock_config,video_mode) = match region {
+            Region::USA | Region::Japan => (ClockConfig::NTSC,VideoMode::Ntsc),
+            Region::Europe => (ClockConfig::PAL,VideoMode::Pal),
+        };
+        info!("Setting region to {:?} and clock to {:?}",region,clock_config);
+        self.bus.get_clock_mut().set_clock_config(clock_config);
+        self.gpu.borrow_mut().get_renderer_mut().set_region(region);
+        self.cdrom.borrow_mut().set_console_region(region);
+        self.gpu.borrow_mut().set_video_mode(video_mode);
+    }
+
+    pub fn get_headless_stop_reason(&self) -> Option<HeadlessStopReason> {
+        self.headless_stop_reason
+    }
+
+    fn check_headless_stop(&self) -> Option<HeadlessStopReason> {
+        let headless = self.config.headless_config.as_ref()?;
+        if let Some(max_frames) = headless.max_frames && self.frames >= max_frames {
+            Some(HeadlessStopReason::MaxFrames)
+        }
+        else if let Some(max_cycles) = headless.max_cycles && self.bus.get_clock().current_time() >= max_cycles {
+            Some(HeadlessStopReason::MaxCycles)
+        }
+        else if self.cpu.is_bios_tty_watch_found() {
+            Some(HeadlessStopReason::TtyMatched)
+        }
+        else if self.movie.as_ref().is_some_and(|movie| movie.is_finished(self.frames)) {
+            Some(HeadlessStopReason::MovieEnded)
+        }
+        else {
+            None
+        }
+    }
+
     pub fn emulate(&mut self) {
-        self.cpu.set_bios_tty_capture_enabled(self.config.tty_enabled);
+        let headless = self.config.headless_config.is_some();
+        let tty_stop_string = self.config.headless_config.as_ref().and_then(|h| h.tty_stop_string.clone());
+        self.cpu.set_bios_tty_capture_enabled(self.config.tty_enabled || tty_stop_string.is_some());
+        self.cpu.set_bios_tty_watch(tty_stop_string);
 
         // send first hblank event
         self.gpu.borrow_mut().send_first_hblank_event(self.bus.get_clock_mut());
@@ -312,7 +524,12 @@ impl Emulator {
         let (loop_tx_cmd, debugger_rx_cmd) = mpsc::channel::<DebuggerResponse>();
         let (debugger_tx_cmd, loop_rx_cmd) = mpsc::channel::<DebuggerCommand>();
 
-        if self.config.debugger_enabled {
+        if self.config.gdb_config.gdb_enabled {
+            let mut gdb_stub = debugger::gdb::GdbStub::new(debugger_rx_cmd, debugger_tx_cmd, self.config.gdb_config.gdb_port);
+            info!("Launching GDB stub ..");
+            spawn(move || gdb_stub.execute() );
+        }
+        else if self.config.debugger_enabled {
             let mut debugger = debugger::Debugger::new(debugger_rx_cmd, debugger_tx_cmd);
             info!("Launching debugger ..");
             spawn(move || debugger.execute() );
@@ -332,28 +549,60 @@ impl Emulator {
 
         // before starting of main loop, sleep a while to let the splash screen to be visible
         while let Ok(event) = self.gui_event_rx.recv() && !matches!(event,GUIEvent::Ready) {}
-            
-        thread::sleep(Duration::from_millis(2000));
+
+        if !headless {
+            thread::sleep(Duration::from_millis(2000));
+        }
         self.gpu.borrow_mut().get_renderer_mut().set_splash_screen();
 
         if let Some(disc_path) = self.config.disc_path.clone() {
             self.load_disc(&disc_path,true);
         }
+        self.start_movie();
 
         self.just_entered_in_step_mode = false;
         self.run_mode = RunMode::FreeMode;
 
         // starting audio device
-        let mut audio_cpal = CpalAudioDevice::new(self.config.audio_config.buffer_capacity_in_millis);
-        if let Ok(()) = audio_cpal.start() {
-            self.audio_device = Some(Box::new(audio_cpal));
+        if headless {
+            info!("Headless mode: audio disabled");
+            self.audio_device = Some(Box::new(NullAudioDevice::new()));
+        }
+        else {
+            let mut audio_cpal = CpalAudioDevice::new(self.config.audio_config.buffer_capacity_in_millis);
+            match audio_cpal.start() {
+                Ok(()) => self.audio_device = Some(Box::new(audio_cpal)),
+                Err(e) => {
+                    warn!("Cannot start audio device: {e}. Audio disabled");
+                    self.audio_device = Some(Box::new(NullAudioDevice::new()));
+                }
+            }
         }
         // schedule first audio event
         self.bus.get_clock_mut().schedule_audio_sample();
 
-        let debugger_enabled = self.config.debugger_enabled;
+        let debugger_enabled = self.config.debugger_enabled || self.config.gdb_config.gdb_enabled;
+        // the debugger needs to stop at every instruction
+        let cached_backend = !debugger_enabled && self.config.cpu_config.cpu_backend == CpuBackend::CachedInterpreter;
+        if cached_backend {
+            info!("CPU backend: cached interpreter");
+        }
 
         'main_loop: while !self.shutting_down {
+            // save states are handled here, where all the events have been processed and no IRQs are pending
+            if let Some(op) = self.pending_state_op.take() {
+                self.execute_state_operation(op);
+            }
+            if self.rewind_pending {
+                self.rewind_pending = false;
+                self.process_rewind();
+            }
+            if headless && let Some(reason) = self.check_headless_stop() {
+                info!("Headless mode: stopping emulation after {} frames and {} cycles, reason: {:?}",self.frames,self.bus.get_clock().current_time(),reason);
+                self.headless_stop_reason = Some(reason);
+                self.shutdown();
+                break;
+            }
             if self.just_entered_in_step_mode {
                 self.send_cpu_info(&loop_tx_cmd);
                 self.just_entered_in_step_mode = false;
@@ -375,7 +624,12 @@ impl Emulator {
                     }
                     continue 'main_loop;
                 }
-                self.last_cycles = self.cpu.execute_next_instruction(&mut self.bus,self.dma_in_progress);
+                self.last_cycles = if cached_backend && !self.dma_in_progress && self.dma.borrow().is_idle() {
+                    self.cpu.execute_block(&mut self.bus)
+                }
+                else {
+                    self.cpu.execute_next_instruction(&mut self.bus,self.dma_in_progress)
+                };
 
                 // DMA
                 self.dma_in_progress = self.dma.borrow_mut().do_dma_for_cpu_cycles(self.last_cycles, &mut self.bus,&mut irq_handler);
@@ -410,17 +664,36 @@ impl Emulator {
                 self.new_frame = self.gpu.borrow_mut().on_raster_line_end(&mut self.bus, irq_handler, event.over_cycles);
 
                 if self.new_frame {
+                    self.frames += 1;
+                    if let Some(headless) = self.config.headless_config.as_ref() && headless.dump_frames.contains(&self.frames) {
+                        let dump_vram = headless.dump_vram;
+                        self.take_snapshot(false,None);
+                        if dump_vram {
+                            self.take_snapshot(true,None);
+                        }
+                    }
                     self.check_input();
+                    self.check_movie_end();
+                    self.check_rumble();
+                    if let Some(rewind) = self.rewind.as_mut() {
+                        self.rewind_pending = rewind.frame_tick();
+                    }
                     self.gpu.borrow_mut().get_renderer_mut().set_last_cd_access(self.last_cd_op.clone());
                     // cheats
-                    if self.config.cheats_config.cheats_enabled && self.cheats_on {
-                        self.cheats.apply(&mut self.bus);
+                    if self.cheats_on {
+                        let joypad_buttons = self.bus.get_sio0_mut().get_controller_mut(0).get_pressed_buttons();
+                        self.cheats.apply(&mut self.bus,joypad_buttons);
                     }
                     // mouse
                     if self.mouse_enabled {
                         let (dx, dy, is_left_pressed, is_right_pressed) = self.mouse_accumulator.consume();
+                        let (dx, dy) = (dx.clamp(-128, 127) as i8, dy.clamp(-128, 127) as i8);
+                        let (dx, dy, is_left_pressed, is_right_pressed) = match self.movie.as_mut() {
+                            Some(movie) => movie.mouse(self.frames,(dx,dy,is_left_pressed,is_right_pressed)),
+                            None => (dx,dy,is_left_pressed,is_right_pressed),
+                        };
                         if let Some(mouse) = self.bus.get_sio0_mut().get_mouse_controller_mut() {
-                            mouse.on_mouse_event(MouseInfo::DXYMotion(dx.clamp(-128, 127) as i8, dy.clamp(-128, 127) as i8));
+                            mouse.on_mouse_event(MouseInfo::DXYMotion(dx, dy));
                         }
                         if let Some(mouse) = self.bus.get_sio0_mut().get_mouse_controller_mut() {
                             mouse.on_mouse_event(MouseInfo::LeftButton(is_left_pressed));
@@ -460,18 +733,50 @@ impl Emulator {
                     self.bus.get_clock_mut().schedule_audio_sample();
                 }
                 let clock = self.bus.get_clock();
-                let perf = self.perf.throttle(clock.current_time(),clock.get_clock_config(),self.warp_mode_enabled);
+                // headless runs are never throttled to real time
+                let unthrottled = self.warp_mode_enabled || self.config.headless_config.is_some();
+                let perf = self.perf.throttle(clock.current_time(),clock.get_clock_config(),unthrottled);
                 self.gpu.borrow_mut().set_last_cpu_perf(perf);
             }
         }
     }
 
     fn check_input(&mut self) {
-        if let Ok(event) = self.gui_event_rx.try_recv() {
+        // movie playback: the recorded inputs replace the live ones
+        let playing_movie = self.movie.as_ref().is_some_and(|movie| !movie.is_recording());
+        if let Some(movie) = self.movie.as_mut() && playing_movie {
+            for input in movie.take_inputs(self.frames) {
+                match input {
+                    MovieInput::Button(controller_id,button,pressed) => self.bus.get_sio0_mut().get_controller_mut(controller_id).on_controller_event(button, pressed),
+                    MovieInput::Axis(controller_id,axis,value) => self.bus.get_sio0_mut().get_controller_mut(controller_id).on_axis_event(axis, value),
+                    MovieInput::Mouse(..) => {}
+                }
+            }
+        }
+        // sticks movements are all applied, the other events are processed one per frame
+        while let Ok(event) = self.gui_event_rx.try_recv() {
             match event {
+                GUIEvent::Controller(..) | GUIEvent::ControllerAxis(..) if playing_movie => {
+                    continue;
+                }
                 GUIEvent::Controller(controller_id, button, pressed) => {
                     //println!("Button {:?} pressed: {}",button,pressed);
                     self.bus.get_sio0_mut().get_controller_mut(controller_id).on_controller_event(button, pressed);
+                    if let Some(movie) = self.movie.as_mut() {
+                        movie.record(self.frames,MovieInput::Button(controller_id,button,pressed));
+                    }
+                }
+                GUIEvent::ControllerAxis(controller_id, axis, value) => {
+                    self.bus.get_sio0_mut().get_controller_mut(controller_id).on_axis_event(axis, value);
+                    if let Some(movie) = self.movie.as_mut() {
+                        movie.record(self.frames,MovieInput::Axis(controller_id,axis,value));
+                    }
+                    continue;
+                }
+                // cheats are not recorded: switching them would desync the playback
+                GUIEvent::InsertDisc(_) | GUIEvent::NextDisc | GUIEvent::PrevDisc | GUIEvent::Reset(_) | GUIEvent::LoadState |
+                GUIEvent::Cheat | GUIEvent::ToggleCheat if self.movie.is_some() => {
+                    self.gpu.borrow_mut().get_renderer_mut().show_message(String::from("Not available during a movie"));
                 }
                 GUIEvent::WarpMode => {
                     self.warp_mode_enabled ^= true;
@@ -501,20 +806,340 @@ impl Emulator {
                 GUIEvent::Cheat => {
                     self.cheats_on ^= true;
                     info!("Cheating is {}",self.cheats_on);
+                    if self.cheats_on {
+                        self.cheats.reset();
+                    }
+                    let msg = if self.cheats_on { format!("Cheats on ({} active)",self.cheats.enabled_count()) } else { String::from("Cheats off") };
+                    self.gpu.borrow_mut().get_renderer_mut().show_message(msg);
+                }
+                GUIEvent::NextCheat => {
+                    let msg = match self.cheats.select_next() {
+                        Some((label,enabled)) => format!("Cheat '{}' {}",label,if enabled { "on" } else { "off" }),
+                        None => String::from("No cheats loaded"),
+                    };
+                    self.gpu.borrow_mut().get_renderer_mut().show_message(msg);
+                }
+                GUIEvent::ToggleCheat => {
+                    let msg = match self.cheats.toggle_selected() {
+                        Some((label,enabled)) => {
+                            info!("Cheat '{}' switched {}",label,if enabled { "on" } else { "off" });
+                            format!("Cheat '{}' {}",label,if enabled { "on" } else { "off" })
+                        }
+                        None => String::from("No cheats loaded"),
+                    };
+                    self.gpu.borrow_mut().get_renderer_mut().show_message(msg);
                 }
                 GUIEvent::Reset(hard_reset) => {
                     self.reset_component(hard_reset);
                 }
+                GUIEvent::SaveState => {
+                    self.pending_state_op = Some(StateOperation::Save);
+                }
+                GUIEvent::LoadState => {
+                    self.pending_state_op = Some(StateOperation::Load);
+                }
+                GUIEvent::Snapshot(whole_vram) => {
+                    self.take_snapshot(whole_vram,None);
+                }
+                GUIEvent::NextStateSlot => {
+                    self.state_slot = (self.state_slot + 1) % STATE_SLOTS;
+                    self.gpu.borrow_mut().get_renderer_mut().show_message(format!("State slot {}",self.state_slot));
+                }
+                GUIEvent::PrevStateSlot => {
+                    self.state_slot = (self.state_slot + STATE_SLOTS - 1) % STATE_SLOTS;
+                    self.gpu.borrow_mut().get_renderer_mut().show_message(format!("State slot {}",self.state_slot));
+                }
+                GUIEvent::Rewind(rewinding) => {
+                    if self.movie.is_some() {
+                        if rewinding {
+                            self.gpu.borrow_mut().get_renderer_mut().show_message(String::from("Not available during a movie"));
+                        }
+                    }
+                    else if self.rewind.is_some() {
+                        self.rewinding = rewinding;
+                    }
+                    else if rewinding {
+                        self.gpu.borrow_mut().get_renderer_mut().show_message(String::from("Rewind is disabled"));
+                    }
+                }
+                GUIEvent::NextDisc => {
+                    self.change_disc(true);
+                }
+                GUIEvent::PrevDisc => {
+                    self.change_disc(false);
+                }
                 GUIEvent::Ready => {}
             }
+            break;
+        }
+    }
+
+    /*
+    Forwards the DualShock motors changes to the renderer
+     */
+    fn check_rumble(&mut self) {
+        for controller_id in 0..2 {
+            if let Some((small,large)) = self.bus.get_sio0_mut().get_controller_mut(controller_id).take_rumble() {
+                self.gpu.borrow_mut().get_renderer_mut().set_rumble(controller_id,small,large);
+            }
+        }
+    }
+
+    /*
+    Swaps the disc with the next/previous one of the playlist: the CDROM shell is opened and then closed with the new disc.
+    The machine is not reset, so memory cards stay attached.
+     */
+    fn change_disc(&mut self,next:bool) {
+        let disc_path = match self.playlist.as_ref() {
+            Some(playlist) if playlist.disc_count() > 1 => playlist.next_disc(next).clone(),
+            _ => {
+                self.gpu.borrow_mut().get_renderer_mut().show_message(String::from("No other disc in playlist"));
+                return;
+            }
+        };
+        info!("Changing disc to '{}'",disc_path);
+        // the playlist moves to the new disc only if it's loaded
+        let msg = if self.load_disc(&disc_path,false) {
+            let index = self.playlist.as_ref().map(|p| (p.current_index() + 1,p.disc_count())).unwrap_or_default();
+            format!("Disc {}/{}",index.0,index.1)
         }
+        else {
+            String::from("Cannot load disc")
+        };
+        self.gpu.borrow_mut().get_renderer_mut().show_message(msg);
     }
     
+    fn get_disc_stem(&self) -> String {
+        self.disc_path.as_ref()
+            .and_then(|path| Path::new(path).file_stem().map(|stem| stem.to_string_lossy().to_string()))
+            .unwrap_or_else(|| String::from("bios"))
+    }
+
+    fn get_state_file_path(&self) -> PathBuf {
+        Path::new(&self.config.save_state_config.states_path).join(format!("{}.ss{}",self.get_disc_stem(),self.state_slot))
+    }
+
+    fn take_snapshot(&mut self,whole_vram:bool,file_name:Option<String>) {
+        let path = match file_name {
+            Some(file_name) => PathBuf::from(file_name),
+            None => {
+                let suffix = if whole_vram { "_vram" } else { "" };
+                Path::new(&self.config.snapshot_config.snapshots_path).join(format!("{}_{:06}{suffix}.png",self.get_disc_stem(),self.frames))
+            }
+        };
+        let result = if whole_vram {
+            self.gpu.borrow().dump_vram_png(&path)
+        }
+        else {
+            self.gpu.borrow().dump_frame_png(&path)
+        };
+        match result {
+            Ok(_) => {
+                info!("Snapshot saved to {}",path.display());
+                self.gpu.borrow_mut().get_renderer_mut().show_message(format!("Snapshot saved to {}",path.display()));
+            }
+            Err(e) => {
+                error!("Cannot save snapshot: {e}");
+            }
+        }
+    }
+
+    fn execute_state_operation(&mut self,op:StateOperation) {
+        let path = self.get_state_file_path();
+        let result = match op {
+            StateOperation::Save => self.save_state(&path),
+            StateOperation::Load => self.load_state(&path),
+        };
+        let msg = match result {
+            Ok(_) => {
+                info!("State {:?} on slot {} done: {}",op,self.state_slot,path.display());
+                match op {
+                    StateOperation::Save => format!("State saved on slot {}",self.state_slot),
+                    StateOperation::Load => format!("State loaded from slot {}",self.state_slot),
+                }
+            }
+            Err(e) => {
+                error!("State {:?} on slot {} failed: {}",op,self.state_slot,e);
+                match op {
+                    StateOperation::Save => format!("Cannot save state on slot {}",self.state_slot),
+                    StateOperation::Load => format!("Cannot load state from slot {}",self.state_slot),
+                }
+            }
+        };
+        self.gpu.borrow_mut().get_renderer_mut().show_message(msg);
+    }
+
+    fn save_machine_state(&mut self) -> Result<Vec<u8>,String> {
+        let mut s = StateSync::new_saving();
+        s.sync(&mut self.cpu);
+        s.sync(&mut self.bus);
+        s.finish()
+    }
+
+    /*
+    Every interval frames a state is pushed to the rewind buffer, or popped while the rewind key is held:
+    the game goes back at the same pace it went forward
+     */
+    fn process_rewind(&mut self) {
+        if !self.rewinding {
+            match self.save_machine_state() {
+                Ok(state) => {
+                    if let Some(rewind) = self.rewind.as_mut() {
+                        rewind.push(state);
+                    }
+                }
+                Err(e) => error!("Cannot take rewind snapshot: {}",e),
+            }
+            return;
+        }
+        let Some(state) = self.rewind.as_mut().and_then(|rewind| rewind.pop()) else {
+            self.gpu.borrow_mut().get_renderer_mut().show_message(String::from("Rewind buffer empty"));
+            return;
+        };
+        let mut s = StateSync::new_loading(state);
+        s.sync(&mut self.cpu);
+        s.sync(&mut self.bus);
+        match s.finish() {
+            Ok(_) => {
+                self.perf.initialized = false;
+                self.dma_in_progress = false;
+                let left = self.rewind.as_ref().map(|rewind| rewind.len()).unwrap_or_default();
+                self.gpu.borrow_mut().get_renderer_mut().show_message(format!("Rewinding ({} snapshots left)",left));
+            }
+            Err(e) => {
+                error!("Cannot rewind: {}",e);
+                self.clear_rewind();
+            }
+        }
+    }
+
+    /*
+    The rewind buffer is emptied when the machine changes outside the normal emulation (state loaded, reset, disc changed)
+     */
+    fn clear_rewind(&mut self) {
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+    }
+
+    fn save_state(&mut self,path:&Path) -> Result<(),String> {
+        let mut s = StateSync::new_saving();
+        let mut header = StateHeader::new(EMU_VERSION,self.bus.get_bios_md5(),self.disc_path.clone());
+        s.sync(&mut header);
+        s.sync(&mut self.cpu);
+        s.sync(&mut self.bus);
+        let state = s.finish()?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Cannot create directory {}: {}",dir.display(),e))?;
+        }
+        fs::write(path,state).map_err(|e| format!("Cannot write state file {}: {}",path.display(),e))
+    }
+
+    fn load_state(&mut self,path:&Path) -> Result<(),String> {
+        let state = fs::read(path).map_err(|e| format!("Cannot read state file {}: {}",path.display(),e))?;
+        let mut s = StateSync::new_loading(state);
+        let mut header = StateHeader::default();
+        s.sync(&mut header);
+        if s.has_error() {
+            return Err("Not a valid state file".to_string());
+        }
+        header.check(self.bus.get_bios_md5())?;
+        if header.emu_version != EMU_VERSION {
+            warn!("State saved with emulator version {}, current version is {}",header.emu_version,EMU_VERSION);
+        }
+        // the disc referenced by the state is loaded before touching the machine
+        let new_disc = match header.disc_path.as_ref() {
+            Some(path) if self.disc_path.as_ref() != Some(path) => {
+                Some(Disc::new(path).map_err(|e| format!("Cannot load disc '{}' referenced by state: {}",path,e))?)
+            }
+            _ => None
+        };
+        // keep a backup of the current machine to restore it if the state is corrupted
+        let backup = self.save_machine_state()?;
+        let disc_changed = new_disc.is_some() || header.disc_path.is_none();
+        let old_disc = if disc_changed { self.cdrom.borrow_mut().set_disc(new_disc) } else { None };
+
+        s.sync(&mut self.cpu);
+        s.sync(&mut self.bus);
+        if let Err(e) = s.finish() {
+            if disc_changed {
+                self.cdrom.borrow_mut().set_disc(old_disc);
+            }
+            let mut s = StateSync::new_loading(backup);
+            s.sync(&mut self.cpu);
+            s.sync(&mut self.bus);
+            s.finish()?;
+            return Err(e);
+        }
+
+        if disc_changed {
+            self.disc_path = header.disc_path.clone();
+            if let Some(playlist) = self.playlist.as_mut() && let Some(path) = self.disc_path.as_ref() && !playlist.select_disc(path) {
+                self.playlist = None;
+            }
+            let name = self.disc_path.as_ref()
+                .and_then(|path| Path::new(path).file_stem().map(|stem| stem.to_string_lossy().to_string()))
+                .unwrap_or_default();
+            self.gpu.borrow_mut().get_renderer_mut().set_last_cd_access(CDOperation::DiscLoading(name));
+        }
+        self.perf.initialized = false;
+        self.last_cd_op = CDOperation::Idle;
+        self.dma_in_progress = false;
+        self.clear_rewind();
+        Ok(())
+    }
+
     fn shutdown(&mut self) {
         info!("Shutting down ...");
+        unimplemented::log_report();
         
         self.bus.get_sio0_mut().get_controller_mut(0).save();
         self.bus.get_sio0_mut().get_controller_mut(1).save();
+
+        if let Some(movie) = self.movie.as_mut() && movie.is_recording() {
+            movie.set_frames(self.frames);
+            if let Err(e) = movie.save() {
+                error!("{}",e);
+            }
+        }
+    }
+
+    /*
+    Input movie recording/playback starts from power-on, after the disc has been loaded
+     */
+    fn start_movie(&mut self) {
+        let Some(movie_config) = self.config.movie_config.clone() else { return };
+        let config_hash = movie::config_hash(&self.config,&self.cheats);
+        match movie_config {
+            MovieConfig::Record(path) => {
+                self.movie = Some(Movie::new_recording(&path,EMU_VERSION,self.bus.get_bios_md5(),self.disc_serial.clone(),config_hash));
+                self.gpu.borrow_mut().get_renderer_mut().show_message(String::from("Recording movie"));
+            }
+            MovieConfig::Play(path) => {
+                match Movie::load(&path,self.bus.get_bios_md5(),self.disc_serial.as_deref(),config_hash) {
+                    Ok(movie) => {
+                        self.movie = Some(movie);
+                        self.gpu.borrow_mut().get_renderer_mut().show_message(String::from("Playing movie"));
+                    }
+                    Err(e) => {
+                        error!("Cannot play movie: {}",e);
+                        self.gpu.borrow_mut().get_renderer_mut().show_message(String::from("Cannot play movie"));
+                    }
+                }
+            }
+        }
+    }
+
+    /*
+    At the end of the playback live inputs are restored (in headless mode the emulation stops)
+     */
+    fn check_movie_end(&mut self) {
+        if self.config.headless_config.is_none() && self.movie.as_ref().is_some_and(|movie| movie.is_finished(self.frames)) {
+            info!("Movie playback ended after {} frames",self.frames);
+            self.movie = None;
+            self.gpu.borrow_mut().get_renderer_mut().show_message(String::from("Movie ended"));
+        }
     }
 
     #[inline(always)]
@@ -530,65 +1155,66 @@ impl Emulator {
                     (false,true)
                 }
                 else {
-                    (false,false)
+                    self.handle_running_commands(loop_rx_cmd,loop_tx_cmd)
                 }
             },
             RunMode::FreeMode => {
-                if self.new_frame || self.paused {
-                    while let Ok(cmd) = loop_rx_cmd.try_recv() {
-                        match cmd {
-                            DebuggerCommand::RunModeChanged(mode) => {
-                                self.run_mode = mode;
-                                if self.run_mode == RunMode::StepByStepMode {
-                                    self.just_entered_in_step_mode = true;
-                                    return (true, false)
-                                }
-                            },
-                            DebuggerCommand::Step => {
-                                self.just_entered_in_step_mode = true;
-                                self.run_mode = RunMode::StepByStepMode;
-                                return (true, true)
-                            }
-                            _ => {
-                                todo!("Unimplemented command {:?}", cmd);
-                            }
+                self.handle_running_commands(loop_rx_cmd,loop_tx_cmd)
+            }
+        }
+    }
+
+    /*
+    Debugger commands received while the CPU is running (free or break mode): checked once per frame
+     */
+    fn handle_running_commands(&mut self,loop_rx_cmd:&Receiver<DebuggerCommand>,loop_tx_cmd:&Sender<DebuggerResponse>) -> (bool,bool) {
+        if self.new_frame || self.paused {
+            while let Ok(cmd) = loop_rx_cmd.try_recv() {
+                match cmd {
+                    DebuggerCommand::RunModeChanged(mode) => {
+                        self.run_mode = mode;
+                        if self.run_mode == RunMode::StepByStepMode {
+                            self.just_entered_in_step_mode = true;
+                            return (true, false)
                         }
+                    },
+                    DebuggerCommand::Step => {
+                        self.just_entered_in_step_mode = true;
+                        self.run_mode = RunMode::StepByStepMode;
+                        return (true, true)
                     }
-                    (false,false)
-                }
-                else {
-                    (false,false)
+                    cmd => self.handle_inspection_command(cmd,loop_tx_cmd),
                 }
             }
         }
+        (false,false)
     }
 
     fn handle_break_mode(&self,breaks:&BreakPoints,loop_tx_cmd:&Sender<DebuggerResponse>) -> bool {
         let pc = self.cpu.get_pc();
-        if let Some(opcode) = breaks.opcode && self.cpu.get_last_opcode() == opcode {
+        if breaks.check_step_out(self.cpu.get_last_opcode()) {
+            info!("Step out: returned to {:08X}",pc);
+            loop_tx_cmd.send(DebuggerResponse::BreakAt(pc,BreakKind::StepOut)).unwrap();
+            true
+        }
+        else if let Some(opcode) = breaks.opcode && self.cpu.get_last_opcode() == opcode {
             info!("Break on opcode {:08X} at {:08X}",opcode,pc - 4);
-            loop_tx_cmd.send(DebuggerResponse::BreakAt(pc)).unwrap();
+            loop_tx_cmd.send(DebuggerResponse::BreakAt(pc,BreakKind::Opcode)).unwrap();
+            true
+        }
+        else if let Some(break_point) = breaks.execute.get(&pc) && self.is_break_point_hit(break_point) {
+            info!("Break on execute at {:08X}",pc);
+            loop_tx_cmd.send(DebuggerResponse::BreakAt(pc,BreakKind::Execute)).unwrap();
             true
         }
-        else if breaks.execute.contains(&pc) {
-            //*hits += 1;
-            //if *hits == 0x100 {
-                info!("Break on execute at {:08X}",pc);
-                loop_tx_cmd.send(DebuggerResponse::BreakAt(pc)).unwrap();
-                true
-            // }
-            // else {
-            //     false
-            // }
-        }
-        else if let Some(break_read_addr) = self.cpu.get_last_mem_read_address() && breaks.read.contains(&break_read_addr) {
+        else if let Some(break_read_addr) = self.cpu.get_last_mem_read_address() && let Some(break_point) = breaks.read.get(&break_read_addr) && self.is_break_point_hit(break_point) {
             info!("Break on read at {:08X}. Read value {:08X}",break_read_addr,self.cpu.get_last_mem_rw_value());
-            loop_tx_cmd.send(DebuggerResponse::BreakAt(break_read_addr)).unwrap();
+            loop_tx_cmd.send(DebuggerResponse::BreakAt(break_read_addr,BreakKind::Read)).unwrap();
             true
         }
-        else if let Some(break_write_addr) = self.cpu.get_last_mem_write_address() && breaks.write.contains(&break_write_addr) {
+        else if let Some(break_write_addr) = self.cpu.get_last_mem_write_address() && let Some(break_point) = breaks.write.get(&break_write_addr) && self.is_break_point_hit(break_point) {
             info!("Break on write at {:08X}. Written value {:08X}",break_write_addr,self.cpu.get_last_mem_rw_value());
-            loop_tx_cmd.send(DebuggerResponse::BreakAt(break_write_addr)).unwrap();
+            loop_tx_cmd.send(DebuggerResponse::BreakAt(break_write_addr,BreakKind::Write)).unwrap();
             true
         }
         else {
@@ -596,33 +1222,74 @@ impl Emulator {
         }
     }
 
+    /*
+    A breakpoint is hit when its condition is true: it breaks from the configured hit on
+     */
+    fn is_break_point_hit(&self,break_point:&BreakPoint) -> bool {
+        let condition_true = match break_point.condition {
+            None => true,
+            Some(condition) => {
+                let value = match condition.operand {
+                    ConditionOperand::Register(register) => Some(self.read_cpu_register(register)),
+                    ConditionOperand::Memory(address,8) => self.bus.peek::<8>(address),
+                    ConditionOperand::Memory(address,16) => self.bus.peek::<16>(address),
+                    ConditionOperand::Memory(address,_) => self.bus.peek::<32>(address),
+                };
+                value.is_some_and(|value| condition.is_true(value))
+            }
+        };
+        condition_true && break_point.hit()
+    }
+
+    fn read_cpu_register(&self,register:CpuRegister) -> u32 {
+        match register {
+            CpuRegister::Gpr(index) => self.cpu.get_registers()[index & 31],
+            CpuRegister::Lo => self.cpu.get_lo(),
+            CpuRegister::Hi => self.cpu.get_hi(),
+            CpuRegister::Pc => self.cpu.get_pc(),
+            CpuRegister::Cop0(index) => self.bus.get_cop0().get_regs()[index & 31],
+        }
+    }
+
     fn handle_step_by_step_mode(&mut self,loop_rx_cmd:&Receiver<DebuggerCommand>,loop_tx_cmd:&Sender<DebuggerResponse>) -> (bool,bool) {
         match loop_rx_cmd.recv().unwrap() {
-            DebuggerCommand::Log(level) => {
-                self.logger.set_log_level(level.as_str());
-                (false,true)
-            },
             DebuggerCommand::Step => {
                 // ok, step
                 (true,false)
             },
+            DebuggerCommand::RunModeChanged(mode) => {
+                self.run_mode = mode;
+                (false,false)
+            },
+            cmd => {
+                self.handle_inspection_command(cmd,loop_tx_cmd);
+                (false,true)
+            }
+        }
+    }
+
+    /*
+    Debugger commands that don't change the run mode, available both in step by step mode and while running
+     */
+    fn handle_inspection_command(&mut self,cmd:DebuggerCommand,loop_tx_cmd:&Sender<DebuggerResponse>) {
+        match cmd {
+            DebuggerCommand::Log(level) => {
+                self.logger.set_log_level(level.as_str());
+            },
+            DebuggerCommand::Snapshot(whole_vram,file_name) => {
+                self.take_snapshot(whole_vram,file_name);
+            },
             DebuggerCommand::ReqCpuRegs => {
-                match self.get_step_info() {
-                    Some(info) => {
-                        loop_tx_cmd.send(info).unwrap();
-                    },
-                    None => {}
+                if let Some(info) = self.get_step_info() {
+                    loop_tx_cmd.send(info).unwrap();
                 }
-                (false,true)
             },
             DebuggerCommand::ReqCop0Regs => {
                 let regs = self.bus.get_cop0().get_regs().clone();
                 loop_tx_cmd.send(DebuggerResponse::Cop0Regs(debugger::Cop0Registers { regs })).unwrap();
-                (false,true)
             },
-            DebuggerCommand::RunModeChanged(mode) => {
-                self.run_mode = mode;
-                (false,false)
+            DebuggerCommand::MemorySearch(command) => {
+                self.memory_search(command,loop_tx_cmd);
             },
             DebuggerCommand::ReadMemory(address, length, size) => {
                 let step = (size >> 3) as u32;
@@ -637,12 +1304,45 @@ impl Emulator {
                         _ => None
                     };
                     buffer.push(e.or_else(|| Some(0xFFFFFFFFu32)).unwrap());
-                    address += step;
+                    address = address.wrapping_add(step);
                 };
                 loop_tx_cmd.send(debugger::DebuggerResponse::Memory(base_address, buffer)).unwrap();
-                (false,true)
             },
+            DebuggerCommand::WriteMemory(address, bytes) => {
+                for (i,byte) in bytes.iter().enumerate() {
+                    self.bus.write::<8>(address.wrapping_add(i as u32),*byte as u32);
+                }
+            },
+            DebuggerCommand::WriteRegister(register, value) => {
+                match register {
+                    CpuRegister::Gpr(0) => {} // r0 is always zero
+                    CpuRegister::Gpr(index) => self.cpu.get_registers_mut()[index & 31] = value,
+                    CpuRegister::Lo => self.cpu.set_lo(value),
+                    CpuRegister::Hi => self.cpu.set_hi(value),
+                    CpuRegister::Pc => self.cpu.set_pc(value),
+                    CpuRegister::Cop0(index) => self.bus.get_cop0_mut().get_regs_mut()[index & 31] = value,
+                }
+            },
+            DebuggerCommand::Step | DebuggerCommand::RunModeChanged(_) => {}
+        }
+    }
+
+    /*
+    RAM search step: a cheat found by the search is added to the cheats and the cheats are switched on (not during a movie)
+     */
+    fn memory_search(&mut self,command:MemorySearchCommand,loop_tx_cmd:&Sender<DebuggerResponse>) {
+        let report = self.memory_search.execute(command,self.bus.get_main_ram());
+        if let Some(code) = report.cheat.as_ref() {
+            if self.movie.is_some() {
+                warn!("Cheat from RAM search not added during a movie: {}",code);
+            }
+            else {
+                info!("Adding cheat from RAM search: {}",code);
+                self.cheats.add(Cheat::new(format!("RAM search {}",code),None,true,Cheats::parse_codes(&vec![code.clone()])));
+                self.cheats_on = true;
+            }
         }
+        loop_tx_cmd.send(DebuggerResponse::MemorySearch(report)).unwrap();
     }
 
     fn send_cpu_info(&mut self,loop_tx_cmd: &Sender<DebuggerResponse>) {
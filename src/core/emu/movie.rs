@@ -0,0 +1,233 @@
+use crate::cheats::Cheats;
+use crate::core::config::Config;
+use crate::core::controllers::{ControllerAxis, ControllerButton};
+use crate::core::savestate::{impl_state_struct, SaveState, StateSync};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/*
+Input movie: the controllers inputs of a session started from power-on, by frame number.
+Recording stores every button/axis change applied to SIO0 and every mouse movement; during playback the recorded inputs
+replace the live ones, so the session is replayed exactly (the emulation is deterministic for the same bios, disc, configuration and memory cards).
+File format (savestate encoding):
+  header: magic, version, emulator version, bios MD5, disc serial, configuration hash, number of frames
+  events: frame, input type and values
+ */
+const MOVIE_MAGIC : &[u8;8] = b"RPS1MOVI";
+const MOVIE_VERSION : u32 = 1;
+
+#[derive(Default)]
+struct MovieHeader {
+    magic: [u8;8],
+    version: u32,
+    emu_version: String,
+    bios_md5: String,
+    disc_serial: Option<String>,
+    config_hash: u64,
+    frames: u64,
+}
+
+impl_state_struct!(MovieHeader { magic, version, emu_version, bios_md5, disc_serial, config_hash, frames });
+
+#[derive(Debug,Clone,Copy)]
+pub(super) enum MovieInput {
+    Button(usize,ControllerButton,bool), // controller, button, pressed
+    Axis(usize,ControllerAxis,u8), // controller, axis, value
+    Mouse(i8,i8,bool,bool), // dx, dy, left button, right button
+}
+
+#[derive(Debug,Clone,Copy)]
+struct MovieEvent {
+    frame: u64,
+    input: MovieInput,
+}
+
+impl Default for MovieEvent {
+    fn default() -> Self {
+        Self { frame: 0, input: MovieInput::Mouse(0,0,false,false) }
+    }
+}
+
+impl SaveState for MovieEvent {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.frame);
+        let mut kind = match self.input {
+            MovieInput::Button(..) => 0u8,
+            MovieInput::Axis(..) => 1,
+            MovieInput::Mouse(..) => 2,
+        };
+        s.sync(&mut kind);
+        if s.is_loading() {
+            self.input = match kind {
+                0 => MovieInput::Button(0,ControllerButton::Select,false),
+                1 => MovieInput::Axis(0,ControllerAxis::RightX,0),
+                2 => MovieInput::Mouse(0,0,false,false),
+                _ => {
+                    s.set_error(format!("Invalid movie input type {} at frame {}",kind,self.frame));
+                    return;
+                }
+            };
+        }
+        match &mut self.input {
+            MovieInput::Button(controller,button,pressed) => {
+                s.sync(controller);
+                s.sync(button);
+                s.sync(pressed);
+            }
+            MovieInput::Axis(controller,axis,value) => {
+                s.sync(controller);
+                s.sync(axis);
+                s.sync(value);
+            }
+            MovieInput::Mouse(dx,dy,left,right) => {
+                s.sync(dx);
+                s.sync(dy);
+                s.sync(left);
+                s.sync(right);
+            }
+        }
+        // the inputs are replayed on the controller they were recorded on: only 2 controllers
+        if let MovieInput::Button(controller,..) | MovieInput::Axis(controller,..) = self.input && controller > 1 {
+            s.set_error(format!("Invalid movie controller {} at frame {}",controller,self.frame));
+        }
+    }
+}
+
+/*
+Hash (FNV-1a) of the configuration properties that change the emulation, and of the loaded cheats
+(configuration codes, cheats file and .cht database entry) with their enabled state
+ */
+pub(super) fn config_hash(config:&Config,cheats:&Cheats) -> u64 {
+    let controllers = &config.controllers;
+    let relevant = format!("{:?}|{:?}/{}|{:?}/{}|{:?}|{}|{}|{}|{}|{}/{}|{}|{:?}",
+        config.region_policy,
+        controllers.controller_1.controller_type,controllers.controller_1.controller_enabled,
+        controllers.controller_2.controller_type,controllers.controller_2.controller_enabled,
+        controllers.tx_rx_cycles,
+        config.memory_config.cpu_write_queue_enabled,
+        config.gpu_config.command_delay_enabled,
+        config.gpu_config.widescreen_hack,
+        config.gpu_config.texture_cache_enabled,
+        config.cdrom_config.fast_cd_enabled,config.cdrom_config.fast_cd_read_speed,
+        config.cheats_config.cheats_enabled,
+        cheats.cheats.iter().map(|cheat| (&cheat.name,&cheat.group,cheat.enabled,&cheat.codes)).collect::<Vec<_>>());
+    relevant.bytes().fold(0xCBF2_9CE4_8422_2325u64,|hash,b| (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3))
+}
+
+pub(super) struct Movie {
+    path: PathBuf,
+    header: MovieHeader,
+    events: Vec<MovieEvent>,
+    recording: bool,
+    // playback: next event to replay and mouse state of the current frame
+    next_event: usize,
+    mouse: (i8,i8,bool,bool),
+}
+
+impl Movie {
+    pub fn new_recording(path:&Path,emu_version:&str,bios_md5:&str,disc_serial:Option<String>,config_hash:u64) -> Self {
+        let header = MovieHeader {
+            magic: *MOVIE_MAGIC,
+            version: MOVIE_VERSION,
+            emu_version: emu_version.to_string(),
+            bios_md5: bios_md5.to_string(),
+            disc_serial,
+            config_hash,
+            frames: 0,
+        };
+        info!("Recording input movie to '{}'",path.display());
+        Self { path: path.to_path_buf(), header, events: Vec::new(), recording: true, next_event: 0, mouse: (0,0,false,false) }
+    }
+
+    /*
+    Loads a movie for playback, checking it has been recorded with the same bios, disc and configuration
+     */
+    pub fn load(path:&Path,bios_md5:&str,disc_serial:Option<&str>,config_hash:u64) -> Result<Self,String> {
+        let data = std::fs::read(path).map_err(|e| format!("Cannot read movie file '{}': {}",path.display(),e))?;
+        let mut s = StateSync::new_loading(data);
+        let mut header = MovieHeader::default();
+        s.sync(&mut header);
+        if s.has_error() || header.magic != *MOVIE_MAGIC {
+            return Err(format!("'{}' is not a movie file",path.display()));
+        }
+        if header.version != MOVIE_VERSION {
+            return Err(format!("Unsupported movie version {}, expected {}",header.version,MOVIE_VERSION));
+        }
+        if !header.bios_md5.eq_ignore_ascii_case(bios_md5) {
+            return Err(format!("Movie recorded with a different bios (MD5 {})",header.bios_md5));
+        }
+        if header.disc_serial.as_deref() != disc_serial {
+            warn!("Movie recorded with disc {:?}, current disc is {:?}: playback may desync",header.disc_serial,disc_serial);
+        }
+        if header.config_hash != config_hash {
+            warn!("Movie recorded with a different configuration (controllers, cheats, ...): playback may desync");
+        }
+        let mut events : Vec<MovieEvent> = Vec::new();
+        s.sync(&mut events);
+        s.finish().map_err(|e| format!("Invalid movie file '{}': {}",path.display(),e))?;
+        info!("Playing input movie '{}': {} frames, {} inputs, recorded with {}",path.display(),header.frames,events.len(),header.emu_version);
+        Ok(Self { path: path.to_path_buf(), header, events, recording: false, next_event: 0, mouse: (0,0,false,false) })
+    }
+
+    pub fn save(&mut self) -> Result<(),String> {
+        let mut s = StateSync::new_saving();
+        s.sync(&mut self.header);
+        s.sync(&mut self.events);
+        let data = s.finish()?;
+        std::fs::write(&self.path,data).map_err(|e| format!("Cannot write movie file '{}': {}",self.path.display(),e))?;
+        info!("Input movie saved to '{}': {} frames, {} inputs",self.path.display(),self.header.frames,self.events.len());
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    pub fn is_finished(&self,frame:u64) -> bool {
+        !self.recording && frame > self.header.frames
+    }
+
+    pub fn record(&mut self,frame:u64,input:MovieInput) {
+        if self.recording {
+            self.events.push(MovieEvent { frame, input });
+        }
+    }
+
+    pub fn set_frames(&mut self,frames:u64) {
+        if self.recording {
+            self.header.frames = frames;
+        }
+    }
+
+    /*
+    Playback: the button and axis inputs of the given frame (the mouse ones are returned by mouse)
+     */
+    pub fn take_inputs(&mut self,frame:u64) -> Vec<MovieInput> {
+        let mut inputs = Vec::new();
+        self.mouse.0 = 0;
+        self.mouse.1 = 0;
+        while let Some(event) = self.events.get(self.next_event) && event.frame <= frame {
+            match event.input {
+                MovieInput::Mouse(dx,dy,left,right) => self.mouse = (dx,dy,left,right),
+                input => inputs.push(input),
+            }
+            self.next_event += 1;
+        }
+        inputs
+    }
+
+    /*
+    Mouse state of the frame: when recording the live one is recorded if changed, when playing the recorded one is returned
+     */
+    pub fn mouse(&mut self,frame:u64,live:(i8,i8,bool,bool)) -> (i8,i8,bool,bool) {
+        if !self.recording {
+            return self.mouse;
+        }
+        let (dx,dy,left,right) = live;
+        if dx != 0 || dy != 0 || (left,right) != (self.mouse.2,self.mouse.3) {
+            self.record(frame,MovieInput::Mouse(dx,dy,left,right));
+        }
+        self.mouse = live;
+        live
+    }
+}
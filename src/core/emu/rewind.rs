@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use tracing::debug;
+
+/*
+Rewind buffer: a ring of machine states taken every interval frames.
+Only the newest state is kept whole: every older state is stored as a delta against the following (newer) one,
+the XOR of the two states compressed with a zero run-length encoding, so unchanged memory costs almost nothing.
+Rewinding pops the newest delta and applies it to the newest state, giving back the previous one, at the same
+interval; when the buffer is full the oldest delta is dropped.
+The states hold RAM, VRAM and SPU RAM as plain byte slices, so the deltas line up with the memory.
+Delta encoding: the length of the older state (u32 LE), then a sequence of
+  zero run length (varint), literal length (varint), literal bytes
+ */
+pub(super) struct RewindBuffer {
+    interval: u32,
+    depth: usize,
+    frames: u32,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+fn write_varint(out:&mut Vec<u8>,mut value:usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data:&[u8],pos:&mut usize) -> Option<usize> {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+        let b = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+        if shift >= usize::BITS {
+            return None;
+        }
+    }
+}
+
+/*
+Encodes the delta that rebuilds older from newer
+ */
+fn encode_delta(older:&[u8],newer:&[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(older.len() as u32).to_le_bytes());
+    let xor = |i:usize| older.get(i).copied().unwrap_or(0) ^ newer.get(i).copied().unwrap_or(0);
+    let len = older.len();
+    let mut i = 0;
+    while i < len {
+        let zero_start = i;
+        while i < len && xor(i) == 0 {
+            i += 1;
+        }
+        let literal_start = i;
+        // a literal ends at the first run of at least 4 zeros
+        let mut zeros = 0;
+        while i < len && zeros < 4 {
+            zeros = if xor(i) == 0 { zeros + 1 } else { 0 };
+            i += 1;
+        }
+        let literal_end = if zeros == 4 { i - 4 } else { i };
+        i = literal_end;
+        write_varint(&mut out,literal_start - zero_start);
+        write_varint(&mut out,literal_end - literal_start);
+        out.extend((literal_start..literal_end).map(xor));
+    }
+    out
+}
+
+/*
+Applies the delta to newer: returns the older state
+ */
+fn decode_delta(delta:&[u8],newer:&[u8]) -> Option<Vec<u8>> {
+    let len = u32::from_le_bytes(delta.get(0..4)?.try_into().ok()?) as usize;
+    let mut older = newer.to_vec();
+    older.resize(len,0);
+    let mut pos = 4;
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta,&mut pos)?;
+        let literal_len = read_varint(delta,&mut pos)?;
+        let literal = delta.get(pos..pos + literal_len)?;
+        older.get_mut(i..i + literal_len)?.iter_mut().zip(literal).for_each(|(b,x)| *b ^= x);
+        pos += literal_len;
+        i += literal_len;
+    }
+    Some(older)
+}
+
+impl RewindBuffer {
+    pub fn new(interval:u32,depth:usize) -> Self {
+        Self { interval: interval.max(1), depth: depth.max(1), frames: 0, newest: None, deltas: VecDeque::new() }
+    }
+
+    /*
+    Called every frame: true if a state must be pushed (or popped while rewinding)
+     */
+    pub fn frame_tick(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    pub fn push(&mut self,state:Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            self.deltas.push_back(encode_delta(&newest,&state));
+            if self.deltas.len() >= self.depth {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(state);
+        debug!("Rewind buffer: {} states, {} bytes of deltas",self.len(),self.deltas.iter().map(|d| d.len()).sum::<usize>());
+    }
+
+    /*
+    Returns the newest state and removes it: the previous one becomes the newest
+     */
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.newest = self.deltas.pop_back().and_then(|delta| decode_delta(&delta,&newest));
+        if self.newest.is_none() {
+            self.deltas.clear();
+        }
+        self.frames = 0;
+        Some(newest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.frames = 0;
+    }
+}
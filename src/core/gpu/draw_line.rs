@@ -99,6 +99,7 @@ impl GPU {
         if dx > 1023 || dy > 511 {
             return 0;
         }
+        self.draw_scaled_line(start,end,start_color,end_color,shaded,semi_transparent);
 
         let total_steps = dx.max(dy);
 
@@ -1,3 +1,4 @@
+use crate::core::cpu::pgxp::PgxpVertex;
 use crate::core::gpu::gp0::DITHER_TABLE;
 use crate::core::gpu::timings::GPUTimings;
 use crate::core::gpu::{Color, Gp0State, SemiTransparency, TextureDepth, Vertex, GPU};
@@ -23,7 +24,7 @@ struct UV {
 
 #[derive(Debug,Clone,Default)]
 struct Polygon {
-    vertex: Vec<(Vertex,Color,UV)>,
+    vertex: Vec<(Vertex,Color,UV,Option<PgxpVertex>)>,
     texture: Option<PolygonTexture>,
 }
 
@@ -94,20 +95,17 @@ impl GPU {
                 let mut word_index = 0usize;
 
                 while !self.cmd_fifo.is_empty() {
-                    let (mut vertex,color) = if is_gouraud {
-                        let color = if word_index == 0 {
-                            shading_color.clone()
-                        }
-                        else {
-                            Color::from_u32(self.cmd_fifo.pop().unwrap())
-                        };
-                        (Vertex::from_command_parameter(self.cmd_fifo.pop().unwrap()),color)
+                    let color = if is_gouraud && word_index > 0 {
+                        Color::from_u32(self.cmd_fifo.pop().unwrap())
                     }
                     else {
-                        (Vertex::from_command_parameter(self.cmd_fifo.pop().unwrap()),shading_color.clone())
+                        shading_color.clone()
                     };
+                    let vertex_word = self.cmd_fifo.pop().unwrap();
+                    let mut vertex = Vertex::from_command_parameter(vertex_word);
                     // add X,Y, drawing offset
                     vertex.add_offset(self.drawing_area.x_offset,self.drawing_area.y_offset);
+                    let precise = self.pgxp_polygon_vertex(vertex_word,&vertex);
 
                     let mut uv = UV::default();
                     if is_textured {
@@ -156,16 +154,14 @@ impl GPU {
                                 texture.texture_depth = texture_depth;
                                 texture.semi_transparency = semi_transparency;
                                 // update gloabl texture info
-                                self.texture.page_base_x = page_base_x;
-                                self.texture.page_base_y = page_base_y;
-                                self.texture.depth = texture_depth;
+                                self.set_texture_page(page_base_x,page_base_y,texture_depth);
                                 self.semi_transparency = semi_transparency;
                                 //self.dithering = is_gouraud || (is_textured && !raw_texture);
                             }
                             _ => {}
                         }
                     }
-                    polygon.vertex.push((vertex,color,uv));
+                    polygon.vertex.push((vertex,color,uv,precise));
                     word_index += 1;
                 }
 
@@ -184,9 +180,17 @@ impl GPU {
         if polygon.vertex.len() < 3 {
             return 0;
         }
-        let mut pixels = self.draw_triangle::<0>(polygon, is_gouraud, is_textured, is_semi_transparent, is_raw_texture);
+        let mut pixels = self.draw_triangle::<0>(polygon, is_gouraud, is_textured, is_semi_transparent, is_raw_texture, 0);
         if polygon.vertex.len() == 4 {
-            pixels += self.draw_triangle::<1>(polygon, is_gouraud, is_textured, is_semi_transparent, is_raw_texture);
+            pixels += self.draw_triangle::<1>(polygon, is_gouraud, is_textured, is_semi_transparent, is_raw_texture, 0);
+        }
+        // upscaled rendering: drawn again at the internal resolution
+        let shift = self.scaled_shift();
+        if shift > 0 {
+            self.draw_triangle::<0>(polygon, is_gouraud, is_textured, is_semi_transparent, is_raw_texture, shift);
+            if polygon.vertex.len() == 4 {
+                self.draw_triangle::<1>(polygon, is_gouraud, is_textured, is_semi_transparent, is_raw_texture, shift);
+            }
         }
         GPUTimings::triangle(pixels,is_gouraud,is_semi_transparent,is_textured)
     }
@@ -218,27 +222,35 @@ impl GPU {
     /// - **Barycentric Interpolation**: Uses precalculated reciprocal triangle area
     ///   (`inv_abc`) to perform efficient interpolation for colors and UV coordinates.
     /// - **Floating Point Arithmetic**: Optimized using standard floats instead of fixed-point.
-    fn draw_triangle<const OFFSET: usize>(&mut self, polygon: &Polygon, is_gouraud: bool, is_textured: bool, is_semi_transparent: bool, is_raw_texture: bool) -> usize {
+    /// - **Upscaling**: With `shift` > 0 the vertices are scaled by 2^shift and the triangle is drawn
+    ///   into the scaled VRAM; textures are always read from the native VRAM.
+    /// - **PGXP**: The upscaled pass places the vertices with their precise (sub-pixel) coordinates, and the
+    ///   pass that is displayed can map the textures with perspective correction.
+    fn draw_triangle<const OFFSET: usize>(&mut self, polygon: &Polygon, is_gouraud: bool, is_textured: bool, is_semi_transparent: bool, is_raw_texture: bool, shift: u32) -> usize {
         let v0 = &polygon.vertex[0 + OFFSET];
         let v1 = &polygon.vertex[1 + OFFSET];
         let v2 = &polygon.vertex[2 + OFFSET];
 
         let mut verts = [v0, v1, v2];
+        let mut points = verts.map(|v| Self::raster_vertex(&v.0, v.3, shift));
 
-        let mut abc = Self::edge_function(&v0.0, &v1.0, &v2.0);
+        let mut abc = Self::edge_function(&points[0], &points[1], &points[2]);
         if abc < 0 {
             verts.swap(0, 1);
+            points.swap(0, 1);
             abc = -abc;
         }
 
-        let (a, ac, a_uv) = verts[0];
-        let (b, bc, b_uv) = verts[1];
-        let (c, cc, c_uv) = verts[2];
+        let (_, ac, a_uv, a_precise) = verts[0];
+        let (_, bc, b_uv, b_precise) = verts[1];
+        let (_, cc, c_uv, c_precise) = verts[2];
 
         if abc == 0 {
             return 0;
         }
 
+        let [a, b, c] = &points;
+
         let tl_ab = Self::is_top_left(a, b);
         let tl_bc = Self::is_top_left(b, c);
         let tl_ca = Self::is_top_left(c, a);
@@ -247,16 +259,16 @@ impl GPU {
         let bias_bc = if tl_bc { 0 } else { -1 };
         let bias_ca = if tl_ca { 0 } else { -1 };
 
-        let mut min_x = cmp::max(self.drawing_area.area_left as i16, cmp::min(a.x, cmp::min(b.x, c.x)));
-        let mut max_x = cmp::min(self.drawing_area.area_right as i16 + 1, cmp::max(a.x, cmp::max(b.x, c.x)));
-        let mut min_y = cmp::max(self.drawing_area.area_top as i16, cmp::min(a.y, cmp::min(b.y, c.y)));
-        let mut max_y = cmp::min(self.drawing_area.area_bottom as i16 + 1, cmp::max(a.y, cmp::max(b.y, c.y)));
+        let mut min_x = cmp::max((self.drawing_area.area_left as i16) << shift, cmp::min(a.x, cmp::min(b.x, c.x)));
+        let mut max_x = cmp::min((self.drawing_area.area_right as i16 + 1) << shift, cmp::max(a.x, cmp::max(b.x, c.x)));
+        let mut min_y = cmp::max((self.drawing_area.area_top as i16) << shift, cmp::min(a.y, cmp::min(b.y, c.y)));
+        let mut max_y = cmp::min((self.drawing_area.area_bottom as i16 + 1) << shift, cmp::max(a.y, cmp::max(b.y, c.y)));
 
         // Ulteriore clipping contro i limiti del VRAM (0-1024, 0-512)
         min_x = cmp::max(0, min_x);
-        max_x = cmp::min(1024, max_x);
+        max_x = cmp::min(1024 << shift, max_x);
         min_y = cmp::max(0, min_y);
-        max_y = cmp::min(512, max_y);
+        max_y = cmp::min(512 << shift, max_y);
 
         if max_x <= min_x || max_y <= min_y {
             return 0;
@@ -272,6 +284,14 @@ impl GPU {
 
         let inv_abc = 1.0 / abc as f32;
 
+        // perspective correction: 1/z of the vertices, only if all of them have the precise depth
+        let perspective = match (a_precise, b_precise, c_precise) {
+            (Some(pa), Some(pb), Some(pc)) if is_textured && self.pgxp_perspective_correct && shift == self.scaled_shift() && pa.z > 0.0 && pb.z > 0.0 && pc.z > 0.0 => {
+                Some((1.0 / pa.z, 1.0 / pb.z, 1.0 / pc.z))
+            }
+            _ => None,
+        };
+
         let mut abp_row = Self::edge_function(a, b, &Vertex { x: min_x, y: min_y });
         let mut bcp_row = Self::edge_function(b, c, &Vertex { x: min_x, y: min_y });
         let mut cap_row = Self::edge_function(c, a, &Vertex { x: min_x, y: min_y });
@@ -301,15 +321,18 @@ impl GPU {
         let b1 = bc.b as f32;
         let b2 = cc.b as f32;
 
+        let skipped_field_lsb = self.skipped_field_lsb();
         for y in min_y..max_y {
             let mut abp = abp_row;
             let mut bcp = bcp_row;
             let mut cap = cap_row;
 
             let vram_y_offset = self.get_vram_offset_15(0, y as u16);
+            // 480 lines interlaced: the lines of the displayed field are not drawn
+            let skipped_line = skipped_field_lsb.is_some_and(|lsb| ((y >> shift) as u16 & 1) == lsb);
 
             for x in min_x..max_x {
-                if (abp + bias_ab >= 0) && (bcp + bias_bc >= 0) && (cap + bias_ca >= 0) {
+                if !skipped_line && (abp + bias_ab >= 0) && (bcp + bias_bc >= 0) && (cap + bias_ca >= 0) {
                     let weight_a = bcp as f32 * inv_abc;
                     let weight_b = cap as f32 * inv_abc;
                     let weight_c = 1.0 - weight_a - weight_b;
@@ -329,8 +352,14 @@ impl GPU {
 
                     if is_textured {
                         semi_transparency_mode = texture_semi_transparency;
-                        let u = u0 * weight_a + u1 * weight_b + u2 * weight_c;
-                        let v = v0 * weight_a + v1 * weight_b + v2 * weight_c;
+                        let (u, v) = match perspective {
+                            Some((qa, qb, qc)) => {
+                                let (wa, wb, wc) = (weight_a * qa, weight_b * qb, weight_c * qc);
+                                let w = wa + wb + wc;
+                                ((u0 * wa + u1 * wb + u2 * wc) / w, (v0 * wa + v1 * wb + v2 * wc) / w)
+                            }
+                            None => (u0 * weight_a + u1 * weight_b + u2 * weight_c, v0 * weight_a + v1 * weight_b + v2 * weight_c),
+                        };
 
                         let texture_pixel = self.get_texture_pixel(clut_x, clut_y, u as u32, v as u32, texture_page_x, texture_page_y, texture_depth);
                         transparent_pixel = texture_pixel == 0x0000;
@@ -350,14 +379,19 @@ impl GPU {
                         let is_semi_transparent_pixel = is_semi_transparent && (!is_textured || texture_semi_transparency_allowed);
 
                         let final_color = if (is_gouraud || (is_textured && !is_raw_texture)) && self.dithering {
-                            let dither_value = DITHER_TABLE[(y & 3) as usize][(x & 3) as usize];
+                            let dither_value = DITHER_TABLE[((y >> shift) & 3) as usize][((x >> shift) & 3) as usize];
                             color.dither(dither_value)
                         } else {
                             color
                         };
 
-                        let offset = vram_y_offset + ((x as usize & 0x3FF) << 1);
-                        self.draw_pixel_offset(offset, final_color.to_u16(), true, is_semi_transparent_pixel, Some(semi_transparency_mode));
+                        if shift == 0 {
+                            let offset = vram_y_offset + ((x as usize & 0x3FF) << 1);
+                            self.draw_pixel_offset(offset, final_color.to_u16(), true, is_semi_transparent_pixel, Some(semi_transparency_mode));
+                        }
+                        else {
+                            self.draw_scaled_pixel(x as usize, y as usize, final_color.to_u16(), true, is_semi_transparent_pixel, Some(semi_transparency_mode));
+                        }
                     }
                 }
                 abp += dx_ab as i32;
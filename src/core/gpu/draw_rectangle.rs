@@ -151,6 +151,10 @@ impl GPU {
                         let clut_x = clut & 0x3F; // 0-5    X coordinate X/16
                         let clut_y = (clut >> 6) & 0x1FF; // 6-14   Y coordinate 0-511
                         for y in 0..height {
+                            if self.is_skipped_field_line(vertex.y as u16) {
+                                vertex.y += 1;
+                                continue;
+                            }
                             let v = base_v.wrapping_add(y as u8);
                             for x in 0..width {
                                 let u = base_u.wrapping_add(x as u8);
@@ -166,6 +170,7 @@ impl GPU {
                                     };
                                     pixels += 1;
                                     self.draw_pixel_offset(self.get_vram_offset_15(vertex.x as u16, vertex.y as u16), color.to_u16(), true, semi_transparent && texture_mask_bit,Some(self.semi_transparency));
+                                    self.draw_scaled_block(vertex.x as u16, vertex.y as u16, color.to_u16(), true, semi_transparent && texture_mask_bit,Some(self.semi_transparency));
                                 }
                                 vertex.x += 1;
                             }
@@ -175,9 +180,14 @@ impl GPU {
                     }
                     None => { // non-textured
                         for _ in 0..height {
+                            if self.is_skipped_field_line(vertex.y as u16) {
+                                vertex.y += 1;
+                                continue;
+                            }
                             for _ in 0..width {
                                 pixels += 1;
                                 self.draw_pixel_offset(self.get_vram_offset_15(vertex.x as u16, vertex.y as u16), color, true, semi_transparent,Some(self.semi_transparency));
+                                self.draw_scaled_block(vertex.x as u16, vertex.y as u16, color, true, semi_transparent,Some(self.semi_transparency));
                                 vertex.x += 1;
                             }
                             vertex.x = origin_x;
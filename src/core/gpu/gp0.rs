@@ -2,6 +2,7 @@ use super::{Color, GP0Operation, Gp0State, SemiTransparency, TextureDepth, VRamC
 use crate::core::clock::{Clock, EventType};
 use crate::core::gpu::timings::GPUTimings;
 use crate::core::interrupt::{InterruptType, IrqHandler};
+use crate::core::savestate::{SaveState, StateSync};
 use tracing::{debug, warn};
 
 pub(super) const DITHER_TABLE: &[[i8; 4]; 4] = &[[-4, 0, -3, 1], [2, -2, 3, -1], [-3, 1, -4, 0], [3, -1, 2, -2]];
@@ -34,7 +35,67 @@ pub(super) const DITHER_TABLE: &[[i8; 4]; 4] = &[[-4, 0, -3, 1], [2, -2, 3, -1],
   Like GP0(00h), these commands don't take up space in the FIFO. So, maybe, they are same as GP0(00h),
   however, the Drawing Area/Offset commands GP0(E3h..E5h) don't take up FIFO space either, so not taking up FIFO space doesn't necessarily mean that the command has no function.
 */
+/*
+Operations are function pointers that cannot be saved: the command that selects the operation is saved instead.
+ */
+impl SaveState for Gp0State {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let (mut index,mut operation) = match *self {
+            Gp0State::WaitingCommand => (0u8,None),
+            Gp0State::WaitingCommandParameters(operation,_) => (1,Some(operation)),
+            Gp0State::VRamCopy(operation,_) => (2,Some(operation)),
+            Gp0State::WaitingPolyline(operation,..) => (3,Some(operation)),
+        };
+        s.sync(&mut index);
+        let mut cmd = operation.and_then(GPU::operation_to_cmd).unwrap_or(0);
+        s.sync(&mut cmd);
+        if s.is_loading() {
+            operation = GPU::cmd_to_operation(cmd).map(|(_,operation)| operation);
+            if index > 0 && operation.is_none() {
+                s.set_error(format!("Invalid GP0 command {:08X} in state",cmd));
+                return;
+            }
+        }
+        match index {
+            0 => *self = Gp0State::WaitingCommand,
+            1 => {
+                let mut pars = if let Gp0State::WaitingCommandParameters(_,pars) = *self { pars } else { None };
+                s.sync(&mut pars);
+                *self = Gp0State::WaitingCommandParameters(operation.unwrap(),pars);
+            }
+            2 => {
+                let mut config = if let Gp0State::VRamCopy(_,config) = *self { config } else { VRamCopyConfig::default() };
+                s.sync(&mut config);
+                *self = Gp0State::VRamCopy(operation.unwrap(),config);
+            }
+            3 => {
+                let (mut arg_size,mut vertex,mut color,mut shaded,mut semi_transparency) = match *self {
+                    Gp0State::WaitingPolyline(_,arg_size,vertex,color,shaded,semi_transparency) => (arg_size,vertex,color,shaded,semi_transparency),
+                    _ => (0,Vertex { x: 0, y: 0 },Color { r: 0, g: 0, b: 0, m: false },false,false),
+                };
+                s.sync(&mut arg_size);
+                s.sync(&mut vertex);
+                s.sync(&mut color);
+                s.sync(&mut shaded);
+                s.sync(&mut semi_transparency);
+                *self = Gp0State::WaitingPolyline(operation.unwrap(),arg_size,vertex,color,shaded,semi_transparency);
+            }
+            _ => s.set_error(format!("Invalid GP0 state {}",index)),
+        }
+    }
+}
+
 impl GPU {
+    /// returns the command (the first of its group) that selects the given operation
+    fn operation_to_cmd(operation:GP0Operation) -> Option<u32> {
+        const CMDS : [u32;16] = [
+            0x20 << 24, 0x40 << 24, 0x60 << 24, 0x80 << 24, 0xA0 << 24, 0xC0 << 24, 0x00 << 24, 0x01 << 24,
+            0x02 << 24, 0xE1 << 24, 0xE2 << 24, 0xE3 << 24, 0xE4 << 24, 0xE5 << 24, 0xE6 << 24, 0x1F << 24,
+        ];
+        CMDS.into_iter().find(|&cmd| {
+            matches!(Self::cmd_to_operation(cmd),Some((_,op)) if std::ptr::fn_addr_eq(op,operation))
+        })
+    }
     /// returns (<if the operation needs parameters>,op)
     fn cmd_to_operation(cmd:u32) -> Option<(bool,GP0Operation)> {
         match (cmd >> 29) & 7 {
@@ -106,6 +167,7 @@ impl GPU {
                     self.gp0state = Gp0State::WaitingCommandParameters(operation,Some(pars - 1));
                 }
                 else { // parameters completed, executing command
+                    self.invalidate_texture_cache_dirty_pages();
                     let cycles = operation(self, cmd,interrupt_handler);
                     self.schedule_command_completion(cycles,clock);
                 }
@@ -124,6 +186,7 @@ impl GPU {
                         self.gp0state = Gp0State::WaitingPolyline(operation,arg_size - 1,v,c,shaded,semi_transparency);
                     }
                     else { // parameters completed, executing command
+                        self.invalidate_texture_cache_dirty_pages();
                         let cycles = operation(self, cmd,interrupt_handler);
                         self.schedule_command_completion(cycles,clock);
                     }
@@ -165,16 +228,15 @@ impl GPU {
     Note: GP0(00h) seems to be often inserted between Texpage and Rectangle commands, maybe it acts as a NOP, which may be required between that commands, for timing reasons...?
      */
     pub(super) fn gp0_draw_mode_settings(&mut self,cmd:u32,_irq_handler:&mut IrqHandler) -> usize {
-        self.texture.page_base_x = (cmd & 0xF) as u8;
-        self.texture.page_base_y = ((cmd >> 4) & 0x1) as u8;
-        self.semi_transparency = SemiTransparency::from_command(cmd);
-        self.texture.depth = match (cmd >> 7) & 3 {
+        let texture_depth = match (cmd >> 7) & 3 {
             0 => TextureDepth::T4Bit,
             1 => TextureDepth::T8Bit,
             2 => TextureDepth::T15Bit,
             3 => TextureDepth::Reserved,
             _ => unreachable!()
         };
+        self.set_texture_page((cmd & 0xF) as u8,((cmd >> 4) & 0x1) as u8,texture_depth);
+        self.semi_transparency = SemiTransparency::from_command(cmd);
         self.dithering = ((cmd >> 9) & 1) != 0;
         self.drawing_area.draw_to_display = ((cmd >> 10) & 1) != 0;
         //self.texture.disabled = ((cmd >> 11) & 1) != 0; // only for V2
@@ -318,11 +380,12 @@ impl GPU {
 
         self.vram[offset] = pixel_to_write as u8;
         self.vram[offset + 1] = (pixel_to_write >> 8) as u8;
+        self.texture_cache_write(offset);
     }
 
     #[inline(always)]
     pub(super) fn draw_pixel(&mut self,v:&Vertex,color:&Color,semi_transparent:bool,semi_transparency: Option<SemiTransparency>,allow_dithering:bool) {
-        if /*self.drawing_area.draw_to_display &&*/ v.is_inside_drawing_area(&self.drawing_area) {
+        if v.is_inside_drawing_area(&self.drawing_area) && !self.is_skipped_field_line(v.y as u16) {
             let color = if allow_dithering && self.dithering {
                 let dither_value = DITHER_TABLE[(v.y & 3) as usize][(v.x & 3) as usize];
                 color.dither(dither_value)
@@ -336,7 +399,7 @@ impl GPU {
 
 
     #[inline(always)]
-    pub(super) fn get_texture_pixel(&self, clut_x: u32, clut_y: u32, u: u32, v: u32, texture_page_x:u8, texture_page_y: u8, texture_depth:TextureDepth) -> u16 {
+    pub(super) fn get_texture_pixel(&mut self, clut_x: u32, clut_y: u32, u: u32, v: u32, texture_page_x:u8, texture_page_y: u8, texture_depth:TextureDepth) -> u16 {
         /*
         GP0(E2h) - Texture Window setting
           0-4    Texture window Mask X   (in 8 pixel steps)
@@ -357,9 +420,13 @@ impl GPU {
             TextureDepth::T4Bit => {
                 let vram_x_pixels = (((texture_page_x as u32) << 6) + (u >> 2)) & 0x3FF;
                 let byte_offset = (((y << 10) + vram_x_pixels) as usize) << 1;
-                let value = self.vram[byte_offset] as u16 | ((self.vram[byte_offset + 1] as u16) << 8);
+                let value = self.read_cached_texel(vram_x_pixels, y, u, v, texture_depth)
+                    .unwrap_or_else(|| self.vram[byte_offset] as u16 | ((self.vram[byte_offset + 1] as u16) << 8));
                 let shift = (u & 3) << 2;
                 let clut_index = ((value >> shift) & 0xF) as u32;
+                if let Some(color) = self.read_cached_clut(clut_x, clut_y, false, clut_index) {
+                    return color;
+                }
                 let clut_base_addr = (((clut_y << 10) + (clut_x << 4)) as usize) << 1;
                 let clut_addr = clut_base_addr + ((clut_index as usize) << 1);
                 self.vram[clut_addr] as u16 | ((self.vram[clut_addr + 1] as u16) << 8)
@@ -367,9 +434,13 @@ impl GPU {
             TextureDepth::T8Bit => {
                 let vram_x_pixels = (((texture_page_x as u32) << 6) + (u >> 1)) & 0x3FF;
                 let byte_offset = (((y << 10) + vram_x_pixels) as usize) << 1;
-                let value = self.vram[byte_offset] as u16 | ((self.vram[byte_offset + 1] as u16) << 8);
+                let value = self.read_cached_texel(vram_x_pixels, y, u, v, texture_depth)
+                    .unwrap_or_else(|| self.vram[byte_offset] as u16 | ((self.vram[byte_offset + 1] as u16) << 8));
                 let shift = (u & 1) << 3;
                 let clut_index = ((value >> shift) & 0xFF) as u32;
+                if let Some(color) = self.read_cached_clut(clut_x, clut_y, true, clut_index) {
+                    return color;
+                }
                 let clut_base_addr = (((clut_y << 10) + (clut_x << 4)) as usize) << 1;
                 let clut_addr = clut_base_addr + ((clut_index as usize) << 1);
 
@@ -378,7 +449,8 @@ impl GPU {
             TextureDepth::T15Bit | TextureDepth::Reserved => {
                 let vram_x_pixels = (((texture_page_x as u32) << 6) + u) & 0x3FF;
                 let byte_offset = (((y << 10) + vram_x_pixels) as usize) << 1;
-                self.vram[byte_offset] as u16 | ((self.vram[byte_offset + 1] as u16) << 8)
+                self.read_cached_texel(vram_x_pixels, y, u, v, texture_depth)
+                    .unwrap_or_else(|| self.vram[byte_offset] as u16 | ((self.vram[byte_offset + 1] as u16) << 8))
             }
         }
     }
@@ -417,6 +489,7 @@ impl GPU {
                     y_size = 0x200;
                 }
                 debug!("Executing Vram->Vram copy src=({},{}) dest=({},{}) x_size={} y_size={}",src_x,src_y,dest_x,dest_y,x_size,y_size);
+                self.invalidate_texture_cache();
                 // perform copy
                 for y in 0..y_size {
                     for x in 0..x_size {
@@ -424,6 +497,7 @@ impl GPU {
                         let pixel = self.get_pixel_15(src_offset);
                         let dest_offset = self.get_vram_offset_15(dest_x + x, dest_y + y);
                         self.draw_pixel_offset(dest_offset, pixel, true,false,None);
+                        self.copy_scaled_block(src_x + x, src_y + y, dest_x + x, dest_y + y);
                     }
                 }
                 self.gp0state = Gp0State::WaitingCommand;
@@ -501,6 +575,7 @@ impl GPU {
                 for hw in [word as u16, (word >> 16) as u16] {
                     self.cpu_vram_copy_buffer.push(hw);
                     if self.cpu_vram_copy_buffer.len() == total_hw {
+                        self.invalidate_texture_cache();
                         let mut buffer_index = 0;
                         for y in 0..config.height {
                             let vram_y = config.coord_y + y;
@@ -509,6 +584,7 @@ impl GPU {
                                 let pixels = self.cpu_vram_copy_buffer[buffer_index];
                                 buffer_index += 1;
                                 self.draw_pixel_offset(self.get_vram_offset_15(vram_x, vram_y), pixels, true, false, None);
+                                self.draw_scaled_block(vram_x, vram_y, pixels, true, false, None);
                             }
                         }
                         self.cpu_vram_copy_buffer.clear();
@@ -539,9 +615,9 @@ impl GPU {
         0
     }
     fn operation_flush_texture_cache(&mut self,cmd:u32,_irq_handler:&mut IrqHandler) -> usize {
-        // TODO: implement texture cache flushing
         self.cmd_fifo.pop(); // discard command
-        debug!("GPU GP0 Flush Texture Cache command {:08X} - not implemented",cmd);
+        debug!("GPU GP0 Flush Texture Cache command {:08X}",cmd);
+        self.invalidate_texture_cache();
         0
     }
     /*
@@ -569,10 +645,12 @@ impl GPU {
                 let width = (((width_height as u16) & 0x3FF) + 0xF) & !0xF;
                 let height = ((width_height >> 16) as u16) & 0x1FF;
                 debug!("Executing Quick VRam Fill color={:04X} pos=({},{}) width={} height={}",fill_color,x_pos,y_pos,width,height);
+                self.invalidate_texture_cache();
                 for y in 0..height {
                     let target_y = y_pos + y;
                     for x in 0..width {
                         self.draw_pixel_offset(self.get_vram_offset_15(x_pos + x, target_y), fill_color, false, false,None);
+                        self.draw_scaled_block(x_pos + x, target_y, fill_color, false, false, None);
                     }
                 }
                 self.gp0state = Gp0State::WaitingCommand;
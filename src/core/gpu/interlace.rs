@@ -0,0 +1,144 @@
+use crate::core::config::DeinterlaceMode;
+use crate::core::gpu::{InterlaceField, VideoVerticalResolution, GPU};
+use tracing::info;
+
+// luma difference (squared) above which a line of the other field is considered combing
+const COMB_THRESHOLD : i32 = 20 * 20;
+
+/*
+Interlaced output.
+In 480 lines mode (GP1(08h) vertical resolution 480 and interlace on) every vertical refresh shows one field: the even or the odd
+lines of the display area, alternately. The games draw the whole 480 lines frame in VRAM, but with GP0(E1h).10 cleared the GPU
+doesn't draw the lines of the field being displayed, so a game running at the field rate updates only the other field every time.
+GPUSTAT.13 reports the current field (always 1 when interlace is off) and GPUSTAT.31 the parity of the line being displayed:
+per field in 480 lines mode, per scanline otherwise, always 0 in vblank.
+A frame is presented for every field, built from the display area by the configured deinterlacing:
+- Weave: both fields as they are in VRAM, full resolution, combing on motion when the fields are drawn at different times
+- Bob: the lines of the current field are doubled, no combing, half the vertical resolution
+- Blend: every line is the average of the two fields, no combing, blurred on motion
+- Adaptive: the lines of the other field are kept where they match the current field, and interpolated from it where they comb
+In the other modes (240 lines, interlaced or not) the display area is shown as it is.
+ */
+#[derive(Copy,Clone)]
+pub(super) enum FrameRow {
+    Line(usize),
+    Blend(usize,usize),
+    Adaptive { line:usize, above:usize, below:usize },
+}
+
+impl FrameRow {
+    /*
+    The pixel of the row, read by read from the lines of the display area
+     */
+    #[inline(always)]
+    pub fn combine(&self,read:impl Fn(usize) -> Option<(u8,u8,u8)>) -> Option<(u8,u8,u8)> {
+        match *self {
+            FrameRow::Line(line) => read(line),
+            FrameRow::Blend(a,b) => Some(Self::average(read(a)?,read(b)?)),
+            FrameRow::Adaptive { line, above, below } => {
+                let (pixel,above,below) = (read(line)?,read(above)?,read(below)?);
+                let luma = |(r,g,b):(u8,u8,u8)| (r as i32 * 2 + g as i32 * 5 + b as i32) >> 3;
+                // combing: the line is brighter or darker than both its neighbours
+                if (luma(pixel) - luma(above)) * (luma(pixel) - luma(below)) > COMB_THRESHOLD {
+                    Some(Self::average(above,below))
+                }
+                else {
+                    Some(pixel)
+                }
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn average(a:(u8,u8,u8),b:(u8,u8,u8)) -> (u8,u8,u8) {
+        (((a.0 as u16 + b.0 as u16) >> 1) as u8,((a.1 as u16 + b.1 as u16) >> 1) as u8,((a.2 as u16 + b.2 as u16) >> 1) as u8)
+    }
+}
+
+impl GPU {
+    pub fn set_deinterlace_mode(&mut self,mode:DeinterlaceMode) {
+        if mode != self.deinterlace_mode {
+            info!("GPU deinterlace mode: {:?}",mode);
+        }
+        self.deinterlace_mode = mode;
+    }
+
+    #[inline(always)]
+    fn is_interlaced_480_lines(&self) -> bool {
+        self.display_config.interlaced && matches!(self.display_config.v_res,VideoVerticalResolution::Y480Lines)
+    }
+
+    // parity of the VRAM lines of the field being displayed
+    #[inline(always)]
+    fn displayed_field_lsb(&self) -> u16 {
+        (self.display_config.vram_y_start + self.display_config.field as u16) & 1
+    }
+
+    // parity of the lines of the field being displayed, relative to the display area start
+    #[inline(always)]
+    fn displayed_field_row_lsb(&self) -> usize {
+        ((self.displayed_field_lsb() ^ self.display_config.vram_y_start) & 1) as usize
+    }
+
+    /*
+    GPUSTAT.13: interlace field, always 1 when interlace is off
+     */
+    pub(super) fn interlace_field_status(&self) -> u32 {
+        if self.display_config.interlaced { self.display_config.field as u32 } else { 1 }
+    }
+
+    /*
+    GPUSTAT.31: odd line being displayed, per field in 480 lines mode and per scanline otherwise, 0 in vblank
+     */
+    pub(super) fn odd_line_status(&self) -> u32 {
+        if self.raster.v_blank {
+            0
+        }
+        else if self.is_interlaced_480_lines() {
+            (self.display_config.field == InterlaceField::Odd) as u32
+        }
+        else {
+            (self.raster.raster_line & 1) as u32
+        }
+    }
+
+    /*
+    The parity of the VRAM lines the rendering commands must not draw: the field being displayed, in 480 lines mode
+    when drawing to the display area is prohibited
+     */
+    #[inline(always)]
+    pub(super) fn skipped_field_lsb(&self) -> Option<u16> {
+        (!self.drawing_area.draw_to_display && self.is_interlaced_480_lines()).then(|| self.displayed_field_lsb())
+    }
+
+    #[inline(always)]
+    pub(super) fn is_skipped_field_line(&self,y:u16) -> bool {
+        self.skipped_field_lsb().is_some_and(|lsb| (y & 1) == lsb)
+    }
+
+    /*
+    The source of the row y of the displayed frame (scaled by shift), as lines relative to the display area start,
+    lines the number of native lines of the frame
+     */
+    pub(super) fn frame_row(&self,y:usize,lines:usize,shift:u32) -> FrameRow {
+        if !self.is_interlaced_480_lines() {
+            return FrameRow::Line(y);
+        }
+        let (line,sub_line) = (y >> shift,y & ((1 << shift) - 1));
+        let field = self.displayed_field_row_lsb();
+        let row = |line:usize| (line << shift) | sub_line;
+        let current_field_line = (line & !1) | field;
+        let other_field_line = (line & !1) | (field ^ 1);
+        match self.deinterlace_mode {
+            DeinterlaceMode::Weave => FrameRow::Line(y),
+            DeinterlaceMode::Bob => FrameRow::Line(row(current_field_line)),
+            DeinterlaceMode::Blend => FrameRow::Blend(row(current_field_line),row(other_field_line)),
+            DeinterlaceMode::Adaptive if (line & 1) == field => FrameRow::Line(y),
+            DeinterlaceMode::Adaptive => {
+                let above = if line > 0 { line - 1 } else { line + 1 };
+                let below = if line + 1 < lines { line + 1 } else { line - 1 };
+                FrameRow::Adaptive { line: y, above: row(above), below: row(below) }
+            }
+        }
+    }
+}
@@ -4,16 +4,23 @@ mod draw_line;
 mod draw_rectangle;
 mod draw_polygon;
 mod timings;
+mod upscale;
+mod pgxp;
+mod texture_cache;
+mod interlace;
 
 use crate::core::clock::Clock;
 use crate::core::clock::EventType;
-use crate::core::config::Config;
+use crate::core::config::{Config, DeinterlaceMode};
+use crate::core::cpu::pgxp::PgxpVertex;
 use crate::core::dma::DmaDevice;
 use crate::core::interrupt::{InterruptType, IrqHandler};
 use crate::core::memory::bus::Bus;
+use crate::core::savestate::{impl_state_enum, impl_state_struct, SaveState, StateSync};
 use crate::core::Resettable;
 use crate::renderer::{GPUFrameBuffer, Renderer};
 use std::cmp;
+use std::path::Path;
 use std::sync::Arc;
 use tracing::info;
 /*
@@ -695,12 +702,19 @@ pub struct GPU {
     last_cpu_perf: u16,
     cpu_vram_copy_buffer: Vec<u16>,
     command_delay_enabled: bool,
+    last_frame: Option<GPUFrameBuffer>,
+    scaled_vram: Option<upscale::ScaledVRam>,
+    pgxp_vertices: Option<pgxp::RecentVertices>,
+    pgxp_perspective_correct: bool,
+    texture_cache: Option<texture_cache::TextureCache>,
+    deinterlace_mode: DeinterlaceMode,
 }
 
 impl Resettable for GPU {
     fn reset_component(&mut self, hard_reset: bool) {
         if hard_reset {
             self.vram.fill(0);
+            self.sync_scaled_vram();
         }
         self.cmd_fifo.clear();
         self.gp0_fifo.clear();
@@ -721,6 +735,7 @@ impl Resettable for GPU {
         self.show_whole_vram = false;
         self.last_cpu_perf = 0;
         self.cpu_vram_copy_buffer.clear();
+        self.invalidate_texture_cache();
 
         self.display_config.horizontal_start = 0x260 + 0;
         self.display_config.horizontal_end = 0x260 + 320 * 8;
@@ -739,6 +754,59 @@ impl Resettable for GPU {
     }
 }
 
+impl_state_struct!(Color { r, g, b, m });
+impl_state_enum!(TextureDepth { TextureDepth::T4Bit, TextureDepth::T8Bit, TextureDepth::T15Bit, TextureDepth::Reserved });
+impl_state_struct!(Texture {
+    page_base_x, page_base_y, depth, rectangle_x_flip, rectangle_y_flip, window_x_mask, window_y_mask, window_x_offset, window_y_offset
+});
+impl_state_struct!(DrawingArea { draw_to_display, area_left, area_top, area_right, area_bottom, x_offset, y_offset });
+impl_state_struct!(VideoHorizontalResolution { 0 });
+impl_state_enum!(InterlaceField { InterlaceField::Even, InterlaceField::Odd });
+impl_state_enum!(VideoVerticalResolution { VideoVerticalResolution::Y240Lines, VideoVerticalResolution::Y480Lines });
+impl_state_enum!(VideoMode { VideoMode::Ntsc, VideoMode::Pal });
+impl_state_enum!(DisplayDepth { DisplayDepth::D15Bits, DisplayDepth::D24Bits });
+impl_state_struct!(DisplayConfig {
+    vram_x_start, vram_y_start, horizontal_start, horizontal_end, vertical_start, vertical_end, field, h_res, v_res,
+    video_mode, display_depth, display_disabled, interlaced
+});
+impl_state_enum!(DMADirection { DMADirection::Off, DMADirection::Fifo, DMADirection::CpuToGp0, DMADirection::VRamToCpu });
+impl_state_struct!(ReadyBits { ready_to_receive_cmd_word, ready_to_send_vram_to_cpu, ready_to_receive_dma_block });
+impl_state_enum!(SemiTransparency {
+    SemiTransparency::Average, SemiTransparency::Additive, SemiTransparency::Subtractive, SemiTransparency::AddQuarter
+});
+impl_state_struct!(CommandFifo { buf, head, tail, len });
+impl_state_struct!(Raster { total_lines, total_cycles, v_blank, h_blank, raster_line });
+impl_state_struct!(VRamCopyConfig { coord_x, coord_y, counter_x, counter_y, width, height });
+impl_state_struct!(Vertex { x, y });
+
+impl SaveState for GPU {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.section("GPU");
+        s.sync(&mut self.vram);
+        s.sync(&mut self.cmd_fifo);
+        s.sync(&mut self.gp0_fifo);
+        s.sync(&mut self.texture);
+        s.sync(&mut self.semi_transparency);
+        s.sync(&mut self.dithering);
+        s.sync(&mut self.force_set_mask_bit);
+        s.sync(&mut self.preserve_masked_pixels);
+        s.sync(&mut self.drawing_area);
+        s.sync(&mut self.display_config);
+        s.sync(&mut self.reverse_flag);
+        s.sync(&mut self.irq);
+        s.sync(&mut self.dma_direction);
+        s.sync(&mut self.ready_bits);
+        s.sync(&mut self.raster);
+        s.sync(&mut self.gpu_read_register);
+        s.sync(&mut self.gp0state);
+        s.sync(&mut self.cpu_vram_copy_buffer);
+        if s.is_loading() {
+            self.sync_scaled_vram();
+            self.invalidate_texture_cache();
+        }
+    }
+}
+
 impl GPU {
     pub fn new(config:&Config,renderer:Box<dyn Renderer>) -> Self {
         let mut gpu = GPU {
@@ -765,14 +833,28 @@ impl GPU {
             last_cpu_perf: 0,
             cpu_vram_copy_buffer: Vec::new(),
             command_delay_enabled: config.gpu_config.command_delay_enabled,
+            last_frame: None,
+            scaled_vram: None,
+            pgxp_vertices: None,
+            pgxp_perspective_correct: false,
+            texture_cache: None,
+            deinterlace_mode: DeinterlaceMode::default(),
         };
 
         gpu.reset_component(false);
         gpu.init_gp1_commands();
+        gpu.set_internal_resolution(config.gpu_config.internal_resolution);
+        gpu.set_pgxp(config.gpu_config.pgxp_enabled,config.gpu_config.pgxp_perspective_correct);
+        gpu.set_texture_cache_enabled(config.gpu_config.texture_cache_enabled);
+        gpu.set_deinterlace_mode(config.gpu_config.deinterlace_mode);
         
         gpu
     }
 
+    pub fn set_command_delay_enabled(&mut self,enabled:bool) {
+        self.command_delay_enabled = enabled;
+    }
+
     pub fn set_video_mode(&mut self,video_mode:VideoMode) {
         self.display_config.video_mode = video_mode;
         self.raster.total_lines = video_mode.total_lines();
@@ -786,6 +868,23 @@ impl GPU {
     pub fn get_renderer_mut(&mut self) -> &mut Box<dyn Renderer> {
         &mut self.renderer
     }
+
+    /*
+    Saves the last displayed frame to a PNG file
+     */
+    pub fn dump_frame_png(&self,path:&Path) -> Result<(),String> {
+        match self.last_frame.as_ref() {
+            Some(frame) => frame.save_png(path),
+            None => Err("No frame available yet".to_string()),
+        }
+    }
+
+    /*
+    Saves the whole 1024x512 VRAM, as 15 bits pixels, to a PNG file
+     */
+    pub fn dump_vram_png(&self,path:&Path) -> Result<(),String> {
+        self.create_frame(true).save_png(path)
+    }
     /*
     1F801814h - GPUSTAT - GPU Status Register (R)
       0-3   Texture page X Base   (N*64)                              ;GP0(E1h).0-3
@@ -835,7 +934,7 @@ impl GPU {
         st |= (self.drawing_area.draw_to_display as u32) << 10;
         st |= (self.force_set_mask_bit as u32) << 11;
         st |= (self.preserve_masked_pixels as u32) << 12;
-        st |= (self.display_config.field as u32) << 13;
+        st |= self.interlace_field_status() << 13;
         st |= (self.reverse_flag as u32) << 14;
         st |= 0 << 15; // forced
         st |= self.display_config.h_res.to_status() << 16;
@@ -859,12 +958,7 @@ impl GPU {
         st |= (self.ready_bits.ready_to_receive_dma_block as u32) << 28;
         st |= (self.dma_direction as u32) << 29;
 
-        let mut bit31 = 1;
-        if self.display_config.field == InterlaceField::Even || self.raster.v_blank {
-            bit31 = 0;
-        }
-
-        st |= bit31 << 31;
+        st |= self.odd_line_status() << 31;
 
         //info!("Reading GPUSTAT: {:08X}",st);
         st
@@ -969,8 +1063,8 @@ impl GPU {
         self.generate_new_frame();
     }
 
-    fn generate_new_frame(&mut self) {
-        let (frame_width,frame_height) = if self.show_whole_vram {
+    fn create_frame(&self,whole_vram:bool) -> GPUFrameBuffer {
+        let (frame_width,frame_height) = if whole_vram {
             (1024,512)
         }
         else {
@@ -979,47 +1073,64 @@ impl GPU {
         };
 
         let visible = self.display_config.visible_area();
-        let crt_width = if self.show_whole_vram { 1024 } else { visible.0 };
-        let crt_height = if self.show_whole_vram {
+        let crt_width = if whole_vram { 1024 } else { visible.0 };
+        let crt_height = if whole_vram {
             512
         }
         else {
             visible.1
         };
 
-        let crt_start_x_offset = ((crt_width as u16).saturating_sub(frame_width as u16) >> 1) as usize;
-        let crt_start_y_offset = ((crt_height as u16).saturating_sub(frame_height as u16) >> 1) as usize;
+        let is24_bit = !whole_vram && matches!(self.display_config.display_depth,DisplayDepth::D24Bits);
+        // upscaled frame from the scaled VRAM
+        let scaled_vram = self.scaled_vram.as_ref().filter(|_| !whole_vram && !is24_bit);
+        let shift = scaled_vram.map_or(0,|scaled| scaled.shift());
+        let (visible_width,visible_height) = (frame_width,frame_height);
+        let (frame_width,frame_height) = (frame_width << shift,frame_height << shift);
+        let (crt_width,crt_height) = (crt_width << shift,crt_height << shift);
+
+        let crt_start_x_offset = crt_width.saturating_sub(frame_width) >> 1;
+        let crt_start_y_offset = crt_height.saturating_sub(frame_height) >> 1;
 
         let mut frame_buffer = vec![0u8; crt_width * crt_height << 2]; // RGBA8
-        if !self.display_config.display_disabled {
-            let vram_x0 = if self.show_whole_vram { 0 } else { self.display_config.vram_x_start as usize };
-            let vram_y0 = if self.show_whole_vram { 0 } else { self.display_config.vram_y_start as usize };
+        if whole_vram || !self.display_config.display_disabled {
+            let vram_x0 = if whole_vram { 0 } else { (self.display_config.vram_x_start as usize) << shift };
+            let vram_y0 = if whole_vram { 0 } else { (self.display_config.vram_y_start as usize) << shift };
 
-            let is24_bit = !self.show_whole_vram && matches!(self.display_config.display_depth,DisplayDepth::D24Bits);
             let row_offset_base = crt_start_y_offset * (crt_width << 2) + (crt_start_x_offset << 2);
             for y in 0..frame_height.min(crt_height) {
                 let mut row_offset = y * (crt_width << 2) + row_offset_base;
+                // interlaced output: the row is taken from the fields as configured by the deinterlace mode
+                let frame_row = if whole_vram { interlace::FrameRow::Line(y) } else { self.frame_row(y,visible_height,shift) };
                 for x in 0..frame_width.min(crt_width) {
-                    let vram_y = vram_y0 + y;
-
-                    let (r, g, b) = if is24_bit {
-                        let byte_offset = ((vram_y & 0x1FF) << 11) + (vram_x0 * 2 + (x & 0x3FF) * 3);
-                        if byte_offset + 2 < self.vram.len() {
-                            let r = self.vram[byte_offset];
-                            let g = self.vram[byte_offset + 1];
-                            let b = self.vram[byte_offset + 2];
-                            (r, g, b)
+                    let read = |line:usize| {
+                        let vram_y = vram_y0 + line;
+                        if is24_bit {
+                            let byte_offset = ((vram_y & 0x1FF) << 11) + (vram_x0 * 2 + (x & 0x3FF) * 3);
+                            if byte_offset + 2 < self.vram.len() {
+                                let r = self.vram[byte_offset];
+                                let g = self.vram[byte_offset + 1];
+                                let b = self.vram[byte_offset + 2];
+                                Some((r, g, b))
+                            } else {
+                                None
+                            }
                         } else {
-                            continue;
+                            let vram_x = vram_x0 + x;
+                            let pixel = match scaled_vram {
+                                Some(scaled) => scaled.get(vram_x,vram_y),
+                                None => self.get_pixel_15(self.get_vram_offset_15(vram_x as u16, vram_y as u16)),
+                            };
+
+                            let r = RGB5_TO_RGB8[(pixel & 0x1F) as usize];
+                            let g = RGB5_TO_RGB8[((pixel >> 5) & 0x1F) as usize];
+                            let b = RGB5_TO_RGB8[((pixel >> 10) & 0x1F) as usize];
+                            Some((r, g, b))
                         }
-                    } else {
-                        let vram_x = vram_x0 + x;
-                        let pixel = self.get_pixel_15(self.get_vram_offset_15(vram_x as u16, vram_y as u16));
-
-                        let r = RGB5_TO_RGB8[(pixel & 0x1F) as usize];
-                        let g = RGB5_TO_RGB8[((pixel >> 5) & 0x1F) as usize];
-                        let b = RGB5_TO_RGB8[((pixel >> 10) & 0x1F) as usize];
-                        (r, g, b)
+                    };
+
+                    let Some((r, g, b)) = frame_row.combine(read) else {
+                        continue;
                     };
 
                     frame_buffer[row_offset] = r;
@@ -1031,7 +1142,13 @@ impl GPU {
             }
         }
 
-        self.renderer.render_frame(GPUFrameBuffer::new(Arc::new(frame_buffer),crt_width,crt_height,frame_width,frame_height,self.show_whole_vram),self.last_cpu_perf);
+        GPUFrameBuffer::new(Arc::new(frame_buffer),crt_width,crt_height,visible_width,visible_height,whole_vram)
+    }
+
+    fn generate_new_frame(&mut self) {
+        let frame = self.create_frame(self.show_whole_vram);
+        self.last_frame = Some(frame.clone());
+        self.renderer.render_frame(frame,self.last_cpu_perf);
     }
 
     pub fn command_completed(&mut self,clock:&mut Clock,interrupt_handler:&mut IrqHandler) {
@@ -1059,6 +1176,9 @@ impl DmaDevice for GPU {
     fn dma_write(&mut self, word: u32,clock:&mut Clock,irq_handler:&mut IrqHandler) {
         self.gp0_cmd(word,clock,irq_handler);
     }
+    fn dma_pgxp_vertex(&mut self, vertex: PgxpVertex) {
+        self.pgxp_vertex(vertex);
+    }
     fn dma_read(&mut self) -> u32 {
         self.gpu_read_read()
     }
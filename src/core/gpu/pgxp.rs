@@ -0,0 +1,82 @@
+use crate::core::cpu::pgxp::PgxpVertex;
+use crate::core::gpu::{Vertex, GPU};
+use tracing::info;
+
+// precise vertices received with the last GP0 words: enough for the vertices of the commands waiting in the GP0 FIFO
+const RECENT_VERTICES : usize = 32;
+
+/*
+PGXP, GPU side: the precise vertices that came along with the GP0 words (see cpu::pgxp).
+They are matched with the polygon vertices by their integer value: the same word sent twice has the same vertex.
+The precise coordinates are used by the upscaled rendering (the native VRAM is always drawn with the integer ones)
+and, optionally, for the perspective correct texture mapping of the displayed image.
+ */
+pub(super) struct RecentVertices {
+    vertices: [Option<PgxpVertex>;RECENT_VERTICES],
+    next: usize,
+}
+
+impl RecentVertices {
+    fn new() -> Self {
+        Self { vertices: [None;RECENT_VERTICES], next: 0 }
+    }
+
+    fn push(&mut self,vertex:PgxpVertex) {
+        self.vertices[self.next] = Some(vertex);
+        self.next = (self.next + 1) % RECENT_VERTICES;
+    }
+
+    // the most recent vertex received with the given word
+    fn find(&self,word:u32) -> Option<PgxpVertex> {
+        (1..=RECENT_VERTICES)
+            .map(|i| self.vertices[(self.next + RECENT_VERTICES - i) % RECENT_VERTICES])
+            .find_map(|vertex| PgxpVertex::matching(vertex,word))
+    }
+}
+
+impl GPU {
+    pub fn set_pgxp(&mut self,enabled:bool,perspective_correct:bool) {
+        if enabled != self.pgxp_vertices.is_some() || perspective_correct != self.pgxp_perspective_correct {
+            info!("GPU PGXP enabled: {enabled}, perspective correct texturing: {}",enabled && perspective_correct);
+        }
+        self.pgxp_vertices = if enabled { Some(RecentVertices::new()) } else { None };
+        self.pgxp_perspective_correct = enabled && perspective_correct;
+    }
+
+    /*
+    Precise vertex of the next GP0 word
+     */
+    pub fn pgxp_vertex(&mut self,vertex:PgxpVertex) {
+        if let Some(vertices) = self.pgxp_vertices.as_mut() {
+            vertices.push(vertex);
+        }
+    }
+
+    /*
+    The precise vertex of the polygon vertex word, moved by the drawing offset as the vertex is
+     */
+    pub(super) fn pgxp_polygon_vertex(&self,word:u32,vertex:&Vertex) -> Option<PgxpVertex> {
+        let precise = self.pgxp_vertices.as_ref()?.find(word)?;
+        let x = precise.x + self.drawing_area.x_offset as f32;
+        let y = precise.y + self.drawing_area.y_offset as f32;
+        // the GPU uses 11 bits coordinates: a vertex out of that range is not the one projected
+        if (x - vertex.x as f32).abs() >= 2.0 || (y - vertex.y as f32).abs() >= 2.0 {
+            return None;
+        }
+        Some(PgxpVertex { x, y, ..precise })
+    }
+
+    /*
+    The vertex at the internal resolution given by shift: the precise coordinates, if any, give the sub-pixel position
+     */
+    #[inline(always)]
+    pub(super) fn raster_vertex(vertex:&Vertex,precise:Option<PgxpVertex>,shift:u32) -> Vertex {
+        match precise {
+            Some(precise) if shift > 0 => {
+                let scale = (1 << shift) as f32;
+                Vertex { x: (precise.x * scale).round() as i16, y: (precise.y * scale).round() as i16 }
+            }
+            _ => vertex.scaled(shift),
+        }
+    }
+}
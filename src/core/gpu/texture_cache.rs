@@ -0,0 +1,179 @@
+use crate::core::gpu::{TextureDepth, GPU};
+use tracing::info;
+
+const CACHE_LINES : usize = 256;
+const INVALID_TAG : u32 = u32::MAX;
+
+/*
+GPU texture cache (2KB): 256 lines of 8 bytes, i.e. 16 texels at 4 bit, 8 at 8 bit, 4 at 15 bit.
+The texture page is tiled with cache blocks of 64x64 (4 bit), 32x64 (8 bit) or 32x32 (15 bit) texels: every block maps
+to the whole cache, so the same line number of two different blocks cannot be cached at once.
+Every line keeps the VRAM halfword address it was loaded from (texture page and texel position) as tag.
+The CLUT is read through a separate CLUT cache, keyed by CLUT position and depth, reloaded when a different CLUT is used.
+Both are invalidated by GP0(01h), by a texture page change and by a VRAM transfer (CPU->VRAM, VRAM->VRAM, fill).
+Rendering marks dirty the 64x256 VRAM pages it writes holding cached texels or the CLUT, and their lines are dropped before
+the next command: a primitive drawing into the area it's sampling reads the stale texels, the following ones the new pixels.
+The cache is not saved in states: it's empty after a load.
+ */
+pub(super) struct TextureCache {
+    lines: Box<[(u32,[u16;4]);CACHE_LINES]>,
+    clut_tag: Option<(u32,u32,bool)>,
+    clut: Box<[u16;256]>,
+    cached_pages: u32,
+    dirty_pages: u32,
+}
+
+/*
+VRAM page (64x256 halfwords, as a texture page) of the halfword at (x,y)
+ */
+#[inline(always)]
+fn page_bit(x:u32,y:u32) -> u32 {
+    1 << (((x & 0x3FF) >> 6) | (((y & 0x1FF) >> 8) << 4))
+}
+
+/*
+VRAM pages of a CLUT: 16 (4 bit) or 256 (8 bit) halfwords from (clut_x * 16,clut_y), up to 5 pages, wrapping at the VRAM width
+ */
+fn clut_page_bits(clut_x:u32,clut_y:u32,eight_bit:bool) -> u32 {
+    let (start,entries) = (clut_x << 4,if eight_bit { 256 } else { 16 });
+    (0..entries).step_by(64).fold(page_bit(start + entries - 1,clut_y),|bits,i| bits | page_bit(start + i,clut_y))
+}
+
+impl TextureCache {
+    fn new() -> Self {
+        Self {
+            lines: Box::new([(INVALID_TAG,[0;4]);CACHE_LINES]),
+            clut_tag: None,
+            clut: Box::new([0;256]),
+            cached_pages: 0,
+            dirty_pages: 0,
+        }
+    }
+
+    pub fn invalidate(&mut self) {
+        self.lines.iter_mut().for_each(|(tag,_)| *tag = INVALID_TAG);
+        self.clut_tag = None;
+        self.cached_pages = 0;
+        self.dirty_pages = 0;
+    }
+
+    /*
+    The pixel at the VRAM byte offset has been drawn
+     */
+    #[inline(always)]
+    fn write(&mut self,offset:usize) {
+        let bit = page_bit((offset >> 1) as u32,(offset >> 11) as u32);
+        self.dirty_pages |= self.cached_pages & bit;
+    }
+
+    fn invalidate_dirty_pages(&mut self) {
+        let dirty_pages = std::mem::take(&mut self.dirty_pages);
+        for (tag,_) in self.lines.iter_mut().filter(|(tag,_)| *tag != INVALID_TAG) {
+            if page_bit(*tag,*tag >> 10) & dirty_pages != 0 {
+                *tag = INVALID_TAG;
+            }
+        }
+        if let Some((clut_x,clut_y,eight_bit)) = self.clut_tag && clut_page_bits(clut_x,clut_y,eight_bit) & dirty_pages != 0 {
+            self.clut_tag = None;
+        }
+        self.cached_pages &= !dirty_pages;
+    }
+
+    /*
+    The halfword at VRAM (x,y) through the cache line number line
+     */
+    #[inline(always)]
+    fn read(&mut self,vram:&[u8],x:u32,y:u32,line:usize) -> u16 {
+        let line_x = x & !3;
+        let tag = (y << 10) | line_x;
+        let (line_tag,data) = &mut self.lines[line];
+        if *line_tag != tag {
+            *line_tag = tag;
+            self.cached_pages |= page_bit(x,y);
+            for (i,hw) in data.iter_mut().enumerate() {
+                let offset = (((y << 10) + ((line_x + i as u32) & 0x3FF)) as usize) << 1;
+                *hw = vram[offset] as u16 | ((vram[offset + 1] as u16) << 8);
+            }
+        }
+        data[(x & 3) as usize]
+    }
+
+    #[inline(always)]
+    fn read_clut(&mut self,vram:&[u8],clut_x:u32,clut_y:u32,eight_bit:bool,index:u32) -> u16 {
+        let tag = Some((clut_x,clut_y,eight_bit));
+        if self.clut_tag != tag {
+            self.clut_tag = tag;
+            let entries = if eight_bit { 256 } else { 16 };
+            self.cached_pages |= clut_page_bits(clut_x,clut_y,eight_bit);
+            for (i,entry) in self.clut.iter_mut().take(entries).enumerate() {
+                let offset = (((clut_y << 10) + (((clut_x << 4) + i as u32) & 0x3FF)) as usize) << 1;
+                *entry = vram[offset] as u16 | ((vram[offset + 1] as u16) << 8);
+            }
+        }
+        self.clut[index as usize]
+    }
+}
+
+impl GPU {
+    pub fn set_texture_cache_enabled(&mut self,enabled:bool) {
+        if enabled != self.texture_cache.is_some() {
+            info!("GPU texture cache enabled: {enabled}");
+        }
+        self.texture_cache = if enabled { Some(TextureCache::new()) } else { None };
+    }
+
+    pub(super) fn invalidate_texture_cache(&mut self) {
+        if let Some(cache) = self.texture_cache.as_mut() {
+            cache.invalidate();
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn texture_cache_write(&mut self,offset:usize) {
+        if let Some(cache) = self.texture_cache.as_mut() {
+            cache.write(offset);
+        }
+    }
+
+    /*
+    Before a rendering command: drops the cached texels overwritten by the previous ones
+     */
+    pub(super) fn invalidate_texture_cache_dirty_pages(&mut self) {
+        if let Some(cache) = self.texture_cache.as_mut() && cache.dirty_pages != 0 {
+            cache.invalidate_dirty_pages();
+        }
+    }
+
+    /*
+    Texture page set by GP0(E1h) or by the texpage attribute of a textured polygon: a different page invalidates the cache
+     */
+    pub(super) fn set_texture_page(&mut self,page_base_x:u8,page_base_y:u8,depth:TextureDepth) {
+        if self.texture.page_base_x != page_base_x || self.texture.page_base_y != page_base_y || self.texture.depth as u8 != depth as u8 {
+            self.invalidate_texture_cache();
+        }
+        self.texture.page_base_x = page_base_x;
+        self.texture.page_base_y = page_base_y;
+        self.texture.depth = depth;
+    }
+
+    /*
+    Texel (after the texture window) of the texture page at (u,v) through the texture cache: the raw halfword read from
+    the VRAM and the cache line, by depth
+     */
+    #[inline(always)]
+    pub(super) fn read_cached_texel(&mut self,x:u32,y:u32,u:u32,v:u32,depth:TextureDepth) -> Option<u16> {
+        let cache = self.texture_cache.as_mut()?;
+        let line = match depth {
+            TextureDepth::T4Bit => ((v & 63) << 2) | ((u >> 4) & 3),
+            TextureDepth::T8Bit => ((v & 63) << 2) | ((u >> 3) & 3),
+            TextureDepth::T15Bit | TextureDepth::Reserved => ((v & 31) << 3) | ((u >> 2) & 7),
+        };
+        Some(cache.read(&self.vram,x,y,line as usize))
+    }
+
+    #[inline(always)]
+    pub(super) fn read_cached_clut(&mut self,clut_x:u32,clut_y:u32,eight_bit:bool,index:u32) -> Option<u16> {
+        let cache = self.texture_cache.as_mut()?;
+        Some(cache.read_clut(&self.vram,clut_x,clut_y,eight_bit,index))
+    }
+}
@@ -0,0 +1,232 @@
+use crate::core::gpu::gp0::DITHER_TABLE;
+use crate::core::gpu::{Color, SemiTransparency, Vertex, GPU};
+use tracing::{info, warn};
+
+/*
+Upscaled shadow VRAM, used to render at an internal resolution multiple (2x,4x,8x) of the native one.
+The native VRAM is always kept exact: it's the one read by textures, CLUTs and VRAM->CPU transfers.
+Every write to the native VRAM is replicated here:
+- polygons are rasterized again with the vertices scaled, so edges, colors and texture coordinates are interpolated at the higher resolution
+- lines are drawn again between the scaled end points, scale pixels thick
+- rectangles, CPU->VRAM transfers and fills write every native pixel as a scale x scale block
+- VRAM->VRAM copies move the scaled blocks, preserving the upscaled details
+Mask bit and semi transparency are applied to the scaled pixels; dithering uses the native coordinates.
+The displayed frame is taken from here (except for 24 bit display mode, that is never drawn by the GPU).
+The shadow VRAM is not saved in states: it's rebuilt from the native one after a load.
+ */
+pub(super) struct ScaledVRam {
+    shift: u32,
+    width: usize,
+    height: usize,
+    pixels: Vec<u16>,
+}
+
+impl ScaledVRam {
+    fn new(shift:u32,vram:&[u8]) -> Self {
+        let width = 1024 << shift;
+        let height = 512 << shift;
+        let mut scaled = Self { shift, width, height, pixels: vec![0;width * height] };
+        scaled.upscale_from(vram);
+        scaled
+    }
+
+    pub fn shift(&self) -> u32 {
+        self.shift
+    }
+
+    // coordinates wrap as the native ones do
+    #[inline(always)]
+    fn offset(&self,x:usize,y:usize) -> usize {
+        ((y & (self.height - 1)) * self.width) + (x & (self.width - 1))
+    }
+
+    #[inline(always)]
+    pub fn get(&self,x:usize,y:usize) -> u16 {
+        self.pixels[self.offset(x,y)]
+    }
+
+    /*
+    Replaces the whole content with the native VRAM, every native pixel becomes a scale x scale block
+     */
+    pub fn upscale_from(&mut self,vram:&[u8]) {
+        for y in 0..self.height {
+            let native_row = (y >> self.shift) << 11;
+            for x in 0..self.width {
+                let native_offset = native_row + ((x >> self.shift) << 1);
+                self.pixels[y * self.width + x] = vram[native_offset] as u16 | (vram[native_offset + 1] as u16) << 8;
+            }
+        }
+    }
+}
+
+impl Vertex {
+    #[inline(always)]
+    pub(super) fn scaled(&self,shift:u32) -> Vertex {
+        Vertex { x: self.x << shift, y: self.y << shift }
+    }
+}
+
+impl GPU {
+    /*
+    Sets the internal resolution multiplier: 1 (native), 2, 4 or 8
+     */
+    pub fn set_internal_resolution(&mut self,scale:usize) {
+        let shift = match scale {
+            1 | 2 | 4 | 8 => scale.trailing_zeros(),
+            _ => {
+                warn!("Unsupported internal resolution {}x, using native resolution",scale);
+                0
+            }
+        };
+        if self.scaled_shift() == shift {
+            return;
+        }
+        self.scaled_vram = if shift == 0 { None } else { Some(ScaledVRam::new(shift,&self.vram)) };
+        info!("GPU internal resolution set to {}x",1 << shift);
+    }
+
+    // 0 if the upscaling is disabled
+    #[inline(always)]
+    pub(super) fn scaled_shift(&self) -> u32 {
+        self.scaled_vram.as_ref().map_or(0,|scaled| scaled.shift)
+    }
+
+    pub(super) fn sync_scaled_vram(&mut self) {
+        if let Some(scaled) = self.scaled_vram.as_mut() {
+            scaled.upscale_from(&self.vram);
+        }
+    }
+
+    /*
+    Same as draw_pixel_offset, on the scaled VRAM at scaled coordinates
+     */
+    #[inline(always)]
+    pub(super) fn draw_scaled_pixel(&mut self,x:usize,y:usize,pixel:u16,use_mask:bool,semi_transparent:bool,semi_transparency:Option<SemiTransparency>) {
+        let preserve_masked_pixels = self.preserve_masked_pixels;
+        let force_set_mask_bit = self.force_set_mask_bit;
+        let Some(scaled) = self.scaled_vram.as_mut() else { return };
+        let offset = scaled.offset(x,y);
+        let old_pixel = scaled.pixels[offset];
+        let mut pixel_to_write = pixel;
+        if use_mask {
+            if preserve_masked_pixels && (old_pixel & 0x8000) != 0 { // pixel is protected
+                return;
+            }
+            if force_set_mask_bit {
+                pixel_to_write |= 0x8000;
+            }
+        }
+        if semi_transparent {
+            pixel_to_write = semi_transparency.unwrap().blend_rgb555(pixel_to_write,old_pixel);
+        }
+        scaled.pixels[offset] = pixel_to_write;
+    }
+
+    /*
+    Draws the scale x scale block of the given native pixel
+     */
+    #[inline]
+    pub(super) fn draw_scaled_block(&mut self,x:u16,y:u16,pixel:u16,use_mask:bool,semi_transparent:bool,semi_transparency:Option<SemiTransparency>) {
+        let shift = self.scaled_shift();
+        if shift == 0 {
+            return;
+        }
+        let x0 = ((x & 0x3FF) as usize) << shift;
+        let y0 = ((y & 0x1FF) as usize) << shift;
+        for y in y0..y0 + (1 << shift) {
+            for x in x0..x0 + (1 << shift) {
+                self.draw_scaled_pixel(x,y,pixel,use_mask,semi_transparent,semi_transparency);
+            }
+        }
+    }
+
+    /*
+    Copies the scaled block of the native pixel (src_x,src_y) to the one of (dest_x,dest_y), the mask setting is applied
+     */
+    #[inline]
+    pub(super) fn copy_scaled_block(&mut self,src_x:u16,src_y:u16,dest_x:u16,dest_y:u16) {
+        let shift = self.scaled_shift();
+        if shift == 0 {
+            return;
+        }
+        let scale = 1 << shift;
+        let (src_x,src_y) = (((src_x & 0x3FF) as usize) << shift,((src_y & 0x1FF) as usize) << shift);
+        let (dest_x,dest_y) = (((dest_x & 0x3FF) as usize) << shift,((dest_y & 0x1FF) as usize) << shift);
+        for y in 0..scale {
+            for x in 0..scale {
+                let pixel = self.scaled_vram.as_ref().unwrap().get(src_x + x,src_y + y);
+                self.draw_scaled_pixel(dest_x + x,dest_y + y,pixel,true,false,None);
+            }
+        }
+    }
+
+    /*
+    Draws the line between the scaled end points: every step along the major axis draws scale pixels along the minor one,
+    and the major axis is extended to cover the whole block of the last native pixel
+     */
+    pub(super) fn draw_scaled_line(&mut self,start:&Vertex,end:&Vertex,start_color:&Color,end_color:&Color,shaded:bool,semi_transparent:bool) {
+        let shift = self.scaled_shift();
+        if shift == 0 {
+            return;
+        }
+        let last = (1 << shift) - 1;
+        let (mut x0,mut y0) = ((start.x as i32) << shift,(start.y as i32) << shift);
+        let (mut x1,mut y1) = ((end.x as i32) << shift,(end.y as i32) << shift);
+        let x_major = (x1 - x0).abs() >= (y1 - y0).abs();
+        let (major_start,major_end) = if x_major { (&mut x0,&mut x1) } else { (&mut y0,&mut y1) };
+        if *major_start <= *major_end {
+            *major_end += last;
+        }
+        else {
+            *major_start += last;
+        }
+
+        let dx = (x1 - x0).abs();
+        let dy = (y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let total_steps = dx.max(dy).max(1);
+
+        let r_step = if shaded { ((end_color.r as i32 - start_color.r as i32) << 16) / total_steps } else { 0 };
+        let g_step = if shaded { ((end_color.g as i32 - start_color.g as i32) << 16) / total_steps } else { 0 };
+        let b_step = if shaded { ((end_color.b as i32 - start_color.b as i32) << 16) / total_steps } else { 0 };
+        let mut r_current = (start_color.r as i32) << 16;
+        let mut g_current = (start_color.g as i32) << 16;
+        let mut b_current = (start_color.b as i32) << 16;
+
+        let mut err = dx - dy;
+        let (mut x,mut y) = (x0,y0);
+        for _ in 0..=dx.max(dy) {
+            let color = Color::new((r_current >> 16) as u8,(g_current >> 16) as u8,(b_current >> 16) as u8,false);
+            for t in 0..=last {
+                let (px,py) = if x_major { (x,y + t) } else { (x + t,y) };
+                let native = Vertex { x: (px >> shift) as i16, y: (py >> shift) as i16 };
+                if native.is_inside_drawing_area(&self.drawing_area) && !self.is_skipped_field_line(native.y as u16) {
+                    let color = if self.dithering {
+                        color.dither(DITHER_TABLE[(native.y & 3) as usize][(native.x & 3) as usize])
+                    }
+                    else {
+                        color
+                    };
+                    let semi_transparency = self.semi_transparency;
+                    self.draw_scaled_pixel(px as usize,py as usize,color.to_u16(),true,semi_transparent,Some(semi_transparency));
+                }
+            }
+
+            let e2 = err << 1;
+            if e2 > -dy {
+                err -= dy;
+                x += sx;
+            }
+            if e2 < dx {
+                err += dx;
+                y += sy;
+            }
+            if shaded {
+                r_current += r_step;
+                g_current += g_step;
+                b_current += b_step;
+            }
+        }
+    }
+}
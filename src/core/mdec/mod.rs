@@ -10,6 +10,7 @@ use tracing::debug;
 use crate::core::clock::Clock;
 use crate::core::dma::DmaDevice;
 use crate::core::interrupt::IrqHandler;
+use crate::core::savestate::{impl_state_enum, impl_state_struct, SaveState, StateSync};
 use crate::core::Resettable;
 /*
 pub const ZIG_ZAG: &[u8; 64] = &[
@@ -185,6 +186,44 @@ impl Resettable for MDec {
     }
 }
 
+impl_state_enum!(DepthBits { DepthBits::Four, DepthBits::Eight, DepthBits::TwentyFour, DepthBits::Fifteen });
+impl_state_struct!(DecodeConfig { depth, signed, bit_15, parameters_remaining });
+impl_state_struct!(Color { r, g, b });
+impl_state_struct!(Buffers { cr_block, cb_block, y_block, idct_buffer, color_out_buffer, mono_out_buffer });
+
+impl SaveState for CommandState {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let (mut index,mut remaining,mut color_table) = match *self {
+            CommandState::Idle => (0u8,0u8,false),
+            CommandState::ReceivingCompressedData => (1,0,false),
+            CommandState::ReceivingLuminanceTable { bytes_remaining, color_table_after } => (2,bytes_remaining,color_table_after),
+            CommandState::ReceivingColorTable { bytes_remaining } => (3,bytes_remaining,false),
+            CommandState::ReceivingScaleTable { halfwords_remaining } => (4,halfwords_remaining,false),
+        };
+        s.sync(&mut index);
+        s.sync(&mut remaining);
+        s.sync(&mut color_table);
+        if s.is_loading() {
+            *self = match index {
+                0 => CommandState::Idle,
+                1 => CommandState::ReceivingCompressedData,
+                2 => CommandState::ReceivingLuminanceTable { bytes_remaining: remaining, color_table_after: color_table },
+                3 => CommandState::ReceivingColorTable { bytes_remaining: remaining },
+                4 => CommandState::ReceivingScaleTable { halfwords_remaining: remaining },
+                _ => {
+                    s.set_error(format!("Invalid MDEC command state {}",index));
+                    CommandState::Idle
+                }
+            }
+        }
+    }
+}
+
+impl_state_struct!(MDec, "MDEC" {
+    command_state, decode_config, data_in, data_out, enable_data_in, enable_data_out,
+    luminance_quant_table, color_quant_table, scale_table, buffers
+});
+
 impl MDec {
     pub fn new() -> Self {
         Self {
@@ -2,6 +2,7 @@ use crate::core::cdrom::CDRom;
 use crate::core::clock::{Clock, ClockConfig};
 use crate::core::config::Config;
 use crate::core::cpu::cop0::Cop0;
+use crate::core::cpu::pgxp::PgxpMemory;
 use crate::core::cpu::Cpu;
 use crate::core::dma::DMAController;
 use crate::core::gpu::GPU;
@@ -13,14 +14,18 @@ use crate::core::memory::{Memory, ReadMemoryAccess, WriteMemoryAccess};
 use crate::core::sio::SIO0;
 use crate::core::spu::Spu;
 use crate::core::timer::Timer;
+use crate::core::savestate::{impl_state_struct, SaveState, StateSync};
 use crate::core::Resettable;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::mem;
 use tracing::{debug, info, warn};
 
 const DEBUG_MEM : bool = false;
 
 const PHYSICAL_MEMORY_SIZE : usize = 2 * 1024 * 1024; // 2M
+pub const CODE_PAGE_SHIFT : u32 = 12; // 4K
+const CODE_PAGES : usize = PHYSICAL_MEMORY_SIZE >> CODE_PAGE_SHIFT;
 
 // Timings
 const SCRATCHPAD_READ_CYCLES: usize = 1;
@@ -158,12 +163,47 @@ impl Interrupt {
     }
 }
 
+/*
+RAM pages holding instructions decoded by the cached interpreter: a write to one of them (CPU, DMA, cheats, EXE loading, state loading)
+marks it dirty, and the CPU drops the blocks of the dirty pages before executing the next block
+ */
+struct CodePages {
+    cached: [u64;CODE_PAGES / 64],
+    dirty: [u64;CODE_PAGES / 64],
+    any_dirty: bool,
+}
+
+impl CodePages {
+    fn new() -> Self {
+        Self { cached: [0;CODE_PAGES / 64], dirty: [0;CODE_PAGES / 64], any_dirty: false }
+    }
+
+    #[inline(always)]
+    fn write(&mut self,ram_offset:usize) {
+        let page = ram_offset >> CODE_PAGE_SHIFT;
+        let bit = 1u64 << (page & 63);
+        if self.cached[page >> 6] & bit != 0 {
+            self.cached[page >> 6] &= !bit;
+            self.dirty[page >> 6] |= bit;
+            self.any_dirty = true;
+        }
+    }
+
+    fn write_all(&mut self) {
+        for (dirty,cached) in self.dirty.iter_mut().zip(self.cached.iter_mut()) {
+            *dirty |= mem::take(cached);
+        }
+        self.any_dirty = self.dirty.iter().any(|d| *d != 0);
+    }
+}
+
 impl Resettable for Bus {
     fn reset_component(&mut self, hard_reset: bool) {
         self.clock.reset_component(hard_reset);
         if hard_reset {
             self.main_ram.fill(0);
             self.scratchpad.fill(0);
+            self.code_pages.write_all();
         }
         self.cop0.reset_component(hard_reset);
         self.timer0.reset_component(hard_reset);
@@ -176,6 +216,34 @@ impl Resettable for Bus {
         self.sio0.reset_component(hard_reset);
         self.mdec.borrow_mut().reset_component(hard_reset);
         self.interrupt.reset();
+        self.pgxp.clear();
+    }
+}
+
+impl_state_struct!(Interrupt { pending, mask });
+
+impl SaveState for Bus {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.section("BUS");
+        s.sync(&mut self.main_ram);
+        if s.is_loading() {
+            self.code_pages.write_all();
+        }
+        s.sync(&mut self.scratchpad);
+        s.sync(&mut self.io_ports);
+        s.sync(&mut self.cache_control_reg);
+        s.sync(&mut self.interrupt);
+        s.sync(&mut self.cop0);
+        s.sync(&mut self.clock);
+        s.sync(&mut self.timer0);
+        s.sync(&mut self.timer1);
+        s.sync(&mut self.timer2);
+        s.sync(&mut self.sio0);
+        s.sync(&mut *self.dma.borrow_mut());
+        s.sync(&mut *self.gpu.borrow_mut());
+        s.sync(&mut *self.cdrom.borrow_mut());
+        s.sync(&mut *self.spu.borrow_mut());
+        s.sync(&mut *self.mdec.borrow_mut());
     }
 }
 
@@ -198,6 +266,8 @@ pub struct Bus {
     cache_control_reg: u32,
     interrupt: Interrupt,
     io_mem_bridge: MemoryBridge<0x1000>,
+    pgxp: PgxpMemory,
+    code_pages: CodePages,
 }
 
 impl InterruptController for Bus {
@@ -237,6 +307,8 @@ impl Bus {
             cache_control_reg: 0,
             interrupt: Interrupt::new(),
             io_mem_bridge: MemoryBridge::<0x1000>::new(),
+            pgxp: PgxpMemory::new(config.gpu_config.pgxp_enabled),
+            code_pages: CodePages::new(),
         };
         bus.init_io_bridge();
         bus.timer0.initial_scheduling(&mut bus.clock);
@@ -262,6 +334,26 @@ impl Bus {
         &mut self.clock
     }
 
+    /*
+    Cached interpreter: the RAM page of the given offset holds decoded instructions
+     */
+    pub fn set_code_page(&mut self,ram_offset:usize) {
+        let page = ram_offset >> CODE_PAGE_SHIFT;
+        self.code_pages.cached[page >> 6] |= 1 << (page & 63);
+    }
+
+    /*
+    Cached interpreter: the RAM pages written since the last call, as a bitmap (bit n = page n)
+     */
+    #[inline(always)]
+    pub fn take_dirty_code_pages(&mut self) -> Option<[u64;CODE_PAGES / 64]> {
+        if !self.code_pages.any_dirty {
+            return None;
+        }
+        self.code_pages.any_dirty = false;
+        Some(mem::take(&mut self.code_pages.dirty))
+    }
+
     pub fn get_clock(&self) -> &Clock {
         &self.clock
     }
@@ -278,10 +370,18 @@ impl Bus {
         &mut self.cop0
     }
 
-    pub fn get_cop0(&mut self) -> &Cop0 {
+    pub fn get_cop0(&self) -> &Cop0 {
         &self.cop0
     }
 
+    pub fn get_pgxp(&self) -> &PgxpMemory {
+        &self.pgxp
+    }
+
+    pub fn get_pgxp_mut(&mut self) -> &mut PgxpMemory {
+        &mut self.pgxp
+    }
+
     pub fn get_main_ram(&self) -> &Vec<u8> {
         &self.main_ram
     }
@@ -294,6 +394,10 @@ impl Bus {
         &self.bios.memory
     }
 
+    pub fn get_bios_md5(&self) -> &String {
+        &self.bios.md5
+    }
+
     fn io_port_read(&self,address:u32) -> u32 {
         self.io_ports[((address - IO_BASE_ADDRESS) >> 2) as usize]
     }
@@ -629,8 +733,11 @@ impl Bus {
                     // 1F801810h-Read  GPUREAD Receive responses to GP0(C0h) and GP1(10h) commands
                     self.io_mem_bridge.read[fun_offset] = |bus,_address,_size| ReadMemoryAccess::Read(bus.gpu.borrow_mut().gpu_read_read(), IO_REG_ACCESS_CYCLES);
                     self.io_mem_bridge.peek[fun_offset] = |bus,_address| Some(bus.gpu.borrow().gpu_read_peek());
-                    self.io_mem_bridge.write[fun_offset] = |bus,_address,value,_size| {
+                    self.io_mem_bridge.write[fun_offset] = |bus,address,value,_size| {
                         let mut irq_handler = IrqHandler::new();
+                        if let Some(vertex) = bus.pgxp.lookup(address,value) {
+                            bus.gpu.borrow_mut().pgxp_vertex(vertex);
+                        }
                         if bus.gpu.borrow_mut().gp0_cmd(value,&mut bus.clock,&mut irq_handler) { // GP0 queue is full
                             return WriteMemoryAccess::Wait;
                         }
@@ -853,6 +960,7 @@ impl Bus {
             warn!("Invalid EXE file size: forced to {}",exe_size);
         }
         self.main_ram[exe_ram_addr as usize..(exe_ram_addr + exe_size) as usize].copy_from_slice(&exe[2048..2048 + exe_size as usize]);
+        self.code_pages.write_all();
         cpu.get_registers_mut()[28] = initial_r28;
         if initial_sp != 0 {
             cpu.get_registers_mut()[29] = initial_sp;
@@ -865,6 +973,7 @@ impl Bus {
     pub fn load_pre_exe(&mut self,bin:Vec<u8>,address:u32) {
         let address = address & 0x1FFFFF;
         self.main_ram[address as usize..(address as usize + bin.len() )].copy_from_slice(&bin);
+        self.code_pages.write_all();
     }
 
     #[inline(never)]
@@ -985,6 +1094,7 @@ impl Bus {
                 if offset < 0x80_0000 { // first 8M
                     let ram_address = offset & 0x1F_FFFF; // // 2MB RAM can be mirrored to the first 8MB (strangely, enabled by default)
                     <Self as Memory>::mem_write::<SIZE>(ram_address,value,&mut self.main_ram);
+                    self.code_pages.write(ram_address as usize);
                     WriteMemoryAccess::Write(RAM_ACCESS_CYCLES)
                 }
                 else {
@@ -1145,6 +1255,7 @@ impl Memory for Bus {
                     _ => unreachable!()
                 }
             }
+            self.code_pages.write(ram_offset);
             return WriteMemoryAccess::Write(RAM_ACCESS_CYCLES);
         }
 
@@ -14,6 +14,8 @@ pub mod cdrom;
 mod mdec;
 mod spu;
 mod bios;
+pub mod savestate;
+pub mod unimplemented;
 
 // CPU Clock   =  33.868800MHz (44100Hz*300h)
 pub const CPU_CLOCK : usize = 33_868_800;
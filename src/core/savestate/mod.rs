@@ -0,0 +1,349 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+
+/*
+Save state support
+Every component implements SaveState with a single sync_state method used both for saving and loading:
+in this way the order of the fields written is always the same of the order of the fields read.
+The StateSync holds the binary buffer: when saving every value is appended to the buffer, when loading every value
+is read from the buffer and assigned to the field.
+Errors (i.e. truncated buffer or wrong section) are recorded inside the StateSync and returned by finish.
+ */
+pub const STATE_MAGIC : &[u8;8] = b"RPS1SAVE";
+pub const STATE_VERSION : u32 = 6;
+
+/*
+Header written at the beginning of every state file
+ */
+#[derive(Default)]
+pub struct StateHeader {
+    pub magic: [u8;8],
+    pub version: u32,
+    pub emu_version: String,
+    pub bios_md5: String,
+    pub disc_path: Option<String>,
+}
+
+impl StateHeader {
+    pub fn new(emu_version:&str,bios_md5:&str,disc_path:Option<String>) -> Self {
+        Self {
+            magic: *STATE_MAGIC,
+            version: STATE_VERSION,
+            emu_version: emu_version.to_string(),
+            bios_md5: bios_md5.to_string(),
+            disc_path,
+        }
+    }
+
+    pub fn check(&self,bios_md5:&str) -> Result<(),String> {
+        if self.magic != *STATE_MAGIC {
+            return Err("Not a valid state file".to_string());
+        }
+        if self.version != STATE_VERSION {
+            return Err(format!("Unsupported state version {}, expected {}",self.version,STATE_VERSION));
+        }
+        if !self.bios_md5.eq_ignore_ascii_case(bios_md5) {
+            return Err(format!("State saved with a different bios (MD5 {})",self.bios_md5));
+        }
+        Ok(())
+    }
+}
+
+pub trait SaveState {
+    fn sync_state(&mut self,s:&mut StateSync);
+
+    /*
+    Syncs every element of a slice, in order: types whose encoding is their memory layout (bytes) copy the whole slice at once
+     */
+    fn sync_slice(slice:&mut [Self],s:&mut StateSync) where Self:Sized {
+        for e in slice.iter_mut() {
+            s.sync(e);
+        }
+    }
+}
+
+pub struct StateSync {
+    buffer: Vec<u8>,
+    pos: usize,
+    loading: bool,
+    error: Option<String>,
+}
+
+impl StateSync {
+    pub fn new_saving() -> Self {
+        Self {
+            buffer: Vec::with_capacity(4 * 1024 * 1024),
+            pos: 0,
+            loading: false,
+            error: None,
+        }
+    }
+
+    pub fn new_loading(buffer:Vec<u8>) -> Self {
+        Self {
+            buffer,
+            pos: 0,
+            loading: true,
+            error: None,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
+
+    #[inline(always)]
+    pub fn sync<T:SaveState + ?Sized>(&mut self,value:&mut T) {
+        value.sync_state(self);
+    }
+
+    pub fn set_error(&mut self,error:String) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    pub fn has_error(&self) -> bool {
+        self.error.is_some()
+    }
+
+    // every element takes at least one byte: a length greater than the remaining bytes means a corrupted state
+    fn check_length(&mut self,len:usize) -> bool {
+        if self.error.is_none() && len > self.buffer.len() - self.pos {
+            self.set_error(format!("Invalid length {} at offset {}",len,self.pos));
+        }
+        self.error.is_none()
+    }
+
+    /*
+    Marks the beginning of a component's section: used to check, during loading, that the buffer is still aligned
+     */
+    pub fn section(&mut self,tag:&str) {
+        let mut section = tag.to_string();
+        self.sync(&mut section);
+        if self.loading && section != tag {
+            self.set_error(format!("Invalid state section: expected '{}' found '{}'",tag,section));
+        }
+    }
+
+    pub fn bytes(&mut self,data:&mut [u8]) {
+        if self.loading {
+            if self.error.is_some() {
+                return;
+            }
+            if self.pos + data.len() > self.buffer.len() {
+                self.set_error(format!("Unexpected end of state data at offset {}",self.pos));
+                return;
+            }
+            data.copy_from_slice(&self.buffer[self.pos..self.pos + data.len()]);
+            self.pos += data.len();
+        }
+        else {
+            self.buffer.extend_from_slice(data);
+        }
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>,String> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.buffer),
+        }
+    }
+}
+
+macro_rules! impl_state_primitive {
+    ($($t:ty),+) => {
+        $(
+            impl SaveState for $t {
+                #[inline(always)]
+                fn sync_state(&mut self,s:&mut StateSync) {
+                    let mut bytes = self.to_le_bytes();
+                    s.bytes(&mut bytes);
+                    if s.is_loading() {
+                        *self = <$t>::from_le_bytes(bytes);
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_state_primitive!(u16,u32,u64,i8,i16,i32,i64,f32,f64);
+
+impl SaveState for u8 {
+    #[inline(always)]
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.bytes(std::slice::from_mut(self));
+    }
+
+    // RAM, VRAM and SPU RAM buffers: copied in bulk
+    fn sync_slice(slice:&mut [Self],s:&mut StateSync) {
+        s.bytes(slice);
+    }
+}
+
+impl SaveState for usize {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let mut value = *self as u64;
+        s.sync(&mut value);
+        *self = value as usize;
+    }
+}
+
+impl SaveState for bool {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let mut value = *self as u8;
+        s.sync(&mut value);
+        *self = value != 0;
+    }
+}
+
+impl SaveState for String {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let mut bytes = self.as_bytes().to_vec();
+        s.sync(&mut bytes);
+        if s.is_loading() {
+            *self = String::from_utf8_lossy(&bytes).to_string();
+        }
+    }
+}
+
+impl<T:SaveState,const N:usize> SaveState for [T;N] {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        T::sync_slice(self,s);
+    }
+}
+
+impl<T:SaveState + Default> SaveState for Vec<T> {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let mut len = self.len();
+        s.sync(&mut len);
+        if s.is_loading() {
+            if !s.check_length(len) {
+                return;
+            }
+            self.clear();
+            self.resize_with(len,T::default);
+        }
+        T::sync_slice(self,s);
+    }
+}
+
+impl<T:SaveState + Default> SaveState for VecDeque<T> {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let mut len = self.len();
+        s.sync(&mut len);
+        if s.is_loading() {
+            if !s.check_length(len) {
+                return;
+            }
+            self.clear();
+            for _ in 0..len {
+                let mut e = T::default();
+                s.sync(&mut e);
+                self.push_back(e);
+            }
+        }
+        else {
+            for e in self.iter_mut() {
+                s.sync(e);
+            }
+        }
+    }
+}
+
+impl<T:SaveState + Default> SaveState for Option<T> {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let mut present = self.is_some();
+        s.sync(&mut present);
+        if s.is_loading() {
+            if present {
+                let mut value = T::default();
+                s.sync(&mut value);
+                *self = Some(value);
+            }
+            else {
+                *self = None;
+            }
+        }
+        else if let Some(value) = self.as_mut() {
+            s.sync(value);
+        }
+    }
+}
+
+impl<T:SaveState + ?Sized> SaveState for Box<T> {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(self.as_mut());
+    }
+}
+
+impl<T:SaveState + Copy> SaveState for Cell<T> {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(self.get_mut());
+    }
+}
+
+impl<A:SaveState,B:SaveState> SaveState for (A,B) {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.0);
+        s.sync(&mut self.1);
+    }
+}
+
+impl<A:SaveState,B:SaveState,C:SaveState> SaveState for (A,B,C) {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.0);
+        s.sync(&mut self.1);
+        s.sync(&mut self.2);
+    }
+}
+
+/*
+Implements SaveState for a struct syncing the given fields in order, optionally preceded by a section tag.
+Must be used inside the module where the struct is defined to access private fields.
+ */
+macro_rules! impl_state_struct {
+    ($t:ty { $($field:tt),* $(,)? }) => {
+        impl $crate::core::savestate::SaveState for $t {
+            fn sync_state(&mut self,s:&mut $crate::core::savestate::StateSync) {
+                $(s.sync(&mut self.$field);)*
+            }
+        }
+    };
+    ($t:ty, $section:literal { $($field:tt),* $(,)? }) => {
+        impl $crate::core::savestate::SaveState for $t {
+            fn sync_state(&mut self,s:&mut $crate::core::savestate::StateSync) {
+                s.section($section);
+                $(s.sync(&mut self.$field);)*
+            }
+        }
+    };
+}
+
+/*
+Implements SaveState for a C-like enum: the variant is stored as its index inside the given list.
+ */
+macro_rules! impl_state_enum {
+    ($t:ty { $($variant:expr),+ $(,)? }) => {
+        impl $crate::core::savestate::SaveState for $t {
+            fn sync_state(&mut self,s:&mut $crate::core::savestate::StateSync) {
+                let variants = [$($variant),+];
+                let mut index = variants.iter().position(|v| std::mem::discriminant(v) == std::mem::discriminant(self)).unwrap_or(0) as u8;
+                s.sync(&mut index);
+                if s.is_loading() {
+                    match variants.into_iter().nth(index as usize) {
+                        Some(variant) => *self = variant,
+                        None => s.set_error(format!("Invalid variant index {} for {}",index,stringify!($t))),
+                    }
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use impl_state_enum;
+pub(crate) use impl_state_struct;
+
+impl_state_struct!(StateHeader { magic, version, emu_version, bios_md5, disc_path });
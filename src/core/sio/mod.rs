@@ -2,6 +2,7 @@ use crate::core::clock::{Clock, EventType};
 use crate::core::config::Config;
 use crate::core::controllers::Controller;
 use crate::core::interrupt::{InterruptType, IrqHandler};
+use crate::core::savestate::{SaveState, StateSync};
 use crate::core::{Resettable, CPU_CLOCK};
 use std::collections::VecDeque;
 use tracing::{debug, info, warn};
@@ -59,14 +60,26 @@ impl Resettable for SIO0 {
     }
 }
 
+impl SaveState for SIO0 {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.section("SIO0");
+        s.sync(&mut self.baud);
+        s.sync(&mut self.mode);
+        s.sync(&mut self.controllers);
+        s.sync(&mut self.selected_device);
+        s.sync(&mut self.irq);
+        s.sync(&mut self.ctrl);
+        s.sync(&mut self.tx_data);
+        s.sync(&mut self.rx_fifo);
+        s.sync(&mut self.tx_idle);
+        s.sync(&mut self.ack_asserted);
+        s.sync(&mut self.timer_target_timestamp);
+    }
+}
+
 impl SIO0 {
     pub fn new(config:&Config) -> SIO0 {
-        let response_cycles = config.controllers.tx_rx_cycles;
-        if let Some(cycles) = response_cycles.as_ref() {
-            info!("Controller tx_rx cycles forced to {} cycles",cycles);
-        }
-
-        SIO0 {
+        let mut sio0 = SIO0 {
             baud: 0,
             mode: 0,
             controllers: [
@@ -87,8 +100,17 @@ impl SIO0 {
             tx_idle: true,
             ack_asserted: false,
             timer_target_timestamp: 0,
-            response_cycles: response_cycles.map(|rc| (rc * CPU_CLOCK / 250_000) as u64),
+            response_cycles: None,
+        };
+        sio0.set_tx_rx_cycles(config.controllers.tx_rx_cycles);
+        sio0
+    }
+
+    pub fn set_tx_rx_cycles(&mut self,tx_rx_cycles:Option<usize>) {
+        if let Some(cycles) = tx_rx_cycles.as_ref() {
+            info!("Controller tx_rx cycles forced to {} cycles",cycles);
         }
+        self.response_cycles = tx_rx_cycles.map(|rc| (rc * CPU_CLOCK / 250_000) as u64);
     }
 
     pub fn get_controller_mut(&mut self,index:usize) -> &mut Controller {
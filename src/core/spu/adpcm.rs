@@ -6,6 +6,7 @@
 //! and loop flags. The remaining 14 bytes contain 4-bit ADPCM sample values.
 
 use tracing::debug;
+use crate::core::savestate::impl_state_struct;
 use crate::core::spu::util::I32Ext;
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -42,6 +43,9 @@ pub struct SpuAdpcmBuffer {
     idx: usize,
 }
 
+impl_state_struct!(AdpcmHeader { shift, filter, loop_start, loop_end, loop_repeat });
+impl_state_struct!(SpuAdpcmBuffer { header, samples, idx });
+
 impl SpuAdpcmBuffer {
     pub fn new() -> Self {
         Self { header: AdpcmHeader::default(), samples: [0; 32], idx: 4 }
@@ -3,6 +3,7 @@
 use tracing::debug;
 use crate::core::spu::util;
 use util::{U16Ext,U32Ext};
+use crate::core::savestate::{impl_state_enum, impl_state_struct, SaveState, StateSync};
 use crate::core::Resettable;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -52,6 +53,17 @@ pub struct EnvelopeSettings {
     pub phase: SweepPhase,
 }
 
+impl Default for EnvelopeSettings {
+    fn default() -> Self {
+        Self { step: 0, shift: 0, direction: EnvelopeDirection::default(), mode: EnvelopeMode::default(), phase: SweepPhase::Positive }
+    }
+}
+
+impl_state_enum!(EnvelopeMode { EnvelopeMode::Linear, EnvelopeMode::Exponential });
+impl_state_enum!(EnvelopeDirection { EnvelopeDirection::Increasing, EnvelopeDirection::Decreasing });
+impl_state_enum!(SweepPhase { SweepPhase::Positive, SweepPhase::Negative });
+impl_state_struct!(EnvelopeSettings { step, shift, direction, mode, phase });
+
 impl EnvelopeSettings {
     pub fn clock(self, level: &mut i16, counter: &mut u16) {
         // Step is interpreted as (7 - N) for increasing and -(8 - N) for decreasing
@@ -135,6 +147,20 @@ impl Default for SweepSetting {
     }
 }
 
+impl SaveState for SweepSetting {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        let mut settings = match self {
+            SweepSetting::Fixed => None,
+            SweepSetting::Sweep(settings) => Some(*settings),
+        };
+        s.sync(&mut settings);
+        *self = match settings {
+            Some(settings) => SweepSetting::Sweep(settings),
+            None => SweepSetting::Fixed,
+        };
+    }
+}
+
 impl SweepSetting {
     fn parse(value: u32) -> Self {
         if !value.bit(15) {
@@ -213,6 +239,9 @@ pub struct VolumeControl {
     pub cd_r: i16,
 }
 
+impl_state_struct!(SweepEnvelope { volume, setting, counter });
+impl_state_struct!(VolumeControl { main_l, main_r, cd_l, cd_r });
+
 impl Resettable for VolumeControl {
     fn reset_component(&mut self, hard_reset: bool) {
         self.main_l.reset_component(hard_reset);
@@ -338,6 +367,12 @@ pub enum AdsrPhase {
     Release,
 }
 
+impl_state_enum!(AdsrPhase { AdsrPhase::Attack, AdsrPhase::Decay, AdsrPhase::Sustain, AdsrPhase::Release });
+impl_state_struct!(AdsrSettings {
+    attack_step, attack_shift, attack_mode, decay_shift, sustain_level, sustain_step, sustain_shift,
+    sustain_direction, sustain_mode, release_shift, release_mode
+});
+
 #[derive(Debug, Clone)]
 pub struct AdsrEnvelope {
     pub level: i16,
@@ -346,6 +381,8 @@ pub struct AdsrEnvelope {
     counter: u16,
 }
 
+impl_state_struct!(AdsrEnvelope { level, settings, phase, counter });
+
 impl AdsrEnvelope {
     pub fn new() -> Self {
         Self { level: 0, settings: AdsrSettings::new(), phase: AdsrPhase::default(), counter: 0 }
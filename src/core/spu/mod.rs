@@ -9,7 +9,9 @@ use crate::core::spu::noise::NoiseGenerator;
 use crate::core::spu::reverb::ReverbUnit;
 use crate::core::spu::util::{I32Ext, U32Ext};
 use crate::core::spu::voice::Voice;
+use crate::core::savestate::{impl_state_enum, impl_state_struct};
 use crate::core::Resettable;
+use crate::core::unimplemented;
 use std::array;
 use std::cell::Cell;
 use std::ops::{Index, IndexMut, Range};
@@ -120,6 +122,8 @@ impl IndexMut<usize> for SoundRam {
     }
 }
 
+impl_state_struct!(SoundRam { ram, irq_enabled, irq_address, irq });
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 enum DataPortMode {
     #[default]
@@ -160,6 +164,12 @@ impl Resettable for DataPort {
     }
 }
 
+impl_state_enum!(DataPortMode { DataPortMode::Off, DataPortMode::ManualWrite, DataPortMode::DmaWrite, DataPortMode::DmaRead });
+impl_state_struct!(DataPort { mode, start_address, current_address });
+impl_state_struct!(ControlRegisters {
+    soft_reset, amplifier_enabled, external_audio_enabled, cd_audio_enabled, external_audio_reverb_enabled, cd_audio_reverb_enabled
+});
+
 impl DataPort {
     fn new() -> Self {
         Self { mode: DataPortMode::default(), start_address: 0, current_address: 0 }
@@ -329,6 +339,8 @@ impl Resettable for Spu {
     }
 }
 
+impl_state_struct!(Spu, "SPU" { sound_ram, voices, control, volume, data_port, reverb, noise, last_irq_bit, capture_buffer_addr });
+
 // Each individual capture buffer is 1KB
 const CAPTURE_BUFFER_MASK: u32 = 0x3FF;
 
@@ -465,12 +477,12 @@ impl Spu {
             0x1D96 => self.reduce_voices_high(|voice| voice.noise_enabled),
             0x1D98 => self.reverb.read_reverb_on_low(),
             0x1D9A => self.reverb.read_reverb_on_high(),
-            0x1D9C => todo!("ENDX voices 0-15 read"),
-            0x1D9E => todo!("ENDX voices 16-23 read"),
+            0x1D9C => self.reduce_voices_low(Voice::is_end_reached),
+            0x1D9E => self.reduce_voices_high(Voice::is_end_reached),
             0x1DA2 => self.reverb.read_buffer_start_address(),
             0x1DA4 => self.sound_ram.read_irq_address(),
             0x1DA6 => self.data_port.read_start_address(),
-            0x1DA8 => todo!("SPU data port read"),
+            0x1DA8 => self.read_data_port().into(),
             0x1DAA => self.control.read_spucnt(
                 &self.sound_ram,
                 &self.data_port,
@@ -501,7 +513,10 @@ impl Spu {
                     (self.voices[voice as usize].volume_r.volume as u16).into()
                 }
             }
-            _ => todo!("SPU read register {address:08X}"),
+            _ => {
+                unimplemented::report("SPU",&format!("read register {address:08X}"));
+                0
+            }
         };
 
         match SIZE {
@@ -616,7 +631,7 @@ impl Spu {
                 );
             }
             0x1DC0..=0x1DFF => self.reverb.write_register(address, value),
-            _ => todo!("SPU write {address:08X} {value:08X}"),
+            _ => unimplemented::report("SPU",&format!("write register {address:08X}")),
         }
     }
 
@@ -716,7 +731,7 @@ impl Spu {
                 self.voices[voice].write_repeat_address(value);
                 debug!("Voice {voice} repeat address: {:05X}", (value & 0xFFFF) << 3);
             }
-            _ => todo!("voice {voice} register write: {address:08X} {value:04X}"),
+            _ => unimplemented::report("SPU",&format!("voice register write {address:08X}")),
         }
     }
 
@@ -1,3 +1,4 @@
+use crate::core::savestate::impl_state_struct;
 use crate::core::spu::util::I16Ext;
 use std::cmp;
 
@@ -9,6 +10,8 @@ pub struct NoiseGenerator {
     timer: i32,
 }
 
+impl_state_struct!(NoiseGenerator { output, step, shift, timer });
+
 impl NoiseGenerator {
     pub fn new() -> Self {
         Self { output: 0, step: 0, shift: 0, timer: 0 }
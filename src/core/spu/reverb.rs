@@ -2,7 +2,9 @@ use std::collections::VecDeque;
 use crate::core::spu::{multiply_volume, multiply_volume_i32, SoundRam, NUM_VOICES, SOUND_RAM_LEN, SOUND_RAM_MASK};
 use std::cmp;
 use tracing::debug;
+use crate::core::savestate::{impl_state_enum, impl_state_struct, SaveState, StateSync};
 use crate::core::Resettable;
+use crate::core::unimplemented;
 use crate::core::spu::voice::Voice;
 use super::util::{I32Ext, U32Ext};
 
@@ -36,6 +38,12 @@ impl Default for FirSampleDeque {
     }
 }
 
+impl SaveState for FirSampleDeque {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.0);
+    }
+}
+
 pub fn fir_filter(samples: &FirSampleDeque) -> i32 {
     FILTER
         .iter()
@@ -77,6 +85,15 @@ impl<T: Copy> StereoValue<T> {
     }
 }
 
+impl<T:SaveState> SaveState for StereoValue<T> {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.sync(&mut self.l);
+        s.sync(&mut self.r);
+    }
+}
+
+impl_state_enum!(ReverbClock { ReverbClock::Left, ReverbClock::Right });
+
 type StereoI16 = StereoValue<i16>;
 type StereoI32 = StereoValue<i32>;
 type StereoU32 = StereoValue<u32>;
@@ -112,6 +129,13 @@ pub struct ReverbUnit {
     pub current_output: (i16, i16),
 }
 
+impl_state_struct!(ReverbUnit {
+    writes_enabled, cd_enabled, voices_enabled, buffer_start_addr, buffer_current_addr, input_volume, output_volume,
+    comb_volumes, reflection_volume_1, reflection_volume_2, apf_volume_1, apf_volume_2, comb_addrs,
+    same_reflect_addr_1, same_reflect_addr_2, diff_reflect_addr_1, diff_reflect_addr_2, apf_addr_1, apf_offset_1,
+    apf_addr_2, apf_offset_2, clock, input_buffer_l, input_buffer_r, output_buffer_l, output_buffer_r, current_output
+});
+
 impl ReverbUnit {
     pub fn clock(
         &mut self,
@@ -393,7 +417,7 @@ impl ReverbUnit {
             0x1DFA => self.write_mrapf2(value),
             0x1DFC => self.write_vlin(value),
             0x1DFE => self.write_vrin(value),
-            _ => todo!("reverb register write {address:08X} {value:04X}"),
+            _ => unimplemented::report("SPU",&format!("reverb register write {address:08X}")),
         }
     }
 
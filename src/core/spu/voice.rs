@@ -1,5 +1,6 @@
 use std::cmp;
 use tracing::{debug, info};
+use crate::core::savestate::impl_state_struct;
 use crate::core::Resettable;
 use crate::core::spu::adpcm::{AdpcmHeader, SpuAdpcmBuffer};
 use crate::core::spu::{adpcm, interpolate, multiply_volume, AdpcmInterpolation, SoundRam, SOUND_RAM_MASK};
@@ -23,6 +24,8 @@ pub struct Voice {
     release_pending: bool,
     keyed_on: bool,
     keyed_off: bool,
+    // ENDX flag: set when a block with the loop end flag has been played, cleared by key on
+    end_reached: bool,
     soft_reset: bool,
     pub adsr: AdsrEnvelope,
     adpcm_buffer: SpuAdpcmBuffer,
@@ -53,10 +56,17 @@ impl Resettable for Voice {
         self.current_address = 0;
         self.keyed_on = false;
         self.keyed_off = false;
+        self.end_reached = false;
         info!("Voice {} reset done", self.voice_number);
     }
 }
 
+impl_state_struct!(Voice {
+    volume_l, volume_r, sample_rate, noise_enabled, pitch_modulation_enabled, start_address, repeat_address,
+    repeat_address_locked, current_address, restart_pending, restart_delay, release_pending, keyed_on, keyed_off,
+    end_reached, soft_reset, adsr, adpcm_buffer, pitch_counter, current_amplitude, current_sample
+});
+
 impl Voice {
     pub fn new(voice_number: usize, adpcm_interpolation: AdpcmInterpolation) -> Self {
         Self {
@@ -76,6 +86,7 @@ impl Voice {
             release_pending: false,
             keyed_on: false,
             keyed_off: false,
+            end_reached: false,
             soft_reset: true,
             adsr: AdsrEnvelope::new(),
             adpcm_buffer: SpuAdpcmBuffer::new(),
@@ -206,6 +217,10 @@ impl Voice {
         }
     }
 
+    pub fn is_end_reached(&self) -> bool {
+        self.end_reached
+    }
+
     pub fn is_keyed_off(&self) -> bool {
         self.keyed_off
     }
@@ -224,6 +239,7 @@ impl Voice {
         self.restart_delay = 5;
 
         self.repeat_address_locked = false;
+        self.end_reached = false;
 
         self.adsr.key_on();
 
@@ -246,6 +262,7 @@ impl Voice {
         }
 
         if loop_end {
+            self.end_reached = true;
             // The SPU can seemingly only read ADPCM blocks from 16-byte boundaries; Valkyrie Profile depends on this
             self.current_address = self.repeat_address & !0xF;
             if !loop_repeat {
@@ -1,6 +1,7 @@
 use tracing::debug;
 use crate::core::clock::{Clock, EventType};
 use crate::core::interrupt::{InterruptType, IrqHandler};
+use crate::core::savestate::{impl_state_enum, SaveState, StateSync};
 use crate::core::Resettable;
 
 #[derive(Debug,PartialEq)]
@@ -182,6 +183,47 @@ impl Resettable for Timer<2> {
     }
 }
 
+impl_state_enum!(TimerClockSource {
+    TimerClockSource::SystemClock,
+    TimerClockSource::DotClock,
+    TimerClockSource::HBlank,
+    TimerClockSource::SystemClockDiv8,
+});
+impl_state_enum!(TimerSyncMode {
+    TimerSyncMode::NoSync,
+    TimerSyncMode::PauseDuringBlank,
+    TimerSyncMode::ResetTo0AtBlank,
+    TimerSyncMode::ResetTo0AtBlankPauseOutside,
+    TimerSyncMode::PauseUntilBlankThenFreeRun,
+    TimerSyncMode::StopAtCurrentValue,
+    TimerSyncMode::FreeRun,
+});
+impl_state_enum!(TimerIRQRepeatMode { TimerIRQRepeatMode::OneShot, TimerIRQRepeatMode::Repeatedly });
+impl_state_enum!(TimerIRQPulseMode { TimerIRQPulseMode::Pulse, TimerIRQPulseMode::Toggle });
+
+impl<const N: usize> SaveState for Timer<N> {
+    fn sync_state(&mut self,s:&mut StateSync) {
+        s.section(format!("TIMER{N}").as_str());
+        s.sync(&mut self.counter);
+        s.sync(&mut self.counter_mode);
+        s.sync(&mut self.counter_target);
+        s.sync(&mut self.clock_source);
+        s.sync(&mut self.sync_mode);
+        s.sync(&mut self.inside_video_blank);
+        s.sync(&mut self.video_blank_occurred_once);
+        s.sync(&mut self.irq_repeat_mode);
+        s.sync(&mut self.irq_pulse_mode);
+        s.sync(&mut self.irq_one_shot_fired);
+        s.sync(&mut self.timer_start_timestamp);
+        s.sync(&mut self.timer_target_timestamp);
+        s.sync(&mut self.blank_start_timestamp);
+        s.sync(&mut self.blank_end_timestamp);
+        s.sync(&mut self.dot_clock_divider);
+        s.sync(&mut self.blank_paused_cycles);
+        s.sync(&mut self.blank_pending_cycles);
+    }
+}
+
 impl<const N: usize> Timer<N> {
     pub fn new() -> Self {
         const { assert!(N < 3) }
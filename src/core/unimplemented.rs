@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+use tracing::warn;
+
+/*
+Unimplemented hardware report.
+Guest accesses whose hardware behavior is unknown (unknown registers, commands, instructions, ...) must not stop the emulation:
+the component applies an open bus/ignore policy and reports the access here. Every kind of access is logged the first time
+it happens and then only counted; the report is logged at exit and shown by the debugger ('hw' command).
+ */
+static REPORT: LazyLock<Mutex<BTreeMap<String,u64>>> = LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/*
+Reports an unimplemented access of the given component (i.e. "SPU"), the access should not contain
+the values written, so that the same access is counted once per kind
+ */
+pub fn report(component:&str,access:&str) {
+    let key = format!("{}: {}",component,access);
+    let mut report = REPORT.lock().unwrap();
+    let count = report.entry(key).or_insert_with_key(|key| {
+        warn!("Unimplemented hardware access {}",key);
+        0
+    });
+    *count += 1;
+}
+
+/*
+The accesses reported so far with their count
+ */
+pub fn entries() -> Vec<(String,u64)> {
+    REPORT.lock().unwrap().iter().map(|(access,count)| (access.clone(),*count)).collect()
+}
+
+pub fn clear() {
+    REPORT.lock().unwrap().clear();
+}
+
+pub fn log_report() {
+    for (access,count) in entries() {
+        warn!("Unimplemented hardware access {} [{} times]",access,count);
+    }
+}
@@ -27,7 +27,9 @@ impl Logger {
                 .with(filter_layer)
                 .with(fmt_layer);
 
-            tracing::subscriber::set_global_default(subscriber).expect("Cannot init global log settings");
+            if tracing::subscriber::set_global_default(subscriber).is_err() {
+                println!("Global log settings already initialized");
+            }
         } else {
             let fmt_layer = fmt::layer();
 
@@ -35,7 +37,9 @@ impl Logger {
                 .with(filter_layer)
                 .with(fmt_layer);
 
-            tracing::subscriber::set_global_default(subscriber).expect("Cannot init global log settings");
+            if tracing::subscriber::set_global_default(subscriber).is_err() {
+                println!("Global log settings already initialized");
+            }
         }
 
         Self { reload_handle }
@@ -1,9 +1,11 @@
-use crate::core::config::{Config, RegionPolicyConfig};
-use crate::core::emu::{EMU_BUILD_DATE_TIME, EMU_NAME, EMU_VERSION};
-use clap::{Parser, ValueEnum};
+use crate::core::config::{Config, CpuBackend, DeinterlaceMode, HeadlessConfig, MovieConfig, RegionPolicyConfig};
+use crate::core::emu::{HeadlessStopReason, EMU_BUILD_DATE_TIME, EMU_NAME, EMU_VERSION};
+use crate::renderer::{GUIEvent, Renderer};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::env;
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::mpsc::Receiver;
 use tracing::info;
 
 mod core;
@@ -11,6 +13,7 @@ mod log;
 mod renderer;
 mod audio;
 mod cheats;
+mod memcard_tool;
 
 #[derive(Parser)]
 #[command(version, about = "Rust Playstation 1 emulator", long_about = None)]
@@ -27,9 +30,15 @@ struct Args {
     /// Region
     #[arg(long, value_name = "REGION")]
     region: Option<ArgRegion>,
+    /// CPU backend
+    #[arg(long, value_name = "BACKEND")]
+    cpu_backend: Option<ArgCpuBackend>,
     /// Debugger enabled
     #[arg(long)]
     debugger: bool,
+    /// GDB remote stub enabled, listening on localhost at the given port (default from configuration)
+    #[arg(long, value_name = "PORT", num_args = 0..=1)]
+    gdb: Option<Option<u16>>,
     /// Log level
     #[arg(long, value_name = "LEVEL")]
     log_level: Option<LogLevel>,
@@ -39,6 +48,59 @@ struct Args {
     /// Full screen enabled
     #[arg(long)]
     full_screen: bool,
+    /// GPU internal resolution multiplier: 1 (native), 2, 4 or 8
+    #[arg(long, value_name = "SCALE")]
+    internal_resolution: Option<usize>,
+    /// PGXP enabled: sub-pixel precision for the 3D vertices
+    #[arg(long)]
+    pgxp: bool,
+    /// Widescreen hack enabled: 3D scenes rendered and displayed at 16:9
+    #[arg(long)]
+    widescreen: bool,
+    /// GPU texture cache emulation enabled: needed by the games that sample the area they're drawing into, slower
+    #[arg(long)]
+    texture_cache: bool,
+    /// Deinterlacing of the 480 lines interlaced modes
+    #[arg(long, value_name = "MODE")]
+    deinterlace: Option<ArgDeinterlaceMode>,
+    /// Fast CD enabled: instant seeks and data read at the given speed multiplier, 2 to 8 (default from configuration)
+    #[arg(long, value_name = "SPEED", num_args = 0..=1)]
+    fast_cd: Option<Option<usize>>,
+    /// Run without window and audio
+    #[arg(long)]
+    headless: bool,
+    /// Headless mode: stop after the given number of frames
+    #[arg(long, value_name = "FRAMES", requires = "headless")]
+    max_frames: Option<u64>,
+    /// Headless mode: stop after the given number of CPU cycles
+    #[arg(long, value_name = "CYCLES", requires = "headless")]
+    max_cycles: Option<u64>,
+    /// Headless mode: stop when the BIOS TTY output contains the given string (exit code 1 if not found)
+    #[arg(long, value_name = "STRING", requires = "headless")]
+    tty_stop: Option<String>,
+    /// Headless mode: comma separated list of frame numbers to dump as PNG
+    #[arg(long, value_name = "FRAMES", value_delimiter = ',', requires = "headless")]
+    dump_frames: Vec<u64>,
+    /// Headless mode: dump the whole VRAM as well
+    #[arg(long, requires = "dump_frames")]
+    dump_vram: bool,
+    /// Record the controllers inputs, from power-on, to the given movie file
+    #[arg(long, value_name = "FILE", conflicts_with = "play_movie")]
+    record_movie: Option<PathBuf>,
+    /// Play the controllers inputs recorded in the given movie file (in headless mode stops at the end of the movie)
+    #[arg(long, value_name = "FILE")]
+    play_movie: Option<PathBuf>,
+    #[command(subcommand)]
+    tool: Option<Tool>,
+}
+
+#[derive(Subcommand)]
+enum Tool {
+    /// Memory card manager: list, import, export and delete saves
+    Memcard {
+        #[command(subcommand)]
+        command: memcard_tool::MemcardCommand,
+    },
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
@@ -61,13 +123,63 @@ enum ArgRegion {
     Auto,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ArgCpuBackend {
+    /// Decodes and executes one instruction at a time
+    Interpreter,
+    /// Keeps the decoded instructions and executes them in blocks: faster
+    Cached,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ArgDeinterlaceMode {
+    /// Both fields as they are in VRAM: full resolution, combing on motion
+    Weave,
+    /// The lines of the current field doubled: no combing, half resolution
+    Bob,
+    /// The average of the two fields: no combing, blurred on motion
+    Blend,
+    /// Weave, with the combing lines interpolated from the current field
+    Adaptive,
+}
+
 const DEFAULT_CONFIG_FILE_NAME : &str = "config.yaml";
 
+fn start_emulator<R:Renderer + 'static>(renderer:R,gui_event_rx:Receiver<GUIEvent>,config:Config) {
+    let logger = log::Logger::new(config.log_config.log_file.clone(),config.log_config.log_severity.clone());
+
+
+    println!("Welcome to {} v{} compiled on {}",EMU_NAME,EMU_VERSION,EMU_BUILD_DATE_TIME);
+    info!("Welcome to {} v{} compiled on {}",EMU_NAME,EMU_VERSION,EMU_BUILD_DATE_TIME);
+    info!("Starting emulator from bios at {}",config.bios_path.as_ref().unwrap());
+
+    let tty_stop_required = config.headless_config.as_ref().is_some_and(|h| h.tty_stop_string.is_some());
+    let mut emu = core::emu::Emulator::new(config,logger,Box::new(renderer),gui_event_rx);
+
+    emu.emulate();
+
+    info!("Emulator is shutting down ...");
+    let unimplemented = core::unimplemented::entries();
+    if !unimplemented.is_empty() {
+        println!("Unimplemented hardware accessed by the game:");
+        for (access,count) in unimplemented {
+            println!("  {} [{} times]",access,count);
+        }
+    }
+    if tty_stop_required && emu.get_headless_stop_reason() != Some(HeadlessStopReason::TtyMatched) {
+        println!("TTY stop string not found");
+        exit(1);
+    }
+}
+
 fn main() {
     let emu_exe = env::current_exe().unwrap();
     let emu_dir = emu_exe.parent().unwrap();
 
     let args = Args::parse();
+    if let Some(Tool::Memcard { command }) = args.tool {
+        memcard_tool::run(command);
+    }
     let mut config = if let Some(config_path) = args.config {
         println!("Loading config file '{}' ...",config_path.display());
         Config::load_or_default(&config_path)
@@ -109,6 +221,13 @@ fn main() {
         };
         config.region_policy = region;
     }
+    // CPU backend
+    if let Some(backend) = args.cpu_backend {
+        config.cpu_config.cpu_backend = match backend {
+            ArgCpuBackend::Interpreter => CpuBackend::Interpreter,
+            ArgCpuBackend::Cached => CpuBackend::CachedInterpreter,
+        };
+    }
     // debugger
     if args.debugger || config.debugger_enabled {
         config.debugger_enabled = true;
@@ -117,10 +236,66 @@ fn main() {
             config.log_config.log_file = Some(PathBuf::from("r-ps1.log"));
         }
     }
+    // gdb stub
+    if let Some(port) = args.gdb {
+        config.gdb_config.gdb_enabled = true;
+        if let Some(port) = port {
+            config.gdb_config.gdb_port = port;
+        }
+    }
     // full screen
     if args.full_screen {
         config.gpu_config.start_full_screen = true;
     }
+    // internal resolution
+    if let Some(scale) = args.internal_resolution {
+        config.gpu_config.internal_resolution = scale;
+    }
+    // PGXP
+    if args.pgxp {
+        config.gpu_config.pgxp_enabled = true;
+    }
+    // widescreen
+    if args.widescreen {
+        config.gpu_config.widescreen_hack = true;
+    }
+    // texture cache
+    if args.texture_cache {
+        config.gpu_config.texture_cache_enabled = true;
+    }
+    // deinterlace
+    if let Some(mode) = args.deinterlace {
+        config.gpu_config.deinterlace_mode = match mode {
+            ArgDeinterlaceMode::Weave => DeinterlaceMode::Weave,
+            ArgDeinterlaceMode::Bob => DeinterlaceMode::Bob,
+            ArgDeinterlaceMode::Blend => DeinterlaceMode::Blend,
+            ArgDeinterlaceMode::Adaptive => DeinterlaceMode::Adaptive,
+        };
+    }
+    // fast CD
+    if let Some(read_speed) = args.fast_cd {
+        config.cdrom_config.fast_cd_enabled = true;
+        if let Some(read_speed) = read_speed {
+            config.cdrom_config.fast_cd_read_speed = read_speed;
+        }
+    }
+    // headless
+    if args.headless {
+        config.headless_config = Some(HeadlessConfig {
+            max_frames: args.max_frames,
+            max_cycles: args.max_cycles,
+            tty_stop_string: args.tty_stop,
+            dump_frames: args.dump_frames,
+            dump_vram: args.dump_vram,
+        });
+    }
+    // input movie
+    if let Some(movie_path) = args.record_movie {
+        config.movie_config = Some(MovieConfig::Record(movie_path));
+    }
+    else if let Some(movie_path) = args.play_movie {
+        config.movie_config = Some(MovieConfig::Play(movie_path));
+    }
 
     let bios_path = Path::new(config.bios_path.as_deref().unwrap());
     if !bios_path.exists() {
@@ -144,18 +319,10 @@ fn main() {
         }
     }
     
-    renderer::pixels::run_loop(|renderer, gui_event_rx, config| {
-        let logger = log::Logger::new(config.log_config.log_file.clone(),config.log_config.log_severity.clone());
-
-
-        println!("Welcome to {} v{} compiled on {}",EMU_NAME,EMU_VERSION,EMU_BUILD_DATE_TIME);
-        info!("Welcome to {} v{} compiled on {}",EMU_NAME,EMU_VERSION,EMU_BUILD_DATE_TIME);
-        info!("Starting emulator from bios at {}",config.bios_path.as_ref().unwrap());
-        
-        let mut emu = core::emu::Emulator::new(config,logger,Box::new(renderer),gui_event_rx);
-        
-        emu.emulate();
-        
-        info!("Emulator is shutting down ...");
-    }, config);
+    if config.headless_config.is_some() {
+        renderer::null::run_headless(start_emulator,config);
+    }
+    else {
+        renderer::pixels::run_loop(start_emulator,config);
+    }
 }
@@ -0,0 +1,141 @@
+use crate::core::controllers::memory_card_fs::{MemoryCardImage, SaveFile, DIRECTORY_ENTRIES};
+use clap::Subcommand;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+/*
+Memory card manager: works on raw (mcd, mcr, ...) or DexDrive (gme) card images.
+Saves are identified by the slot of their first block (1..15), as shown by the list command.
+ */
+#[derive(Subcommand)]
+pub enum MemcardCommand {
+    /// List the saves of a card, deleted ones included
+    List {
+        #[arg(value_name = "CARD")]
+        card: PathBuf,
+    },
+    /// Export a save to a mcs, psv or gme file
+    Export {
+        #[arg(value_name = "CARD")]
+        card: PathBuf,
+        #[arg(value_name = "SLOT")]
+        slot: usize,
+        #[arg(value_name = "FILE")]
+        output: PathBuf,
+    },
+    /// Import a mcs or psv save, or all the saves of another card (gme, mcd, ...)
+    Import {
+        #[arg(value_name = "CARD")]
+        card: PathBuf,
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+    },
+    /// Delete a save (it can be restored with undelete until its blocks are reused)
+    Delete {
+        #[arg(value_name = "CARD")]
+        card: PathBuf,
+        #[arg(value_name = "SLOT")]
+        slot: usize,
+    },
+    /// Restore a deleted save
+    Undelete {
+        #[arg(value_name = "CARD")]
+        card: PathBuf,
+        #[arg(value_name = "SLOT")]
+        slot: usize,
+    },
+    /// Create a new empty card, or erase an existing one with --force
+    Format {
+        #[arg(value_name = "CARD")]
+        card: PathBuf,
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+fn check_slot(slot:usize) -> Result<usize,String> {
+    if (1..=DIRECTORY_ENTRIES).contains(&slot) {
+        Ok(slot)
+    }
+    else {
+        Err(format!("Invalid slot {}: expected 1..{}",slot,DIRECTORY_ENTRIES))
+    }
+}
+
+fn list(card_path:&Path) -> Result<(),String> {
+    let card = MemoryCardImage::open(card_path)?;
+    let saves = card.saves();
+    println!("Memory card '{}': {} saves, {} free blocks",card_path.display(),saves.iter().filter(|s| !s.deleted).count(),card.free_blocks());
+    if saves.is_empty() {
+        return Ok(());
+    }
+    println!("{:<5}{:<8}{:<8}{:<12}{:<10}{:<7}Title","Slot","Blocks","Region","Product","Id","Icons");
+    for save in saves.iter() {
+        let title = match (save.deleted,save.complete) {
+            (false,_) => save.title.clone(),
+            (true,true) => format!("{} [deleted]",save.title),
+            (true,false) => format!("{} [deleted, not recoverable]",save.title),
+        };
+        println!("{:<5}{:<8}{:<8}{:<12}{:<10}{:<7}{}",save.slot,save.blocks.len(),save.region(),save.product_code(),save.identifier(),save.icon_frames,title);
+    }
+    Ok(())
+}
+
+/*
+Runs the command and exits: errors are reported on stderr with exit code 1
+ */
+pub fn run(command:MemcardCommand) -> ! {
+    match execute(command) {
+        Ok(_) => exit(0),
+        Err(e) => {
+            eprintln!("{}",e);
+            exit(1);
+        }
+    }
+}
+
+fn execute(command:MemcardCommand) -> Result<(),String> {
+    match command {
+        MemcardCommand::List { card } => list(&card),
+        MemcardCommand::Export { card, slot, output } => {
+            let save = MemoryCardImage::open(&card)?.export_save(check_slot(slot)?)?;
+            save.save(&output)?;
+            println!("Save '{}' ({} blocks) exported to '{}'",save.file_name(),save.blocks(),output.display());
+            Ok(())
+        }
+        MemcardCommand::Import { card, input } => {
+            let mut memory_card = MemoryCardImage::open(&card)?;
+            let saves = SaveFile::load(&input)?;
+            if saves.is_empty() {
+                return Err(format!("No saves found in '{}'",input.display()));
+            }
+            for save in saves.iter() {
+                let slot = memory_card.import_save(save).map_err(|e| format!("Cannot import save '{}': {}",save.file_name(),e))?;
+                println!("Save '{}' ({} blocks) imported at slot {}",save.file_name(),save.blocks(),slot);
+            }
+            memory_card.save(&card)
+        }
+        MemcardCommand::Delete { card, slot } => {
+            let mut memory_card = MemoryCardImage::open(&card)?;
+            let save = memory_card.delete_save(check_slot(slot)?)?;
+            memory_card.save(&card)?;
+            println!("Save '{}' deleted",save.file_name);
+            Ok(())
+        }
+        MemcardCommand::Undelete { card, slot } => {
+            let mut memory_card = MemoryCardImage::open(&card)?;
+            let save = memory_card.undelete_save(check_slot(slot)?)?;
+            memory_card.save(&card)?;
+            println!("Save '{}' restored",save.file_name);
+            Ok(())
+        }
+        MemcardCommand::Format { card, force } => {
+            if card.exists() && !force {
+                return Err(format!("'{}' already exists: use --force to erase it",card.display()));
+            }
+            MemoryCardImage::new_formatted().save(&card)?;
+            println!("Memory card '{}' formatted",card.display());
+            Ok(())
+        }
+    }
+}
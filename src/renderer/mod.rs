@@ -1,11 +1,12 @@
 pub mod pixels;
+pub mod null;
 mod text_renderer;
 
 use crate::core::cdrom::{CDOperation, Region};
 use crate::core::config::Config;
-use crate::core::controllers::ControllerButton;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use crate::core::controllers::{ControllerAxis, ControllerButton};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU16, Ordering};
 use std::sync::{mpsc, Arc};
 
 #[derive(Debug, Clone)]
@@ -18,11 +19,14 @@ pub enum PS1Event {
     Shutdown,
     SetRegion(Region),
     AudioMute(bool),
+    Message(String),
+    Widescreen(bool),
 }
 
 #[derive(Debug, Clone)]
 pub enum GUIEvent {
     Controller(usize, ControllerButton, bool),
+    ControllerAxis(usize, ControllerAxis, u8),
     WarpMode,
     Paused,
     VRAMDebugMode,
@@ -30,7 +34,17 @@ pub enum GUIEvent {
     Mute,
     InsertDisc(PathBuf),
     Cheat,
+    NextCheat,
+    ToggleCheat,
     Reset(bool),
+    SaveState,
+    LoadState,
+    NextStateSlot,
+    PrevStateSlot,
+    Snapshot(bool),
+    NextDisc,
+    PrevDisc,
+    Rewind(bool),
     Ready,
 }
 
@@ -53,6 +67,14 @@ impl GPUFrameBuffer {
     pub fn new(frame: Arc<Vec<u8>>, crt_width: usize, crt_height: usize, visible_width:usize,visible_height:usize,debug_frame:bool) -> GPUFrameBuffer {
         GPUFrameBuffer { frame, crt_width, crt_height,visible_width,visible_height,debug_frame }
     }
+
+    pub fn save_png(&self,path:&Path) -> Result<(),String> {
+        if let Some(dir) = path.parent() && !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create directory {}: {}",dir.display(),e))?;
+        }
+        image::save_buffer_with_format(path,&self.frame,self.crt_width as u32,self.crt_height as u32,image::ExtendedColorType::Rgba8,image::ImageFormat::Png)
+            .map_err(|e| format!("Cannot save PNG {}: {}",path.display(),e))
+    }
 }
 
 pub type EmuStarter<R> = fn(R,mpsc::Receiver<GUIEvent>,Config);
@@ -96,6 +118,52 @@ impl MouseAccumulator {
     }
 }
 
+/*
+DualShock state of both controllers shared with the USB controller loop, written by the emulator:
+the motors values, and if the controller has analog sticks (the per-game controller type can change at disc load)
+ */
+#[derive(Debug)]
+pub struct DualShockState {
+    // small << 8 | large
+    motors: [AtomicU16;2],
+    changed: AtomicBool,
+    analog_sticks: [AtomicBool;2],
+}
+
+impl DualShockState {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            motors: [AtomicU16::new(0),AtomicU16::new(0)],
+            changed: AtomicBool::new(false),
+            analog_sticks: [AtomicBool::new(false),AtomicBool::new(false)],
+        })
+    }
+
+    pub fn set_analog_sticks(&self,controller:usize,enabled:bool) {
+        self.analog_sticks[controller].store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn has_analog_sticks(&self,controller:usize) -> bool {
+        self.analog_sticks[controller].load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self,controller:usize,small:u8,large:u8) {
+        self.motors[controller].store(((small as u16) << 8) | large as u16, Ordering::Relaxed);
+        self.changed.store(true, Ordering::Release);
+    }
+
+    // (small,large) of each controller, if changed since the last call
+    pub fn consume(&self) -> Option<[(u8,u8);2]> {
+        if !self.changed.swap(false, Ordering::Acquire) {
+            return None;
+        }
+        Some(std::array::from_fn(|i| {
+            let motors = self.motors[i].load(Ordering::Relaxed);
+            ((motors >> 8) as u8,motors as u8)
+        }))
+    }
+}
+
 pub trait Renderer {
     fn set_splash_screen(&mut self);
     fn render_frame(&mut self, frame: GPUFrameBuffer,last_performance:u16);
@@ -106,4 +174,10 @@ pub trait Renderer {
     fn set_region(&mut self,region:Region);
     fn set_audio_mute(&mut self,mute:bool);
     fn get_mouse_accumulator(&self) -> Arc<MouseAccumulator>;
+    fn show_message(&mut self,msg:String);
+    fn set_rumble(&mut self,controller:usize,small:u8,large:u8);
+    // true if the controller receives the USB sticks as analog axes, false to map them to the d-pad
+    fn set_analog_sticks(&mut self,controller:usize,enabled:bool);
+    // true to display at 16:9 instead of 4:3
+    fn set_widescreen(&mut self,widescreen:bool);
 }
\ No newline at end of file
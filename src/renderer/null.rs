@@ -0,0 +1,56 @@
+use crate::core::cdrom::{CDOperation, Region};
+use crate::core::config::Config;
+use crate::renderer::{EmuStarter, GPUFrameBuffer, GUIEvent, MouseAccumulator, Renderer};
+use std::sync::{mpsc, Arc};
+use tracing::info;
+
+/*
+Renderer without window: frames are discarded, used in headless mode
+ */
+pub struct NullRenderer {
+    mouse_acc: Arc<MouseAccumulator>,
+}
+
+impl NullRenderer {
+    pub fn new() -> Self {
+        Self {
+            mouse_acc: MouseAccumulator::new(),
+        }
+    }
+}
+
+impl Default for NullRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer for NullRenderer {
+    fn set_splash_screen(&mut self) {}
+    fn render_frame(&mut self, _frame: GPUFrameBuffer,_last_performance:u16) {}
+    fn set_warp_mode(&mut self,_enabled:bool) {}
+    fn set_paused(&mut self,_paused:bool) {}
+    fn set_last_cd_access(&mut self,_access:CDOperation) {}
+    fn shutdown(&mut self) {}
+    fn set_region(&mut self,_region:Region) {}
+    fn set_audio_mute(&mut self,_mute:bool) {}
+    fn get_mouse_accumulator(&self) -> Arc<MouseAccumulator> {
+        Arc::clone(&self.mouse_acc)
+    }
+    fn show_message(&mut self,msg:String) {
+        info!("{msg}");
+    }
+    fn set_rumble(&mut self,_controller:usize,_small:u8,_large:u8) {}
+    fn set_analog_sticks(&mut self,_controller:usize,_enabled:bool) {}
+    fn set_widescreen(&mut self,_widescreen:bool) {}
+}
+
+/*
+Runs the emulator in the current thread, without GUI
+ */
+pub fn run_headless(start:EmuStarter<NullRenderer>,config:Config) {
+    let (gui_event_tx, gui_event_rx) = mpsc::channel::<GUIEvent>();
+    let _ = gui_event_tx.send(GUIEvent::Ready);
+
+    start(NullRenderer::new(),gui_event_rx,config);
+}
@@ -1,20 +1,21 @@
-use super::{CDOperation, GUIEvent, MouseAccumulator};
+use super::{CDOperation, DualShockState, GUIEvent, MouseAccumulator};
 use super::{EmuStarter, GPUFrameBuffer, PS1Event, Renderer};
 use crate::core::cdrom::Region;
 use crate::core::config::{Config, ControllerType};
-use crate::core::controllers::ControllerButton;
+use crate::core::controllers::{ControllerAxis, ControllerButton};
 use crate::core::emu::EMU_NAME;
 use crate::core::emu::EMU_VERSION;
 use crate::renderer::text_renderer::TextRenderer;
 use fast_image_resize::images::Image;
 use fast_image_resize::{FilterType, PixelType, ResizeAlg, ResizeOptions, Resizer};
-use gilrs::{Event, EventType, Gilrs};
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder};
+use gilrs::{Event, EventType, GamepadId, Gilrs};
 use pixels::{wgpu, Pixels, PixelsBuilder, SurfaceTexture};
 use std::collections::HashMap;
 use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time::Instant;
-use tracing::{debug, error, info};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 use winit::application::ApplicationHandler;
 use winit::dpi::PhysicalSize;
 use winit::event::{DeviceEvent, DeviceId, MouseButton, WindowEvent};
@@ -32,15 +33,19 @@ const DEFAULT_HEIGHT: usize = (480.0 * DEFAULT_SCALE) as usize;
 const FONT_SIZE: f32 = 14.0;
 
 const FPS_PERIOD_MILLIS: u128 = 600;
+const OSD_MESSAGE_MILLIS: u128 = 2000;
+// USB events polling timeout: rumble changes are applied between events
+const USB_POLL_MILLIS: u64 = 10;
 
 pub struct GPUPixelsRenderer {
     event_proxy: EventLoopProxy<PS1Event>,
     mouse_acc: Arc<MouseAccumulator>,
+    dualshock: Arc<DualShockState>,
 }
 
 impl GPUPixelsRenderer {
-    pub fn new(event_proxy: EventLoopProxy<PS1Event>, mouse_acc:Arc<MouseAccumulator>) -> Self {
-        Self { event_proxy, mouse_acc }
+    pub fn new(event_proxy: EventLoopProxy<PS1Event>, mouse_acc:Arc<MouseAccumulator>, dualshock:Arc<DualShockState>) -> Self {
+        Self { event_proxy, mouse_acc, dualshock }
     }
 }
 
@@ -69,9 +74,21 @@ impl Renderer for GPUPixelsRenderer {
     fn set_audio_mute(&mut self,mute:bool) {
         let _ = self.event_proxy.send_event(PS1Event::AudioMute(mute));
     }
+    fn show_message(&mut self,msg:String) {
+        let _ = self.event_proxy.send_event(PS1Event::Message(msg));
+    }
     fn get_mouse_accumulator(&self) -> Arc<MouseAccumulator> {
         Arc::clone(&self.mouse_acc)
     }
+    fn set_rumble(&mut self,controller:usize,small:u8,large:u8) {
+        self.dualshock.set(controller,small,large);
+    }
+    fn set_analog_sticks(&mut self,controller:usize,enabled:bool) {
+        self.dualshock.set_analog_sticks(controller,enabled);
+    }
+    fn set_widescreen(&mut self,widescreen:bool) {
+        let _ = self.event_proxy.send_event(PS1Event::Widescreen(widescreen));
+    }
 }
 
 pub fn run_loop(start:EmuStarter<GPUPixelsRenderer>,config:Config) {
@@ -89,10 +106,12 @@ pub fn run_loop(start:EmuStarter<GPUPixelsRenderer>,config:Config) {
 
     let mouse_acc = MouseAccumulator::new();
     let mouse_acc_proxy = mouse_acc.clone();
+    let dualshock = DualShockState::new();
+    let usb_dualshock = dualshock.clone();
 
-    thread::spawn(move || start(GPUPixelsRenderer::new(proxy,mouse_acc),gui_event_rx,emu_config));
+    thread::spawn(move || start(GPUPixelsRenderer::new(proxy,mouse_acc,dualshock),gui_event_rx,emu_config));
     if config.controllers.controller_1.attach_to_usb || config.controllers.controller_2.attach_to_usb {
-        thread::spawn(move || usb_controller_loop(usb_config, usb_event_tx));
+        thread::spawn(move || usb_controller_loop(usb_config, usb_event_tx, usb_dualshock));
     }
     else {
         info!("USB controller loop disabled");
@@ -149,7 +168,38 @@ impl USBDirections {
     }
 }
 
-fn usb_controller_loop(config:Config,gui_event_tx:mpsc::Sender<GUIEvent>) {
+/*
+Converts a gilrs axis value (-1.0..1.0, up is positive) to the DualShock range (00h..FFh, up/left is 00h)
+ */
+fn usb_axis_value(value:f32,inverted:bool) -> u8 {
+    let value = if inverted { -value } else { value };
+    ((value.clamp(-1.0,1.0) + 1.0) * 127.5).round() as u8
+}
+
+fn usb_rumble_effect(gilrs:&mut Gilrs,gamepad:GamepadId,small:u8,large:u8) -> Option<Effect> {
+    if !gilrs.gamepad(gamepad).is_ff_supported() {
+        return None;
+    }
+    let effect = EffectBuilder::new()
+        .add_effect(BaseEffect { kind: BaseEffectType::Strong { magnitude: large as u16 * 257 }, ..Default::default() })
+        .add_effect(BaseEffect { kind: BaseEffectType::Weak { magnitude: small as u16 * 257 }, ..Default::default() })
+        .gamepads(&[gamepad])
+        .finish(gilrs);
+    match effect {
+        Ok(effect) => {
+            if let Err(e) = effect.play() {
+                warn!("Cannot play rumble effect on gamepad #{gamepad}: {e}");
+            }
+            Some(effect)
+        }
+        Err(e) => {
+            warn!("Cannot create rumble effect on gamepad #{gamepad}: {e}");
+            None
+        }
+    }
+}
+
+fn usb_controller_loop(config:Config,gui_event_tx:mpsc::Sender<GUIEvent>,dualshock:Arc<DualShockState>) {
     let mut gilrs = match Gilrs::new() {
         Ok(gilrs) => {
             gilrs
@@ -171,6 +221,9 @@ fn usb_controller_loop(config:Config,gui_event_tx:mpsc::Sender<GUIEvent>) {
         (gilrs::Button::RightTrigger2,ControllerButton::R2),
         (gilrs::Button::Start,ControllerButton::Start),
         (gilrs::Button::Select,ControllerButton::Select),
+        (gilrs::Button::LeftThumb,ControllerButton::L3),
+        (gilrs::Button::RightThumb,ControllerButton::R3),
+        (gilrs::Button::Mode,ControllerButton::Analog),
     ]);
 
     let mut directions = USBDirections::new(config.controllers.usb_direction_resolution);
@@ -182,40 +235,43 @@ fn usb_controller_loop(config:Config,gui_event_tx:mpsc::Sender<GUIEvent>) {
             gilrs::Button::DPadLeft => Some(ControllerButton::Left),
             gilrs::Button::DPadRight => Some(ControllerButton::Right),
             gilrs::Button::DPadUp => Some(ControllerButton::Up),
-            gilrs::Button::DPadDown => Some(ControllerButton::Left),
+            gilrs::Button::DPadDown => Some(ControllerButton::Down),
             _ => None,
         }
     };
 
-    let mut controller_ids = [-1,-1];
+    let mut controller_ids : [Option<GamepadId>;2] = [None,None];
     let c1_usb_enabled = config.controllers.controller_1.attach_to_usb;
     let c2_usb_enabled = config.controllers.controller_2.attach_to_usb;
+    let mut axes = [[0x80u8;4];2];
+    let mut effects : [Option<Effect>;2] = [None,None];
 
     loop {
-        while let Some(Event { id, event, time, .. }) = gilrs.next_event_blocking(Some(std::time::Duration::from_secs(1))) {
+        if let Some(Event { id, event, time, .. }) = gilrs.next_event_blocking(Some(Duration::from_millis(USB_POLL_MILLIS))) {
             debug!("{:?} New event from {}: {:?}", time, id, event);
-            let id : usize = id.into();
-            if controller_ids[0] == -1 && c1_usb_enabled && controller_ids[1] != id as i32 {
-                controller_ids[0] = id as i32;
+            if controller_ids[0].is_none() && c1_usb_enabled && controller_ids[1] != Some(id) {
+                controller_ids[0] = Some(id);
                 info!("Controller #0 assigned to gamepad #{id}");
             }
-            else if controller_ids[1] == -1 && c2_usb_enabled && controller_ids[0] != id as i32 {
-                controller_ids[1] = id as i32;
+            else if controller_ids[1].is_none() && c2_usb_enabled && controller_ids[0] != Some(id) {
+                controller_ids[1] = Some(id);
                 info!("Controller #1 assigned to gamepad #{id}");
             }
-            let controller_id = if controller_ids[0] == id as i32 { 0 } else { 1 };
+            let controller_id = if controller_ids[0] == Some(id) { 0 } else { 1 };
 
             match event {
                 EventType::Connected => {
                     info!("New gamepad #{} connected", id);
                 }
                 EventType::Disconnected => {
-                    if controller_ids[0] == id as i32 {
-                        controller_ids[0] = -1;
+                    if controller_ids[0] == Some(id) {
+                        controller_ids[0] = None;
+                        effects[0] = None;
                         info!("Gamepad #{} disconnected from controller #0", id);
                     }
-                    else if controller_ids[1] == id as i32 {
-                        controller_ids[1] = -1;
+                    else if controller_ids[1] == Some(id) {
+                        controller_ids[1] = None;
+                        effects[1] = None;
                         info!("Gamepad #{} disconnected from controller #1", id);
                     }
                 }
@@ -249,6 +305,20 @@ fn usb_controller_loop(config:Config,gui_event_tx:mpsc::Sender<GUIEvent>) {
                         }
                     }
                 }
+                // analog controllers receive the sticks, the others map them to the d-pad
+                EventType::AxisChanged(axis,value,_code) if dualshock.has_analog_sticks(controller_id) => {
+                    let axis = match axis {
+                        gilrs::Axis::LeftStickX => Some((ControllerAxis::LeftX,usb_axis_value(value,false))),
+                        gilrs::Axis::LeftStickY => Some((ControllerAxis::LeftY,usb_axis_value(value,true))),
+                        gilrs::Axis::RightStickX => Some((ControllerAxis::RightX,usb_axis_value(value,false))),
+                        gilrs::Axis::RightStickY => Some((ControllerAxis::RightY,usb_axis_value(value,true))),
+                        _ => None
+                    };
+                    if let Some((axis,value)) = axis && axes[controller_id][axis as usize] != value {
+                        axes[controller_id][axis as usize] = value;
+                        let _ = gui_event_tx.send(GUIEvent::ControllerAxis(controller_id, axis, value));
+                    }
+                }
                 EventType::AxisChanged(axis,value,_code) => {
                     match axis {
                         gilrs::Axis::LeftStickX | gilrs::Axis::RightStickX=> {
@@ -263,6 +333,16 @@ fn usb_controller_loop(config:Config,gui_event_tx:mpsc::Sender<GUIEvent>) {
                 _ => {}
             }
         }
+
+        if let Some(motors) = dualshock.consume() {
+            for (controller,(small,large)) in motors.into_iter().enumerate() {
+                // dropping the previous effect stops it
+                effects[controller] = None;
+                if let Some(gamepad) = controller_ids[controller] && (small != 0 || large != 0) {
+                    effects[controller] = usb_rumble_effect(&mut gilrs,gamepad,small,large);
+                }
+            }
+        }
     }
 }
 
@@ -299,6 +379,8 @@ struct PixelsRenderer {
     text_renderer: TextRenderer,
     mouse_acc: Arc<MouseAccumulator>,
     mouse_enabled: bool,
+    osd_message: Option<(String,Instant)>,
+    widescreen: bool,
 }
 
 impl PixelsRenderer {
@@ -336,9 +418,16 @@ impl PixelsRenderer {
             text_renderer: TextRenderer::new(),
             mouse_acc: mouse_acc.clone(),
             mouse_enabled: false,
+            osd_message: None,
+            widescreen: false,
         };
 
         renderer.full_screen = renderer.config.gpu_config.start_full_screen;
+        renderer.widescreen = renderer.config.gpu_config.widescreen_hack || renderer.config.gpu_config.widescreen_anamorphic;
+        if renderer.widescreen {
+            // initial window at 16:9
+            renderer.width = renderer.height * 16 / 9;
+        }
         renderer.mouse_enabled = matches!(renderer.config.controllers.controller_1.controller_type,ControllerType::Mouse) || matches!(renderer.config.controllers.controller_2.controller_type,ControllerType::Mouse);
 
         if let Some(renderer_type) = renderer.config.gpu_config.rendering_type.as_ref() {
@@ -450,9 +539,13 @@ impl PixelsRenderer {
         }
     }
 
-    fn get_adjusted_image_size_4_3(window_size:PhysicalSize<u32>) -> (u32, u32) {
+    // 16:9 in widescreen mode, 4:3 otherwise
+    fn display_aspect(&self) -> f32 {
+        if self.widescreen { 16.0 / 9.0 } else { 4.0 / 3.0 }
+    }
+
+    fn get_adjusted_image_size(window_size:PhysicalSize<u32>,target_aspect:f32) -> (u32, u32) {
         let window_aspect = window_size.width as f32 / window_size.height as f32;
-        let target_aspect = 4.0 / 3.0;
 
         if window_aspect > target_aspect {
             // bande laterali
@@ -469,6 +562,7 @@ impl PixelsRenderer {
 
     fn new_frame(&mut self, frame: &GPUFrameBuffer,last_performance:u16) {
         self.last_performance = last_performance;
+        let display_aspect = self.display_aspect();
         if let Some(pixels) = &mut self.pixels {
             self.visible_width = frame.visible_width;
             self.visible_height = frame.visible_height;
@@ -500,7 +594,7 @@ impl PixelsRenderer {
                 let (adjusted_width,adjusted_height) = if self.debug_mode {
                     (window_size.width,window_size.height)
                 } else {
-                    Self::get_adjusted_image_size_4_3(window_size)
+                    Self::get_adjusted_image_size(window_size,display_aspect)
                 };
 
                 if pixels.resize_buffer(adjusted_width, adjusted_height).is_err() {
@@ -519,7 +613,7 @@ impl PixelsRenderer {
             let (adjusted_width,adjusted_height) = if self.debug_mode {
                 (window_size.width,window_size.height)
             } else {
-                Self::get_adjusted_image_size_4_3(window_size)
+                Self::get_adjusted_image_size(window_size,display_aspect)
             };
             let mut image_dst = Image::new(adjusted_width, adjusted_height, PixelType::U8x4);
             self.image_resizer.resize(&image_src,&mut image_dst,&self.image_resizer_options).unwrap();
@@ -533,6 +627,16 @@ impl PixelsRenderer {
                 self.resize_adjust_pending = true;
             }
 
+            if let Some((msg,shown_at)) = self.osd_message.as_ref() {
+                if shown_at.elapsed().as_millis() < OSD_MESSAGE_MILLIS {
+                    let font_size = window_size.width as f32 / DEFAULT_WIDTH as f32 * FONT_SIZE;
+                    self.text_renderer.draw_text(pixels.frame_mut(),self.pixels_dimension.0,msg,10,10,font_size,[255,255,0,255]);
+                }
+                else {
+                    self.osd_message = None;
+                }
+            }
+
             if pixels.render().is_err() {
                 println!("Pixels render error");
             }
@@ -680,6 +784,16 @@ impl ApplicationHandler<PS1Event> for PixelsRenderer {
             PS1Event::AudioMute(on) => {
                 self.audio_muted = on;
             }
+            PS1Event::Message(msg) => {
+                self.osd_message = Some((msg,Instant::now()));
+            }
+            PS1Event::Widescreen(widescreen) => {
+                if self.widescreen != widescreen {
+                    info!("Display aspect ratio set to {}",if widescreen { "16:9" } else { "4:3" });
+                    self.widescreen = widescreen;
+                    self.resize_adjust_pending = true;
+                }
+            }
         }
     }
 
@@ -737,6 +851,13 @@ impl ApplicationHandler<PS1Event> for PixelsRenderer {
             WindowEvent::KeyboardInput { event, .. } => {
                 self.last_key = event.state.is_pressed();
                 if let PhysicalKey::Code(keycode) = event.physical_key {
+                    // rewind while the key is held
+                    if keycode == KeyCode::Backspace {
+                        if !event.repeat {
+                            let _ = self.gui_event_tx.send(GUIEvent::Rewind(self.last_key));
+                        }
+                        return;
+                    }
                     // check user commands
                     if !self.last_key {
                         match keycode {
@@ -756,8 +877,9 @@ impl ApplicationHandler<PS1Event> for PixelsRenderer {
                                 let _ = self.gui_event_tx.send(GUIEvent::Mute);
                                 return;
                             }
-                            KeyCode::F4 => { // check cheat mode
-                                let _ = self.gui_event_tx.send(GUIEvent::Cheat);
+                            KeyCode::F4 => { // check cheat mode, with shift selects the next cheat, with ctrl switches on/off the selected one
+                                let event = if self.key_modifiers.shift_key() { GUIEvent::NextCheat } else if self.key_modifiers.control_key() { GUIEvent::ToggleCheat } else { GUIEvent::Cheat };
+                                let _ = self.gui_event_tx.send(event);
                                 return;
                             }
                             KeyCode::F10 => { // check fullscreen mode
@@ -777,6 +899,28 @@ impl ApplicationHandler<PS1Event> for PixelsRenderer {
                                 let _ = self.gui_event_tx.send(GUIEvent::Reset(self.key_modifiers.shift_key()));
                                 return;
                             }
+                            KeyCode::F6 => { // save state on current slot
+                                let _ = self.gui_event_tx.send(GUIEvent::SaveState);
+                                return;
+                            }
+                            KeyCode::F7 => { // load state from current slot
+                                let _ = self.gui_event_tx.send(GUIEvent::LoadState);
+                                return;
+                            }
+                            KeyCode::F8 => { // change state slot
+                                let event = if self.key_modifiers.shift_key() { GUIEvent::PrevStateSlot } else { GUIEvent::NextStateSlot };
+                                let _ = self.gui_event_tx.send(event);
+                                return;
+                            }
+                            KeyCode::F9 => { // frame snapshot, with shift the whole VRAM
+                                let _ = self.gui_event_tx.send(GUIEvent::Snapshot(self.key_modifiers.shift_key()));
+                                return;
+                            }
+                            KeyCode::F11 => { // next disc of the playlist, with shift the previous one
+                                let event = if self.key_modifiers.shift_key() { GUIEvent::PrevDisc } else { GUIEvent::NextDisc };
+                                let _ = self.gui_event_tx.send(event);
+                                return;
+                            }
                             _ => {}
                         }
                     }
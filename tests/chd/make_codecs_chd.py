@@ -0,0 +1,341 @@
+#!/usr/bin/env python3
+"""
+Builds codecs.chd, the CHD v5 image used by the unit tests of src/core/cdrom/chd.rs, and prints the MD5 of every hunk.
+No reference tool (chdman) is needed: the compressed data come from the reference zlib and liblzma libraries (Python zlib and
+lzma modules), FLAC frames and the compressed map are written following the FLAC specification (RFC 9639) and the MAME
+CHD v5 format (chd.cpp compress_v5_map, huffman.cpp export_tree_rle). The MD5s are computed on the source data, before compression.
+
+Hunks are 1 CD frame (2352 bytes of sector + 96 bytes of subcode), compressors: zlib, lzma, cdzl, cdfl.
+  hunk 0      zlib (type 0)
+  hunk 1      lzma (type 1)
+  hunk 2      cdzl (type 2): Mode 1 sector with sync header and ECC stripped, to be regenerated
+  hunk 3      cdfl (type 3): 588 stereo samples in 4 FLAC frames of 148 and 144 samples (independent, left/side, side/right, mid/side stereo)
+  hunk 4      uncompressed
+  hunk 5      self reference to hunk 0
+  hunk 6      self reference to the last one (hunk 0)
+  hunk 7      self reference to the last one + 1 (hunk 1)
+  hunk 8..11  zlib, 4 hunks of the same type: RLE small
+  hunk 12..35 lzma, 24 hunks of the same type: RLE large
+"""
+import hashlib, lzma, math, random, struct, sys, zlib
+
+random.seed(5)
+FRAME = 2448
+SECTOR = 2352
+SUBCODE = 96
+
+# map entry types
+TYPE_0, TYPE_1, TYPE_2, TYPE_3, NONE, SELF, PARENT, RLE_SMALL, RLE_LARGE, SELF_0, SELF_1 = 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10
+
+class BitWriter:
+    def __init__(self):
+        self.bits = []
+    def write(self, value, count):
+        self.bits += [(value >> i) & 1 for i in range(count - 1, -1, -1)]
+    def write_signed(self, value, count):
+        self.write(value & ((1 << count) - 1), count)
+    def unary(self, value):
+        self.bits += [0] * value + [1]
+    def align(self):
+        self.bits += [0] * (-len(self.bits) % 8)
+    def data(self):
+        self.align()
+        return bytes(int(''.join(map(str, self.bits[i:i + 8])), 2) for i in range(0, len(self.bits), 8))
+
+def raw_deflate(data):
+    c = zlib.compressobj(9, zlib.DEFLATED, -15)
+    return c.compress(data) + c.flush()
+
+def raw_lzma(data):
+    return lzma.compress(data, format=lzma.FORMAT_RAW, filters=[{'id': lzma.FILTER_LZMA1, 'lc': 3, 'lp': 0, 'pb': 2, 'dict_size': 1 << 16}])
+
+# ECC (P and Q parity) of a Mode 1 sector, from ecma-130
+def ecc_tables():
+    f, b = [0] * 256, [0] * 256
+    for i in range(256):
+        j = (i << 1) ^ (0x11D if i & 0x80 else 0)
+        f[i] = j & 0xFF
+        b[i ^ f[i]] = i
+    return f, b
+ECC_F, ECC_B = ecc_tables()
+
+def ecc_block(src, major_count, minor_count, major_mult, minor_inc):
+    size = major_count * minor_count
+    out = [0] * (2 * major_count)
+    for major in range(major_count):
+        index = (major >> 1) * major_mult + (major & 1)
+        a = b = 0
+        for _ in range(minor_count):
+            t = src[index]
+            index += minor_inc
+            if index >= size:
+                index -= size
+            a ^= t
+            b ^= t
+            a = ECC_F[a]
+        a = ECC_B[ECC_F[a] ^ b]
+        out[major] = a
+        out[major + major_count] = a ^ b
+    return out
+
+def edc(data):
+    crc = 0
+    for byte in data:
+        crc ^= byte
+        for _ in range(8):
+            crc = (crc >> 1) ^ (0xD8018001 if crc & 1 else 0)
+    return crc
+
+def mode1_sector(lba):
+    s = bytearray(SECTOR)
+    s[0:12] = b'\x00' + b'\xff' * 10 + b'\x00'
+    msf = lba + 150
+    s[12:15] = bytes(int(str(v), 16) for v in (msf // 4500, (msf // 75) % 60, msf % 75))
+    s[15] = 1
+    s[16:16 + 2048] = bytes(random.randrange(256) for _ in range(2048))
+    s[0x810:0x814] = struct.pack('<I', edc(s[0:0x810]))
+    s[0x81C:0x81C + 172] = bytes(ecc_block(s[12:], 86, 24, 2, 86))
+    s[0x8C8:0x8C8 + 104] = bytes(ecc_block(s[12:], 52, 43, 86, 88))
+    return bytes(s)
+
+def cd_codec(sector, strip_ecc, compress):
+    ecc_bits = 0
+    if strip_ecc:
+        ecc_bits = 1
+        s = bytearray(sector)
+        s[0:12] = bytes(12)
+        s[0x81C:0x81C + 276] = bytes(276)
+        sector = bytes(s)
+    base = compress(sector)
+    subcode = raw_deflate(bytes(SUBCODE))
+    return bytes([ecc_bits]) + struct.pack('>H', len(base)) + base + subcode
+
+# FLAC frames (RFC 9639), 16 bits stereo at 44100Hz, CRCs not checked by the decoder
+def rice_partitions(bw, residuals, order, block_size, partition_order):
+    bw.write(0, 2)  # 4 bits rice parameters
+    bw.write(partition_order, 4)
+    size = block_size >> partition_order
+    index = 0
+    for p in range(1 << partition_order):
+        count = size - order if p == 0 else size
+        part = residuals[index:index + count]
+        zigzag = [(r << 1) if r >= 0 else ((-r) << 1) - 1 for r in part]
+        param = max(0, int(math.log2(sum(zigzag) / len(zigzag) + 1)))
+        bw.write(param, 4)
+        for u in zigzag:
+            bw.unary(u >> param)
+            bw.write(u & ((1 << param) - 1), param)
+        index += count
+
+def subframe(bw, x, bps, kind):
+    bw.write(0, 1)
+    if kind == 'verbatim':
+        bw.write(1, 6)
+        bw.write(0, 1)
+        for v in x:
+            bw.write_signed(v, bps)
+    elif kind == 'fixed2':
+        bw.write(8 + 2, 6)
+        bw.write(0, 1)
+        for v in x[:2]:
+            bw.write_signed(v, bps)
+        rice_partitions(bw, [x[i] - (2 * x[i - 1] - x[i - 2]) for i in range(2, len(x))], 2, len(x), 1)
+    elif kind == 'lpc2':
+        # order 2, 6 bits precision, shift 4: x[i-1] * 24/16 - x[i-2] * 8/16
+        bw.write(32 + 1, 6)
+        bw.write(0, 1)
+        for v in x[:2]:
+            bw.write_signed(v, bps)
+        bw.write(6 - 1, 4)
+        bw.write_signed(4, 5)
+        bw.write_signed(24, 6)
+        bw.write_signed(-8, 6)
+        rice_partitions(bw, [x[i] - ((24 * x[i - 1] - 8 * x[i - 2]) >> 4) for i in range(2, len(x))], 2, len(x), 2)
+
+def flac_frame(left, right, assignment, kinds, number):
+    bw = BitWriter()
+    bw.write(0x3FFE, 14)
+    bw.write(0, 2)
+    bw.write(7, 4)  # block size - 1 in 16 bits
+    bw.write(9, 4)  # 44100Hz
+    bw.write(assignment, 4)
+    bw.write(4, 3)  # 16 bits
+    bw.write(0, 1)
+    bw.write(number, 8)
+    bw.write(len(left) - 1, 16)
+    bw.write(0, 8)  # CRC-8
+    side = [l - r for l, r in zip(left, right)]
+    channels = {
+        1: [(left, 16), (right, 16)],
+        8: [(left, 16), (side, 17)],
+        9: [(side, 17), (right, 16)],
+        10: [([(l + r) >> 1 for l, r in zip(left, right)], 16), (side, 17)],
+    }[assignment]
+    for (samples, bps), kind in zip(channels, kinds):
+        subframe(bw, samples, bps, kind)
+    bw.align()
+    bw.write(0, 16)  # CRC-16
+    return bw.data()
+
+def cdfl_hunk():
+    samples = [(int(12000 * math.sin(i / 9.0) + random.randrange(-40, 40)), int(9000 * math.cos(i / 13.0) + random.randrange(-40, 40))) for i in range(588)]
+    pcm = b''.join(struct.pack('>hh', l, r) for l, r in samples)
+    frames = b''
+    modes = [(1, ('verbatim', 'fixed2')), (8, ('lpc2', 'fixed2')), (9, ('fixed2', 'lpc2')), (10, ('lpc2', 'verbatim'))]
+    # the block sizes are multiples of 4, as needed by the Rice partitions (2 or 4 per subframe)
+    for n, (assignment, kinds) in enumerate(modes):
+        block = samples[n * 148:(n + 1) * 148]
+        frames += flac_frame([l for l, _ in block], [r for _, r in block], assignment, kinds, n)
+    return pcm + bytes(SUBCODE), frames + raw_deflate(bytes(SUBCODE))
+
+def random_frame():
+    return bytes(random.randrange(256) for _ in range(FRAME // 2)) * 2
+
+# hunks: (type, stored data, uncompressed frame, self reference)
+hunks = []
+raw0 = random_frame()
+hunks.append((TYPE_0, raw_deflate(raw0), raw0, None))
+raw1 = random_frame()
+hunks.append((TYPE_1, raw_lzma(raw1), raw1, None))
+sector = mode1_sector(16)
+hunks.append((TYPE_2, cd_codec(sector, True, raw_deflate), sector + bytes(SUBCODE), None))
+pcm, flac = cdfl_hunk()
+hunks.append((TYPE_3, flac, pcm, None))
+raw4 = random_frame()
+hunks.append((NONE, raw4, raw4, None))
+hunks.append((SELF, b'', raw0, 0))
+hunks.append((SELF_0, b'', raw0, 0))
+hunks.append((SELF_1, b'', raw1, 1))
+for _ in range(4):
+    raw = random_frame()
+    hunks.append((TYPE_0, raw_deflate(raw), raw, None))
+for _ in range(24):
+    raw = random_frame()
+    hunks.append((TYPE_1, raw_lzma(raw), raw, None))
+
+# compressed map: compression types Huffman coded with RLE of the repeated types, then lengths and self references
+def encode_runs(types):
+    # the RLE codes stand for a hunk of the last type followed by (2 + n) repeats (small) or (2 + 16 + n) repeats (large)
+    symbols = []
+    i = 0
+    while i < len(types):
+        symbols.append(types[i])
+        j = i + 1
+        while j < len(types) and types[j] == types[i]:
+            j += 1
+        repeats = j - i - 1
+        while repeats > 0:
+            if repeats >= 1 + 2 + 16:
+                n = min(repeats - 1 - 2 - 16, 255)
+                symbols += [RLE_LARGE, n >> 4, n & 15]
+                repeats -= 1 + 2 + 16 + n
+            elif repeats >= 1 + 2:
+                n = min(repeats - 1 - 2, 15)
+                symbols += [RLE_SMALL, n]
+                repeats -= 1 + 2 + n
+            else:
+                symbols.append(types[i])
+                repeats -= 1
+        i = j
+    return symbols
+
+def huffman_lengths(symbols):
+    freq = [symbols.count(s) for s in range(16)]
+    nodes = [(f, [s]) for s, f in enumerate(freq) if f > 0]
+    lengths = [0] * 16
+    while len(nodes) > 1:
+        nodes.sort(key=lambda n: n[0])
+        (f1, s1), (f2, s2) = nodes[0], nodes[1]
+        for s in s1 + s2:
+            lengths[s] += 1
+        nodes = nodes[2:] + [(f1 + f2, s1 + s2)]
+    assert max(lengths) <= 8
+    return lengths
+
+def canonical_codes(lengths):
+    histogram = [0] * 33
+    for l in lengths:
+        histogram[l] += 1
+    start = 0
+    for length in range(32, 0, -1):
+        next_start = (start + histogram[length]) >> 1
+        histogram[length] = start
+        start = next_start
+    codes = [0] * 16
+    for s, l in enumerate(lengths):
+        if l:
+            codes[s] = histogram[l]
+            histogram[l] += 1
+    return codes
+
+def write_tree_rle(bw, lengths):
+    def write_bits(value, repeat):
+        while repeat > 0:
+            if value == 1:
+                bw.write(1, 4)
+                bw.write(1, 4)
+                repeat -= 1
+            elif repeat <= 2:
+                bw.write(value, 4)
+                repeat -= 1
+            else:
+                n = min(repeat - 3, 15)
+                bw.write(1, 4)
+                bw.write(value, 4)
+                bw.write(n, 4)
+                repeat -= n + 3
+    last, repeat = None, 0
+    for l in lengths:
+        if l == last:
+            repeat += 1
+        else:
+            if repeat:
+                write_bits(last, repeat)
+            last, repeat = l, 1
+    write_bits(last, repeat)
+
+types = [t for t, _, _, _ in hunks]
+symbols = encode_runs(types)
+lengths = huffman_lengths(symbols)
+codes = canonical_codes(lengths)
+length_bits = max(len(d) for t, d, _, _ in hunks if t <= TYPE_3).bit_length()
+self_bits = max(r for _, _, _, r in hunks if r is not None).bit_length() or 1
+
+bw = BitWriter()
+write_tree_rle(bw, lengths)
+for s in symbols:
+    bw.write(codes[s], lengths[s])
+for t, data, _, reference in hunks:
+    if t <= TYPE_3:
+        bw.write(len(data), length_bits)
+        bw.write(0, 16)  # CRC-16, not checked
+    elif t == NONE:
+        bw.write(0, 16)
+    elif t == SELF:
+        bw.write(reference, self_bits)
+map_data = bw.data()
+
+HEADER_SIZE = 124
+out = bytearray(HEADER_SIZE)
+first_offset = len(out)
+for _, data, _, _ in hunks:
+    out += data
+map_offset = len(out)
+out += struct.pack('>I', len(map_data)) + struct.pack('>Q', first_offset)[2:] + b'\0\0' + bytes([length_bits, self_bits, 0, 0]) + map_data
+meta_offset = len(out)
+metadata = b'TRACK:1 TYPE:MODE1_RAW SUBTYPE:NONE FRAMES:%d PREGAP:0 PGTYPE:MODE1 PGSUB:RW POSTGAP:0\0' % len(hunks)
+out += b'CHT2' + struct.pack('>I', (1 << 24) | len(metadata)) + struct.pack('>Q', 0) + metadata
+header = bytearray(HEADER_SIZE)
+header[0:8] = b'MComprHD'
+header[8:12] = struct.pack('>I', HEADER_SIZE)
+header[12:16] = struct.pack('>I', 5)
+header[16:32] = b'zlib' + b'lzma' + b'cdzl' + b'cdfl'
+header[32:40] = struct.pack('>Q', len(hunks) * FRAME)
+header[40:48] = struct.pack('>Q', map_offset)
+header[48:56] = struct.pack('>Q', meta_offset)
+header[56:60] = struct.pack('>I', FRAME)
+header[60:64] = struct.pack('>I', FRAME)
+out[0:HEADER_SIZE] = header
+open(sys.argv[1] if len(sys.argv) > 1 else 'codecs.chd', 'wb').write(out)
+for n, (_, _, frame, _) in enumerate(hunks):
+    print(n, hashlib.md5(frame).hexdigest())
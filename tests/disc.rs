@@ -0,0 +1,57 @@
+/*
+Disc identification: the boot serial read from SYSTEM.CNF through the ISO 9660 file system, and the region derived from it.
+The test image is a minimal iso (2048 bytes sectors, rebuilt as Mode 1 sectors by the disc loader).
+ */
+use r_ps1::core::cdrom::disc::Disc;
+use r_ps1::core::cdrom::Region;
+use std::fs;
+
+const SECTOR_SIZE : usize = 2048;
+const PVD_LBA : usize = 16;
+const ROOT_DIR_LBA : usize = 18;
+const SYSTEM_CNF_LBA : usize = 19;
+const SYSTEM_CNF : &[u8] = b"BOOT = cdrom:\\SLUS_005.94;1\r\nTCB = 4\r\nEVENT = 10\r\nSTACK = 801FFFF0\r\n";
+
+fn directory_record(lba:usize,size:usize,name:&[u8]) -> Vec<u8> {
+    let length = (33 + name.len() + 1) & !1;
+    let mut record = vec![0u8;length];
+    record[0] = length as u8;
+    record[2..6].copy_from_slice(&(lba as u32).to_le_bytes());
+    record[10..14].copy_from_slice(&(size as u32).to_le_bytes());
+    record[25] = if name.len() == 1 { 2 } else { 0 };
+    record[32] = name.len() as u8;
+    record[33..33 + name.len()].copy_from_slice(name);
+    record
+}
+
+fn make_iso() -> Vec<u8> {
+    let mut iso = vec![0u8;(SYSTEM_CNF_LBA + 1) * SECTOR_SIZE];
+    let pvd = &mut iso[PVD_LBA * SECTOR_SIZE..(PVD_LBA + 1) * SECTOR_SIZE];
+    pvd[0] = 1;
+    pvd[1..6].copy_from_slice(b"CD001");
+    pvd[6] = 1;
+    pvd[156..156 + 34].copy_from_slice(&directory_record(ROOT_DIR_LBA,SECTOR_SIZE,b"\0"));
+    let terminator = &mut iso[(PVD_LBA + 1) * SECTOR_SIZE..(PVD_LBA + 2) * SECTOR_SIZE];
+    terminator[0] = 0xFF;
+    terminator[1..6].copy_from_slice(b"CD001");
+
+    let records = [
+        directory_record(ROOT_DIR_LBA,SECTOR_SIZE,b"\0"),
+        directory_record(ROOT_DIR_LBA,SECTOR_SIZE,b"\x01"),
+        directory_record(SYSTEM_CNF_LBA,SYSTEM_CNF.len(),b"SYSTEM.CNF;1"),
+    ].concat();
+    iso[ROOT_DIR_LBA * SECTOR_SIZE..ROOT_DIR_LBA * SECTOR_SIZE + records.len()].copy_from_slice(&records);
+    iso[SYSTEM_CNF_LBA * SECTOR_SIZE..SYSTEM_CNF_LBA * SECTOR_SIZE + SYSTEM_CNF.len()].copy_from_slice(SYSTEM_CNF);
+    iso
+}
+
+#[test]
+fn iso_boot_serial() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("game.iso");
+    fs::write(&path,make_iso()).unwrap();
+
+    let disc = Disc::new(&path.to_string_lossy().to_string()).unwrap();
+    assert_eq!(disc.get_serial(),Some("SLUS_005.94"));
+    assert_eq!(disc.get_region(),Some(Region::USA));
+}
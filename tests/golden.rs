@@ -0,0 +1,119 @@
+/*
+Golden image regression harness.
+Every sub directory of tests/golden is a test case containing:
+- case.yaml: exe file name, frame number to capture and comparison tolerance
+- the homebrew EXE file, booted through the bios and loaded with Bus::load_exe
+- golden.png: the expected frame. Cases without a reference capture use a frame of r-ps1 itself, so they only detect regressions
+  (see tests/golden/README.md)
+The bios is taken from the RPS1_BIOS environment variable, so the test is ignored by default: run it with --ignored.
+Setting RPS1_UPDATE_GOLDEN=1 the captured frames replace the golden images.
+ */
+use r_ps1::core::config::{Config, ControllerConfig, ControllersConfig, HeadlessConfig, SnapshotConfig};
+use r_ps1::core::emu::Emulator;
+use r_ps1::log::Logger;
+use r_ps1::renderer::null::NullRenderer;
+use r_ps1::renderer::GUIEvent;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+const GOLDEN_DIR : &str = "tests/golden";
+
+#[derive(Debug, Deserialize)]
+struct GoldenCase {
+    exe: String,
+    frame: u64,
+    // maximum difference allowed for every color channel
+    #[serde(default)]
+    channel_tolerance: u8,
+    // maximum percentage of pixels exceeding the channel tolerance
+    #[serde(default)]
+    max_mismatch_percent: f32,
+}
+
+fn capture_frame(bios:&str,exe:&Path,frame:u64,output_dir:&Path) -> Result<PathBuf,String> {
+    let controllers = ControllersConfig::default();
+    let config = Config {
+        bios_path: Some(bios.to_string()),
+        disc_path: Some(exe.to_string_lossy().to_string()),
+        controllers: ControllersConfig {
+            controller_1: ControllerConfig { attach_to_usb: false, ..controllers.controller_1 },
+            controller_2: ControllerConfig { attach_to_usb: false, ..controllers.controller_2 },
+            ..controllers
+        },
+        snapshot_config: SnapshotConfig { snapshots_path: output_dir.to_string_lossy().to_string() },
+        headless_config: Some(HeadlessConfig {
+            max_frames: Some(frame),
+            dump_frames: vec![frame],
+            ..HeadlessConfig::default()
+        }),
+        ..Config::default()
+    };
+
+    let (gui_event_tx, gui_event_rx) = mpsc::channel::<GUIEvent>();
+    let _ = gui_event_tx.send(GUIEvent::Ready);
+    let logger = Logger::new(None,"error".to_string());
+    let mut emu = Emulator::new(config,logger,Box::new(NullRenderer::new()),gui_event_rx);
+    emu.emulate();
+
+    fs::read_dir(output_dir).map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .find(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .ok_or_else(|| format!("Frame {frame} has not been captured"))
+}
+
+fn compare(captured:&Path,golden:&Path,case:&GoldenCase) -> Result<(),String> {
+    let captured = image::open(captured).map_err(|e| e.to_string())?.to_rgba8();
+    let golden = image::open(golden).map_err(|e| format!("Cannot open golden image {}: {}",golden.display(),e))?.to_rgba8();
+    if captured.dimensions() != golden.dimensions() {
+        return Err(format!("Frame size {:?} differs from golden size {:?}",captured.dimensions(),golden.dimensions()));
+    }
+    let mismatches = captured.pixels().zip(golden.pixels())
+        .filter(|(c,g)| c.0.iter().zip(g.0.iter()).any(|(a,b)| a.abs_diff(*b) > case.channel_tolerance))
+        .count();
+    let mismatch_percent = mismatches as f32 * 100.0 / (golden.width() * golden.height()) as f32;
+    if mismatch_percent > case.max_mismatch_percent {
+        Err(format!("{mismatches} pixels ({mismatch_percent:.3}%) differ from golden image"))
+    }
+    else {
+        Ok(())
+    }
+}
+
+#[test]
+#[ignore = "needs RPS1_BIOS"]
+fn golden_images() {
+    let bios = std::env::var("RPS1_BIOS").expect("RPS1_BIOS must be set to the bios image path");
+    let update = std::env::var("RPS1_UPDATE_GOLDEN").is_ok_and(|v| v == "1");
+    let mut cases = fs::read_dir(GOLDEN_DIR).unwrap()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.join("case.yaml").exists())
+        .collect::<Vec<_>>();
+    cases.sort();
+
+    let mut failures = Vec::new();
+    for case_dir in cases.iter() {
+        let case_name = case_dir.file_name().unwrap().to_string_lossy().to_string();
+        let case : GoldenCase = serde_yaml::from_str(&fs::read_to_string(case_dir.join("case.yaml")).unwrap()).unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        let golden = case_dir.join("golden.png");
+
+        let result = capture_frame(&bios,&case_dir.join(&case.exe),case.frame,output_dir.path()).and_then(|captured| {
+            if update {
+                fs::copy(&captured,&golden).map(|_| ()).map_err(|e| e.to_string())
+            }
+            else {
+                compare(&captured,&golden,&case)
+            }
+        });
+        match result {
+            Ok(_) => println!("{case_name}: ok"),
+            Err(e) => {
+                println!("{case_name}: {e}");
+                failures.push(case_name);
+            }
+        }
+    }
+    assert!(failures.is_empty(),"Golden image tests failed: {:?}",failures);
+}
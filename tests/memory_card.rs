@@ -0,0 +1,117 @@
+/*
+Memory card manager round trips: a save exported to mcs, psv or gme and imported back must give the same card.
+The test card holds a 2 blocks save built by hand, as written by the BIOS; a damaged block chain must be listed, not panic.
+ */
+use r_ps1::core::controllers::memory_card_fs::{MemoryCardImage, SaveFile, BLOCK_SIZE, CARD_SIZE};
+use std::fs;
+use std::path::Path;
+
+const FILE_NAME : &[u8] = b"BASLUS-00594GAME0";
+const TITLE : &[u8] = b"\x82\x60\x82\x6F\x82\x64 SAVE";
+
+fn set_checksum(frame:&mut [u8]) {
+    frame[127] = frame[..127].iter().fold(0,|c,b| c ^ b);
+}
+
+/*
+Formatted card with a save in blocks 1 and 2
+ */
+fn make_card(dir:&Path) -> Vec<u8> {
+    let path = dir.join("source.mcd");
+    MemoryCardImage::new_formatted().save(&path).unwrap();
+    let mut card = fs::read(&path).unwrap();
+    for (block,state,next) in [(1usize,0x51u32,1u16),(2,0x53,0xFFFF)] {
+        let frame = &mut card[block * 128..(block + 1) * 128];
+        frame[0..4].copy_from_slice(&state.to_le_bytes());
+        if block == 1 {
+            frame[4..8].copy_from_slice(&(2 * BLOCK_SIZE as u32).to_le_bytes());
+            frame[0x0A..0x0A + FILE_NAME.len()].copy_from_slice(FILE_NAME);
+        }
+        frame[8..10].copy_from_slice(&next.to_le_bytes());
+        set_checksum(frame);
+    }
+    for (i,b) in card[BLOCK_SIZE..3 * BLOCK_SIZE].iter_mut().enumerate() {
+        *b = (i * 7 + i / 251) as u8;
+    }
+    let title_frame = &mut card[BLOCK_SIZE..BLOCK_SIZE + 128];
+    title_frame[0..4].copy_from_slice(&[b'S',b'C',0x11,2]);
+    title_frame[4..68].fill(0);
+    title_frame[4..4 + TITLE.len()].copy_from_slice(TITLE);
+    fs::write(&path,&card).unwrap();
+    card
+}
+
+fn round_trip(extension:&str) {
+    let dir = tempfile::tempdir().unwrap();
+    let source = make_card(dir.path());
+    let card = MemoryCardImage::open(&dir.path().join("source.mcd")).unwrap();
+    let saves = card.saves();
+    assert_eq!(saves.len(),1);
+    assert_eq!(saves[0].blocks,vec![1,2]);
+    assert_eq!(saves[0].product_code(),"SLUS-00594");
+    assert_eq!(saves[0].title,"APE SAVE");
+
+    let export_path = dir.path().join(format!("save.{}",extension));
+    card.export_save(1).unwrap().save(&export_path).unwrap();
+    let imported = SaveFile::load(&export_path).unwrap();
+    assert_eq!(imported.len(),1);
+    assert_eq!(imported[0].file_name(),"BASLUS-00594GAME0");
+    assert_eq!(imported[0].blocks(),2);
+
+    let mut target = MemoryCardImage::new_formatted();
+    assert_eq!(target.import_save(&imported[0]).unwrap(),1);
+    let target_path = dir.path().join("target.mcd");
+    target.save(&target_path).unwrap();
+    assert_eq!(fs::read(&target_path).unwrap(),source,"card rebuilt from the {} file differs",extension);
+}
+
+#[test]
+fn mcs_round_trip() {
+    round_trip("mcs");
+}
+
+#[test]
+fn psv_round_trip() {
+    round_trip("psv");
+}
+
+#[test]
+fn gme_round_trip() {
+    round_trip("gme");
+}
+
+#[test]
+fn gme_card_with_padding() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = make_card(dir.path());
+    let gme_path = dir.path().join("card.gme");
+    MemoryCardImage::open(&dir.path().join("source.mcd")).unwrap().save(&gme_path).unwrap();
+    let mut gme = fs::read(&gme_path).unwrap();
+    assert_eq!(gme.len(),0xF40 + CARD_SIZE);
+    gme.extend([0u8;0x200]);
+    fs::write(&gme_path,&gme).unwrap();
+
+    let card = MemoryCardImage::open(&gme_path).unwrap();
+    let raw_path = dir.path().join("card.mcd");
+    card.save(&raw_path).unwrap();
+    assert_eq!(fs::read(&raw_path).unwrap(),source);
+}
+
+#[test]
+fn corrupt_block_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut source = make_card(dir.path());
+    // next block pointer of the first block past the directory
+    let frame = &mut source[128..256];
+    frame[8..10].copy_from_slice(&0x3FFu16.to_le_bytes());
+    set_checksum(frame);
+    let path = dir.path().join("corrupt.mcd");
+    fs::write(&path,&source).unwrap();
+
+    let card = MemoryCardImage::open(&path).unwrap();
+    let saves = card.saves();
+    assert_eq!(saves.len(),1);
+    assert_eq!(saves[0].blocks,vec![1]);
+    assert!(!saves[0].complete);
+    let _ = card.export_save(1);
+}